@@ -364,14 +364,35 @@ pub async fn wait_for_pci_device(
     let matcher = PciMatcher::new(&sysfs_rel_path)?;
 
     let uev = wait_for_uevent(sandbox, matcher).await?;
+    pci_address_from_devpath(&uev.devpath)
+}
+
+// Dragonball plugs the VFIO devices on its PCI bus without notifying the
+// guest, so rescan the bus if the device is not there yet. No uevent is
+// emitted for a device probed by the rescan or already probed before the
+// agent started, hence return its address right away when it is found.
+fn rescan_pci_device(pcipath: &pci::Path) -> Result<Option<pci::Address>> {
+    let root_bus_sysfs = format!("{}{}", SYSFS_DIR, create_pci_root_bus_path());
+    let sysfs_rel_path = pcipath_to_sysfs(&root_bus_sysfs, pcipath)?;
+    let sysfs_path = format!("{}{}", root_bus_sysfs, sysfs_rel_path);
 
-    let addr = uev
-        .devpath
+    if !Path::new(&sysfs_path).exists() {
+        fs::write(SYSFS_PCI_BUS_RESCAN_FILE, "1")
+            .with_context(|| format!("failed to rescan PCI bus for {}", pcipath))?;
+    }
+    if Path::new(&sysfs_path).exists() {
+        return pci_address_from_devpath(&sysfs_rel_path).map(Some);
+    }
+    Ok(None)
+}
+
+// Get the address of the PCI device from the last component of its path
+fn pci_address_from_devpath(devpath: &str) -> Result<pci::Address> {
+    let addr = devpath
         .rsplit('/')
         .next()
-        .ok_or_else(|| anyhow!("Bad device path {:?} in uevent", &uev.devpath))?;
-    let addr = pci::Address::from_str(addr)?;
-    Ok(addr)
+        .ok_or_else(|| anyhow!("Bad PCI device path {:?}", devpath))?;
+    pci::Address::from_str(addr)
 }
 
 #[derive(Debug)]
@@ -728,7 +749,10 @@ async fn vfio_device_handler(device: &Device, sandbox: &Arc<Mutex<Sandbox>>) ->
             pci::Address::from_str(host).context("Bad host PCI address in VFIO option {:?}")?;
         let pcipath = pci::Path::from_str(pcipath)?;
 
-        let guestdev = wait_for_pci_device(sandbox, &pcipath).await?;
+        let guestdev = match rescan_pci_device(&pcipath)? {
+            Some(guestdev) => guestdev,
+            None => wait_for_pci_device(sandbox, &pcipath).await?,
+        };
         if vfio_in_guest {
             pci_driver_override(SYSFS_BUS_PCI_PATH, guestdev, "vfio-pci")?;
 
@@ -1240,6 +1264,15 @@ mod tests {
         assert_eq!(env[2], "NOTAPCIDEVICE_blah=abcd:ef:01.0");
     }
 
+    #[test]
+    fn test_pci_address_from_devpath() {
+        let addr =
+            pci_address_from_devpath("/devices/pci0000:00/0000:00:02.0/0000:01:03.0").unwrap();
+        assert_eq!(addr, pci::Address::from_str("0000:01:03.0").unwrap());
+
+        assert!(pci_address_from_devpath("/devices/pci0000:00/virtio0").is_err());
+    }
+
     #[test]
     fn test_pcipath_to_sysfs() {
         let testdir = tempdir().expect("failed to create tmpdir");
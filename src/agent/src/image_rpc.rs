@@ -18,6 +18,7 @@ use protocols::image;
 use tokio::sync::Mutex;
 use ttrpc::{self, error::get_rpc_status as ttrpc_error};
 
+use crate::policy::AGENT_POLICY;
 use crate::rpc::{verify_cid, CONTAINER_BASE};
 use crate::sandbox::Sandbox;
 use crate::AGENT_CONFIG;
@@ -230,6 +231,15 @@ impl protocols::image_ttrpc_async::Image for ImageService {
         _ctx: &ttrpc::r#async::TtrpcContext,
         req: image::PullImageRequest,
     ) -> ttrpc::Result<image::PullImageResponse> {
+        if let Some(policy) = AGENT_POLICY.read().await.as_ref() {
+            policy.check_pull_image(&req).map_err(|e| {
+                ttrpc_error(
+                    ttrpc::Code::PERMISSION_DENIED,
+                    format!("PullImageRequest is denied: {:?}", e),
+                )
+            })?;
+        }
+
         match self.pull_image(&req).await {
             Ok(r) => {
                 let mut resp = image::PullImageResponse::new();
@@ -46,12 +46,14 @@ mod namespace;
 mod netlink;
 mod network;
 mod pci;
+mod policy;
 pub mod random;
 mod sandbox;
 mod signal;
 mod uevent;
 mod util;
 mod version;
+mod volume;
 mod watcher;
 
 use mount::{cgroups_mount, general_mount};
@@ -339,6 +341,10 @@ async fn start_sandbox(
     let (tx, rx) = tokio::sync::oneshot::channel();
     sandbox.lock().await.sender = Some(tx);
 
+    policy::load_policy(&config.container_policy_path)
+        .await
+        .context("load container policy")?;
+
     // vsock:///dev/vsock, port
     let mut server = rpc::start(sandbox.clone(), config.server_addr.as_str())?;
     server.start().await?;
@@ -0,0 +1,576 @@
+// Copyright (c) 2022 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// The container policy restricts the content of the requests the agent accepts, e.g. the
+// commands a container may run, in addition to the per-endpoint allowlist of the agent
+// config. It's loaded from the json document at `container_policy_path`, for example:
+//
+// {
+//     "create_container": {
+//         "process": {
+//             "allowed_commands": [["^/pause$"], ["^nginx$", "^-g$", "^daemon off;$"]],
+//             "allowed_env": ["^PATH=", "^HOSTNAME="],
+//             "allowed_cwd": ["^/$"],
+//             "allowed_uids": [0],
+//             "allowed_gids": [0]
+//         },
+//         "allowed_hooks": [],
+//         "allowed_mount_sources": ["^/run/kata-containers/shared/containers/"]
+//     },
+//     "exec_process": {
+//         "allowed_commands": []
+//     },
+//     "storage": {
+//         "allowed_sources": ["^/dev/vd[a-z]$", "^kataShared$"]
+//     },
+//     "copy_file": {
+//         "allowed_paths": ["^/run/kata-containers/shared/containers/"]
+//     },
+//     "pull_image": {
+//         "allowed_images": ["^registry.example.com/"]
+//     },
+//     "signal_process": {
+//         "allowed_signals": [9, 15]
+//     },
+//     "allow_update_container": false,
+//     "allow_update_interface": true,
+//     "allow_update_routes": true,
+//     "allow_add_arp_neighbors": true,
+//     "allow_set_iptables": false
+// }
+//
+// A command is matched argument by argument, the OCI hooks are matched as their path followed
+// by their arguments. A rule which is not set doesn't restrict the requests, while an empty
+// list rejects all of them.
+
+use std::convert::TryFrom;
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use protocols::agent::{
+    AddARPNeighborsRequest, CopyFileRequest, CreateContainerRequest, CreateSandboxRequest,
+    ExecProcessRequest, SetIPTablesRequest, SignalProcessRequest, Storage, UpdateContainerRequest,
+    UpdateInterfaceRequest, UpdateRoutesRequest,
+};
+use protocols::image::PullImageRequest;
+use protocols::oci::{Hook, Process};
+use regex::Regex;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+lazy_static! {
+    pub static ref AGENT_POLICY: RwLock<Option<AgentPolicy>> = RwLock::new(None);
+}
+
+fn compile(patterns: &[String]) -> std::result::Result<Vec<Regex>, regex::Error> {
+    patterns.iter().map(|p| Regex::new(p)).collect()
+}
+
+// A list of regular expressions, a value is allowed if it matches one of them
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct Patterns(Vec<Regex>);
+
+impl TryFrom<Vec<String>> for Patterns {
+    type Error = regex::Error;
+
+    fn try_from(patterns: Vec<String>) -> std::result::Result<Self, Self::Error> {
+        compile(&patterns).map(Patterns)
+    }
+}
+
+impl Patterns {
+    fn is_match(&self, value: &str) -> bool {
+        self.0.iter().any(|re| re.is_match(value))
+    }
+}
+
+// A list of commands, each of them a list of regular expressions matching the arguments of the
+// command one by one
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "Vec<Vec<String>>")]
+pub struct Commands(Vec<Vec<Regex>>);
+
+impl TryFrom<Vec<Vec<String>>> for Commands {
+    type Error = regex::Error;
+
+    fn try_from(commands: Vec<Vec<String>>) -> std::result::Result<Self, Self::Error> {
+        commands
+            .iter()
+            .map(|c| compile(c))
+            .collect::<std::result::Result<Vec<Vec<Regex>>, regex::Error>>()
+            .map(Commands)
+    }
+}
+
+impl Commands {
+    fn is_match(&self, args: &[String]) -> bool {
+        self.0.iter().any(|command| {
+            command.len() == args.len()
+                && command.iter().zip(args).all(|(re, arg)| re.is_match(arg))
+        })
+    }
+}
+
+// check the value against the patterns of a rule, a rule which is not set allows everything
+fn check_rule(rule: &Option<Patterns>, name: &str, value: &str) -> Result<()> {
+    match rule {
+        Some(patterns) if !patterns.is_match(value) => {
+            Err(anyhow!("{} {:?} is not allowed by the policy", name, value))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_command(rule: &Option<Commands>, name: &str, args: &[String]) -> Result<()> {
+    match rule {
+        Some(commands) if !commands.is_match(args) => {
+            Err(anyhow!("{} {:?} is not allowed by the policy", name, args))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_id(rule: &Option<Vec<u32>>, name: &str, id: u32) -> Result<()> {
+    match rule {
+        Some(ids) if !ids.contains(&id) => {
+            Err(anyhow!("{} {} is not allowed by the policy", name, id))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_allowed(allowed: bool, name: &str) -> Result<()> {
+    if !allowed {
+        return Err(anyhow!("{} is not allowed by the policy", name));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessPolicy {
+    #[serde(default)]
+    pub allowed_commands: Option<Commands>,
+    #[serde(default)]
+    pub allowed_env: Option<Patterns>,
+    #[serde(default)]
+    pub allowed_cwd: Option<Patterns>,
+    #[serde(default)]
+    pub allowed_uids: Option<Vec<u32>>,
+    #[serde(default)]
+    pub allowed_gids: Option<Vec<u32>>,
+}
+
+impl ProcessPolicy {
+    fn check(&self, process: &Process) -> Result<()> {
+        check_command(&self.allowed_commands, "command", process.get_Args())?;
+
+        if let Some(patterns) = &self.allowed_env {
+            for env in process.get_Env() {
+                if !patterns.is_match(env) {
+                    // only show the name, the values may be secrets
+                    let name = env.split('=').next().unwrap_or_default();
+                    return Err(anyhow!("env {:?} is not allowed by the policy", name));
+                }
+            }
+        }
+
+        check_rule(&self.allowed_cwd, "cwd", process.get_Cwd())?;
+
+        let user = process.get_User();
+        check_id(&self.allowed_uids, "uid", user.UID)?;
+        for gid in std::iter::once(&user.GID).chain(user.get_AdditionalGids()) {
+            check_id(&self.allowed_gids, "gid", *gid)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateContainerPolicy {
+    #[serde(default)]
+    pub process: ProcessPolicy,
+    #[serde(default)]
+    pub allowed_hooks: Option<Commands>,
+    #[serde(default)]
+    pub allowed_mount_sources: Option<Patterns>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoragePolicy {
+    #[serde(default)]
+    pub allowed_sources: Option<Patterns>,
+}
+
+impl StoragePolicy {
+    fn check(&self, storages: &[Storage]) -> Result<()> {
+        for storage in storages {
+            check_rule(&self.allowed_sources, "storage source", &storage.source)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CopyFilePolicy {
+    #[serde(default)]
+    pub allowed_paths: Option<Patterns>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PullImagePolicy {
+    #[serde(default)]
+    pub allowed_images: Option<Patterns>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignalProcessPolicy {
+    #[serde(default)]
+    pub allowed_signals: Option<Vec<u32>>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentPolicy {
+    #[serde(default)]
+    pub create_container: CreateContainerPolicy,
+    #[serde(default)]
+    pub exec_process: ProcessPolicy,
+    #[serde(default)]
+    pub storage: StoragePolicy,
+    #[serde(default)]
+    pub copy_file: CopyFilePolicy,
+    #[serde(default)]
+    pub pull_image: PullImagePolicy,
+    #[serde(default)]
+    pub signal_process: SignalProcessPolicy,
+    #[serde(default = "default_true")]
+    pub allow_update_container: bool,
+    #[serde(default = "default_true")]
+    pub allow_update_interface: bool,
+    #[serde(default = "default_true")]
+    pub allow_update_routes: bool,
+    #[serde(default = "default_true")]
+    pub allow_add_arp_neighbors: bool,
+    #[serde(default = "default_true")]
+    pub allow_set_iptables: bool,
+}
+
+impl AgentPolicy {
+    #[instrument]
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read policy file {}", path))?;
+        Self::from_json(&content).with_context(|| format!("parse policy file {}", path))
+    }
+
+    pub fn from_json(content: &str) -> Result<Self> {
+        serde_json::from_str(content).map_err(|e| anyhow!(e))
+    }
+
+    pub fn check_create_container(&self, req: &CreateContainerRequest) -> Result<()> {
+        let policy = &self.create_container;
+        let spec = req.get_OCI();
+
+        policy.process.check(spec.get_Process())?;
+
+        let hooks = spec.get_Hooks();
+        for hook in hooks
+            .get_Prestart()
+            .iter()
+            .chain(hooks.get_Poststart())
+            .chain(hooks.get_Poststop())
+        {
+            check_command(&policy.allowed_hooks, "hook", &hook_command(hook))?;
+        }
+
+        for m in spec.get_Mounts() {
+            // only the bind mounts expose the guest files to the container
+            if m.field_type == "bind" || m.options.iter().any(|o| o == "bind" || o == "rbind") {
+                check_rule(&policy.allowed_mount_sources, "mount source", &m.source)?;
+            }
+        }
+
+        self.storage.check(req.get_storages())
+    }
+
+    pub fn check_exec_process(&self, req: &ExecProcessRequest) -> Result<()> {
+        self.exec_process.check(req.get_process())
+    }
+
+    pub fn check_create_sandbox(&self, req: &CreateSandboxRequest) -> Result<()> {
+        self.storage.check(req.get_storages())
+    }
+
+    pub fn check_signal_process(&self, req: &SignalProcessRequest) -> Result<()> {
+        check_id(
+            &self.signal_process.allowed_signals,
+            "signal",
+            req.get_signal(),
+        )
+    }
+
+    pub fn check_update_container(&self, _req: &UpdateContainerRequest) -> Result<()> {
+        check_allowed(self.allow_update_container, "updating containers")
+    }
+
+    pub fn check_update_interface(&self, _req: &UpdateInterfaceRequest) -> Result<()> {
+        check_allowed(self.allow_update_interface, "updating interfaces")
+    }
+
+    pub fn check_update_routes(&self, _req: &UpdateRoutesRequest) -> Result<()> {
+        check_allowed(self.allow_update_routes, "updating routes")
+    }
+
+    pub fn check_add_arp_neighbors(&self, _req: &AddARPNeighborsRequest) -> Result<()> {
+        check_allowed(self.allow_add_arp_neighbors, "adding ARP neighbors")
+    }
+
+    pub fn check_copy_file(&self, req: &CopyFileRequest) -> Result<()> {
+        check_rule(&self.copy_file.allowed_paths, "path", &req.path)
+    }
+
+    pub fn check_set_iptables(&self, _req: &SetIPTablesRequest) -> Result<()> {
+        check_allowed(self.allow_set_iptables, "setting iptables")
+    }
+
+    pub fn check_pull_image(&self, req: &PullImageRequest) -> Result<()> {
+        check_rule(&self.pull_image.allowed_images, "image", &req.image)
+    }
+}
+
+// the command of a hook is its path followed by its arguments, the first argument being the
+// name of the program as in execv(3)
+fn hook_command(hook: &Hook) -> Vec<String> {
+    std::iter::once(hook.get_Path().to_string())
+        .chain(hook.get_Args().iter().skip(1).cloned())
+        .collect()
+}
+
+// load the policy at path, an empty path means no policy is enforced
+#[instrument]
+pub async fn load_policy(path: &str) -> Result<()> {
+    if path.is_empty() {
+        return Ok(());
+    }
+
+    let policy = AgentPolicy::from_file(path)?;
+    *AGENT_POLICY.write().await = Some(policy);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protobuf::RepeatedField;
+    use protocols::oci::{Hooks, Mount, Spec, User};
+
+    const POLICY: &str = r#"{
+        "create_container": {
+            "process": {
+                "allowed_commands": [["^/pause$"], ["^nginx$", "^-g$", "^daemon off;$"]],
+                "allowed_env": ["^PATH="],
+                "allowed_cwd": ["^/$"],
+                "allowed_uids": [0],
+                "allowed_gids": [0]
+            },
+            "allowed_hooks": [["^/usr/bin/hook$", "^prestart$"]],
+            "allowed_mount_sources": ["^/run/kata-containers/shared/containers/"]
+        },
+        "exec_process": {
+            "allowed_commands": []
+        },
+        "storage": {
+            "allowed_sources": ["^/dev/vd[a-z]$"]
+        },
+        "signal_process": {
+            "allowed_signals": [9, 15]
+        },
+        "allow_update_container": false,
+        "allow_set_iptables": false
+    }"#;
+
+    fn strings(values: &[&str]) -> RepeatedField<String> {
+        RepeatedField::from_vec(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn create_container_request(args: &[&str], mount_source: &str) -> CreateContainerRequest {
+        let mut process = Process::new();
+        process.set_Args(strings(args));
+        process.set_Env(strings(&["PATH=/bin"]));
+        process.set_Cwd("/".to_string());
+
+        let mut mount = Mount::new();
+        mount.set_field_type("bind".to_string());
+        mount.set_source(mount_source.to_string());
+
+        let mut spec = Spec::new();
+        spec.set_Process(process);
+        spec.set_Mounts(RepeatedField::from_vec(vec![mount]));
+
+        let mut req = CreateContainerRequest::new();
+        req.set_OCI(spec);
+        req
+    }
+
+    #[test]
+    fn test_check_create_container() {
+        let policy = AgentPolicy::from_json(POLICY).unwrap();
+        let source = "/run/kata-containers/shared/containers/cid-hostname";
+
+        let req = create_container_request(&["/pause"], source);
+        assert!(policy.check_create_container(&req).is_ok());
+
+        let req = create_container_request(&["nginx", "-g", "daemon off;"], source);
+        assert!(policy.check_create_container(&req).is_ok());
+
+        // the arguments are matched one by one, not as a command line
+        let req = create_container_request(&["nginx", "-g daemon", "off;"], source);
+        assert!(policy.check_create_container(&req).is_err());
+        let req = create_container_request(&["/pause", "-v"], source);
+        assert!(policy.check_create_container(&req).is_err());
+
+        let req = create_container_request(&["/bin/sh", "-c", "id"], source);
+        let err = policy.check_create_container(&req).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("command [\"/bin/sh\", \"-c\", \"id\"]"));
+
+        let req = create_container_request(&["/pause"], "/etc");
+        let err = policy.check_create_container(&req).unwrap_err();
+        assert!(err.to_string().contains("mount source \"/etc\""));
+    }
+
+    #[test]
+    fn test_check_create_container_process() {
+        let policy = AgentPolicy::from_json(POLICY).unwrap();
+        let source = "/run/kata-containers/shared/containers/cid-hostname";
+
+        let mut req = create_container_request(&["/pause"], source);
+        req.mut_OCI()
+            .mut_Process()
+            .set_Env(strings(&["PATH=/bin", "TOKEN=secret"]));
+        let err = policy.check_create_container(&req).unwrap_err();
+        assert!(err.to_string().contains("env \"TOKEN\""));
+        assert!(!err.to_string().contains("secret"));
+
+        let mut req = create_container_request(&["/pause"], source);
+        req.mut_OCI().mut_Process().set_Cwd("/root".to_string());
+        assert!(policy.check_create_container(&req).is_err());
+
+        let mut req = create_container_request(&["/pause"], source);
+        let mut user = User::new();
+        user.set_AdditionalGids(vec![0, 10]);
+        req.mut_OCI().mut_Process().set_User(user);
+        let err = policy.check_create_container(&req).unwrap_err();
+        assert!(err.to_string().contains("gid 10"));
+    }
+
+    #[test]
+    fn test_check_create_container_hooks() {
+        let policy = AgentPolicy::from_json(POLICY).unwrap();
+        let source = "/run/kata-containers/shared/containers/cid-hostname";
+
+        let mut hook = Hook::new();
+        hook.set_Path("/usr/bin/hook".to_string());
+        hook.set_Args(strings(&["hook", "prestart"]));
+        let mut hooks = Hooks::new();
+        hooks.set_Prestart(RepeatedField::from_vec(vec![hook.clone()]));
+
+        let mut req = create_container_request(&["/pause"], source);
+        req.mut_OCI().set_Hooks(hooks.clone());
+        assert!(policy.check_create_container(&req).is_ok());
+
+        hook.set_Path("/tmp/hook".to_string());
+        hooks.set_Poststop(RepeatedField::from_vec(vec![hook]));
+        req.mut_OCI().set_Hooks(hooks);
+        let err = policy.check_create_container(&req).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("hook [\"/tmp/hook\", \"prestart\"]"));
+    }
+
+    #[test]
+    fn test_check_storages() {
+        let policy = AgentPolicy::from_json(POLICY).unwrap();
+
+        let mut storage = Storage::new();
+        storage.set_source("/dev/vda".to_string());
+        let mut req = CreateSandboxRequest::new();
+        req.set_storages(RepeatedField::from_vec(vec![storage.clone()]));
+        assert!(policy.check_create_sandbox(&req).is_ok());
+
+        storage.set_source("/dev/sda".to_string());
+        req.set_storages(RepeatedField::from_vec(vec![storage.clone()]));
+        assert!(policy.check_create_sandbox(&req).is_err());
+
+        let mut req = create_container_request(
+            &["/pause"],
+            "/run/kata-containers/shared/containers/cid-hostname",
+        );
+        req.set_storages(RepeatedField::from_vec(vec![storage]));
+        assert!(policy.check_create_container(&req).is_err());
+    }
+
+    #[test]
+    fn test_check_rules() {
+        let policy = AgentPolicy::from_json(POLICY).unwrap();
+
+        // an empty list rejects everything
+        let mut req = ExecProcessRequest::new();
+        req.mut_process().set_Args(strings(&["/bin/sh"]));
+        assert!(policy.check_exec_process(&req).is_err());
+
+        // a rule which is not set allows everything
+        let mut req = CopyFileRequest::new();
+        req.set_path("/etc/hosts".to_string());
+        assert!(policy.check_copy_file(&req).is_ok());
+
+        let mut req = SignalProcessRequest::new();
+        req.set_signal(15);
+        assert!(policy.check_signal_process(&req).is_ok());
+        req.set_signal(10);
+        assert!(policy.check_signal_process(&req).is_err());
+
+        assert!(policy
+            .check_set_iptables(&SetIPTablesRequest::new())
+            .is_err());
+        assert!(policy
+            .check_update_container(&UpdateContainerRequest::new())
+            .is_err());
+        assert!(policy
+            .check_update_interface(&UpdateInterfaceRequest::new())
+            .is_ok());
+        assert!(policy
+            .check_update_routes(&UpdateRoutesRequest::new())
+            .is_ok());
+        assert!(policy
+            .check_add_arp_neighbors(&AddARPNeighborsRequest::new())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        assert!(AgentPolicy::from_json(r#"{"unknown": {}}"#).is_err());
+        assert!(AgentPolicy::from_json(r#"{"copy_file": {"allowed_paths": ["("]}}"#).is_err());
+        // the commands are lists of arguments
+        assert!(
+            AgentPolicy::from_json(r#"{"exec_process": {"allowed_commands": ["/bin/sh"]}}"#)
+                .is_err()
+        );
+    }
+}
@@ -59,9 +59,11 @@ use crate::mount::{add_storages, baremount, STORAGE_HANDLER_LIST};
 use crate::namespace::{NSTYPEIPC, NSTYPEPID, NSTYPEUTS};
 use crate::network::setup_guest_dns;
 use crate::pci;
+use crate::policy::AGENT_POLICY;
 use crate::random;
 use crate::sandbox::Sandbox;
 use crate::version::{AGENT_VERSION, API_VERSION};
+use crate::volume;
 use crate::AGENT_CONFIG;
 
 use crate::trace_rpc_call;
@@ -78,6 +80,7 @@ use std::os::unix::prelude::PermissionsExt;
 use std::process::{Command, Stdio};
 use std::time::Duration;
 
+use kata_sys_util::rand::RandomBytes;
 use nix::unistd::{Gid, Uid};
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
@@ -142,6 +145,20 @@ macro_rules! is_allowed {
     };
 }
 
+// check the request against the container policy, if any
+macro_rules! is_allowed_by_policy {
+    ($check:ident, $req:ident) => {
+        if let Some(policy) = AGENT_POLICY.read().await.as_ref() {
+            policy.$check(&$req).map_err(|e| {
+                ttrpc_error!(
+                    ttrpc::Code::PERMISSION_DENIED,
+                    format!("{} is denied: {:?}", $req.descriptor().name(), e),
+                )
+            })?;
+        }
+    };
+}
+
 #[derive(Clone, Debug)]
 pub struct AgentService {
     sandbox: Arc<Mutex<Sandbox>>,
@@ -755,6 +772,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "create_container", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_create_container, req);
         match self.do_create_container(req).await {
             Err(e) => Err(ttrpc_error!(ttrpc::Code::INTERNAL, e)),
             Ok(_) => Ok(Empty::new()),
@@ -795,6 +813,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "exec_process", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_exec_process, req);
         match self.do_exec_process(req).await {
             Err(e) => Err(ttrpc_error!(ttrpc::Code::INTERNAL, e)),
             Ok(_) => Ok(Empty::new()),
@@ -808,6 +827,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "signal_process", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_signal_process, req);
         match self.do_signal_process(req).await {
             Err(e) => Err(ttrpc_error!(ttrpc::Code::INTERNAL, e)),
             Ok(_) => Ok(Empty::new()),
@@ -833,6 +853,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "update_container", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_update_container, req);
         let cid = req.container_id.clone();
         let res = req.resources;
 
@@ -1041,6 +1062,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Interface> {
         trace_rpc_call!(ctx, "update_interface", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_update_interface, req);
 
         let interface = req.interface.into_option().ok_or_else(|| {
             ttrpc_error!(
@@ -1069,6 +1091,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Routes> {
         trace_rpc_call!(ctx, "update_routes", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_update_routes, req);
 
         let new_routes = req
             .routes
@@ -1149,6 +1172,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<SetIPTablesResponse> {
         trace_rpc_call!(ctx, "set_iptables", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_set_iptables, req);
 
         info!(sl!(), "set_ip_tables request received");
 
@@ -1315,6 +1339,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "create_sandbox", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_create_sandbox, req);
 
         {
             let sandbox = self.sandbox.clone();
@@ -1415,6 +1440,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "add_arp_neighbors", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_add_arp_neighbors, req);
 
         let neighs = req
             .neighbors
@@ -1543,6 +1569,7 @@ impl agent_ttrpc::AgentService for AgentService {
     ) -> ttrpc::Result<Empty> {
         trace_rpc_call!(ctx, "copy_file", req);
         is_allowed!(req);
+        is_allowed_by_policy!(check_copy_file, req);
 
         do_copy_file(&req).map_err(|e| ttrpc_error!(ttrpc::Code::INTERNAL, e))?;
 
@@ -1633,6 +1660,25 @@ impl agent_ttrpc::AgentService for AgentService {
         Ok(resp)
     }
 
+    async fn resize_volume(
+        &self,
+        ctx: &TtrpcContext,
+        req: protocols::agent::ResizeVolumeRequest,
+    ) -> ttrpc::Result<Empty> {
+        trace_rpc_call!(ctx, "resize_volume", req);
+        is_allowed!(req);
+
+        info!(
+            sl!(),
+            "resize volume {} to {} bytes", req.volume_guest_path, req.size
+        );
+        volume::resize_volume(&req.volume_guest_path, req.size)
+            .await
+            .map_err(|e| ttrpc_error!(ttrpc::Code::INTERNAL, e))?;
+
+        Ok(Empty::new())
+    }
+
     async fn add_swap(
         &self,
         ctx: &TtrpcContext,
@@ -2042,12 +2088,30 @@ fn do_copy_file(req: &CopyFileRequest) -> Result<()> {
     if sflag.contains(stat::SFlag::S_IFLNK) {
         let src = PathBuf::from(String::from_utf8(req.data.clone()).unwrap());
 
-        unistd::symlinkat(&src, None, &path)?;
-        let path_str = CString::new(path.to_str().unwrap())?;
+        // the symlink may be copied again when it changes, replace it atomically as the
+        // ConfigMap updates swap the ..data symlink. The temporary link gets a random hidden
+        // name, so that it can't clash with the other files of the directory.
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Path {:?} has no file name", path))?;
+        let tmplink = dir.join(format!(
+            ".{}.kata-tmp-{:x}",
+            file_name.to_string_lossy(),
+            RandomBytes::new(8)
+        ));
+
+        let path_str = CString::new(tmplink.to_str().unwrap())?;
+
+        unistd::symlinkat(&src, None, &tmplink)?;
         let ret = unsafe { libc::lchown(path_str.as_ptr(), req.uid as u32, req.gid as u32) };
-        Errno::result(ret).map(drop)?;
+        let result = Errno::result(ret)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| fs::rename(&tmplink, &path).map_err(anyhow::Error::from));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmplink);
+        }
 
-        return Ok(());
+        return result;
     }
 
     let mut tmpfile = path.clone();
@@ -0,0 +1,286 @@
+// Copyright (c) 2022 Alibaba Cloud
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::instrument;
+
+const PROC_SELF_MOUNTINFO: &str = "/proc/self/mountinfo";
+const SYS_DEV_BLOCK: &str = "/sys/dev/block";
+const SECTOR_SIZE: u64 = 512;
+
+const GROWPART: &str = "growpart";
+const RESIZE2FS: &str = "resize2fs";
+const XFS_GROWFS: &str = "xfs_growfs";
+// growpart exits with 1 and prints NOCHANGE when the partition already fills the disk
+const GROWPART_NOCHANGE: &str = "NOCHANGE";
+
+// The block device and filesystem backing a mount point
+#[derive(Debug, PartialEq)]
+struct MountedBlockDevice {
+    // major:minor of the block device
+    dev: String,
+    fs_type: String,
+}
+
+// decode the octal escapes, e.g. "\040" for a space, of a path in a mountinfo file
+fn unescape_octal(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = bytes.get(i + 1..i + 4);
+        match digits {
+            Some(d) if bytes[i] == b'\\' && d.iter().all(|c| (b'0'..=b'7').contains(c)) => {
+                decoded.push(d.iter().fold(0u8, |v, c| (v << 3) | (c - b'0')));
+                i += 4;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// find the block device mounted on mount_point from the content of a mountinfo file
+fn parse_mountinfo(content: &str, mount_point: &str) -> Result<MountedBlockDevice> {
+    // the last mount on the mount point hides the previous ones
+    for line in content.lines().rev() {
+        // 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+        let (mount_fields, fs_fields) = match line.split_once(" - ") {
+            Some(f) => f,
+            None => continue,
+        };
+        let mount_fields: Vec<&str> = mount_fields.split_whitespace().collect();
+        if mount_fields.len() < 5 || unescape_octal(mount_fields[4]) != mount_point {
+            continue;
+        }
+
+        let fs_type = fs_fields
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("no filesystem type in mountinfo line {:?}", line))?;
+        return Ok(MountedBlockDevice {
+            dev: mount_fields[2].to_string(),
+            fs_type: fs_type.to_string(),
+        });
+    }
+
+    Err(anyhow!("{} is not a mount point", mount_point))
+}
+
+// read the size in bytes of a block device from its sysfs directory
+fn get_block_device_size(sys_path: &Path) -> Result<u64> {
+    let size_path = sys_path.join("size");
+    let sectors = fs::read_to_string(&size_path)
+        .with_context(|| format!("read {:?}", &size_path))?
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("parse {:?}", &size_path))?;
+    Ok(sectors * SECTOR_SIZE)
+}
+
+fn device_name(sys_path: &Path) -> Result<String> {
+    sys_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.to_string())
+        .ok_or_else(|| anyhow!("invalid block device path {:?}", sys_path))
+}
+
+// run a command, its output is part of the error if it fails
+fn run_command(cmd: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(cmd)
+        .args(args)
+        .output()
+        .with_context(|| format!("run {}, is it installed in the guest", cmd))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {:?} exited with {:?}, stdout: {:?}, stderr: {:?}",
+            cmd,
+            args,
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+// ask the kernel to rescan the disk, so that a new capacity is noticed for the block
+// devices not resized automatically, e.g. the scsi ones
+fn rescan_disk(disk_sys_path: &Path) -> Result<()> {
+    let rescan = disk_sys_path.join("device").join("rescan");
+    if rescan.exists() {
+        fs::write(&rescan, "1").with_context(|| format!("rescan {:?}", &rescan))?;
+    }
+    Ok(())
+}
+
+// grow the partition to the end of the disk
+fn grow_partition(disk: &str, partition_sys_path: &Path) -> Result<()> {
+    let number_path = partition_sys_path.join("partition");
+    let number =
+        fs::read_to_string(&number_path).with_context(|| format!("read {:?}", &number_path))?;
+    let disk_dev = format!("/dev/{}", disk);
+    let args = [disk_dev.as_str(), number.trim()];
+
+    match Command::new(GROWPART).args(args).output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) if String::from_utf8_lossy(&output.stdout).contains(GROWPART_NOCHANGE) => Ok(()),
+        Ok(output) => Err(anyhow!(
+            "{} {:?} exited with {:?}, stdout: {:?}, stderr: {:?}",
+            GROWPART,
+            args,
+            output.status.code(),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )),
+        Err(e) => Err(anyhow!(
+            "run {} to grow partition {} of {}, is it installed in the guest: {}",
+            GROWPART,
+            number.trim(),
+            disk_dev,
+            e
+        )),
+    }
+}
+
+// grow the mounted filesystem online to the size of its block device
+fn grow_filesystem(fs_type: &str, dev: &str, mount_point: &str) -> Result<()> {
+    match fs_type {
+        "ext2" | "ext3" | "ext4" => run_command(RESIZE2FS, &[dev]),
+        "xfs" => run_command(XFS_GROWFS, &[mount_point]),
+        _ => Err(anyhow!(
+            "online resize of {} filesystem on {} is not supported, only ext2/3/4 and xfs are",
+            fs_type,
+            mount_point
+        )),
+    }
+}
+
+/// Resize the volume mounted on the mount point, the block device is rescanned, its
+/// partition, if any, grown to the end of the disk and the filesystem grown online.
+#[instrument]
+pub async fn resize_volume(mount_point: &str, size: u64) -> Result<()> {
+    // the resize tools may take a while on large filesystems, keep them off the runtime
+    let mount_point = mount_point.to_string();
+    tokio::task::spawn_blocking(move || do_resize_volume(&mount_point, size))
+        .await
+        .context("join resize volume task")?
+}
+
+fn do_resize_volume(mount_point: &str, size: u64) -> Result<()> {
+    let mountinfo = fs::read_to_string(PROC_SELF_MOUNTINFO)
+        .with_context(|| format!("read {}", PROC_SELF_MOUNTINFO))?;
+    let mounted = parse_mountinfo(&mountinfo, mount_point)?;
+
+    let dev_sys_path =
+        fs::canonicalize(Path::new(SYS_DEV_BLOCK).join(&mounted.dev)).with_context(|| {
+            format!(
+                "{} is mounted from {}, which is not a block device",
+                mount_point, mounted.dev
+            )
+        })?;
+    let dev_name = device_name(&dev_sys_path)?;
+
+    // a partition is a child of its disk in sysfs
+    let is_partition = dev_sys_path.join("partition").exists();
+    let disk_sys_path: PathBuf = if is_partition {
+        dev_sys_path
+            .parent()
+            .map(|p| p.to_path_buf())
+            .ok_or_else(|| anyhow!("no disk for partition {:?}", &dev_sys_path))?
+    } else {
+        dev_sys_path.clone()
+    };
+    let disk_name = device_name(&disk_sys_path)?;
+
+    rescan_disk(&disk_sys_path)?;
+    let disk_size = get_block_device_size(&disk_sys_path)?;
+    if disk_size < size {
+        return Err(anyhow!(
+            "size of disk {} is {} bytes, smaller than the requested {} bytes",
+            disk_name,
+            disk_size,
+            size
+        ));
+    }
+
+    if is_partition {
+        grow_partition(&disk_name, &dev_sys_path)
+            .with_context(|| format!("grow partition {}", dev_name))?;
+    }
+
+    grow_filesystem(&mounted.fs_type, &format!("/dev/{}", dev_name), mount_point)
+        .with_context(|| format!("grow filesystem on {}", mount_point))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 253:0 / / rw,relatime shared:1 - ext4 /dev/vda1 rw
+36 22 254:16 / /run/kata-containers/sandbox/storage/vol rw,relatime shared:2 - xfs /dev/vdb rw
+37 22 254:32 / /run/kata-containers/sandbox/storage/vol rw,relatime shared:3 - ext4 /dev/vdc rw
+38 22 0:5 / /dev rw,nosuid shared:4 - devtmpfs devtmpfs rw,size=1024k
+39 22 254:48 / /mnt/my\\040vol rw,relatime shared:5 - ext4 /dev/vdd rw";
+
+    #[test]
+    fn test_parse_mountinfo() {
+        assert_eq!(
+            parse_mountinfo(MOUNTINFO, "/").unwrap(),
+            MountedBlockDevice {
+                dev: "253:0".to_string(),
+                fs_type: "ext4".to_string(),
+            }
+        );
+
+        // the last mount wins
+        assert_eq!(
+            parse_mountinfo(MOUNTINFO, "/run/kata-containers/sandbox/storage/vol").unwrap(),
+            MountedBlockDevice {
+                dev: "254:32".to_string(),
+                fs_type: "ext4".to_string(),
+            }
+        );
+
+        let err = parse_mountinfo(MOUNTINFO, "/run/kata-containers").unwrap_err();
+        assert!(err.to_string().contains("is not a mount point"));
+
+        // the paths are octal escaped in mountinfo
+        assert_eq!(
+            parse_mountinfo(MOUNTINFO, "/mnt/my vol").unwrap(),
+            MountedBlockDevice {
+                dev: "254:48".to_string(),
+                fs_type: "ext4".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unescape_octal() {
+        assert_eq!(unescape_octal("/mnt/plain"), "/mnt/plain");
+        assert_eq!(unescape_octal("/mnt/a\\040b\\011c"), "/mnt/a b\tc");
+        assert_eq!(unescape_octal("/mnt/back\\134slash"), "/mnt/back\\slash");
+        // not an escape sequence
+        assert_eq!(unescape_octal("/mnt/a\\09"), "/mnt/a\\09");
+        assert_eq!(unescape_octal("/mnt/a\\"), "/mnt/a\\");
+    }
+
+    #[test]
+    fn test_grow_filesystem_unsupported() {
+        let err = grow_filesystem("btrfs", "/dev/vdb", "/mnt").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("online resize of btrfs filesystem on /mnt is not supported"));
+    }
+}
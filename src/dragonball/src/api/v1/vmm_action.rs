@@ -13,12 +13,17 @@ use log::{debug, error, info, warn};
 
 use crate::error::{Result, StartMicroVmError, StopMicrovmError};
 use crate::event_manager::EventManager;
+use crate::vcpu::VcpuManagerError;
 use crate::vm::{CpuTopology, KernelConfigInfo, VmConfigInfo};
 use crate::vmm::Vmm;
 
 use self::VmConfigError::*;
 use self::VmmActionError::MachineConfig;
 
+#[cfg(feature = "virtio-balloon")]
+pub use crate::device_manager::balloon_dev_mgr::{
+    BalloonDeviceConfigInfo, BalloonDeviceError, BalloonDeviceMgr, MemoryResizeInfo,
+};
 #[cfg(feature = "virtio-blk")]
 pub use crate::device_manager::blk_dev_mgr::{
     BlockDeviceConfigInfo, BlockDeviceConfigUpdateInfo, BlockDeviceError, BlockDeviceMgr,
@@ -27,6 +32,10 @@ pub use crate::device_manager::blk_dev_mgr::{
 pub use crate::device_manager::fs_dev_mgr::{
     FsDeviceConfigInfo, FsDeviceConfigUpdateInfo, FsDeviceError, FsDeviceMgr, FsMountConfigInfo,
 };
+#[cfg(feature = "host-device")]
+pub use crate::device_manager::vfio_dev_mgr::{
+    VfioDeviceConfigInfo, VfioDeviceError, VfioDeviceMgr,
+};
 #[cfg(feature = "virtio-net")]
 pub use crate::device_manager::virtio_net_dev_mgr::{
     VirtioNetDeviceConfigInfo, VirtioNetDeviceConfigUpdateInfo, VirtioNetDeviceError,
@@ -93,10 +102,28 @@ pub enum VmmActionError {
     #[error("virtio-fs device error: {0}")]
     FsDevice(#[source] FsDeviceError),
 
+    #[cfg(feature = "host-device")]
+    /// VFIO device related errors.
+    #[error("vfio device error: {0}")]
+    VfioDevice(#[source] VfioDeviceError),
+
     #[cfg(feature = "hotplug")]
     /// The action `ResizeVcpu` Failed
     #[error("vcpu resize error : {0}")]
     ResizeVcpu(#[source] VcpuResizeError),
+
+    #[cfg(feature = "virtio-balloon")]
+    /// The action `InsertBalloonDevice` or `ResizeMemory` failed.
+    #[error("virtio-balloon device error: {0}")]
+    Balloon(#[source] BalloonDeviceError),
+
+    /// The action `PauseMicroVm` or `ResumeMicroVm` is not allowed in the current VM state.
+    #[error("the VM can't be paused or resumed in state {0:?}")]
+    PauseResumeNotAllowed(InstanceState),
+
+    /// The action `PauseMicroVm` or `ResumeMicroVm` failed.
+    #[error("failed to pause or resume the VM: {0}")]
+    PauseResume(#[source] VcpuManagerError),
 }
 
 /// This enum represents the public interface of the VMM. Each action contains various
@@ -153,6 +180,17 @@ pub enum VmmAction {
     /// are the RX and TX rate limiters.
     UpdateNetworkInterface(VirtioNetDeviceConfigUpdateInfo),
 
+    #[cfg(all(feature = "virtio-net", feature = "hotplug", feature = "dbs-upcall"))]
+    /// Notify the guest to detach a network interface according to the given iface_id, after
+    /// microVM start. The result of the guest is sent through the returned
+    /// `VmmData::SyncHotplug` receiver.
+    PrepareRemoveNetworkDevice(String),
+
+    #[cfg(feature = "virtio-net")]
+    /// Remove a network interface according to the given iface_id. After microVM start, the
+    /// guest must have detached the device with `PrepareRemoveNetworkDevice`.
+    RemoveNetworkDevice(String),
+
     #[cfg(feature = "virtio-fs")]
     /// Add a new shared fs device or update one that already exists using the
     /// `FsDeviceConfig` as input. This action can only be called before the microVM has
@@ -169,9 +207,39 @@ pub enum VmmAction {
     /// Update fs rate limiter, after microVM start.
     UpdateFsDevice(FsDeviceConfigUpdateInfo),
 
+    #[cfg(feature = "host-device")]
+    /// Add a new host PCI device passed through with VFIO using the `VfioDeviceConfigInfo` as
+    /// input. The guest has to rescan its PCI bus to discover the device added after the
+    /// microVM has booted.
+    InsertVfioDevice(VfioDeviceConfigInfo),
+
+    #[cfg(feature = "host-device")]
+    /// Remove a host PCI device according to the given hostdev_id. The guest isn't notified
+    /// about the removal.
+    RemoveVfioDevice(String),
+
     #[cfg(feature = "hotplug")]
     /// Resize Vcpu number in the guest.
     ResizeVcpu(VcpuResizeInfo),
+
+    #[cfg(feature = "virtio-balloon")]
+    /// Add a new virtio-balloon device or update the one that already exists using the
+    /// `BalloonDeviceConfigInfo` as input. This action can only be called before the microVM has
+    /// booted.
+    InsertBalloonDevice(BalloonDeviceConfigInfo),
+
+    #[cfg(feature = "virtio-balloon")]
+    /// Resize the memory available to the guest, by inflating or deflating the balloon. The
+    /// memory size can't exceed the memory size of the microVM configuration.
+    ResizeMemory(MemoryResizeInfo),
+
+    /// Pause the vcpus of the microVM. This action can only be called when the microVM is
+    /// running.
+    PauseMicroVm,
+
+    /// Resume the vcpus of the microVM. This action can only be called when the microVM is
+    /// paused.
+    ResumeMicroVm,
 }
 
 /// The enum represents the response sent by the VMM in case of success. The response is either
@@ -182,6 +250,9 @@ pub enum VmmData {
     Empty,
     /// The microVM configuration represented by `VmConfigInfo`.
     MachineConfiguration(Box<VmConfigInfo>),
+    /// The receiver of the guest result of a hotplug request, `None` if the upcall channel is
+    /// reset before the guest replies.
+    SyncHotplug(Receiver<Option<i32>>),
 }
 
 /// Request data type used to communicate between the API and the VMM.
@@ -261,6 +332,14 @@ impl VmmService {
             VmmAction::UpdateNetworkInterface(netif_update) => {
                 self.update_net_rate_limiters(vmm, netif_update)
             }
+            #[cfg(all(feature = "virtio-net", feature = "hotplug", feature = "dbs-upcall"))]
+            VmmAction::PrepareRemoveNetworkDevice(iface_id) => {
+                self.prepare_remove_virtio_net_device(vmm, event_mgr, &iface_id)
+            }
+            #[cfg(feature = "virtio-net")]
+            VmmAction::RemoveNetworkDevice(iface_id) => {
+                self.remove_virtio_net_device(vmm, event_mgr, &iface_id)
+            }
             #[cfg(feature = "virtio-fs")]
             VmmAction::InsertFsDevice(fs_cfg) => self.add_fs_device(vmm, fs_cfg),
 
@@ -272,8 +351,22 @@ impl VmmService {
             VmmAction::UpdateFsDevice(fs_update_cfg) => {
                 self.update_fs_rate_limiters(vmm, fs_update_cfg)
             }
+            #[cfg(feature = "host-device")]
+            VmmAction::InsertVfioDevice(vfio_cfg) => self.add_vfio_device(vmm, event_mgr, vfio_cfg),
+            #[cfg(feature = "host-device")]
+            VmmAction::RemoveVfioDevice(hostdev_id) => {
+                self.remove_vfio_device(vmm, event_mgr, &hostdev_id)
+            }
             #[cfg(feature = "hotplug")]
             VmmAction::ResizeVcpu(vcpu_resize_cfg) => self.resize_vcpu(vmm, vcpu_resize_cfg),
+            #[cfg(feature = "virtio-balloon")]
+            VmmAction::InsertBalloonDevice(balloon_cfg) => {
+                self.add_balloon_device(vmm, event_mgr, balloon_cfg)
+            }
+            #[cfg(feature = "virtio-balloon")]
+            VmmAction::ResizeMemory(resize_cfg) => self.resize_memory(vmm, resize_cfg),
+            VmmAction::PauseMicroVm => self.pause_microvm(vmm),
+            VmmAction::ResumeMicroVm => self.resume_microvm(vmm),
         };
 
         debug!("send vmm response: {:?}", response);
@@ -343,6 +436,30 @@ impl VmmService {
             .map_err(StartMicroVm)
     }
 
+    fn pause_microvm(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let state = vm.shared_info().read().unwrap().state;
+        if state != InstanceState::Running {
+            return Err(VmmActionError::PauseResumeNotAllowed(state));
+        }
+
+        vm.pause_microvm()
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::PauseResume)
+    }
+
+    fn resume_microvm(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let state = vm.shared_info().read().unwrap().state;
+        if state != InstanceState::Paused {
+            return Err(VmmActionError::PauseResumeNotAllowed(state));
+        }
+
+        vm.resume_microvm()
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::PauseResume)
+    }
+
     fn shutdown_microvm(&mut self, vmm: &mut Vmm) -> VmmRequestResult {
         vmm.event_ctx.exit_evt_triggered = true;
 
@@ -561,6 +678,91 @@ impl VmmService {
             .map_err(VmmActionError::VirtioNet)
     }
 
+    #[cfg(all(feature = "virtio-net", feature = "hotplug", feature = "dbs-upcall"))]
+    fn prepare_remove_virtio_net_device(
+        &mut self,
+        vmm: &mut Vmm,
+        event_mgr: &mut EventManager,
+        iface_id: &str,
+    ) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let ctx = vm
+            .create_device_op_context(Some(event_mgr.epoll_manager()))
+            .map_err(|e| {
+                if let StartMicroVmError::UpcallServerNotReady = e {
+                    VmmActionError::UpcallServerNotReady
+                } else {
+                    VmmActionError::StartMicroVm(e)
+                }
+            })?;
+
+        VirtioNetDeviceMgr::prepare_remove_device(vm.device_manager(), &ctx, iface_id)
+            .map(VmmData::SyncHotplug)
+            .map_err(VmmActionError::VirtioNet)
+    }
+
+    #[cfg(feature = "virtio-net")]
+    fn remove_virtio_net_device(
+        &mut self,
+        vmm: &mut Vmm,
+        event_mgr: &mut EventManager,
+        iface_id: &str,
+    ) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let ctx = vm
+            .create_device_op_context(Some(event_mgr.epoll_manager()))
+            .map_err(|e| {
+                if let StartMicroVmError::UpcallServerNotReady = e {
+                    VmmActionError::UpcallServerNotReady
+                } else {
+                    VmmActionError::StartMicroVm(e)
+                }
+            })?;
+
+        VirtioNetDeviceMgr::remove_device(vm.device_manager_mut(), ctx, iface_id)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::VirtioNet)
+    }
+
+    #[cfg(feature = "host-device")]
+    fn add_vfio_device(
+        &mut self,
+        vmm: &mut Vmm,
+        event_mgr: &mut EventManager,
+        config: VfioDeviceConfigInfo,
+    ) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let ctx = vm
+            .create_device_op_context(Some(event_mgr.epoll_manager()))
+            .map_err(|e| {
+                if let StartMicroVmError::UpcallServerNotReady = e {
+                    return VmmActionError::UpcallServerNotReady;
+                }
+                VmmActionError::VfioDevice(VfioDeviceError::UpdateNotAllowedPostBoot)
+            })?;
+
+        VfioDeviceMgr::insert_device(vm.device_manager_mut(), ctx, config)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::VfioDevice)
+    }
+
+    #[cfg(feature = "host-device")]
+    fn remove_vfio_device(
+        &mut self,
+        vmm: &mut Vmm,
+        event_mgr: &mut EventManager,
+        hostdev_id: &str,
+    ) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let ctx = vm
+            .create_device_op_context(Some(event_mgr.epoll_manager()))
+            .map_err(|_| VmmActionError::VfioDevice(VfioDeviceError::UpdateNotAllowedPostBoot))?;
+
+        VfioDeviceMgr::remove_device(vm.device_manager_mut(), ctx, hostdev_id)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::VfioDevice)
+    }
+
     #[cfg(feature = "virtio-fs")]
     fn add_fs_device(&mut self, vmm: &mut Vmm, config: FsDeviceConfigInfo) -> VmmRequestResult {
         let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
@@ -614,6 +816,38 @@ impl VmmService {
             .map_err(VmmActionError::FsDevice)
     }
 
+    #[cfg(feature = "virtio-balloon")]
+    fn add_balloon_device(
+        &mut self,
+        vmm: &mut Vmm,
+        event_mgr: &mut EventManager,
+        config: BalloonDeviceConfigInfo,
+    ) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        if vm.is_vm_initialized() {
+            return Err(VmmActionError::Balloon(
+                BalloonDeviceError::UpdateNotAllowedPostBoot,
+            ));
+        }
+
+        let ctx = vm
+            .create_device_op_context(Some(event_mgr.epoll_manager()))
+            .map_err(VmmActionError::StartMicroVm)?;
+        BalloonDeviceMgr::insert_device(vm.device_manager_mut(), ctx, config)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::Balloon)
+    }
+
+    #[cfg(feature = "virtio-balloon")]
+    fn resize_memory(&mut self, vmm: &mut Vmm, config: MemoryResizeInfo) -> VmmRequestResult {
+        let vm = vmm.get_vm_mut().ok_or(VmmActionError::InvalidVMID)?;
+        let vm_mem_size_mib = vm.vm_config().mem_size_mib as u64;
+
+        BalloonDeviceMgr::resize_memory(vm.device_manager_mut(), vm_mem_size_mib, config)
+            .map(|_| VmmData::Empty)
+            .map_err(VmmActionError::Balloon)
+    }
+
     #[cfg(feature = "hotplug")]
     fn resize_vcpu(&mut self, vmm: &mut Vmm, config: VcpuResizeInfo) -> VmmRequestResult {
         if !cfg!(target_arch = "x86_64") {
@@ -1069,6 +1303,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_vmm_action_pause_resume_microvm() {
+        skip_if_not_root!();
+
+        let tests = &mut [
+            // invalid state (uninitialized)
+            TestData::new(
+                VmmAction::PauseMicroVm,
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::PauseResumeNotAllowed(
+                            InstanceState::Uninitialized
+                        ))
+                    ));
+                },
+            ),
+            // invalid state (running)
+            TestData::new(
+                VmmAction::ResumeMicroVm,
+                InstanceState::Running,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::PauseResumeNotAllowed(
+                            InstanceState::Running
+                        ))
+                    ));
+                },
+            ),
+        ];
+
+        for t in tests.iter_mut() {
+            t.check_request();
+        }
+    }
+
     #[test]
     fn test_vmm_action_shutdown_microvm() {
         skip_if_not_root!();
@@ -1228,6 +1500,50 @@ mod tests {
         }
     }
 
+    #[cfg(all(feature = "host-device", feature = "hotplug"))]
+    #[test]
+    fn test_vmm_action_vfio_device() {
+        skip_if_not_root!();
+
+        let tests = &mut [
+            // success, the device is only attached at boot
+            TestData::new(
+                VmmAction::InsertVfioDevice(VfioDeviceConfigInfo {
+                    hostdev_id: "vfio0".to_string(),
+                    bus_slot_func: "0000:01:00.0".to_string(),
+                    ..Default::default()
+                }),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(result.is_ok());
+                },
+            ),
+            // invalid id
+            TestData::new(
+                VmmAction::RemoveVfioDevice("vfio0".to_string()),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::VfioDevice(
+                            VfioDeviceError::InvalidDeviceId(_)
+                        ))
+                    ));
+                    let err_string = format!("{}", result.unwrap_err());
+                    let expected_err = String::from(
+                        "vfio device error: \
+                    invalid vfio device id 'vfio0'",
+                    );
+                    assert_eq!(err_string, expected_err);
+                },
+            ),
+        ];
+
+        for t in tests.iter_mut() {
+            t.check_request();
+        }
+    }
+
     #[cfg(feature = "virtio-fs")]
     #[test]
     fn test_vmm_action_insert_fs_device() {
@@ -1357,6 +1673,90 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "virtio-net")]
+    #[test]
+    fn test_vmm_action_remove_network_device() {
+        skip_if_not_root!();
+
+        let tests = &mut [
+            // hotplug unready
+            TestData::new(
+                VmmAction::RemoveNetworkDevice(String::from("1")),
+                InstanceState::Running,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::StartMicroVm(
+                            StartMicroVmError::UpcallMissVsock
+                        ))
+                    ));
+                },
+            ),
+            // invalid id
+            TestData::new(
+                VmmAction::RemoveNetworkDevice(String::from("1")),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::VirtioNet(
+                            VirtioNetDeviceError::InvalidIfaceId(_)
+                        ))
+                    ));
+                    let err_string = format!("{}", result.unwrap_err());
+                    let expected_err = String::from(
+                        "virtio-net device error: \
+                        invalid virtio-net iface id '1'",
+                    );
+                    assert_eq!(err_string, expected_err);
+                },
+            ),
+        ];
+
+        for t in tests.iter_mut() {
+            t.check_request();
+        }
+    }
+
+    #[cfg(all(feature = "virtio-net", feature = "hotplug", feature = "dbs-upcall"))]
+    #[test]
+    fn test_vmm_action_prepare_remove_network_device() {
+        skip_if_not_root!();
+
+        let tests = &mut [
+            // hotplug unready
+            TestData::new(
+                VmmAction::PrepareRemoveNetworkDevice(String::from("1")),
+                InstanceState::Running,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::StartMicroVm(
+                            StartMicroVmError::UpcallMissVsock
+                        ))
+                    ));
+                },
+            ),
+            // invalid id
+            TestData::new(
+                VmmAction::PrepareRemoveNetworkDevice(String::from("1")),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::VirtioNet(
+                            VirtioNetDeviceError::InvalidIfaceId(_)
+                        ))
+                    ));
+                },
+            ),
+        ];
+
+        for t in tests.iter_mut() {
+            t.check_request();
+        }
+    }
+
     #[cfg(feature = "virtio-net")]
     #[test]
     fn test_vmm_action_update_network_interface() {
@@ -1452,4 +1852,70 @@ mod tests {
             t.check_request();
         }
     }
+
+    #[cfg(feature = "virtio-balloon")]
+    #[test]
+    fn test_vmm_action_balloon_device() {
+        skip_if_not_root!();
+
+        let tests = &mut [
+            // invalid state
+            TestData::new(
+                VmmAction::InsertBalloonDevice(BalloonDeviceConfigInfo::default()),
+                InstanceState::Running,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::Balloon(
+                            BalloonDeviceError::UpdateNotAllowedPostBoot
+                        ))
+                    ));
+                },
+            ),
+            // success
+            TestData::new(
+                VmmAction::InsertBalloonDevice(BalloonDeviceConfigInfo {
+                    balloon_id: "balloon0".to_string(),
+                    size_mib: 64,
+                    ..Default::default()
+                }),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(result.is_ok());
+                },
+            ),
+            // no balloon device
+            TestData::new(
+                VmmAction::ResizeMemory(MemoryResizeInfo { mem_size_mib: 64 }),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::Balloon(
+                            BalloonDeviceError::BalloonDeviceNotExist
+                        ))
+                    ));
+                },
+            ),
+            // memory size exceeds the VM memory
+            TestData::new(
+                VmmAction::ResizeMemory(MemoryResizeInfo {
+                    mem_size_mib: 1 << 20,
+                }),
+                InstanceState::Uninitialized,
+                &|result| {
+                    assert!(matches!(
+                        result,
+                        Err(VmmActionError::Balloon(
+                            BalloonDeviceError::InvalidMemorySize(_, _)
+                        ))
+                    ));
+                },
+            ),
+        ];
+
+        for t in tests.iter_mut() {
+            t.check_request();
+        }
+    }
 }
@@ -0,0 +1,211 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{Error, PciDevice, Result};
+
+/// Number of device slots on a PCI bus.
+const PCI_BUS_MAX_DEVICES: u8 = 32;
+/// Slot of the host bridge on the root bus.
+pub(crate) const PCI_HOST_BRIDGE_DEVICE_ID: u8 = 0;
+
+#[derive(Default)]
+struct PciBusState {
+    // Allocated device IDs, with the device registered with each of them.
+    devices: BTreeMap<u8, Option<Arc<dyn PciDevice>>>,
+}
+
+/// A PCI bus, hosting up to 32 single-function devices.
+///
+/// A device ID is allocated before creating a device, and the device is registered on the bus
+/// once it's ready to handle the configuration space accesses of the guest.
+pub struct PciBus {
+    bus_id: u8,
+    state: Mutex<PciBusState>,
+}
+
+impl PciBus {
+    pub(crate) fn new(bus_id: u8) -> Self {
+        let mut state = PciBusState::default();
+        // The slot of the host bridge is never handed out.
+        state.devices.insert(PCI_HOST_BRIDGE_DEVICE_ID, None);
+
+        PciBus {
+            bus_id,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Get the bus ID.
+    pub fn bus_id(&self) -> u8 {
+        self.bus_id
+    }
+
+    /// Allocate a device ID, `id` if specified, or the first free one otherwise.
+    ///
+    /// Return `None` if the specified ID is invalid or in use, or if the bus is full.
+    pub fn allocate_device_id(&self, id: Option<u8>) -> Option<u8> {
+        // Do not expect poisoned lock.
+        let mut state = self.state.lock().unwrap();
+        let id = match id {
+            Some(id) if id < PCI_BUS_MAX_DEVICES && !state.devices.contains_key(&id) => id,
+            Some(_) => return None,
+            None => (0..PCI_BUS_MAX_DEVICES).find(|id| !state.devices.contains_key(id))?,
+        };
+        state.devices.insert(id, None);
+
+        Some(id)
+    }
+
+    /// Free the device ID `id`, unregistering the device registered with it.
+    pub fn free_device_id(&self, id: u8) {
+        if id != PCI_HOST_BRIDGE_DEVICE_ID {
+            self.state.lock().unwrap().devices.remove(&id);
+        }
+    }
+
+    /// Register `device` with its allocated device ID.
+    pub fn register_device(&self, device: Arc<dyn PciDevice>) -> Result<()> {
+        let id = device.id();
+        let mut state = self.state.lock().unwrap();
+        match state.devices.get_mut(&id) {
+            Some(Some(_)) => Err(Error::DeviceIdInUse(id)),
+            Some(slot) => {
+                *slot = Some(device);
+                Ok(())
+            }
+            None => Err(Error::InvalidDeviceId(id)),
+        }
+    }
+
+    /// Get the device registered with the device ID `id`.
+    pub fn get_device(&self, id: u8) -> Option<Arc<dyn PciDevice>> {
+        self.state
+            .lock()
+            .unwrap()
+            .devices
+            .get(&id)
+            .and_then(|device| device.clone())
+    }
+
+    pub(crate) fn register_host_bridge(&self, device: Arc<dyn PciDevice>) -> Result<()> {
+        if device.id() != PCI_HOST_BRIDGE_DEVICE_ID {
+            return Err(Error::InvalidDeviceId(device.id()));
+        }
+        self.register_device(device)
+    }
+
+    /// Read the configuration space of the function `func` of the device `dev`, all ones are
+    /// read if there is no such device.
+    pub fn read_config(&self, dev: u8, func: u8, offset: u32, data: &mut [u8]) {
+        match self.get_device(dev) {
+            Some(device) if func == 0 => device.read_config(offset, data),
+            _ => data.iter_mut().for_each(|b| *b = 0xff),
+        }
+    }
+
+    /// Write the configuration space of the function `func` of the device `dev`, the write is
+    /// ignored if there is no such device.
+    pub fn write_config(&self, dev: u8, func: u8, offset: u32, data: &[u8]) {
+        if let Some(device) = self.get_device(dev) {
+            if func == 0 {
+                device.write_config(offset, data);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::convert::TryInto;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    pub(crate) struct TestDevice {
+        pub(crate) id: u8,
+        pub(crate) reg: AtomicU32,
+    }
+
+    impl PciDevice for TestDevice {
+        fn id(&self) -> u8 {
+            self.id
+        }
+
+        fn write_config(&self, offset: u32, data: &[u8]) {
+            if offset == 0 && data.len() == 4 {
+                self.reg.store(
+                    u32::from_le_bytes(data.try_into().unwrap()),
+                    Ordering::SeqCst,
+                );
+            }
+        }
+
+        fn read_config(&self, offset: u32, data: &mut [u8]) {
+            let value = self.reg.load(Ordering::SeqCst) >> ((offset % 4) * 8);
+            for (index, byte) in data.iter_mut().enumerate() {
+                *byte = (value >> (index * 8)) as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn test_allocate_device_id() {
+        let bus = PciBus::new(0);
+        assert_eq!(bus.allocate_device_id(None), Some(1));
+        assert_eq!(bus.allocate_device_id(Some(5)), Some(5));
+        assert_eq!(bus.allocate_device_id(Some(5)), None);
+        assert_eq!(
+            bus.allocate_device_id(Some(PCI_HOST_BRIDGE_DEVICE_ID)),
+            None
+        );
+        assert_eq!(bus.allocate_device_id(Some(PCI_BUS_MAX_DEVICES)), None);
+        assert_eq!(bus.allocate_device_id(None), Some(2));
+
+        bus.free_device_id(1);
+        assert_eq!(bus.allocate_device_id(None), Some(1));
+        for _ in 3..PCI_BUS_MAX_DEVICES - 1 {
+            assert!(bus.allocate_device_id(None).is_some());
+        }
+        assert_eq!(bus.allocate_device_id(None), None);
+    }
+
+    #[test]
+    fn test_register_device() {
+        let bus = PciBus::new(0);
+        let device = Arc::new(TestDevice {
+            id: 3,
+            reg: AtomicU32::new(0x1234_5678),
+        });
+        assert!(matches!(
+            bus.register_device(device.clone()),
+            Err(Error::InvalidDeviceId(3))
+        ));
+
+        assert_eq!(bus.allocate_device_id(Some(3)), Some(3));
+        bus.register_device(device.clone()).unwrap();
+        assert!(matches!(
+            bus.register_device(device.clone()),
+            Err(Error::DeviceIdInUse(3))
+        ));
+
+        let mut data = [0u8; 2];
+        bus.read_config(3, 0, 2, &mut data);
+        assert_eq!(data, [0x34, 0x12]);
+        bus.read_config(3, 1, 0, &mut data);
+        assert_eq!(data, [0xff, 0xff]);
+        bus.read_config(4, 0, 0, &mut data);
+        assert_eq!(data, [0xff, 0xff]);
+
+        bus.write_config(3, 0, 0, &[0xaa, 0xbb, 0xcc, 0xdd]);
+        assert_eq!(device.reg.load(Ordering::SeqCst), 0xddcc_bbaa);
+
+        // Freeing the device ID unregisters the device.
+        bus.free_device_id(3);
+        assert!(bus.get_device(3).is_none());
+        bus.read_config(3, 0, 0, &mut data);
+        assert_eq!(data, [0xff, 0xff]);
+    }
+}
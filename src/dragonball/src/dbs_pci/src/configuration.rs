@@ -0,0 +1,515 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// Copyright 2018 The Chromium OS Authors. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Emulation of the PCI configuration space header of type 0.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::{Error, Result};
+
+/// Number of 32-bit registers in the configuration space.
+pub const NUM_CONFIGURATION_REGISTERS: usize = 64;
+/// Number of BAR registers in the configuration space header.
+pub const NUM_BAR_REGS: usize = 6;
+
+const COMMAND_REG: usize = 1;
+const COMMAND_REG_WRITABLE_MASK: u32 = 0x0000_ffff;
+const STATUS_REG_CAPABILITIES_USED_MASK: u32 = 0x0010_0000;
+const HEADER_TYPE_REG: usize = 3;
+const BAR0_REG: usize = 4;
+const SUBSYSTEM_REG: usize = 11;
+const CAPABILITY_LIST_HEAD_REG: usize = 13;
+const INTERRUPT_LINE_PIN_REG: usize = 15;
+const INTERRUPT_LINE_WRITABLE_MASK: u32 = 0x0000_00ff;
+
+const BAR_IO_ADDR_MASK: u32 = 0xffff_fffc;
+const BAR_MEM_ADDR_MASK: u32 = 0xffff_fff0;
+const BAR_IO_BIT: u32 = 0x1;
+const BAR_MEM_64BIT: u32 = 0x4;
+const BAR_PREFETCHABLE: u32 = 0x8;
+
+const FIRST_CAPABILITY_OFFSET: usize = 0x40;
+const CAPABILITY_MAX_OFFSET: usize = NUM_CONFIGURATION_REGISTERS * 4;
+
+/// Class code of a PCI device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PciClassCode {
+    /// Mass storage controller.
+    MassStorage = 0x01,
+    /// Network controller.
+    NetworkController = 0x02,
+    /// Display controller.
+    DisplayController = 0x03,
+    /// Bridge device.
+    BridgeDevice = 0x06,
+    /// Device which doesn't fit any class.
+    Other = 0xff,
+}
+
+/// Type of the configuration space header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PciHeaderType {
+    /// Header of a PCI device.
+    Device = 0x00,
+    /// Header of a PCI-to-PCI bridge.
+    Bridge = 0x01,
+}
+
+/// Type of the region described by a BAR.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PciBarRegionType {
+    /// Memory region decoded below 4GiB.
+    Memory32BitRegion,
+    /// Io port region.
+    IoRegion,
+    /// Memory region which may be placed above 4GiB, it takes two BAR registers.
+    Memory64BitRegion,
+}
+
+/// Identifier of a PCI capability.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PciCapabilityId {
+    /// Power management.
+    PowerManagement = 0x01,
+    /// Message signaled interrupts.
+    Msi = 0x05,
+    /// Vendor specific.
+    VendorSpecific = 0x09,
+    /// PCI Express.
+    PciExpress = 0x10,
+    /// Extended message signaled interrupts.
+    MsiX = 0x11,
+}
+
+/// Emulated configuration space of a PCI device.
+///
+/// The registers are read and written as 32-bit values, the bits which aren't set in the writable
+/// mask of a register keep their value when the guest writes the register. The BAR registers only
+/// accept addresses aligned on the size of their region, so the guest reads back the size mask
+/// after writing all ones into a BAR.
+#[derive(Clone)]
+pub struct PciConfiguration {
+    registers: [u32; NUM_CONFIGURATION_REGISTERS],
+    writable_bits: [u32; NUM_CONFIGURATION_REGISTERS],
+    bars: [Option<(PciBarRegionType, u64)>; NUM_BAR_REGS],
+    last_capability: Option<(usize, usize)>,
+}
+
+impl PciConfiguration {
+    /// Create a configuration space header.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        vendor_id: u16,
+        device_id: u16,
+        class_code: PciClassCode,
+        subclass: u8,
+        prog_if: u8,
+        header_type: PciHeaderType,
+        subsystem_vendor_id: u16,
+        subsystem_id: u16,
+    ) -> Self {
+        let mut registers = [0u32; NUM_CONFIGURATION_REGISTERS];
+        let mut writable_bits = [0u32; NUM_CONFIGURATION_REGISTERS];
+
+        registers[0] = u32::from(device_id) << 16 | u32::from(vendor_id);
+        writable_bits[COMMAND_REG] = COMMAND_REG_WRITABLE_MASK;
+        registers[2] =
+            (class_code as u32) << 24 | u32::from(subclass) << 16 | u32::from(prog_if) << 8;
+        registers[HEADER_TYPE_REG] = (header_type as u32) << 16;
+        registers[SUBSYSTEM_REG] = u32::from(subsystem_id) << 16 | u32::from(subsystem_vendor_id);
+        writable_bits[INTERRUPT_LINE_PIN_REG] = INTERRUPT_LINE_WRITABLE_MASK;
+
+        PciConfiguration {
+            registers,
+            writable_bits,
+            bars: [None; NUM_BAR_REGS],
+            last_capability: None,
+        }
+    }
+
+    /// Read the 32-bit register at `reg_idx`.
+    pub fn read_reg(&self, reg_idx: usize) -> u32 {
+        self.registers.get(reg_idx).copied().unwrap_or(0xffff_ffff)
+    }
+
+    /// Write the 32-bit register at `reg_idx`, only the writable bits are updated.
+    pub fn write_reg(&mut self, reg_idx: usize, value: u32) {
+        if let Some(reg) = self.registers.get_mut(reg_idx) {
+            let mask = self.writable_bits[reg_idx];
+            *reg = (*reg & !mask) | (value & mask);
+        }
+    }
+
+    /// Read `data.len()` bytes at the byte `offset` of the configuration space.
+    ///
+    /// The access must not cross a 32-bit register, all ones are read otherwise.
+    pub fn read_config(&self, offset: usize, data: &mut [u8]) {
+        let shift = offset % 4;
+        if data.is_empty() || shift + data.len() > 4 {
+            data.iter_mut().for_each(|b| *b = 0xff);
+            return;
+        }
+        let value = self.read_reg(offset / 4) >> (shift * 8);
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = (value >> (index * 8)) as u8;
+        }
+    }
+
+    /// Write `data` at the byte `offset` of the configuration space.
+    ///
+    /// The access must not cross a 32-bit register, it's ignored otherwise.
+    pub fn write_config(&mut self, offset: usize, data: &[u8]) {
+        let reg_idx = offset / 4;
+        if let Some(value) = merge_config_write(self.read_reg(reg_idx), offset, data) {
+            self.write_reg(reg_idx, value);
+        }
+    }
+
+    /// Set the interrupt pin of the device, 0 if the device doesn't use the INTx interrupts.
+    pub fn set_interrupt_pin(&mut self, pin: u8) {
+        let reg = &mut self.registers[INTERRUPT_LINE_PIN_REG];
+        *reg = (*reg & 0xffff_00ff) | u32::from(pin) << 8;
+    }
+
+    /// Describe the region of the BAR `index`, placed at `addr`.
+    ///
+    /// A 64-bit memory region also takes the register of the BAR `index + 1`.
+    pub fn add_bar(
+        &mut self,
+        index: usize,
+        addr: u64,
+        size: u64,
+        region_type: PciBarRegionType,
+        prefetchable: bool,
+    ) -> Result<()> {
+        let last = match region_type {
+            PciBarRegionType::Memory64BitRegion => index + 1,
+            _ => index,
+        };
+        if last >= NUM_BAR_REGS || self.bars[index].is_some() || self.bars[last].is_some() {
+            return Err(Error::InvalidBarIndex(index));
+        }
+        let addr_limit = match region_type {
+            PciBarRegionType::IoRegion => 1u64 << 16,
+            PciBarRegionType::Memory32BitRegion => 1u64 << 32,
+            PciBarRegionType::Memory64BitRegion => u64::MAX,
+        };
+        if !size.is_power_of_two() || size > addr_limit {
+            return Err(Error::InvalidBarSize(size, index));
+        }
+        let in_limit = matches!(addr.checked_add(size), Some(end) if end <= addr_limit);
+        if addr & (size - 1) != 0 || !in_limit {
+            return Err(Error::InvalidBarAddress(addr, index));
+        }
+
+        let reg_idx = BAR0_REG + index;
+        let mask = !(size - 1);
+        match region_type {
+            PciBarRegionType::IoRegion => {
+                self.registers[reg_idx] = (addr as u32 & BAR_IO_ADDR_MASK) | BAR_IO_BIT;
+                self.writable_bits[reg_idx] = mask as u32 & BAR_IO_ADDR_MASK;
+            }
+            PciBarRegionType::Memory32BitRegion | PciBarRegionType::Memory64BitRegion => {
+                let mut flags = if prefetchable { BAR_PREFETCHABLE } else { 0 };
+                if region_type == PciBarRegionType::Memory64BitRegion {
+                    flags |= BAR_MEM_64BIT;
+                    self.registers[reg_idx + 1] = (addr >> 32) as u32;
+                    self.writable_bits[reg_idx + 1] = (mask >> 32) as u32;
+                    self.bars[index + 1] = Some((region_type, 0));
+                }
+                self.registers[reg_idx] = (addr as u32 & BAR_MEM_ADDR_MASK) | flags;
+                self.writable_bits[reg_idx] = mask as u32 & BAR_MEM_ADDR_MASK;
+            }
+        }
+        self.bars[index] = Some((region_type, size));
+
+        Ok(())
+    }
+
+    /// Get the type of the region of the BAR `index`, if it has one.
+    ///
+    /// The upper register of a 64-bit BAR has no region.
+    pub fn get_bar_type(&self, index: usize) -> Option<PciBarRegionType> {
+        match self.bars.get(index) {
+            Some(Some((region_type, size))) if *size != 0 => Some(*region_type),
+            _ => None,
+        }
+    }
+
+    /// Get the size of the region of the BAR `index`, 0 if it has none.
+    pub fn get_bar_size(&self, index: usize) -> u64 {
+        match self.bars.get(index) {
+            Some(Some((_, size))) => *size,
+            _ => 0,
+        }
+    }
+
+    /// Get the address programmed into the BAR `index`.
+    pub fn get_bar_addr(&self, index: usize) -> u64 {
+        let reg_idx = BAR0_REG + index;
+        match self.get_bar_type(index) {
+            Some(PciBarRegionType::IoRegion) => {
+                u64::from(self.registers[reg_idx] & BAR_IO_ADDR_MASK)
+            }
+            Some(PciBarRegionType::Memory32BitRegion) => {
+                u64::from(self.registers[reg_idx] & BAR_MEM_ADDR_MASK)
+            }
+            Some(PciBarRegionType::Memory64BitRegion) => {
+                u64::from(self.registers[reg_idx + 1]) << 32
+                    | u64::from(self.registers[reg_idx] & BAR_MEM_ADDR_MASK)
+            }
+            None => 0,
+        }
+    }
+
+    /// Get the index of the BAR whose register(s) contain the register `reg_idx`.
+    ///
+    /// The returned flag is true if the register is the upper one of a 64-bit BAR.
+    pub fn get_bar_index(&self, reg_idx: usize) -> Option<(usize, bool)> {
+        if !(BAR0_REG..BAR0_REG + NUM_BAR_REGS).contains(&reg_idx) {
+            return None;
+        }
+        let index = reg_idx - BAR0_REG;
+        match self.bars[index] {
+            Some((_, 0)) => Some((index - 1, true)),
+            Some(_) => Some((index, false)),
+            None => None,
+        }
+    }
+
+    /// Check whether the BAR `index` is being sized by the guest, i.e. all its address bits are
+    /// set after the guest wrote all ones into it.
+    pub fn is_bar_sizing(&self, index: usize) -> bool {
+        let size = self.get_bar_size(index);
+        if size == 0 {
+            return false;
+        }
+        let addr = self.get_bar_addr(index);
+        let mask = match self.get_bar_type(index) {
+            Some(PciBarRegionType::IoRegion) => !(size - 1) & u64::from(BAR_IO_ADDR_MASK),
+            Some(PciBarRegionType::Memory32BitRegion) => !(size - 1) & u64::from(BAR_MEM_ADDR_MASK),
+            _ => !(size - 1) & !0xf,
+        };
+        addr & mask == mask
+    }
+
+    /// Add a capability with the payload `data`, the identifier and the next pointer are filled
+    /// in by the configuration space.
+    ///
+    /// Return the offset of the capability in the configuration space.
+    pub fn add_capability(&mut self, cap_id: PciCapabilityId, data: &[u8]) -> Result<usize> {
+        let cap_len = data.len() + 2;
+        let offset = match self.last_capability {
+            Some((offset, len)) => align_to_dword(offset + len),
+            None => FIRST_CAPABILITY_OFFSET,
+        };
+        if offset + cap_len > CAPABILITY_MAX_OFFSET {
+            return Err(Error::CapabilityNoSpace(cap_len));
+        }
+
+        let mut bytes = vec![cap_id as u8, 0];
+        bytes.extend_from_slice(data);
+        for (index, byte) in bytes.iter().enumerate() {
+            self.set_byte(offset + index, *byte);
+        }
+        match self.last_capability {
+            Some((last, _)) => self.set_byte(last + 1, offset as u8),
+            None => {
+                self.set_byte(CAPABILITY_LIST_HEAD_REG * 4, offset as u8);
+                self.registers[COMMAND_REG] |= STATUS_REG_CAPABILITIES_USED_MASK;
+            }
+        }
+        self.last_capability = Some((offset, cap_len));
+
+        Ok(offset)
+    }
+
+    fn set_byte(&mut self, offset: usize, value: u8) {
+        let shift = (offset % 4) * 8;
+        let reg = &mut self.registers[offset / 4];
+        *reg = (*reg & !(0xff << shift)) | u32::from(value) << shift;
+    }
+}
+
+/// Merge a write of `data` at the byte `offset` of the configuration space into the value `reg`
+/// of the 32-bit register containing it.
+///
+/// Return `None` if the write is empty or crosses the register.
+pub(crate) fn merge_config_write(reg: u32, offset: usize, data: &[u8]) -> Option<u32> {
+    let shift = offset % 4;
+    if data.is_empty() || shift + data.len() > 4 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 4];
+    LittleEndian::write_u32(&mut bytes, reg);
+    bytes[shift..shift + data.len()].copy_from_slice(data);
+
+    Some(LittleEndian::read_u32(&bytes))
+}
+
+fn align_to_dword(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_config() -> PciConfiguration {
+        PciConfiguration::new(
+            0x8086,
+            0x1234,
+            PciClassCode::NetworkController,
+            0x80,
+            0,
+            PciHeaderType::Device,
+            0x8086,
+            0x5678,
+        )
+    }
+
+    #[test]
+    fn test_config_header() {
+        let mut config = new_config();
+        assert_eq!(config.read_reg(0), 0x1234_8086);
+        assert_eq!(config.read_reg(2), 0x0280_0000);
+        assert_eq!(config.read_reg(SUBSYSTEM_REG), 0x5678_8086);
+
+        // The ids are read-only.
+        config.write_reg(0, 0);
+        assert_eq!(config.read_reg(0), 0x1234_8086);
+
+        // Partial accesses within a register.
+        let mut data = [0u8; 2];
+        config.read_config(2, &mut data);
+        assert_eq!(data, [0x34, 0x12]);
+        config.write_config(4, &[0x06]);
+        assert_eq!(config.read_reg(COMMAND_REG), 0x6);
+
+        // Accesses crossing a register are rejected.
+        let mut data = [0u8; 4];
+        config.read_config(2, &mut data);
+        assert_eq!(data, [0xff; 4]);
+        config.write_config(6, &[0xff, 0xff, 0xff]);
+        assert_eq!(config.read_reg(COMMAND_REG), 0x6);
+
+        config.set_interrupt_pin(1);
+        config.write_config(0x3c, &[0x0a]);
+        assert_eq!(config.read_reg(INTERRUPT_LINE_PIN_REG), 0x010a);
+    }
+
+    #[test]
+    fn test_bar_sizing() {
+        let mut config = new_config();
+        config
+            .add_bar(
+                0,
+                0xc000_0000,
+                0x1000,
+                PciBarRegionType::Memory32BitRegion,
+                false,
+            )
+            .unwrap();
+        config
+            .add_bar(
+                1,
+                0x1_0000_0000,
+                0x1_0000_0000,
+                PciBarRegionType::Memory64BitRegion,
+                true,
+            )
+            .unwrap();
+        config
+            .add_bar(3, 0xc100, 0x100, PciBarRegionType::IoRegion, false)
+            .unwrap();
+
+        assert_eq!(config.read_reg(BAR0_REG), 0xc000_0000);
+        assert_eq!(config.read_reg(BAR0_REG + 1), 0x0000_000c);
+        assert_eq!(config.read_reg(BAR0_REG + 2), 0x1);
+        assert_eq!(config.read_reg(BAR0_REG + 3), 0xc101);
+        assert_eq!(config.get_bar_addr(1), 0x1_0000_0000);
+        assert_eq!(config.get_bar_type(2), None);
+        assert_eq!(config.get_bar_index(BAR0_REG + 2), Some((1, true)));
+        assert_eq!(config.get_bar_index(BAR0_REG + 4), None);
+
+        // Write all ones to read the size masks.
+        for reg_idx in BAR0_REG..BAR0_REG + 4 {
+            config.write_reg(reg_idx, 0xffff_ffff);
+        }
+        assert_eq!(config.read_reg(BAR0_REG), 0xffff_f000);
+        assert_eq!(config.read_reg(BAR0_REG + 1), 0x0000_000c);
+        assert_eq!(config.read_reg(BAR0_REG + 2), 0xffff_ffff);
+        assert_eq!(config.read_reg(BAR0_REG + 3), 0xffff_ff01);
+        assert!(config.is_bar_sizing(0));
+        assert!(config.is_bar_sizing(1));
+        assert!(config.is_bar_sizing(3));
+
+        // Move the BARs.
+        config.write_reg(BAR0_REG, 0xd000_0000);
+        config.write_reg(BAR0_REG + 2, 0x2);
+        config.write_reg(BAR0_REG + 3, 0xc200);
+        assert_eq!(config.get_bar_addr(0), 0xd000_0000);
+        assert_eq!(config.get_bar_addr(1), 0x2_0000_0000);
+        assert_eq!(config.get_bar_addr(3), 0xc200);
+        assert!(!config.is_bar_sizing(0));
+
+        // Invalid BARs.
+        assert!(matches!(
+            config.add_bar(0, 0, 0x1000, PciBarRegionType::Memory32BitRegion, false),
+            Err(Error::InvalidBarIndex(0))
+        ));
+        assert!(matches!(
+            config.add_bar(5, 0, 0x1000, PciBarRegionType::Memory64BitRegion, false),
+            Err(Error::InvalidBarIndex(5))
+        ));
+        assert!(matches!(
+            config.add_bar(4, 0, 0x1800, PciBarRegionType::Memory32BitRegion, false),
+            Err(Error::InvalidBarSize(0x1800, 4))
+        ));
+        assert!(matches!(
+            config.add_bar(
+                4,
+                0xffff_f000,
+                0x2000,
+                PciBarRegionType::Memory32BitRegion,
+                false
+            ),
+            Err(Error::InvalidBarAddress(0xffff_f000, 4))
+        ));
+    }
+
+    #[test]
+    fn test_add_capability() {
+        let mut config = new_config();
+        assert_eq!(
+            config.read_reg(COMMAND_REG) & STATUS_REG_CAPABILITIES_USED_MASK,
+            0
+        );
+
+        let first = config
+            .add_capability(PciCapabilityId::VendorSpecific, &[0x1, 0x2, 0x3])
+            .unwrap();
+        let second = config
+            .add_capability(PciCapabilityId::PowerManagement, &[0u8; 6])
+            .unwrap();
+        assert_eq!(first, FIRST_CAPABILITY_OFFSET);
+        assert_eq!(second, FIRST_CAPABILITY_OFFSET + 8);
+        assert_ne!(
+            config.read_reg(COMMAND_REG) & STATUS_REG_CAPABILITIES_USED_MASK,
+            0
+        );
+        assert_eq!(
+            config.read_reg(CAPABILITY_LIST_HEAD_REG) & 0xff,
+            first as u32
+        );
+        assert_eq!(config.read_reg(first / 4), 0x0201_4809);
+        assert_eq!(config.read_reg(second / 4) & 0xffff, 0x0001);
+
+        assert!(matches!(
+            config.add_capability(PciCapabilityId::VendorSpecific, &[0u8; 200]),
+            Err(Error::CapabilityNoSpace(202))
+        ));
+    }
+}
@@ -0,0 +1,17 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+/// Trait for the devices plugged into a PCI bus.
+///
+/// The configuration space accesses of the guest are forwarded to the device by the bus, they
+/// never cross a 32-bit register.
+pub trait PciDevice: Send + Sync {
+    /// Get the device ID, i.e. the slot of the device on the bus.
+    fn id(&self) -> u8;
+
+    /// Write `data` at the byte `offset` of the configuration space.
+    fn write_config(&self, offset: u32, data: &[u8]);
+
+    /// Read `data.len()` bytes at the byte `offset` of the configuration space.
+    fn read_config(&self, offset: u32, data: &mut [u8]);
+}
@@ -0,0 +1,114 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! PCI bus emulation for the Dragonball sandbox.
+//!
+//! The crate provides:
+//! - a PCI root bus with a host bridge, whose configuration space is accessed by the guest
+//!   through the legacy `0xCF8`/`0xCFC` configuration io ports trapped by [`PciRootDevice`],
+//! - the [`VfioPciDevice`], which passes a host PCI device through to the guest with VFIO.
+//!
+//! Only the configuration mechanism #1 is supported, so the guest sees the first 256 bytes of the
+//! configuration space of each device, the PCI Express extended configuration space isn't
+//! reachable.
+
+#![deny(missing_docs)]
+
+use std::sync::Arc;
+
+use dbs_device::device_manager::IoManagerContext;
+use dbs_interrupt::KvmIrqManager;
+
+mod bus;
+pub use self::bus::PciBus;
+
+mod configuration;
+pub use self::configuration::{
+    PciBarRegionType, PciCapabilityId, PciClassCode, PciConfiguration, PciHeaderType, NUM_BAR_REGS,
+};
+
+mod device;
+pub use self::device::PciDevice;
+
+mod msi;
+mod msix;
+
+mod root_device;
+pub use self::root_device::{PciRootDevice, PCI_CONFIG_IO_PORT, PCI_CONFIG_IO_PORT_SIZE};
+
+mod vfio;
+pub use self::vfio::{VfioPciDevice, VfioPciError};
+
+/// Errors associated with the PCI bus.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The bus ID isn't supported.
+    #[error("invalid PCI bus id {0}")]
+    InvalidBusId(u8),
+
+    /// The device ID hasn't been allocated on the bus.
+    #[error("invalid PCI device id {0}")]
+    InvalidDeviceId(u8),
+
+    /// A device is already registered with the device ID.
+    #[error("PCI device id {0} is already in use")]
+    DeviceIdInUse(u8),
+
+    /// The BAR index is out of range or the BAR is already set.
+    #[error("invalid BAR index {0}")]
+    InvalidBarIndex(usize),
+
+    /// The BAR size isn't a power of two or doesn't fit the BAR type.
+    #[error("invalid size {0:#x} of BAR {1}")]
+    InvalidBarSize(u64, usize),
+
+    /// The BAR address isn't aligned on its size or doesn't fit the BAR type.
+    #[error("invalid address {0:#x} of BAR {1}")]
+    InvalidBarAddress(u64, usize),
+
+    /// There is no space left in the configuration space for the capability.
+    #[error("no space left for a capability of {0} bytes")]
+    CapabilityNoSpace(usize),
+}
+
+/// Specialized version of `std::result::Result` for PCI bus operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Context of the virtual machine needed by the PCI devices.
+pub trait PciSystemContext: Sync + Send + Clone + 'static {
+    /// Context to register and unregister the io ranges of the devices.
+    type D: IoManagerContext;
+
+    /// Get the context to update the io ranges, when the guest moves a BAR of a device.
+    fn get_device_manager_context(&self) -> Self::D;
+
+    /// Get the interrupt manager to create the MSI/MSI-X interrupts of the devices.
+    fn get_interrupt_manager(&self) -> &KvmIrqManager;
+}
+
+/// Create a PCI root bus.
+///
+/// Only the bus 0 is supported, as the guest kernel only probes the buses behind bridges in
+/// addition to it.
+pub fn create_pci_root_bus(bus_id: u8) -> Result<Arc<PciBus>> {
+    if bus_id != 0 {
+        return Err(Error::InvalidBusId(bus_id));
+    }
+
+    Ok(Arc::new(PciBus::new(bus_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_pci_root_bus() {
+        let bus = create_pci_root_bus(0).unwrap();
+        assert_eq!(bus.bus_id(), 0);
+        assert!(matches!(
+            create_pci_root_bus(1),
+            Err(Error::InvalidBusId(1))
+        ));
+    }
+}
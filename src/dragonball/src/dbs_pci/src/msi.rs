@@ -0,0 +1,260 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Emulation of the MSI capability.
+
+use dbs_interrupt::MsiIrqSourceConfig;
+
+use crate::configuration::merge_config_write;
+
+const MSI_CTL_ENABLE: u16 = 0x1;
+const MSI_CTL_MULTI_MSG_CAPABLE_SHIFT: u16 = 1;
+const MSI_CTL_MULTI_MSG_ENABLE_SHIFT: u16 = 4;
+const MSI_CTL_MULTI_MSG_MASK: u16 = 0x7;
+const MSI_CTL_64_BIT: u16 = 0x80;
+const MSI_CTL_PER_VECTOR_MASK: u16 = 0x100;
+// The vectors which may be enabled, at most 32.
+const MSI_MAX_MULTI_MSG: u16 = 5;
+
+const MSI_ADDR_LO_MASK: u32 = 0xffff_fffc;
+const MSI_DATA_MASK: u32 = 0x0000_ffff;
+const MSI_MAX_REGS: usize = 6;
+
+/// Change of the MSI capability requested by a write of the guest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum MsiUpdate {
+    /// Nothing to do.
+    None,
+    /// The MSI interrupts have been enabled.
+    Enable,
+    /// The MSI interrupts have been disabled.
+    Disable,
+    /// The message address or data changed while the MSI interrupts are enabled.
+    Reconfigure,
+    /// The mask bits changed while the MSI interrupts are enabled.
+    Mask,
+}
+
+/// Emulated MSI capability of a device.
+///
+/// The capability registers are kept in the VMM, the guest programs the message of each vector
+/// into them and the VMM routes the interrupts of the device accordingly.
+#[derive(Clone, Debug)]
+pub struct MsiState {
+    regs: [u32; MSI_MAX_REGS],
+    writable_bits: [u32; MSI_MAX_REGS],
+}
+
+impl MsiState {
+    /// Create the state of an MSI capability, whose message control register is `msg_ctl`.
+    pub fn new(msg_ctl: u16) -> Self {
+        let capable = ((msg_ctl >> MSI_CTL_MULTI_MSG_CAPABLE_SHIFT) & MSI_CTL_MULTI_MSG_MASK)
+            .min(MSI_MAX_MULTI_MSG);
+        let msg_ctl = (msg_ctl & (MSI_CTL_64_BIT | MSI_CTL_PER_VECTOR_MASK))
+            | capable << MSI_CTL_MULTI_MSG_CAPABLE_SHIFT;
+        let mut state = MsiState {
+            regs: [0; MSI_MAX_REGS],
+            writable_bits: [0; MSI_MAX_REGS],
+        };
+
+        state.regs[0] = u32::from(msg_ctl) << 16;
+        state.writable_bits[0] =
+            u32::from(MSI_CTL_ENABLE | MSI_CTL_MULTI_MSG_MASK << MSI_CTL_MULTI_MSG_ENABLE_SHIFT)
+                << 16;
+        state.writable_bits[1] = MSI_ADDR_LO_MASK;
+        if state.is_64bit() {
+            state.writable_bits[2] = 0xffff_ffff;
+        }
+        state.writable_bits[state.data_reg()] = MSI_DATA_MASK;
+        if let Some(reg) = state.mask_reg() {
+            state.writable_bits[reg] = 0xffff_ffff;
+        }
+
+        state
+    }
+
+    /// Size in bytes of the capability, including its identifier and next pointer.
+    pub fn size(&self) -> usize {
+        match self.mask_reg() {
+            // The mask register is followed by the pending register.
+            Some(reg) => (reg + 2) * 4,
+            None => self.data_reg() * 4 + 2,
+        }
+    }
+
+    /// Check whether the MSI interrupts are enabled.
+    pub fn enabled(&self) -> bool {
+        self.msg_ctl() & MSI_CTL_ENABLE != 0
+    }
+
+    /// Number of vectors supported by the device.
+    pub fn num_vectors(&self) -> u32 {
+        1 << ((self.msg_ctl() >> MSI_CTL_MULTI_MSG_CAPABLE_SHIFT) & MSI_CTL_MULTI_MSG_MASK)
+    }
+
+    /// Number of vectors enabled by the guest.
+    pub fn num_enabled_vectors(&self) -> u32 {
+        let enabled = (self.msg_ctl() >> MSI_CTL_MULTI_MSG_ENABLE_SHIFT) & MSI_CTL_MULTI_MSG_MASK;
+        (1 << enabled).min(self.num_vectors())
+    }
+
+    /// Check whether the vector `index` is masked by the guest.
+    pub fn is_masked(&self, index: u32) -> bool {
+        match self.mask_reg() {
+            Some(reg) => self.regs[reg] & (1 << index) != 0,
+            None => false,
+        }
+    }
+
+    /// Get the interrupt configuration of the vector `index`.
+    ///
+    /// The vectors of a multiple message share the address, the index of a vector is set in the
+    /// low bits of the data.
+    pub fn source_config(&self, index: u32) -> MsiIrqSourceConfig {
+        let data = self.regs[self.data_reg()] & MSI_DATA_MASK;
+        let vectors = self.num_enabled_vectors();
+
+        MsiIrqSourceConfig {
+            high_addr: if self.is_64bit() { self.regs[2] } else { 0 },
+            low_addr: self.regs[1],
+            data: (data & !(vectors - 1)) | (index & (vectors - 1)),
+            msg_ctl: u32::from(self.msg_ctl()),
+            device_id: None,
+        }
+    }
+
+    /// Read `data.len()` bytes at the byte `offset` of the capability.
+    pub fn read(&self, offset: usize, data: &mut [u8]) {
+        let shift = offset % 4;
+        if shift + data.len() > 4 || offset / 4 >= MSI_MAX_REGS {
+            data.iter_mut().for_each(|b| *b = 0xff);
+            return;
+        }
+        let value = self.regs[offset / 4] >> (shift * 8);
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = (value >> (index * 8)) as u8;
+        }
+    }
+
+    /// Write `data` at the byte `offset` of the capability.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> MsiUpdate {
+        let reg_idx = offset / 4;
+        if reg_idx >= MSI_MAX_REGS {
+            return MsiUpdate::None;
+        }
+        let value = match merge_config_write(self.regs[reg_idx], offset, data) {
+            Some(value) => value,
+            None => return MsiUpdate::None,
+        };
+        let old = self.clone();
+        let mask = self.writable_bits[reg_idx];
+        self.regs[reg_idx] = (self.regs[reg_idx] & !mask) | (value & mask);
+
+        match (old.enabled(), self.enabled()) {
+            (false, true) => MsiUpdate::Enable,
+            (true, false) => MsiUpdate::Disable,
+            (true, true) if old.num_enabled_vectors() != self.num_enabled_vectors() => {
+                // The number of vectors can't change while enabled, enable them again.
+                MsiUpdate::Enable
+            }
+            (true, true) if Some(reg_idx) == self.mask_reg() => MsiUpdate::Mask,
+            (true, true) if old.regs[reg_idx] != self.regs[reg_idx] => MsiUpdate::Reconfigure,
+            _ => MsiUpdate::None,
+        }
+    }
+
+    fn msg_ctl(&self) -> u16 {
+        (self.regs[0] >> 16) as u16
+    }
+
+    fn is_64bit(&self) -> bool {
+        self.msg_ctl() & MSI_CTL_64_BIT != 0
+    }
+
+    fn data_reg(&self) -> usize {
+        if self.is_64bit() {
+            3
+        } else {
+            2
+        }
+    }
+
+    fn mask_reg(&self) -> Option<usize> {
+        if self.msg_ctl() & MSI_CTL_PER_VECTOR_MASK != 0 {
+            Some(self.data_reg() + 1)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msi_32bit() {
+        // 4 vectors capable, 32-bit address, no per vector masking.
+        let mut msi = MsiState::new(0x2 << MSI_CTL_MULTI_MSG_CAPABLE_SHIFT);
+        assert_eq!(msi.size(), 10);
+        assert_eq!(msi.num_vectors(), 4);
+        assert_eq!(msi.num_enabled_vectors(), 1);
+        assert!(!msi.enabled());
+
+        assert_eq!(msi.write(4, &0xfee0_0000u32.to_le_bytes()), MsiUpdate::None);
+        assert_eq!(msi.write(8, &[0x20, 0x00]), MsiUpdate::None);
+        // Enable 2 vectors.
+        assert_eq!(msi.write(2, &[0x11, 0x00]), MsiUpdate::Enable);
+        assert!(msi.enabled());
+        assert_eq!(msi.num_enabled_vectors(), 2);
+
+        let config = msi.source_config(1);
+        assert_eq!(config.low_addr, 0xfee0_0000);
+        assert_eq!(config.high_addr, 0);
+        assert_eq!(config.data, 0x21);
+        assert!(!msi.is_masked(1));
+
+        assert_eq!(msi.write(8, &[0x40, 0x00]), MsiUpdate::Reconfigure);
+        assert_eq!(msi.source_config(0).data, 0x40);
+        assert_eq!(msi.write(8, &[0x40, 0x00]), MsiUpdate::None);
+        assert_eq!(msi.write(2, &[0x31, 0x00]), MsiUpdate::Enable);
+        assert_eq!(msi.num_enabled_vectors(), 4);
+        assert_eq!(msi.write(2, &[0x30, 0x00]), MsiUpdate::Disable);
+
+        // The read-only bits are kept.
+        let mut data = [0u8; 4];
+        msi.write(0, &[0xff, 0xff, 0xff, 0xff]);
+        msi.read(0, &mut data);
+        assert_eq!(u32::from_le_bytes(data), 0x0075_0000);
+    }
+
+    #[test]
+    fn test_msi_64bit_masking() {
+        let mut msi = MsiState::new(MSI_CTL_64_BIT | MSI_CTL_PER_VECTOR_MASK);
+        assert_eq!(msi.size(), 24);
+        assert_eq!(msi.num_vectors(), 1);
+
+        msi.write(4, &0xfee0_1000u32.to_le_bytes());
+        msi.write(8, &0x1u32.to_le_bytes());
+        msi.write(12, &[0x30, 0x00]);
+        assert_eq!(msi.write(2, &[0x71, 0x00]), MsiUpdate::Enable);
+        // The enabled vectors are capped to the supported ones.
+        assert_eq!(msi.num_enabled_vectors(), 1);
+
+        let config = msi.source_config(0);
+        assert_eq!(config.high_addr, 0x1);
+        assert_eq!(config.low_addr, 0xfee0_1000);
+        assert_eq!(config.data, 0x30);
+
+        assert_eq!(msi.write(16, &0x1u32.to_le_bytes()), MsiUpdate::Mask);
+        assert!(msi.is_masked(0));
+        assert_eq!(msi.write(16, &0x0u32.to_le_bytes()), MsiUpdate::Mask);
+        assert!(!msi.is_masked(0));
+
+        // The pending bits are read-only.
+        let mut data = [0u8; 4];
+        msi.write(20, &0xffff_ffffu32.to_le_bytes());
+        msi.read(20, &mut data);
+        assert_eq!(data, [0; 4]);
+    }
+}
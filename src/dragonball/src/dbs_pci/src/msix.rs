@@ -0,0 +1,320 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Emulation of the MSI-X capability and of the MSI-X table and pending bit array.
+
+use byteorder::{ByteOrder, LittleEndian};
+use dbs_interrupt::MsiIrqSourceConfig;
+
+/// Size in bytes of an entry of the MSI-X table.
+pub const MSIX_TABLE_ENTRY_SIZE: usize = 16;
+
+const MSIX_CTL_TABLE_SIZE_MASK: u16 = 0x07ff;
+const MSIX_CTL_FUNCTION_MASK: u16 = 0x4000;
+const MSIX_CTL_ENABLE: u16 = 0x8000;
+const MSIX_BIR_MASK: u32 = 0x7;
+const MSIX_VECTOR_CTL_MASKED: u32 = 0x1;
+
+/// Change of the MSI-X state requested by a write of the guest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum MsixUpdate {
+    /// Nothing to do.
+    None,
+    /// The MSI-X interrupts have been enabled.
+    Enable,
+    /// The MSI-X interrupts have been disabled.
+    Disable,
+    /// The function mask changed while the MSI-X interrupts are enabled.
+    FunctionMask,
+    /// The entry of the vector changed while the MSI-X interrupts are enabled.
+    Entry(u32),
+}
+
+/// An entry of the MSI-X table.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MsixTableEntry {
+    /// Lower 32 bits of the message address.
+    pub msg_addr_lo: u32,
+    /// Upper 32 bits of the message address.
+    pub msg_addr_hi: u32,
+    /// Message data.
+    pub msg_data: u32,
+    /// Vector control, the bit 0 masks the vector.
+    pub vector_ctl: u32,
+}
+
+impl MsixTableEntry {
+    /// Check whether the vector is masked.
+    pub fn masked(&self) -> bool {
+        self.vector_ctl & MSIX_VECTOR_CTL_MASKED != 0
+    }
+
+    fn read_reg(&self, reg_idx: usize) -> u32 {
+        match reg_idx {
+            0 => self.msg_addr_lo,
+            1 => self.msg_addr_hi,
+            2 => self.msg_data,
+            _ => self.vector_ctl,
+        }
+    }
+
+    fn write_reg(&mut self, reg_idx: usize, value: u32) {
+        match reg_idx {
+            0 => self.msg_addr_lo = value & 0xffff_fffc,
+            1 => self.msg_addr_hi = value,
+            2 => self.msg_data = value,
+            _ => self.vector_ctl = value & MSIX_VECTOR_CTL_MASKED,
+        }
+    }
+}
+
+/// Emulated MSI-X capability of a device, with its table and pending bit array (PBA).
+///
+/// The table and the PBA are kept in the VMM, the guest accesses to the parts of the BARs which
+/// contain them must be trapped and forwarded to the state.
+#[derive(Clone, Debug)]
+pub struct MsixState {
+    msg_ctl: u16,
+    table: Vec<MsixTableEntry>,
+    table_bir: u32,
+    table_offset: u32,
+    pba_bir: u32,
+    pba_offset: u32,
+}
+
+impl MsixState {
+    /// Create the state of an MSI-X capability, whose message control, table and PBA registers
+    /// are `msg_ctl`, `table` and `pba`.
+    ///
+    /// The table is limited to `max_vectors` entries, the guest reads the limited size from the
+    /// message control register.
+    pub fn new(msg_ctl: u16, table: u32, pba: u32, max_vectors: u16) -> Self {
+        let size = ((msg_ctl & MSIX_CTL_TABLE_SIZE_MASK) + 1).min(max_vectors.max(1));
+        let entry = MsixTableEntry {
+            vector_ctl: MSIX_VECTOR_CTL_MASKED,
+            ..Default::default()
+        };
+
+        MsixState {
+            msg_ctl: size - 1,
+            table: vec![entry; size as usize],
+            table_bir: table & MSIX_BIR_MASK,
+            table_offset: table & !MSIX_BIR_MASK,
+            pba_bir: pba & MSIX_BIR_MASK,
+            pba_offset: pba & !MSIX_BIR_MASK,
+        }
+    }
+
+    /// Number of entries of the table.
+    pub fn num_vectors(&self) -> u32 {
+        self.table.len() as u32
+    }
+
+    /// Check whether the MSI-X interrupts are enabled.
+    pub fn enabled(&self) -> bool {
+        self.msg_ctl & MSIX_CTL_ENABLE != 0
+    }
+
+    /// Check whether all the vectors are masked by the function mask.
+    pub fn function_masked(&self) -> bool {
+        self.msg_ctl & MSIX_CTL_FUNCTION_MASK != 0
+    }
+
+    /// Check whether the vector `index` is masked, by its entry or by the function mask.
+    pub fn is_masked(&self, index: u32) -> bool {
+        match self.table.get(index as usize) {
+            Some(entry) => self.function_masked() || entry.masked(),
+            None => true,
+        }
+    }
+
+    /// Get the message control register.
+    pub fn msg_ctl(&self) -> u16 {
+        self.msg_ctl
+    }
+
+    /// Get the BAR index and offset of the table.
+    pub fn table_location(&self) -> (u32, u64) {
+        (self.table_bir, u64::from(self.table_offset))
+    }
+
+    /// Get the BAR index and offset of the PBA.
+    pub fn pba_location(&self) -> (u32, u64) {
+        (self.pba_bir, u64::from(self.pba_offset))
+    }
+
+    /// Size in bytes of the table.
+    pub fn table_size(&self) -> u64 {
+        (self.table.len() * MSIX_TABLE_ENTRY_SIZE) as u64
+    }
+
+    /// Size in bytes of the PBA, made of 64-bit words.
+    pub fn pba_size(&self) -> u64 {
+        ((self.table.len() as u64 + 63) & !63) / 8
+    }
+
+    /// Get the interrupt configuration of the vector `index`.
+    pub fn source_config(&self, index: u32) -> MsiIrqSourceConfig {
+        let entry = self.table.get(index as usize).copied().unwrap_or_default();
+
+        MsiIrqSourceConfig {
+            high_addr: entry.msg_addr_hi,
+            low_addr: entry.msg_addr_lo,
+            data: entry.msg_data,
+            msg_ctl: entry.vector_ctl,
+            device_id: None,
+        }
+    }
+
+    /// Write the message control register, only the enable and function mask bits are writable.
+    pub(crate) fn write_msg_ctl(&mut self, value: u16) -> MsixUpdate {
+        let old = self.msg_ctl;
+        let mask = MSIX_CTL_ENABLE | MSIX_CTL_FUNCTION_MASK;
+        self.msg_ctl = (old & !mask) | (value & mask);
+
+        match (old & MSIX_CTL_ENABLE != 0, self.enabled()) {
+            (false, true) => MsixUpdate::Enable,
+            (true, false) => MsixUpdate::Disable,
+            (true, true) if (old ^ self.msg_ctl) & MSIX_CTL_FUNCTION_MASK != 0 => {
+                MsixUpdate::FunctionMask
+            }
+            _ => MsixUpdate::None,
+        }
+    }
+
+    /// Read `data.len()` bytes at the byte `offset` of the table.
+    ///
+    /// Only 32-bit and 64-bit aligned accesses are supported, all ones are read otherwise.
+    pub fn read_table(&self, offset: u64, data: &mut [u8]) {
+        let index = offset as usize / MSIX_TABLE_ENTRY_SIZE;
+        let reg_idx = (offset as usize % MSIX_TABLE_ENTRY_SIZE) / 4;
+        match (self.table.get(index), data.len()) {
+            (Some(entry), 4) if offset & 0x3 == 0 => {
+                LittleEndian::write_u32(data, entry.read_reg(reg_idx))
+            }
+            (Some(entry), 8) if offset & 0x7 == 0 => {
+                LittleEndian::write_u32(&mut data[..4], entry.read_reg(reg_idx));
+                LittleEndian::write_u32(&mut data[4..], entry.read_reg(reg_idx + 1));
+            }
+            _ => data.iter_mut().for_each(|b| *b = 0xff),
+        }
+    }
+
+    /// Write `data` at the byte `offset` of the table.
+    ///
+    /// Only 32-bit and 64-bit aligned accesses are supported, they're ignored otherwise.
+    pub(crate) fn write_table(&mut self, offset: u64, data: &[u8]) -> MsixUpdate {
+        let index = offset as usize / MSIX_TABLE_ENTRY_SIZE;
+        let reg_idx = (offset as usize % MSIX_TABLE_ENTRY_SIZE) / 4;
+        let enabled = self.enabled();
+        let entry = match self.table.get_mut(index) {
+            Some(entry) => entry,
+            None => return MsixUpdate::None,
+        };
+        let old = *entry;
+        match data.len() {
+            4 if offset & 0x3 == 0 => entry.write_reg(reg_idx, LittleEndian::read_u32(data)),
+            8 if offset & 0x7 == 0 => {
+                entry.write_reg(reg_idx, LittleEndian::read_u32(&data[..4]));
+                entry.write_reg(reg_idx + 1, LittleEndian::read_u32(&data[4..]));
+            }
+            _ => return MsixUpdate::None,
+        }
+
+        if enabled && old != *entry {
+            MsixUpdate::Entry(index as u32)
+        } else {
+            MsixUpdate::None
+        }
+    }
+
+    /// Read `data.len()` bytes at the byte `offset` of the PBA, `pending` tells whether a vector
+    /// has a pending interrupt.
+    pub fn read_pba<F: Fn(u32) -> bool>(&self, offset: u64, data: &mut [u8], pending: F) {
+        for (index, byte) in data.iter_mut().enumerate() {
+            let first = (offset as u32 + index as u32) * 8;
+            *byte = 0;
+            for bit in 0..8 {
+                let vector = first + bit;
+                if vector < self.num_vectors() && pending(vector) {
+                    *byte |= 1 << bit;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msix_state() {
+        let mut msix = MsixState::new(0x3f | MSIX_CTL_ENABLE, 0x2000 | 0x2, 0x3000 | 0x2, 16);
+        // The table size is limited and the control bits of the device are dropped.
+        assert_eq!(msix.num_vectors(), 16);
+        assert_eq!(msix.msg_ctl(), 0xf);
+        assert!(!msix.enabled());
+        assert_eq!(msix.table_location(), (2, 0x2000));
+        assert_eq!(msix.pba_location(), (2, 0x3000));
+        assert_eq!(msix.table_size(), 256);
+        assert_eq!(msix.pba_size(), 8);
+        assert!(msix.is_masked(0));
+        assert!(msix.is_masked(16));
+
+        // Program the entry 1 with a 64-bit and a 32-bit write.
+        let addr = 0x1_fee0_0000u64;
+        assert_eq!(
+            msix.write_table(0x10, &addr.to_le_bytes()),
+            MsixUpdate::None
+        );
+        assert_eq!(
+            msix.write_table(0x18, &0x41u32.to_le_bytes()),
+            MsixUpdate::None
+        );
+        assert_eq!(
+            msix.write_table(0x1c, &0u32.to_le_bytes()),
+            MsixUpdate::None
+        );
+        let config = msix.source_config(1);
+        assert_eq!(config.high_addr, 0x1);
+        assert_eq!(config.low_addr, 0xfee0_0000);
+        assert_eq!(config.data, 0x41);
+        assert!(!msix.is_masked(1));
+
+        let mut data = [0u8; 8];
+        msix.read_table(0x18, &mut data);
+        assert_eq!(u64::from_le_bytes(data), 0x41);
+        let mut data = [0u8; 2];
+        msix.read_table(0x18, &mut data);
+        assert_eq!(data, [0xff, 0xff]);
+
+        // Updates are reported once enabled.
+        assert_eq!(msix.write_msg_ctl(MSIX_CTL_ENABLE), MsixUpdate::Enable);
+        assert_eq!(
+            msix.write_table(0x1c, &1u32.to_le_bytes()),
+            MsixUpdate::Entry(1)
+        );
+        assert!(msix.is_masked(1));
+        assert_eq!(
+            msix.write_table(0x1c, &0u32.to_le_bytes()),
+            MsixUpdate::Entry(1)
+        );
+        assert_eq!(
+            msix.write_table(0x1c, &0u32.to_le_bytes()),
+            MsixUpdate::None
+        );
+        assert_eq!(
+            msix.write_msg_ctl(MSIX_CTL_ENABLE | MSIX_CTL_FUNCTION_MASK),
+            MsixUpdate::FunctionMask
+        );
+        assert!(msix.is_masked(1));
+        assert_eq!(msix.msg_ctl(), 0xc00f);
+        assert_eq!(msix.write_msg_ctl(0), MsixUpdate::Disable);
+
+        // Pending bits.
+        let mut data = [0u8; 8];
+        msix.read_pba(0, &mut data, |vector| vector == 1 || vector == 9);
+        assert_eq!(data, [0x2, 0x2, 0, 0, 0, 0, 0, 0]);
+    }
+}
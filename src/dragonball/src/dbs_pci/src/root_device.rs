@@ -0,0 +1,224 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The PCI root device, which implements the PCI configuration mechanism #1.
+//!
+//! The guest selects a configuration space register by writing its address into the 32-bit
+//! `CONFIG_ADDRESS` port at `0xCF8`, then accesses the register through the `CONFIG_DATA` ports
+//! at `0xCFC` to `0xCFF`.
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use byteorder::{ByteOrder, LittleEndian};
+use dbs_device::resources::{DeviceResources, Resource};
+use dbs_device::{DeviceIo, PioAddress};
+
+use crate::bus::PCI_HOST_BRIDGE_DEVICE_ID;
+use crate::{Error, PciBus, PciClassCode, PciConfiguration, PciDevice, PciHeaderType, Result};
+
+/// Base of the PCI configuration io ports.
+pub const PCI_CONFIG_IO_PORT: u16 = 0xcf8;
+/// Size of the PCI configuration io ports.
+pub const PCI_CONFIG_IO_PORT_SIZE: u16 = 0x8;
+
+const CONFIG_ADDRESS_OFFSET: u16 = 0;
+const CONFIG_DATA_OFFSET: u16 = 4;
+const CONFIG_ADDRESS_ENABLE: u32 = 0x8000_0000;
+const CONFIG_ADDRESS_MASK: u32 = 0x80ff_fffc;
+
+// Intel virtual host bridge.
+const HOST_BRIDGE_VENDOR_ID: u16 = 0x8086;
+const HOST_BRIDGE_DEVICE_ID: u16 = 0x0d57;
+
+/// The host bridge at the slot 0 of the root bus.
+struct PciHostBridge {
+    config: Mutex<PciConfiguration>,
+}
+
+impl PciHostBridge {
+    fn new() -> Self {
+        PciHostBridge {
+            config: Mutex::new(PciConfiguration::new(
+                HOST_BRIDGE_VENDOR_ID,
+                HOST_BRIDGE_DEVICE_ID,
+                PciClassCode::BridgeDevice,
+                0, // host bridge
+                0,
+                PciHeaderType::Device,
+                0,
+                0,
+            )),
+        }
+    }
+}
+
+impl PciDevice for PciHostBridge {
+    fn id(&self) -> u8 {
+        PCI_HOST_BRIDGE_DEVICE_ID
+    }
+
+    fn write_config(&self, offset: u32, data: &[u8]) {
+        // Do not expect poisoned lock.
+        self.config
+            .lock()
+            .unwrap()
+            .write_config(offset as usize, data);
+    }
+
+    fn read_config(&self, offset: u32, data: &mut [u8]) {
+        self.config
+            .lock()
+            .unwrap()
+            .read_config(offset as usize, data);
+    }
+}
+
+/// The PCI root device, which traps the PCI configuration io ports.
+pub struct PciRootDevice {
+    bus: Arc<PciBus>,
+    config_address: Mutex<u32>,
+}
+
+impl PciRootDevice {
+    /// Create the root device of the PCI root bus `bus`, with the host bridge.
+    pub fn create(bus_id: u8, bus: Arc<PciBus>) -> Result<Self> {
+        if bus.bus_id() != bus_id {
+            return Err(Error::InvalidBusId(bus_id));
+        }
+        bus.register_host_bridge(Arc::new(PciHostBridge::new()))?;
+
+        Ok(PciRootDevice {
+            bus,
+            config_address: Mutex::new(0),
+        })
+    }
+
+    // Decode the selected register into the device, function and byte offset, if the access is
+    // enabled and targets the bus.
+    fn decode_config_address(&self, data_offset: u16) -> Option<(u8, u8, u32)> {
+        // Do not expect poisoned lock.
+        let address = *self.config_address.lock().unwrap();
+        let bus = (address >> 16) as u8;
+        if address & CONFIG_ADDRESS_ENABLE == 0 || bus != self.bus.bus_id() {
+            return None;
+        }
+        let dev = ((address >> 11) & 0x1f) as u8;
+        let func = ((address >> 8) & 0x7) as u8;
+        let offset = (address & 0xfc) + u32::from(data_offset);
+
+        Some((dev, func, offset))
+    }
+}
+
+impl DeviceIo for PciRootDevice {
+    fn pio_read(&self, _base: PioAddress, offset: PioAddress, data: &mut [u8]) {
+        let offset = offset.raw_value();
+        if offset == CONFIG_ADDRESS_OFFSET && data.len() == 4 {
+            LittleEndian::write_u32(data, *self.config_address.lock().unwrap());
+        } else if offset >= CONFIG_DATA_OFFSET
+            && offset as usize + data.len() <= PCI_CONFIG_IO_PORT_SIZE as usize
+        {
+            match self.decode_config_address(offset - CONFIG_DATA_OFFSET) {
+                Some((dev, func, offset)) => self.bus.read_config(dev, func, offset, data),
+                None => data.iter_mut().for_each(|b| *b = 0xff),
+            }
+        } else {
+            data.iter_mut().for_each(|b| *b = 0xff);
+        }
+    }
+
+    fn pio_write(&self, _base: PioAddress, offset: PioAddress, data: &[u8]) {
+        let offset = offset.raw_value();
+        if offset == CONFIG_ADDRESS_OFFSET && data.len() == 4 {
+            *self.config_address.lock().unwrap() =
+                LittleEndian::read_u32(data) & CONFIG_ADDRESS_MASK;
+        } else if offset >= CONFIG_DATA_OFFSET
+            && offset as usize + data.len() <= PCI_CONFIG_IO_PORT_SIZE as usize
+        {
+            if let Some((dev, func, offset)) =
+                self.decode_config_address(offset - CONFIG_DATA_OFFSET)
+            {
+                self.bus.write_config(dev, func, offset, data);
+            }
+        }
+    }
+
+    fn get_assigned_resources(&self) -> DeviceResources {
+        let mut resources = DeviceResources::new();
+        resources.append(Resource::PioAddressRange {
+            base: PCI_CONFIG_IO_PORT,
+            size: PCI_CONFIG_IO_PORT_SIZE,
+        });
+        resources
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+    use crate::bus::tests::TestDevice;
+    use crate::create_pci_root_bus;
+
+    fn config_address(dev: u8, reg: u32) -> [u8; 4] {
+        (CONFIG_ADDRESS_ENABLE | u32::from(dev) << 11 | reg).to_le_bytes()
+    }
+
+    #[test]
+    fn test_pci_root_device() {
+        let bus = create_pci_root_bus(0).unwrap();
+        assert!(matches!(
+            PciRootDevice::create(1, bus.clone()),
+            Err(Error::InvalidBusId(1))
+        ));
+        let root = PciRootDevice::create(0, bus.clone()).unwrap();
+        assert_eq!(
+            root.get_assigned_resources().get_pio_address_ranges(),
+            vec![(PCI_CONFIG_IO_PORT, PCI_CONFIG_IO_PORT_SIZE)]
+        );
+        let base = PioAddress(PCI_CONFIG_IO_PORT);
+
+        // The host bridge is at the slot 0.
+        let mut data = [0u8; 4];
+        root.pio_write(base, PioAddress(0), &config_address(0, 0));
+        root.pio_read(base, PioAddress(0), &mut data);
+        assert_eq!(data, config_address(0, 0));
+        root.pio_read(base, PioAddress(4), &mut data);
+        assert_eq!(
+            LittleEndian::read_u32(&data),
+            u32::from(HOST_BRIDGE_DEVICE_ID) << 16 | u32::from(HOST_BRIDGE_VENDOR_ID)
+        );
+        root.pio_write(base, PioAddress(0), &config_address(0, 8));
+        root.pio_read(base, PioAddress(4), &mut data);
+        assert_eq!(data[3], PciClassCode::BridgeDevice as u8);
+
+        // Partial accesses to a registered device.
+        let device = Arc::new(TestDevice {
+            id: 2,
+            reg: AtomicU32::new(0x1234_5678),
+        });
+        assert_eq!(bus.allocate_device_id(Some(2)), Some(2));
+        bus.register_device(device).unwrap();
+        root.pio_write(base, PioAddress(0), &config_address(2, 0));
+        let mut word = [0u8; 2];
+        root.pio_read(base, PioAddress(6), &mut word);
+        assert_eq!(word, [0x34, 0x12]);
+        root.pio_write(base, PioAddress(4), &[0xaa, 0xbb, 0xcc, 0xdd]);
+        root.pio_read(base, PioAddress(4), &mut data);
+        assert_eq!(data, [0xaa, 0xbb, 0xcc, 0xdd]);
+
+        // Empty slots and disabled accesses read all ones.
+        root.pio_write(base, PioAddress(0), &config_address(3, 0));
+        root.pio_read(base, PioAddress(4), &mut data);
+        assert_eq!(data, [0xff; 4]);
+        root.pio_write(base, PioAddress(0), &[0, 0, 0, 0]);
+        root.pio_read(base, PioAddress(4), &mut data);
+        assert_eq!(data, [0xff; 4]);
+    }
+}
@@ -0,0 +1,1070 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Passthrough of a host PCI device to the guest with VFIO.
+//!
+//! The configuration space of the host device is read and written through the VFIO config
+//! region, except for the registers which the VMM emulates:
+//! - the BARs, which describe the guest addresses allocated for the regions of the device,
+//! - the expansion ROM BAR and the interrupt pin, the ROM and the INTx interrupt aren't supported,
+//! - the MSI capability and the message control of the MSI-X capability, which are used to route
+//!   the interrupts of the device into the guest.
+//!
+//! The BARs which can be mmapped are mapped into the guest with KVM memory slots, the accesses to
+//! the other ones, and to the BARs containing the MSI-X table or the PBA, are trapped and
+//! forwarded to the VFIO regions.
+
+use std::any::Any;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::ptr::null_mut;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use byteorder::{ByteOrder, LittleEndian};
+use dbs_device::device_manager::IoManagerContext;
+use dbs_device::resources::{DeviceResources, Resource, ResourceConstraint};
+use dbs_device::{DeviceIo, IoAddress, PioAddress};
+use dbs_interrupt::{
+    InterruptManager, InterruptSourceConfig, InterruptSourceGroup, InterruptSourceType,
+};
+use kvm_bindings::kvm_userspace_memory_region;
+use kvm_ioctls::VmFd;
+use log::{error, info, warn};
+use vfio_bindings::bindings::vfio::{
+    VFIO_PCI_BAR0_REGION_INDEX, VFIO_PCI_CONFIG_REGION_INDEX, VFIO_PCI_MSIX_IRQ_INDEX,
+    VFIO_PCI_MSI_IRQ_INDEX, VFIO_REGION_INFO_FLAG_MMAP,
+};
+use vfio_ioctls::{VfioContainer, VfioDevice, VfioError, VfioRegionInfoCap};
+
+use crate::configuration::merge_config_write;
+use crate::msi::{MsiState, MsiUpdate};
+use crate::msix::{MsixState, MsixUpdate};
+use crate::{
+    Error, PciBarRegionType, PciBus, PciCapabilityId, PciClassCode, PciConfiguration, PciDevice,
+    PciHeaderType, PciSystemContext, NUM_BAR_REGS,
+};
+
+const PAGE_SIZE: u64 = 0x1000;
+const PCI_CONFIG_SPACE_SIZE: u64 = 0x100;
+
+const VENDOR_DEVICE_ID_REG: usize = 0;
+const COMMAND_STATUS_OFFSET: u64 = 0x4;
+const STATUS_CAPABILITIES_LIST: u32 = 0x0010_0000;
+const BAR0_REG: usize = 4;
+const LAST_BAR_REG: usize = BAR0_REG + NUM_BAR_REGS - 1;
+const ROM_BAR_REG: usize = 12;
+const CAPABILITY_LIST_HEAD_OFFSET: u64 = 0x34;
+const INTERRUPT_LINE_PIN_REG: usize = 15;
+const FIRST_CAPABILITY_OFFSET: u64 = 0x40;
+// Guard against a looping capability list.
+const MAX_CAPABILITIES: usize = 48;
+
+const BAR_IO_BIT: u32 = 0x1;
+const BAR_MEM_TYPE_MASK: u32 = 0x6;
+const BAR_MEM_TYPE_64BIT: u32 = 0x4;
+const BAR_PREFETCHABLE: u32 = 0x8;
+
+// Upper limit of the MSI-X vectors exposed to the guest, the MSI interrupts of a device are
+// limited to 256 by the interrupt manager.
+const MAX_MSIX_VECTORS: u32 = 256;
+
+/// Errors associated with the VFIO PCI devices.
+#[derive(Debug, thiserror::Error)]
+pub enum VfioPciError {
+    /// The VFIO config region of the device is missing or too small.
+    #[error("invalid configuration space of the VFIO PCI device")]
+    InvalidConfigSpace,
+
+    /// The resources assigned to the device don't match its requirements.
+    #[error("invalid resources assigned to the VFIO PCI device")]
+    InvalidResources,
+
+    /// Failed to set up the emulated configuration space.
+    #[error("failed to set up the PCI configuration space: {0}")]
+    PciConfiguration(#[source] Error),
+
+    /// Failed to mmap the region of a BAR.
+    #[error("failed to mmap the region of BAR {0}: {1}")]
+    MmapRegion(usize, #[source] io::Error),
+
+    /// Failed to set the KVM memory slot of a BAR.
+    #[error("failed to set the KVM memory slot of BAR {0}: {1}")]
+    KvmMemSlot(usize, #[source] kvm_ioctls::Error),
+
+    /// Failed to set up the MSI/MSI-X interrupts.
+    #[error("failed to set up the MSI/MSI-X interrupts: {0}")]
+    InterruptManager(#[source] io::Error),
+
+    /// Failure from the VFIO subsystem.
+    #[error("failure in VFIO operations: {0}")]
+    Vfio(#[source] VfioError),
+}
+
+fn read_device_config_u32(vfio_dev: &VfioDevice, offset: u64) -> u32 {
+    let mut data = [0u8; 4];
+    vfio_dev.region_read(VFIO_PCI_CONFIG_REGION_INDEX, &mut data, offset);
+    LittleEndian::read_u32(&data)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum IrqKind {
+    Msi,
+    Msix,
+}
+
+// The interrupt group of the enabled MSI or MSI-X interrupts.
+struct VfioPciIrq {
+    kind: IrqKind,
+    group: Arc<Box<dyn InterruptSourceGroup>>,
+    // The vectors whose irqfd is unregistered from KVM because the guest masked them, the
+    // interrupts raised by the device stay pending in the irqfd.
+    masked: Vec<bool>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct VfioPciBarMmap {
+    slot: u32,
+    host_addr: u64,
+    size: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct VfioPciBar {
+    index: usize,
+    region_type: PciBarRegionType,
+    prefetchable: bool,
+    // The size of the VFIO region, the size of the BAR is rounded up to a power of two.
+    region_size: u64,
+    size: u64,
+    mmappable: bool,
+    // The address at which the BAR is mapped or trapped.
+    addr: u64,
+    mmap: Option<VfioPciBarMmap>,
+}
+
+impl VfioPciBar {
+    fn region_index(&self) -> u32 {
+        VFIO_PCI_BAR0_REGION_INDEX + self.index as u32
+    }
+
+    fn resource(&self, addr: u64) -> Resource {
+        match self.region_type {
+            PciBarRegionType::IoRegion => Resource::PioAddressRange {
+                base: addr as u16,
+                size: self.size as u16,
+            },
+            _ => Resource::MmioAddressRange {
+                base: addr,
+                size: self.size,
+            },
+        }
+    }
+}
+
+struct VfioPciState {
+    config: PciConfiguration,
+    vendor_device_id: Option<u32>,
+    bars: Vec<VfioPciBar>,
+    // The emulated capabilities, with their offset in the configuration space.
+    msi: Option<(u64, MsiState)>,
+    msix: Option<(u64, MsixState)>,
+    irq: Option<VfioPciIrq>,
+    resources: DeviceResources,
+}
+
+impl VfioPciState {
+    fn new(vendor_device_id: Option<u32>) -> Self {
+        VfioPciState {
+            // Only the BARs of the emulated configuration space are used.
+            config: PciConfiguration::new(
+                0,
+                0,
+                PciClassCode::Other,
+                0,
+                0,
+                PciHeaderType::Device,
+                0,
+                0,
+            ),
+            vendor_device_id,
+            bars: Vec::new(),
+            msi: None,
+            msix: None,
+            irq: None,
+            resources: DeviceResources::new(),
+        }
+    }
+
+    // Find the MSI and MSI-X capabilities of the device, if the device supports the interrupts.
+    fn parse_capabilities(&mut self, vfio_dev: &VfioDevice) {
+        let irq_count = |index| vfio_dev.get_irq_info(index).map_or(0, |info| info.count);
+
+        if read_device_config_u32(vfio_dev, COMMAND_STATUS_OFFSET) & STATUS_CAPABILITIES_LIST == 0 {
+            return;
+        }
+        let mut cap =
+            u64::from(read_device_config_u32(vfio_dev, CAPABILITY_LIST_HEAD_OFFSET) & 0xfc);
+        for _ in 0..MAX_CAPABILITIES {
+            if cap < FIRST_CAPABILITY_OFFSET {
+                break;
+            }
+            let header = read_device_config_u32(vfio_dev, cap);
+            let msg_ctl = (header >> 16) as u16;
+            match header & 0xff {
+                id if id == PciCapabilityId::Msi as u32
+                    && irq_count(VFIO_PCI_MSI_IRQ_INDEX) != 0 =>
+                {
+                    self.msi = Some((cap, MsiState::new(msg_ctl)));
+                }
+                id if id == PciCapabilityId::MsiX as u32 => {
+                    let max_vectors = irq_count(VFIO_PCI_MSIX_IRQ_INDEX).min(MAX_MSIX_VECTORS);
+                    if max_vectors != 0 {
+                        let table = read_device_config_u32(vfio_dev, cap + 4);
+                        let pba = read_device_config_u32(vfio_dev, cap + 8);
+                        self.msix =
+                            Some((cap, MsixState::new(msg_ctl, table, pba, max_vectors as u16)));
+                    }
+                }
+                _ => {}
+            }
+            cap = u64::from((header >> 8) & 0xfc);
+        }
+    }
+
+    // Describe the BARs of the device, after its capabilities have been parsed.
+    fn parse_bars(&mut self, vfio_dev: &VfioDevice) {
+        let mut index = 0;
+        while index < NUM_BAR_REGS {
+            let reg = read_device_config_u32(vfio_dev, (BAR0_REG + index) as u64 * 4);
+            let region_type = if reg & BAR_IO_BIT != 0 {
+                PciBarRegionType::IoRegion
+            } else if reg & BAR_MEM_TYPE_MASK == BAR_MEM_TYPE_64BIT {
+                PciBarRegionType::Memory64BitRegion
+            } else {
+                PciBarRegionType::Memory32BitRegion
+            };
+            let region_index = VFIO_PCI_BAR0_REGION_INDEX + index as u32;
+            let region_size = vfio_dev.get_region_size(region_index);
+            let bar_index = index;
+            index += match region_type {
+                PciBarRegionType::Memory64BitRegion => 2,
+                _ => 1,
+            };
+            if region_size == 0 {
+                continue;
+            }
+
+            let size = match region_type {
+                PciBarRegionType::IoRegion if region_size > u64::from(u16::MAX) / 2 => {
+                    warn!(
+                        "ignore the io BAR {} of {:#x} bytes",
+                        bar_index, region_size
+                    );
+                    continue;
+                }
+                PciBarRegionType::IoRegion => region_size.next_power_of_two(),
+                _ => region_size.next_power_of_two().max(PAGE_SIZE),
+            };
+            // The MSI-X table and PBA are emulated, so the BARs containing them are trapped.
+            let has_msix = match self.msix.as_ref() {
+                Some((_, msix)) => {
+                    msix.table_location().0 as usize == bar_index
+                        || msix.pba_location().0 as usize == bar_index
+                }
+                None => false,
+            };
+            let mmappable = region_type != PciBarRegionType::IoRegion
+                && !has_msix
+                && region_size & (PAGE_SIZE - 1) == 0
+                && vfio_dev.get_region_flags(region_index) & VFIO_REGION_INFO_FLAG_MMAP != 0
+                && !vfio_dev
+                    .get_region_caps(region_index)
+                    .iter()
+                    .any(|cap| matches!(cap, VfioRegionInfoCap::SparseMmap(_)));
+
+            self.bars.push(VfioPciBar {
+                index: bar_index,
+                region_type,
+                prefetchable: reg & BAR_PREFETCHABLE != 0,
+                region_size,
+                size,
+                mmappable,
+                addr: 0,
+                mmap: None,
+            });
+        }
+    }
+
+    fn trapped_bar(&self, is_io: bool, base: u64) -> Option<VfioPciBar> {
+        self.bars
+            .iter()
+            .find(|bar| {
+                bar.mmap.is_none()
+                    && bar.addr == base
+                    && (bar.region_type == PciBarRegionType::IoRegion) == is_io
+            })
+            .copied()
+    }
+
+    // Check whether the guest masked the vector `index` of the enabled interrupts.
+    fn is_vector_masked(&self, kind: IrqKind, index: u32) -> bool {
+        match (kind, &self.msi, &self.msix) {
+            (IrqKind::Msi, Some((_, msi)), _) => msi.is_masked(index),
+            (IrqKind::Msix, _, Some((_, msix))) => msix.is_masked(index),
+            _ => true,
+        }
+    }
+
+    fn source_config(&self, kind: IrqKind, index: u32) -> InterruptSourceConfig {
+        let config = match (kind, &self.msi, &self.msix) {
+            (IrqKind::Msi, Some((_, msi)), _) => msi.source_config(index),
+            (IrqKind::Msix, _, Some((_, msix))) => msix.source_config(index),
+            _ => Default::default(),
+        };
+        InterruptSourceConfig::MsiIrq(config)
+    }
+}
+
+// The state shared by the device and the handlers of its trapped BARs.
+struct VfioPciInner<C: PciSystemContext> {
+    vfio_dev: VfioDevice,
+    vm_fd: Arc<VmFd>,
+    context: C,
+    state: Mutex<VfioPciState>,
+}
+
+impl<C: PciSystemContext> VfioPciInner<C> {
+    fn state(&self) -> MutexGuard<'_, VfioPciState> {
+        // Do not expect poisoned lock.
+        self.state.lock().unwrap()
+    }
+
+    fn read_device_config_u32(&self, offset: u64) -> u32 {
+        read_device_config_u32(&self.vfio_dev, offset)
+    }
+
+    fn read_config_reg(&self, state: &VfioPciState, reg_idx: usize) -> u32 {
+        let offset = reg_idx as u64 * 4;
+        match (reg_idx, state.vendor_device_id) {
+            (VENDOR_DEVICE_ID_REG, Some(vendor_device_id)) => vendor_device_id,
+            (BAR0_REG..=LAST_BAR_REG, _) => state.config.read_reg(reg_idx),
+            (ROM_BAR_REG, _) => 0,
+            // No INTx interrupt.
+            (INTERRUPT_LINE_PIN_REG, _) => self.read_device_config_u32(offset) & 0xffff_00ff,
+            _ => {
+                let value = self.read_device_config_u32(offset);
+                match (&state.msi, &state.msix) {
+                    (Some((cap, msi)), _) if (*cap..*cap + msi.size() as u64).contains(&offset) => {
+                        let mut data = [0u8; 4];
+                        msi.read((offset - cap) as usize, &mut data);
+                        let emulated = LittleEndian::read_u32(&data);
+                        if offset == *cap {
+                            // The identifier and the next pointer of the capability.
+                            (emulated & 0xffff_0000) | (value & 0xffff)
+                        } else {
+                            emulated
+                        }
+                    }
+                    (_, Some((cap, msix))) if offset == *cap => {
+                        u32::from(msix.msg_ctl()) << 16 | (value & 0xffff)
+                    }
+                    _ => value,
+                }
+            }
+        }
+    }
+
+    fn read_config(&self, offset: u32, data: &mut [u8]) {
+        let offset = offset as usize;
+        let shift = offset % 4;
+        if data.is_empty() || shift + data.len() > 4 || offset as u64 >= PCI_CONFIG_SPACE_SIZE {
+            data.iter_mut().for_each(|b| *b = 0xff);
+            return;
+        }
+
+        let state = self.state();
+        let value = self.read_config_reg(&state, offset / 4) >> (shift * 8);
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = (value >> (index * 8)) as u8;
+        }
+    }
+
+    fn write_config(self: &Arc<Self>, offset: u32, data: &[u8]) {
+        let offset = offset as u64;
+        let reg_idx = offset as usize / 4;
+        if offset >= PCI_CONFIG_SPACE_SIZE {
+            return;
+        }
+
+        let mut guard = self.state();
+        let state = &mut *guard;
+        match reg_idx {
+            VENDOR_DEVICE_ID_REG | ROM_BAR_REG => {}
+            BAR0_REG..=LAST_BAR_REG => {
+                let value =
+                    match merge_config_write(state.config.read_reg(reg_idx), offset as usize, data)
+                    {
+                        Some(value) => value,
+                        None => return,
+                    };
+                state.config.write_reg(reg_idx, value);
+                if let Some((index, upper)) = state.config.get_bar_index(reg_idx) {
+                    // A 64-bit BAR is moved once its upper register is written.
+                    let is_64bit = state.config.get_bar_type(index)
+                        == Some(PciBarRegionType::Memory64BitRegion);
+                    if upper == is_64bit {
+                        self.move_bar(state, index);
+                    }
+                }
+            }
+            _ => match (state.msi.as_mut(), state.msix.as_mut()) {
+                (Some((cap, msi)), _) if (*cap..*cap + msi.size() as u64).contains(&offset) => {
+                    let update = msi.write((offset - *cap) as usize, data);
+                    self.update_msi(state, update);
+                }
+                (_, Some((cap, msix))) if offset / 4 == *cap / 4 => {
+                    let value = match merge_config_write(
+                        u32::from(msix.msg_ctl()) << 16,
+                        offset as usize,
+                        data,
+                    ) {
+                        Some(value) => value,
+                        None => return,
+                    };
+                    let update = msix.write_msg_ctl((value >> 16) as u16);
+                    self.update_msix(state, update);
+                }
+                _ => self
+                    .vfio_dev
+                    .region_write(VFIO_PCI_CONFIG_REGION_INDEX, data, offset),
+            },
+        }
+    }
+
+    // Move the BAR `index` to the address programmed by the guest.
+    fn move_bar(self: &Arc<Self>, state: &mut VfioPciState, index: usize) {
+        if state.config.is_bar_sizing(index) {
+            return;
+        }
+        let addr = state.config.get_bar_addr(index);
+        let bar = match state.bars.iter_mut().find(|bar| bar.index == index) {
+            Some(bar) if bar.addr != addr => bar,
+            _ => return,
+        };
+
+        if let Some(mmap) = bar.mmap {
+            if let Err(e) = self.set_mem_slot(mmap.slot, addr, mmap.size, mmap.host_addr) {
+                error!("failed to move BAR {} to {:#x}: {}", index, addr, e);
+                return;
+            }
+        } else {
+            let ctx = self.context.get_device_manager_context();
+            let mut tx = ctx.begin_tx();
+            let handler = Arc::new(VfioPciBarIo {
+                inner: self.clone(),
+            });
+            if let Err(e) = ctx
+                .unregister_device_io(&mut tx, &[bar.resource(bar.addr)])
+                .and_then(|_| ctx.register_device_io(&mut tx, handler, &[bar.resource(addr)]))
+            {
+                ctx.cancel_tx(tx);
+                error!("failed to move BAR {} to {:#x}: {:?}", index, addr, e);
+                return;
+            }
+            ctx.commit_tx(tx);
+        }
+        bar.addr = addr;
+    }
+
+    fn set_mem_slot(
+        &self,
+        slot: u32,
+        guest_addr: u64,
+        size: u64,
+        host_addr: u64,
+    ) -> Result<(), kvm_ioctls::Error> {
+        let region = kvm_userspace_memory_region {
+            slot,
+            flags: 0,
+            guest_phys_addr: guest_addr,
+            memory_size: size,
+            userspace_addr: host_addr,
+        };
+        // Safe because the host mapping outlives the memory slot, it's only unmapped after the
+        // slot is deleted.
+        unsafe { self.vm_fd.set_user_memory_region(region) }
+    }
+
+    fn map_bar(&self, bar: &VfioPciBar, slot: u32) -> Result<VfioPciBarMmap, VfioPciError> {
+        let region_index = bar.region_index();
+        // Safe because the result is checked and the mapping is only accessed by the guest.
+        let host_addr = unsafe {
+            libc::mmap(
+                null_mut(),
+                bar.region_size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                self.vfio_dev.as_raw_fd(),
+                self.vfio_dev.get_region_offset(region_index) as libc::off_t,
+            )
+        };
+        if host_addr == libc::MAP_FAILED {
+            return Err(VfioPciError::MmapRegion(
+                bar.index,
+                io::Error::last_os_error(),
+            ));
+        }
+
+        let mmap = VfioPciBarMmap {
+            slot,
+            host_addr: host_addr as u64,
+            size: bar.region_size,
+        };
+        if let Err(e) = self.set_mem_slot(slot, bar.addr, mmap.size, mmap.host_addr) {
+            // Safe because the mapping was just created and isn't used.
+            unsafe { libc::munmap(host_addr, mmap.size as usize) };
+            return Err(VfioPciError::KvmMemSlot(bar.index, e));
+        }
+
+        Ok(mmap)
+    }
+
+    fn unmap_bars(&self, state: &mut VfioPciState) -> Result<(), VfioPciError> {
+        let mut result = Ok(());
+        for bar in state.bars.iter_mut() {
+            if let Some(mmap) = bar.mmap.take() {
+                // A memory slot is deleted by setting its size to 0.
+                if let Err(e) = self.set_mem_slot(mmap.slot, bar.addr, 0, mmap.host_addr) {
+                    // Keep the host mapping, which may still be accessed by the guest.
+                    result = Err(VfioPciError::KvmMemSlot(bar.index, e));
+                    continue;
+                }
+                // Safe because the mapping isn't accessed by the guest anymore.
+                unsafe { libc::munmap(mmap.host_addr as *mut libc::c_void, mmap.size as usize) };
+            }
+        }
+        result
+    }
+
+    fn update_msi(&self, state: &mut VfioPciState, update: MsiUpdate) {
+        match update {
+            MsiUpdate::None => {}
+            MsiUpdate::Enable => {
+                self.disable_irq(state);
+                if let Err(e) = self.enable_irq(state, IrqKind::Msi) {
+                    error!("failed to enable the MSI interrupts: {}", e);
+                }
+            }
+            MsiUpdate::Disable => self.disable_irq(state),
+            MsiUpdate::Reconfigure => {
+                let count = state.irq.as_ref().map_or(0, |irq| irq.masked.len() as u32);
+                for index in 0..count {
+                    self.update_irq(state, index);
+                }
+            }
+            MsiUpdate::Mask => self.sync_irq_masks(state),
+        }
+    }
+
+    fn update_msix(&self, state: &mut VfioPciState, update: MsixUpdate) {
+        match update {
+            MsixUpdate::None => {}
+            MsixUpdate::Enable => {
+                self.disable_irq(state);
+                if let Err(e) = self.enable_irq(state, IrqKind::Msix) {
+                    error!("failed to enable the MSI-X interrupts: {}", e);
+                }
+            }
+            MsixUpdate::Disable => self.disable_irq(state),
+            MsixUpdate::FunctionMask => self.sync_irq_masks(state),
+            MsixUpdate::Entry(index) => {
+                self.update_irq(state, index);
+                self.sync_irq_masks(state);
+            }
+        }
+    }
+
+    fn enable_irq(&self, state: &mut VfioPciState, kind: IrqKind) -> Result<(), VfioPciError> {
+        let (base, count) = match (kind, &state.msi, &state.msix) {
+            (IrqKind::Msi, Some((_, msi)), _) => (
+                state.resources.get_pci_msi_irqs(),
+                msi.num_enabled_vectors(),
+            ),
+            (IrqKind::Msix, _, Some((_, msix))) => {
+                (state.resources.get_pci_msix_irqs(), msix.num_vectors())
+            }
+            _ => return Err(VfioPciError::InvalidResources),
+        };
+        let base = match base {
+            Some((base, size)) if count <= size => base,
+            _ => return Err(VfioPciError::InvalidResources),
+        };
+
+        let irq_manager = self.context.get_interrupt_manager();
+        let group = irq_manager
+            .create_group(InterruptSourceType::MsiIrq, base, count)
+            .map_err(VfioPciError::InterruptManager)?;
+        let configs: Vec<InterruptSourceConfig> = (0..count)
+            .map(|index| state.source_config(kind, index))
+            .collect();
+        if let Err(e) = group.enable(&configs) {
+            let _ = irq_manager.destroy_group(group);
+            return Err(VfioPciError::InterruptManager(e));
+        }
+
+        let fds = (0..count)
+            .filter_map(|index| group.notifier(index))
+            .collect();
+        let result = match kind {
+            IrqKind::Msi => self.vfio_dev.enable_msi(fds),
+            IrqKind::Msix => self.vfio_dev.enable_msix(fds),
+        };
+        if let Err(e) = result {
+            let _ = group.disable();
+            let _ = irq_manager.destroy_group(group);
+            return Err(VfioPciError::Vfio(e));
+        }
+
+        state.irq = Some(VfioPciIrq {
+            kind,
+            group,
+            masked: vec![false; count as usize],
+        });
+        self.sync_irq_masks(state);
+
+        Ok(())
+    }
+
+    fn disable_irq(&self, state: &mut VfioPciState) {
+        let irq = match state.irq.take() {
+            Some(irq) => irq,
+            None => return,
+        };
+
+        let result = match irq.kind {
+            IrqKind::Msi => self.vfio_dev.disable_msi(),
+            IrqKind::Msix => self.vfio_dev.disable_msix(),
+        };
+        if let Err(e) = result {
+            warn!("failed to disable the interrupts of the VFIO device: {}", e);
+        }
+        for (index, masked) in irq.masked.iter().enumerate() {
+            if *masked {
+                // Drop the pending interrupt and register the irqfd again, the group
+                // unregisters all of them when disabled.
+                if let Some(irqfd) = irq.group.notifier(index as u32) {
+                    let _ = irqfd.read();
+                }
+                let _ = irq.group.unmask(index as u32);
+            }
+        }
+        if let Err(e) = irq.group.disable() {
+            warn!("failed to disable the interrupt group: {}", e);
+        }
+        if let Err(e) = self
+            .context
+            .get_interrupt_manager()
+            .destroy_group(irq.group)
+        {
+            warn!("failed to destroy the interrupt group: {}", e);
+        }
+    }
+
+    // Update the route of the vector `index` after the guest changed its message.
+    fn update_irq(&self, state: &VfioPciState, index: u32) {
+        if let Some(irq) = state.irq.as_ref() {
+            if index < irq.masked.len() as u32 {
+                let config = state.source_config(irq.kind, index);
+                if let Err(e) = irq.group.update(index, &config) {
+                    error!("failed to update the interrupt vector {}: {}", index, e);
+                }
+            }
+        }
+    }
+
+    // Mask or unmask the vectors whose mask has been changed by the guest.
+    fn sync_irq_masks(&self, state: &mut VfioPciState) {
+        let mut irq = match state.irq.take() {
+            Some(irq) => irq,
+            None => return,
+        };
+        for index in 0..irq.masked.len() {
+            let masked = state.is_vector_masked(irq.kind, index as u32);
+            if masked == irq.masked[index] {
+                continue;
+            }
+            // An interrupt left pending in the irqfd is injected once the irqfd is registered.
+            let result = if masked {
+                irq.group.mask(index as u32)
+            } else {
+                irq.group.unmask(index as u32)
+            };
+            match result {
+                Ok(()) => irq.masked[index] = masked,
+                Err(e) => error!("failed to mask or unmask the vector {}: {}", index, e),
+            }
+        }
+        state.irq = Some(irq);
+    }
+
+    fn bar_read(&self, is_io: bool, base: u64, offset: u64, data: &mut [u8]) {
+        let state = self.state();
+        let bar = match state.trapped_bar(is_io, base) {
+            Some(bar) => bar,
+            None => {
+                data.iter_mut().for_each(|b| *b = 0xff);
+                return;
+            }
+        };
+
+        if let Some((_, msix)) = state.msix.as_ref() {
+            let (table_bir, table_offset) = msix.table_location();
+            let (pba_bir, pba_offset) = msix.pba_location();
+            if table_bir as usize == bar.index
+                && (table_offset..table_offset + msix.table_size()).contains(&offset)
+            {
+                msix.read_table(offset - table_offset, data);
+                return;
+            }
+            if pba_bir as usize == bar.index
+                && (pba_offset..pba_offset + msix.pba_size()).contains(&offset)
+            {
+                let irq = state.irq.as_ref().filter(|irq| irq.kind == IrqKind::Msix);
+                msix.read_pba(offset - pba_offset, data, |vector| match irq {
+                    Some(irq) => {
+                        irq.masked.get(vector as usize) == Some(&true)
+                            && irq.group.get_pending_state(vector)
+                    }
+                    None => false,
+                });
+                return;
+            }
+        }
+
+        if offset + data.len() as u64 <= bar.region_size {
+            self.vfio_dev.region_read(bar.region_index(), data, offset);
+        } else {
+            data.iter_mut().for_each(|b| *b = 0xff);
+        }
+    }
+
+    fn bar_write(&self, is_io: bool, base: u64, offset: u64, data: &[u8]) {
+        let mut guard = self.state();
+        let state = &mut *guard;
+        let bar = match state.trapped_bar(is_io, base) {
+            Some(bar) => bar,
+            None => return,
+        };
+
+        if let Some((_, msix)) = state.msix.as_mut() {
+            let (table_bir, table_offset) = msix.table_location();
+            let (pba_bir, pba_offset) = msix.pba_location();
+            if table_bir as usize == bar.index
+                && (table_offset..table_offset + msix.table_size()).contains(&offset)
+            {
+                let update = msix.write_table(offset - table_offset, data);
+                self.update_msix(state, update);
+                return;
+            }
+            // The PBA is read-only.
+            if pba_bir as usize == bar.index
+                && (pba_offset..pba_offset + msix.pba_size()).contains(&offset)
+            {
+                return;
+            }
+        }
+
+        if offset + data.len() as u64 <= bar.region_size {
+            self.vfio_dev.region_write(bar.region_index(), data, offset);
+        }
+    }
+}
+
+impl<C: PciSystemContext> Drop for VfioPciInner<C> {
+    fn drop(&mut self) {
+        // The device may be dropped without being cleared if it failed to be added.
+        let mut state = self.state();
+        self.disable_irq(&mut state);
+        if let Err(e) = self.unmap_bars(&mut state) {
+            error!("failed to unmap the BARs of the VFIO device: {}", e);
+        }
+    }
+}
+
+// Handler of the trapped BARs moved by the guest.
+struct VfioPciBarIo<C: PciSystemContext> {
+    inner: Arc<VfioPciInner<C>>,
+}
+
+impl<C: PciSystemContext> DeviceIo for VfioPciBarIo<C> {
+    fn read(&self, base: IoAddress, offset: IoAddress, data: &mut [u8]) {
+        self.inner
+            .bar_read(false, base.raw_value(), offset.raw_value(), data);
+    }
+
+    fn write(&self, base: IoAddress, offset: IoAddress, data: &[u8]) {
+        self.inner
+            .bar_write(false, base.raw_value(), offset.raw_value(), data);
+    }
+
+    fn pio_read(&self, base: PioAddress, offset: PioAddress, data: &mut [u8]) {
+        self.inner.bar_read(
+            true,
+            u64::from(base.raw_value()),
+            u64::from(offset.raw_value()),
+            data,
+        );
+    }
+
+    fn pio_write(&self, base: PioAddress, offset: PioAddress, data: &[u8]) {
+        self.inner.bar_write(
+            true,
+            u64::from(base.raw_value()),
+            u64::from(offset.raw_value()),
+            data,
+        );
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A host PCI device passed through to the guest with VFIO.
+///
+/// The device is created with a device ID allocated on the PCI bus, then activated with the
+/// resources allocated for its requirements, before being registered on the bus and in the io
+/// manager.
+pub struct VfioPciDevice<C: PciSystemContext> {
+    id: u8,
+    sysfs_path: String,
+    bus: Weak<PciBus>,
+    inner: Arc<VfioPciInner<C>>,
+    // The container holds the DMA mappings of the guest memory for the device.
+    _vfio_container: Arc<VfioContainer>,
+}
+
+impl<C: PciSystemContext> VfioPciDevice<C> {
+    /// Create the device passing the host device `vfio_dev` through with the device ID `id`.
+    ///
+    /// The vendor and device IDs of the host device are replaced by `vendor_device_id`, as
+    /// `vendor_id << 16 | device_id`, if it isn't zero.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        id: u8,
+        sysfs_path: String,
+        bus: Weak<PciBus>,
+        vfio_dev: VfioDevice,
+        vendor_device_id: u32,
+        vm_fd: Arc<VmFd>,
+        context: C,
+        vfio_container: Arc<VfioContainer>,
+    ) -> Result<Self, VfioPciError> {
+        if vfio_dev.get_region_size(VFIO_PCI_CONFIG_REGION_INDEX) < PCI_CONFIG_SPACE_SIZE {
+            return Err(VfioPciError::InvalidConfigSpace);
+        }
+
+        // The register holds the device ID in its upper half.
+        let vendor_device_id = if vendor_device_id != 0 {
+            Some((vendor_device_id & 0xffff) << 16 | vendor_device_id >> 16)
+        } else {
+            None
+        };
+        let mut state = VfioPciState::new(vendor_device_id);
+        state.parse_capabilities(&vfio_dev);
+        state.parse_bars(&vfio_dev);
+        let inner = VfioPciInner {
+            vfio_dev,
+            vm_fd,
+            context,
+            state: Mutex::new(state),
+        };
+
+        Ok(VfioPciDevice {
+            id,
+            sysfs_path,
+            bus,
+            inner: Arc::new(inner),
+            _vfio_container: vfio_container,
+        })
+    }
+
+    /// Get the device ID, i.e. the slot of the device on the PCI bus.
+    pub fn device_id(&self) -> u8 {
+        self.id
+    }
+
+    /// Get the sysfs path of the host device.
+    pub fn sysfs_path(&self) -> &str {
+        &self.sysfs_path
+    }
+
+    /// Get the ID of the PCI bus of the device, if the bus still exists.
+    pub fn bus_id(&self) -> Option<u8> {
+        self.bus.upgrade().map(|bus| bus.bus_id())
+    }
+
+    /// Get the resources needed by the device: the guest addresses of its BARs, a KVM memory
+    /// slot for each mmapped BAR, and the MSI/MSI-X interrupts.
+    pub fn get_resource_requirements(&self, requests: &mut Vec<ResourceConstraint>) {
+        let state = self.inner.state();
+        for bar in state.bars.iter() {
+            match bar.region_type {
+                PciBarRegionType::IoRegion => {
+                    requests.push(ResourceConstraint::pio_with_constraints(
+                        bar.size as u16,
+                        None,
+                        bar.size as u16,
+                    ))
+                }
+                _ => requests.push(ResourceConstraint::mmio_with_constraints(
+                    bar.size, None, bar.size,
+                )),
+            }
+            if bar.mmappable {
+                requests.push(ResourceConstraint::new_kvm_mem_slot(1, None));
+            }
+        }
+        if let Some((_, msi)) = state.msi.as_ref() {
+            requests.push(ResourceConstraint::new_pci_msi_irq(msi.num_vectors()));
+        }
+        if let Some((_, msix)) = state.msix.as_ref() {
+            requests.push(ResourceConstraint::new_pci_msix_irq(msix.num_vectors()));
+        }
+    }
+
+    /// Activate the device with the `resources` allocated for its requirements, the mmappable
+    /// BARs are mapped into the guest.
+    pub fn activate(&self, resources: DeviceResources) -> Result<(), VfioPciError> {
+        let mut state = self.inner.state();
+        if let Err(e) = self.activate_bars(&mut state, &resources) {
+            let _ = self.inner.unmap_bars(&mut state);
+            return Err(e);
+        }
+        state.resources = resources;
+        info!(
+            "VFIO PCI device {} activated at slot {} of bus {:?}",
+            self.sysfs_path,
+            self.id,
+            self.bus_id()
+        );
+
+        Ok(())
+    }
+
+    fn activate_bars(
+        &self,
+        state: &mut VfioPciState,
+        resources: &DeviceResources,
+    ) -> Result<(), VfioPciError> {
+        let mut iter = resources.get_all_resources().iter();
+        for bar in state.bars.iter_mut() {
+            bar.addr = match (bar.region_type, iter.next()) {
+                (PciBarRegionType::IoRegion, Some(Resource::PioAddressRange { base, .. })) => {
+                    u64::from(*base)
+                }
+                (
+                    PciBarRegionType::Memory32BitRegion | PciBarRegionType::Memory64BitRegion,
+                    Some(Resource::MmioAddressRange { base, .. }),
+                ) => *base,
+                _ => return Err(VfioPciError::InvalidResources),
+            };
+            state
+                .config
+                .add_bar(
+                    bar.index,
+                    bar.addr,
+                    bar.size,
+                    bar.region_type,
+                    bar.prefetchable,
+                )
+                .map_err(VfioPciError::PciConfiguration)?;
+            if bar.mmappable {
+                let slot = match iter.next() {
+                    Some(Resource::KvmMemSlot(slot)) => *slot,
+                    _ => return Err(VfioPciError::InvalidResources),
+                };
+                bar.mmap = Some(self.inner.map_bar(bar, slot)?);
+            }
+        }
+        if (state.msi.is_some() && resources.get_pci_msi_irqs().is_none())
+            || (state.msix.is_some() && resources.get_pci_msix_irqs().is_none())
+        {
+            return Err(VfioPciError::InvalidResources);
+        }
+
+        Ok(())
+    }
+
+    /// Clear the device before it's removed: disable its interrupts, unmap its BARs from the
+    /// guest and reset it.
+    pub fn clear_device(&self) -> Result<(), VfioPciError> {
+        let mut state = self.inner.state();
+        self.inner.disable_irq(&mut state);
+        self.inner.unmap_bars(&mut state)?;
+        self.inner.vfio_dev.reset();
+
+        Ok(())
+    }
+}
+
+impl<C: PciSystemContext> DeviceIo for VfioPciDevice<C> {
+    fn read(&self, base: IoAddress, offset: IoAddress, data: &mut [u8]) {
+        self.inner
+            .bar_read(false, base.raw_value(), offset.raw_value(), data);
+    }
+
+    fn write(&self, base: IoAddress, offset: IoAddress, data: &[u8]) {
+        self.inner
+            .bar_write(false, base.raw_value(), offset.raw_value(), data);
+    }
+
+    fn pio_read(&self, base: PioAddress, offset: PioAddress, data: &mut [u8]) {
+        self.inner.bar_read(
+            true,
+            u64::from(base.raw_value()),
+            u64::from(offset.raw_value()),
+            data,
+        );
+    }
+
+    fn pio_write(&self, base: PioAddress, offset: PioAddress, data: &[u8]) {
+        self.inner.bar_write(
+            true,
+            u64::from(base.raw_value()),
+            u64::from(offset.raw_value()),
+            data,
+        );
+    }
+
+    fn get_assigned_resources(&self) -> DeviceResources {
+        self.inner.state().resources.clone()
+    }
+
+    fn get_trapped_io_resources(&self) -> DeviceResources {
+        let state = self.inner.state();
+        let mut resources = DeviceResources::new();
+        for bar in state.bars.iter().filter(|bar| bar.mmap.is_none()) {
+            resources.append(bar.resource(bar.addr));
+        }
+        resources
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<C: PciSystemContext> PciDevice for VfioPciDevice<C> {
+    fn id(&self) -> u8 {
+        self.id
+    }
+
+    fn write_config(&self, offset: u32, data: &[u8]) {
+        self.inner.write_config(offset, data);
+    }
+
+    fn read_config(&self, offset: u32, data: &mut [u8]) {
+        self.inner.read_config(offset, data);
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use dbs_virtio_devices as virtio;
+use dbs_virtio_devices::balloon::{Balloon, BalloonConfig};
+use serde_derive::{Deserialize, Serialize};
+use slog::{error, info};
+
+use crate::address_space_manager::GuestAddressSpaceImpl;
+use crate::config_manager::{ConfigItem, DeviceConfigInfo, DeviceConfigInfos};
+use crate::device_manager::{DbsMmioV2Device, DeviceManager, DeviceMgrError, DeviceOpContext};
+
+// The flag of whether to use the shared irq.
+const USE_SHARED_IRQ: bool = true;
+// The flag of whether to use the generic irq.
+const USE_GENERIC_IRQ: bool = true;
+
+/// Errors associated with `BalloonDeviceConfig`.
+#[derive(Debug, thiserror::Error)]
+pub enum BalloonDeviceError {
+    /// The balloon device was already used.
+    #[error("the virtio-balloon ID was already added to a different device")]
+    BalloonDeviceAlreadyExists,
+
+    /// Only one balloon device is supported by the guest driver.
+    #[error("only one virtio-balloon device is supported")]
+    TooManyBalloonDevices,
+
+    /// No balloon device has been configured.
+    #[error("no virtio-balloon device is configured")]
+    BalloonDeviceNotExist,
+
+    /// Cannot perform the requested operation after booting the microVM.
+    #[error("the update operation is not allowed after boot")]
+    UpdateNotAllowedPostBoot,
+
+    /// The requested memory size is bigger than the memory of the microVM.
+    #[error("the memory size {0} MiB exceeds the memory size {1} MiB of the VM")]
+    InvalidMemorySize(u64, u64),
+
+    /// Cannot create a virtio-balloon device.
+    #[error("failed to create virtio-balloon device: {0}")]
+    CreateBalloonDevice(#[source] virtio::Error),
+
+    /// Cannot initialize a virtio-balloon device or add it to the MMIO bus.
+    #[error("failure while registering virtio-balloon device: {0}")]
+    RegisterBalloonDevice(#[source] DeviceMgrError),
+
+    /// Cannot resize the balloon.
+    #[error("failed to resize virtio-balloon device: {0}")]
+    ResizeBalloon(#[source] virtio::Error),
+
+    /// The device manager errors.
+    #[error("DeviceManager error: {0}")]
+    DeviceManager(#[source] DeviceMgrError),
+}
+
+/// Configuration information for a virtio-balloon device.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct BalloonDeviceConfigInfo {
+    /// Unique identifier of the balloon device.
+    pub balloon_id: String,
+    /// Target size of the balloon in MiB, the memory held by the balloon is returned to the host.
+    pub size_mib: u64,
+    /// Use shared irq
+    pub use_shared_irq: Option<bool>,
+    /// Use generic irq
+    pub use_generic_irq: Option<bool>,
+    /// Let the guest deflate the balloon when it's running out of memory.
+    pub f_deflate_on_oom: bool,
+    /// Let the guest report its free pages, which are returned to the host.
+    pub f_reporting: bool,
+}
+
+impl ConfigItem for BalloonDeviceConfigInfo {
+    type Err = BalloonDeviceError;
+
+    fn id(&self) -> &str {
+        &self.balloon_id
+    }
+
+    fn check_conflicts(&self, other: &Self) -> Result<(), BalloonDeviceError> {
+        if self.balloon_id == other.balloon_id {
+            Err(BalloonDeviceError::BalloonDeviceAlreadyExists)
+        } else {
+            Err(BalloonDeviceError::TooManyBalloonDevices)
+        }
+    }
+}
+
+/// Guest memory size requested for the microVM.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct MemoryResizeInfo {
+    /// Memory size in MiB left to the guest, the remaining memory is held by the balloon.
+    pub mem_size_mib: u64,
+}
+
+/// Balloon Device Info
+pub type BalloonDeviceInfo = DeviceConfigInfo<BalloonDeviceConfigInfo>;
+
+/// Device manager to manage the virtio-balloon device.
+pub struct BalloonDeviceMgr {
+    pub(crate) info_list: DeviceConfigInfos<BalloonDeviceConfigInfo>,
+    pub(crate) use_shared_irq: bool,
+}
+
+impl BalloonDeviceMgr {
+    /// Insert or update the balloon device configuration.
+    ///
+    /// The balloon device can't be hotplugged, it must be inserted before booting the microVM.
+    /// The balloon is resized by `resize_memory()` after boot.
+    pub fn insert_device(
+        device_mgr: &mut DeviceManager,
+        ctx: DeviceOpContext,
+        config: BalloonDeviceConfigInfo,
+    ) -> std::result::Result<(), BalloonDeviceError> {
+        if ctx.is_hotplug {
+            return Err(BalloonDeviceError::UpdateNotAllowedPostBoot);
+        }
+
+        info!(
+            ctx.logger(),
+            "add virtio-balloon device configuration";
+            "subsystem" => "balloon_dev_mgr",
+            "id" => &config.balloon_id,
+            "size_mib" => config.size_mib,
+        );
+        device_mgr
+            .balloon_manager
+            .info_list
+            .insert_or_update(&config)?;
+
+        Ok(())
+    }
+
+    /// Resize the balloon, so that `config.mem_size_mib` of the `vm_mem_size_mib` guest memory is
+    /// left to the guest.
+    ///
+    /// Before boot, only the initial size of the balloon is updated.
+    pub fn resize_memory(
+        device_mgr: &mut DeviceManager,
+        vm_mem_size_mib: u64,
+        config: MemoryResizeInfo,
+    ) -> std::result::Result<(), BalloonDeviceError> {
+        if config.mem_size_mib > vm_mem_size_mib {
+            return Err(BalloonDeviceError::InvalidMemorySize(
+                config.mem_size_mib,
+                vm_mem_size_mib,
+            ));
+        }
+        if device_mgr.balloon_manager.info_list.is_empty() {
+            return Err(BalloonDeviceError::BalloonDeviceNotExist);
+        }
+
+        let size_mib = vm_mem_size_mib - config.mem_size_mib;
+        let logger = &device_mgr.logger;
+        let info = &mut device_mgr.balloon_manager.info_list[0];
+        info!(
+            logger,
+            "resize virtio-balloon device";
+            "subsystem" => "balloon_dev_mgr",
+            "id" => &info.config.balloon_id,
+            "size_mib" => size_mib,
+        );
+
+        if let Some(device) = info.device.as_ref() {
+            if let Some(mmio_dev) = device.as_any().downcast_ref::<DbsMmioV2Device>() {
+                let guard = mmio_dev.state();
+                let inner_dev = guard.get_inner_device();
+                if let Some(balloon_dev) = inner_dev
+                    .as_any()
+                    .downcast_ref::<Balloon<GuestAddressSpaceImpl>>()
+                {
+                    balloon_dev.set_size(size_mib).map_err(|e| {
+                        error!(logger, "failed to resize balloon: {:?}", e;
+                            "subsystem" => "balloon_dev_mgr");
+                        BalloonDeviceError::ResizeBalloon(e)
+                    })?;
+                }
+            }
+        }
+        info.config.size_mib = size_mib;
+
+        Ok(())
+    }
+
+    /// Attach the configured balloon device to the virtual machine instance.
+    pub fn attach_devices(
+        &mut self,
+        ctx: &mut DeviceOpContext,
+    ) -> std::result::Result<(), BalloonDeviceError> {
+        for info in self.info_list.iter_mut() {
+            info!(
+                ctx.logger(),
+                "attach virtio-balloon device";
+                "subsystem" => "balloon_dev_mgr",
+                "id" => &info.config.balloon_id,
+                "size_mib" => info.config.size_mib,
+            );
+
+            let device = Self::create_device(&info.config, ctx)
+                .map_err(BalloonDeviceError::CreateBalloonDevice)?;
+            let device = DeviceManager::create_mmio_virtio_device(
+                device,
+                ctx,
+                info.config.use_shared_irq.unwrap_or(self.use_shared_irq),
+                info.config.use_generic_irq.unwrap_or(USE_GENERIC_IRQ),
+            )
+            .map_err(BalloonDeviceError::RegisterBalloonDevice)?;
+            info.set_device(device);
+        }
+
+        Ok(())
+    }
+
+    fn create_device(
+        cfg: &BalloonDeviceConfigInfo,
+        ctx: &mut DeviceOpContext,
+    ) -> std::result::Result<Box<Balloon<GuestAddressSpaceImpl>>, virtio::Error> {
+        let epoll_mgr = ctx.epoll_mgr.clone().ok_or(virtio::Error::InvalidInput)?;
+        let balloon_config = BalloonConfig {
+            f_deflate_on_oom: cfg.f_deflate_on_oom,
+            f_reporting: cfg.f_reporting,
+        };
+
+        let balloon_device = Balloon::new(epoll_mgr, balloon_config)?;
+        balloon_device.set_size(cfg.size_mib)?;
+
+        Ok(Box::new(balloon_device))
+    }
+}
+
+impl Default for BalloonDeviceMgr {
+    /// Create a new balloon device manager.
+    fn default() -> Self {
+        BalloonDeviceMgr {
+            info_list: DeviceConfigInfos::new(),
+            use_shared_irq: USE_SHARED_IRQ,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balloon_config_conflicts() {
+        let config = BalloonDeviceConfigInfo {
+            balloon_id: "balloon0".to_string(),
+            size_mib: 128,
+            ..Default::default()
+        };
+        let mut other = config.clone();
+        assert!(matches!(
+            config.check_conflicts(&other),
+            Err(BalloonDeviceError::BalloonDeviceAlreadyExists)
+        ));
+
+        other.balloon_id = "balloon1".to_string();
+        assert!(matches!(
+            config.check_conflicts(&other),
+            Err(BalloonDeviceError::TooManyBalloonDevices)
+        ));
+
+        let mut mgr = BalloonDeviceMgr::default();
+        mgr.info_list.insert_or_update(&config).unwrap();
+        let updated = BalloonDeviceConfigInfo {
+            size_mib: 256,
+            ..config.clone()
+        };
+        mgr.info_list.insert_or_update(&updated).unwrap();
+        assert_eq!(mgr.info_list.len(), 1);
+        assert_eq!(mgr.info_list[0].config.size_mib, 256);
+        assert!(mgr.info_list.insert_or_update(&other).is_err());
+    }
+}
@@ -66,6 +66,8 @@ pub use self::legacy::aarch64::{COM1, COM2, RTC};
 pub mod vsock_dev_mgr;
 #[cfg(feature = "virtio-vsock")]
 use self::vsock_dev_mgr::VsockDeviceMgr;
+#[cfg(feature = "vhost-vsock")]
+mod vhost_vsock;
 
 #[cfg(feature = "virtio-blk")]
 /// virtio-block device manager
@@ -84,6 +86,18 @@ use self::virtio_net_dev_mgr::VirtioNetDeviceMgr;
 pub mod fs_dev_mgr;
 #[cfg(feature = "virtio-fs")]
 use self::fs_dev_mgr::FsDeviceMgr;
+#[cfg(feature = "host-device")]
+/// Device manager for host devices passed through with VFIO.
+pub mod vfio_dev_mgr;
+#[cfg(feature = "host-device")]
+use self::vfio_dev_mgr::VfioDeviceMgr;
+
+#[cfg(feature = "virtio-balloon")]
+/// Device manager for virtio-balloon devices.
+pub mod balloon_dev_mgr;
+#[cfg(feature = "virtio-balloon")]
+use self::balloon_dev_mgr::BalloonDeviceMgr;
+
 #[cfg(feature = "virtio-fs")]
 mod memory_region_handler;
 #[cfg(feature = "virtio-fs")]
@@ -139,6 +153,11 @@ pub enum DeviceMgrError {
     /// Failed to free device resource.
     #[error("failed to free device resources: {0}")]
     ResourceError(#[source] crate::resource_manager::ResourceError),
+
+    #[cfg(feature = "host-device")]
+    /// Failed to remove the VFIO devices.
+    #[error("failed to remove vfio devices: {0}")]
+    VfioDevice(#[source] Box<vfio_dev_mgr::VfioDeviceError>),
 }
 
 /// Specialized version of `std::result::Result` for device manager operations.
@@ -149,7 +168,7 @@ pub type Result<T> = ::std::result::Result<T, DeviceMgrError>;
 pub type DbsVirtioDevice = Box<
     dyn VirtioDevice<
         GuestAddressSpaceImpl,
-        virtio_queue::QueueStateSync,
+        virtio_queue::QueueSync,
         vm_memory::GuestRegionMmap,
     >,
 >;
@@ -157,7 +176,7 @@ pub type DbsVirtioDevice = Box<
 /// Type of the dragonball virtio mmio devices.
 #[cfg(feature = "dbs-virtio-devices")]
 pub type DbsMmioV2Device =
-    MmioV2Device<GuestAddressSpaceImpl, virtio_queue::QueueStateSync, vm_memory::GuestRegionMmap>;
+    MmioV2Device<GuestAddressSpaceImpl, virtio_queue::QueueSync, vm_memory::GuestRegionMmap>;
 
 /// Struct to support transactional operations for device management.
 pub struct DeviceManagerTx {
@@ -372,7 +391,7 @@ impl DeviceOpContext {
 
     pub(crate) fn remove_hotplug_mmio_device(
         &self,
-        _dev: &Arc<DbsMmioV2Device>,
+        _dev: &Arc<dyn DeviceIo>,
         _callback: Option<()>,
     ) -> Result<()> {
         Err(DeviceMgrError::InvalidOperation)
@@ -437,7 +456,7 @@ impl DeviceOpContext {
 
     pub(crate) fn remove_hotplug_mmio_device(
         &self,
-        dev: &Arc<DbsMmioV2Device>,
+        dev: &Arc<dyn DeviceIo>,
         callback: Option<Box<dyn Fn(UpcallClientResponse) + Send>>,
     ) -> Result<()> {
         if !self.is_hotplug {
@@ -485,6 +504,12 @@ pub struct DeviceManager {
 
     #[cfg(feature = "virtio-fs")]
     fs_manager: Arc<Mutex<FsDeviceMgr>>,
+
+    #[cfg(feature = "host-device")]
+    pub(crate) vfio_manager: VfioDeviceMgr,
+
+    #[cfg(feature = "virtio-balloon")]
+    pub(crate) balloon_manager: BalloonDeviceMgr,
 }
 
 impl DeviceManager {
@@ -500,7 +525,7 @@ impl DeviceManager {
             io_lock: Arc::new(Mutex::new(())),
             irq_manager: Arc::new(KvmIrqManager::new(vm_fd.clone())),
             res_manager,
-            vm_fd,
+            vm_fd: vm_fd.clone(),
             logger: logger.new(slog::o!()),
 
             con_manager: ConsoleManager::new(epoll_manager, logger),
@@ -515,6 +540,10 @@ impl DeviceManager {
             virtio_net_manager: VirtioNetDeviceMgr::default(),
             #[cfg(feature = "virtio-fs")]
             fs_manager: Arc::new(Mutex::new(FsDeviceMgr::default())),
+            #[cfg(feature = "host-device")]
+            vfio_manager: VfioDeviceMgr::new(vm_fd, logger),
+            #[cfg(feature = "virtio-balloon")]
+            balloon_manager: BalloonDeviceMgr::default(),
         }
     }
 
@@ -676,6 +705,16 @@ impl DeviceManager {
         #[cfg(feature = "virtio-vsock")]
         self.vsock_manager.attach_devices(&mut ctx)?;
 
+        #[cfg(feature = "host-device")]
+        self.vfio_manager
+            .attach_devices(&mut ctx)
+            .map_err(StartMicroVmError::VfioDeviceError)?;
+
+        #[cfg(feature = "virtio-balloon")]
+        self.balloon_manager
+            .attach_devices(&mut ctx)
+            .map_err(StartMicroVmError::BalloonDeviceError)?;
+
         #[cfg(feature = "virtio-blk")]
         self.block_manager
             .generate_kernel_boot_args(kernel_config)
@@ -696,7 +735,6 @@ impl DeviceManager {
 
     /// Start all registered devices when booting the associated virtual machine.
     pub fn start_devices(&mut self) -> std::result::Result<(), StartMicroVmError> {
-        // TODO: add vfio support here. issue #4589.
         Ok(())
     }
 
@@ -718,6 +756,12 @@ impl DeviceManager {
 
         #[cfg(feature = "virtio-blk")]
         self.block_manager.remove_devices(&mut ctx)?;
+        #[cfg(feature = "virtio-net")]
+        self.virtio_net_manager.remove_devices(&mut ctx)?;
+        #[cfg(feature = "host-device")]
+        self.vfio_manager
+            .remove_devices(&mut ctx)
+            .map_err(|e| DeviceMgrError::VfioDevice(Box::new(e)))?;
         Ok(())
     }
 }
@@ -837,7 +881,7 @@ impl DeviceManager {
 
 #[cfg(feature = "dbs-virtio-devices")]
 impl DeviceManager {
-    fn get_virtio_device_info(device: &Arc<DbsMmioV2Device>) -> Result<(u64, u64, u32)> {
+    fn get_virtio_device_info<D: DeviceIo + ?Sized>(device: &Arc<D>) -> Result<(u64, u64, u32)> {
         let resources = device.get_assigned_resources();
         let irq = resources
             .get_legacy_irq()
@@ -1043,6 +1087,10 @@ mod tests {
                 virtio_net_manager: VirtioNetDeviceMgr::default(),
                 #[cfg(feature = "virtio-vsock")]
                 vsock_manager: VsockDeviceMgr::default(),
+                #[cfg(feature = "host-device")]
+                vfio_manager: VfioDeviceMgr::new(vm_fd.clone(), &logger),
+                #[cfg(feature = "virtio-balloon")]
+                balloon_manager: BalloonDeviceMgr::default(),
                 #[cfg(target_arch = "aarch64")]
                 mmio_device_info: HashMap::new(),
 
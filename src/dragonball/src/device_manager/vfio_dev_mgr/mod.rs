@@ -0,0 +1,539 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Device manager for host PCI devices passed through to the guest with VFIO.
+//!
+//! The host devices must have been bound to the `vfio-pci` driver before being added to the
+//! virtual machine. They are plugged into the PCI root bus managed by [`PciSystemManager`],
+//! hot-added devices are discovered by the guest when it rescans the PCI bus.
+
+mod pci_vfio;
+pub use self::pci_vfio::{PciSystemManager, PCI_ROOT_BUS_ID};
+
+use std::path::Path;
+use std::sync::Arc;
+
+use dbs_device::device_manager::IoManagerContext;
+use dbs_device::DeviceIo;
+use dbs_pci::{VfioPciDevice, VfioPciError};
+use kvm_bindings::{kvm_create_device, kvm_device_type_KVM_DEV_TYPE_VFIO};
+use kvm_ioctls::VmFd;
+use serde_derive::{Deserialize, Serialize};
+use vfio_ioctls::{VfioContainer, VfioDevice, VfioError};
+use vm_memory::{Address, GuestAddressSpace, GuestMemory, GuestMemoryRegion};
+
+use crate::address_space_manager::GuestAddressSpaceImpl;
+use crate::config_manager::{ConfigItem, DeviceConfigInfo, DeviceConfigInfos};
+use crate::device_manager::{DeviceManager, DeviceMgrError, DeviceOpContext};
+use crate::resource_manager::ResourceError;
+
+// Sysfs directory of the host PCI devices.
+const SYSFS_PCI_DEVICES: &str = "/sys/bus/pci/devices";
+
+macro_rules! info(
+    ($l:expr, $($args:tt)+) => {
+        slog::info!($l, $($args)+; slog::o!("subsystem" => "vfio_dev_mgr"))
+    };
+);
+
+macro_rules! error(
+    ($l:expr, $($args:tt)+) => {
+        slog::error!($l, $($args)+; slog::o!("subsystem" => "vfio_dev_mgr"))
+    };
+);
+
+/// Errors associated with VFIO device operations.
+#[derive(Debug, thiserror::Error)]
+pub enum VfioDeviceError {
+    /// The device ID doesn't exist.
+    #[error("invalid vfio device id '{0}'")]
+    InvalidDeviceId(String),
+
+    /// The device ID is already in use.
+    #[error("the device ID {0} already exists")]
+    DeviceIDAlreadyExist(String),
+
+    /// The host device is already passed through by another device.
+    #[error("the host device {0} is already in use")]
+    HostDeviceInUse(String),
+
+    /// The guest PCI slot is already used by another device.
+    #[error("the guest PCI slot {0} is already in use")]
+    GuestDeviceIdInUse(u8),
+
+    /// The update is not allowed after booting the microvm.
+    #[error("update operation is not allowed after boot")]
+    UpdateNotAllowedPostBoot,
+
+    /// There is no free slot on the PCI root bus.
+    #[error("no free slot on the PCI root bus")]
+    NoAvailPciSlot,
+
+    /// Failed to create the KVM VFIO device.
+    #[error("failed to create the KVM VFIO device: {0}")]
+    CreateKvmDevice(#[source] kvm_ioctls::Error),
+
+    /// Failure from the VFIO subsystem.
+    #[error("failure in VFIO operations: {0}")]
+    Vfio(#[source] VfioError),
+
+    /// Failed to map the guest memory for DMA.
+    #[error("failed to map guest memory for DMA: {0}")]
+    DmaMap(#[source] VfioError),
+
+    /// Failed to create the VFIO PCI device.
+    #[error("failed to create VFIO PCI device: {0}")]
+    CreateVfioPciDevice(#[source] VfioPciError),
+
+    /// Failure from the PCI subsystem.
+    #[error("failure in PCI bus operations: {0}")]
+    PciBus(#[source] dbs_pci::Error),
+
+    /// Failed to allocate resources for the device.
+    #[error("failed to allocate device resources: {0}")]
+    AllocateDeviceResource(#[source] ResourceError),
+
+    /// Failure from device manager.
+    #[error("device manager errors: {0}")]
+    DeviceManager(#[from] DeviceMgrError),
+}
+
+/// Specialized version of `std::result::Result` for VFIO device operations.
+pub type Result<T> = std::result::Result<T, VfioDeviceError>;
+
+/// Configuration information for a host PCI device passed through with VFIO.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct VfioDeviceConfigInfo {
+    /// Unique identifier of the device.
+    pub hostdev_id: String,
+    /// PCI address of the host device, in the `DDDD:BB:DD.F` format.
+    pub bus_slot_func: String,
+    /// Sysfs path of the host device, derived from `bus_slot_func` if empty.
+    pub sysfs_path: String,
+    /// Vendor and device ID of the host device as `vendor_id << 16 | device_id`, zero to
+    /// expose the ones of the host device.
+    pub vendor_device_id: u32,
+    /// Slot of the device on the guest PCI root bus, allocated if not specified.
+    pub guest_dev_id: Option<u8>,
+}
+
+impl VfioDeviceConfigInfo {
+    /// Get the sysfs path of the host device.
+    pub fn sysfs_path(&self) -> String {
+        if self.sysfs_path.is_empty() {
+            format!("{}/{}", SYSFS_PCI_DEVICES, self.bus_slot_func)
+        } else {
+            self.sysfs_path.clone()
+        }
+    }
+}
+
+impl ConfigItem for VfioDeviceConfigInfo {
+    type Err = VfioDeviceError;
+
+    fn id(&self) -> &str {
+        &self.hostdev_id
+    }
+
+    fn check_conflicts(&self, other: &Self) -> Result<()> {
+        if self.hostdev_id == other.hostdev_id {
+            Err(VfioDeviceError::DeviceIDAlreadyExist(
+                self.hostdev_id.clone(),
+            ))
+        } else if self.sysfs_path() == other.sysfs_path() {
+            Err(VfioDeviceError::HostDeviceInUse(self.sysfs_path()))
+        } else {
+            match self.guest_dev_id {
+                Some(id) if other.guest_dev_id == Some(id) => {
+                    Err(VfioDeviceError::GuestDeviceIdInUse(id))
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+/// VFIO Device Info
+pub type VfioDeviceInfo = DeviceConfigInfo<VfioDeviceConfigInfo>;
+
+/// Type of the VFIO PCI devices.
+pub type DbsVfioPciDevice = VfioPciDevice<PciSystemManager>;
+
+/// Device manager to manage all VFIO devices.
+///
+/// All the devices share the same VFIO container, which maps the whole guest memory for DMA.
+/// The container is created along with the first device and released with the last one.
+pub struct VfioDeviceMgr {
+    vm_fd: Arc<VmFd>,
+    info_list: DeviceConfigInfos<VfioDeviceConfigInfo>,
+    vfio_container: Option<Arc<VfioContainer>>,
+    pci_system_manager: Option<PciSystemManager>,
+    logger: slog::Logger,
+}
+
+impl VfioDeviceMgr {
+    /// Create a new VFIO device manager.
+    pub fn new(vm_fd: Arc<VmFd>, logger: &slog::Logger) -> Self {
+        VfioDeviceMgr {
+            vm_fd,
+            info_list: DeviceConfigInfos::new(),
+            vfio_container: None,
+            pci_system_manager: None,
+            logger: logger.new(slog::o!()),
+        }
+    }
+
+    /// Gets the index of the device with the specified `hostdev_id` if it exists in the list.
+    pub fn get_index_of_hostdev_id(&self, id: &str) -> Option<usize> {
+        self.info_list
+            .iter()
+            .position(|info| info.config.hostdev_id.eq(id))
+    }
+
+    /// Insert or update a VFIO device into the manager.
+    ///
+    /// The device is passed through immediately if the virtual machine is running, the guest
+    /// has to rescan its PCI bus to discover it.
+    pub fn insert_device(
+        device_mgr: &mut DeviceManager,
+        mut ctx: DeviceOpContext,
+        config: VfioDeviceConfigInfo,
+    ) -> Result<()> {
+        if !cfg!(feature = "hotplug") && ctx.is_hotplug {
+            return Err(VfioDeviceError::UpdateNotAllowedPostBoot);
+        }
+
+        let mgr = &mut device_mgr.vfio_manager;
+        info!(
+            ctx.logger(),
+            "add vfio device configuration, id {}, host device {}",
+            &config.hostdev_id,
+            &config.bus_slot_func
+        );
+
+        if ctx.is_hotplug && mgr.get_index_of_hostdev_id(&config.hostdev_id).is_some() {
+            return Err(VfioDeviceError::DeviceIDAlreadyExist(config.hostdev_id));
+        }
+        let index = mgr.info_list.insert_or_update(&config)?;
+
+        if ctx.is_hotplug {
+            match mgr.create_device(&config, &mut ctx) {
+                Ok(device) => mgr.info_list[index].set_device(device),
+                Err(e) => {
+                    error!(
+                        ctx.logger(),
+                        "failed to hot-add vfio device {}, {:?}", &config.hostdev_id, e
+                    );
+                    mgr.info_list.remove(index);
+                    mgr.release_vfio_container(&ctx);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a VFIO device from the manager.
+    ///
+    /// The guest isn't notified about the removal, so the device should not be used by the
+    /// guest anymore when removed.
+    pub fn remove_device(
+        device_mgr: &mut DeviceManager,
+        mut ctx: DeviceOpContext,
+        hostdev_id: &str,
+    ) -> Result<()> {
+        if !cfg!(feature = "hotplug") {
+            return Err(VfioDeviceError::UpdateNotAllowedPostBoot);
+        }
+
+        let mgr = &mut device_mgr.vfio_manager;
+        let index = mgr
+            .get_index_of_hostdev_id(hostdev_id)
+            .ok_or_else(|| VfioDeviceError::InvalidDeviceId(hostdev_id.to_owned()))?;
+        if let Some(mut info) = mgr.info_list.remove(index) {
+            info!(
+                ctx.logger(),
+                "remove vfio device {}", &info.config.hostdev_id
+            );
+            if let Some(device) = info.device.take() {
+                mgr.destroy_device(device, &mut ctx)?;
+            }
+        }
+        mgr.release_vfio_container(&ctx);
+
+        Ok(())
+    }
+
+    /// Create the PCI root bus and attach all configured VFIO devices to the virtual machine
+    /// instance.
+    pub fn attach_devices(&mut self, ctx: &mut DeviceOpContext) -> Result<()> {
+        // The PCI root bus is always created so that the guest kernel probes it at boot,
+        // otherwise the hot-added devices can't be discovered.
+        let pci_system_manager =
+            PciSystemManager::new(ctx.irq_manager.clone(), ctx.io_context.clone())?;
+        pci_system_manager.register_root_device(ctx)?;
+        self.pci_system_manager = Some(pci_system_manager);
+
+        for index in 0..self.info_list.len() {
+            let config = self.info_list[index].config.clone();
+            info!(
+                ctx.logger(),
+                "attach vfio device, id {}, host device {}",
+                &config.hostdev_id,
+                &config.bus_slot_func
+            );
+            let device = self.create_device(&config, ctx)?;
+            self.info_list[index].set_device(device);
+        }
+
+        Ok(())
+    }
+
+    /// Remove all VFIO devices when shutting down the virtual machine instance.
+    pub fn remove_devices(&mut self, ctx: &mut DeviceOpContext) -> Result<()> {
+        while !self.info_list.is_empty() {
+            if let Some(mut info) = self.info_list.remove(self.info_list.len() - 1) {
+                info!(
+                    ctx.logger(),
+                    "remove vfio device {}", &info.config.hostdev_id
+                );
+                if let Some(device) = info.device.take() {
+                    self.destroy_device(device, ctx)?;
+                }
+            }
+        }
+        self.release_vfio_container(ctx);
+
+        Ok(())
+    }
+
+    fn create_device(
+        &mut self,
+        config: &VfioDeviceConfigInfo,
+        ctx: &mut DeviceOpContext,
+    ) -> Result<Arc<dyn DeviceIo>> {
+        let pci_system_manager =
+            self.pci_system_manager
+                .clone()
+                .ok_or(VfioDeviceError::DeviceManager(
+                    DeviceMgrError::InvalidOperation,
+                ))?;
+        let vfio_container = self.get_vfio_container(ctx)?;
+
+        let sysfs_path = config.sysfs_path();
+        let vfio_device = VfioDevice::new(Path::new(&sysfs_path), vfio_container.clone())
+            .map_err(VfioDeviceError::Vfio)?;
+
+        let bus = pci_system_manager.pci_root_bus();
+        let dev_id = bus
+            .allocate_device_id(config.guest_dev_id)
+            .ok_or(VfioDeviceError::NoAvailPciSlot)?;
+        let device = match VfioPciDevice::create(
+            dev_id,
+            sysfs_path,
+            Arc::downgrade(&bus),
+            vfio_device,
+            config.vendor_device_id,
+            self.vm_fd.clone(),
+            pci_system_manager,
+            vfio_container,
+        ) {
+            Ok(device) => Arc::new(device),
+            Err(e) => {
+                bus.free_device_id(dev_id);
+                return Err(VfioDeviceError::CreateVfioPciDevice(e));
+            }
+        };
+
+        // The BARs and the MSI/MSI-X interrupts of the device.
+        let mut requests = Vec::new();
+        device.get_resource_requirements(&mut requests);
+        let resources = match ctx.res_manager.allocate_device_resources(&requests, false) {
+            Ok(resources) => resources,
+            Err(e) => {
+                bus.free_device_id(dev_id);
+                return Err(VfioDeviceError::AllocateDeviceResource(e));
+            }
+        };
+        if let Err(e) = device
+            .activate(resources.clone())
+            .map_err(VfioDeviceError::CreateVfioPciDevice)
+            .and_then(|_| {
+                bus.register_device(device.clone())
+                    .map_err(VfioDeviceError::PciBus)
+            })
+            .and_then(|_| Self::register_device_io(device.clone(), ctx))
+        {
+            bus.free_device_id(dev_id);
+            let _ = ctx.res_manager.free_device_resources(&resources);
+            return Err(e);
+        }
+
+        Ok(device)
+    }
+
+    fn destroy_device(
+        &mut self,
+        device: Arc<dyn DeviceIo>,
+        ctx: &mut DeviceOpContext,
+    ) -> Result<()> {
+        let resources = device.get_trapped_io_resources();
+        let mut tx = ctx.io_context.begin_tx();
+        if let Err(e) = ctx.io_context.unregister_device_io(&mut tx, &resources) {
+            ctx.io_context.cancel_tx(tx);
+            return Err(VfioDeviceError::DeviceManager(DeviceMgrError::IoManager(e)));
+        }
+        ctx.io_context.commit_tx(tx);
+
+        let vfio_pci_device = device.as_any().downcast_ref::<DbsVfioPciDevice>().ok_or(
+            VfioDeviceError::DeviceManager(DeviceMgrError::InvalidOperation),
+        )?;
+        if let Some(pci_system_manager) = self.pci_system_manager.as_ref() {
+            pci_system_manager
+                .pci_root_bus()
+                .free_device_id(vfio_pci_device.device_id());
+        }
+        vfio_pci_device
+            .clear_device()
+            .map_err(VfioDeviceError::CreateVfioPciDevice)?;
+
+        ctx.res_manager
+            .free_device_resources(&device.get_assigned_resources())
+            .map_err(|e| VfioDeviceError::DeviceManager(DeviceMgrError::ResourceError(e)))
+    }
+
+    fn register_device_io(device: Arc<dyn DeviceIo>, ctx: &mut DeviceOpContext) -> Result<()> {
+        let resources = device.get_trapped_io_resources();
+        let mut tx = ctx.io_context.begin_tx();
+        if let Err(e) = ctx
+            .io_context
+            .register_device_io(&mut tx, device, &resources)
+        {
+            ctx.io_context.cancel_tx(tx);
+            Err(VfioDeviceError::DeviceManager(DeviceMgrError::IoManager(e)))
+        } else {
+            ctx.io_context.commit_tx(tx);
+            Ok(())
+        }
+    }
+
+    // Get the VFIO container shared by all devices, the guest memory is mapped for DMA when
+    // the container is created.
+    fn get_vfio_container(&mut self, ctx: &DeviceOpContext) -> Result<Arc<VfioContainer>> {
+        if let Some(container) = self.vfio_container.as_ref() {
+            return Ok(container.clone());
+        }
+
+        let mut vfio_dev = kvm_create_device {
+            type_: kvm_device_type_KVM_DEV_TYPE_VFIO,
+            fd: 0,
+            flags: 0,
+        };
+        let kvm_device = self
+            .vm_fd
+            .create_device(&mut vfio_dev)
+            .map_err(VfioDeviceError::CreateKvmDevice)?;
+        let container =
+            Arc::new(VfioContainer::new(Arc::new(kvm_device)).map_err(VfioDeviceError::Vfio)?);
+
+        let vm_as = ctx.get_vm_as()?;
+        Self::map_guest_memory(&container, &vm_as)?;
+        self.vfio_container = Some(container.clone());
+
+        Ok(container)
+    }
+
+    // Release the VFIO container once the last device is removed.
+    fn release_vfio_container(&mut self, ctx: &DeviceOpContext) {
+        if !self.info_list.is_empty() {
+            return;
+        }
+
+        if let Some(container) = self.vfio_container.take() {
+            if let Ok(vm_as) = ctx.get_vm_as() {
+                let mem = vm_as.memory();
+                for region in mem.iter() {
+                    if let Err(e) =
+                        container.vfio_dma_unmap(region.start_addr().raw_value(), region.len())
+                    {
+                        error!(self.logger, "failed to unmap guest memory for DMA, {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn map_guest_memory(container: &VfioContainer, vm_as: &GuestAddressSpaceImpl) -> Result<()> {
+        let mem = vm_as.memory();
+        for region in mem.iter() {
+            container
+                .vfio_dma_map(
+                    region.start_addr().raw_value(),
+                    region.len(),
+                    region.as_ptr() as u64,
+                )
+                .map_err(VfioDeviceError::DmaMap)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vfio_config_sysfs_path() {
+        let mut config = VfioDeviceConfigInfo {
+            hostdev_id: "vfio0".to_string(),
+            bus_slot_func: "0000:01:00.0".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.sysfs_path(), "/sys/bus/pci/devices/0000:01:00.0");
+
+        config.sysfs_path = "/sys/devices/pci0000:00/0000:00:02.0/0000:01:00.0".to_string();
+        assert_eq!(
+            config.sysfs_path(),
+            "/sys/devices/pci0000:00/0000:00:02.0/0000:01:00.0"
+        );
+    }
+
+    #[test]
+    fn test_vfio_config_conflicts() {
+        let config = VfioDeviceConfigInfo {
+            hostdev_id: "vfio0".to_string(),
+            bus_slot_func: "0000:01:00.0".to_string(),
+            guest_dev_id: Some(3),
+            ..Default::default()
+        };
+
+        let mut other = config.clone();
+        assert!(matches!(
+            config.check_conflicts(&other),
+            Err(VfioDeviceError::DeviceIDAlreadyExist(_))
+        ));
+
+        other.hostdev_id = "vfio1".to_string();
+        assert!(matches!(
+            config.check_conflicts(&other),
+            Err(VfioDeviceError::HostDeviceInUse(_))
+        ));
+
+        other.bus_slot_func = "0000:02:00.0".to_string();
+        assert!(matches!(
+            config.check_conflicts(&other),
+            Err(VfioDeviceError::GuestDeviceIdInUse(3))
+        ));
+
+        other.guest_dev_id = None;
+        assert!(config.check_conflicts(&other).is_ok());
+
+        let mut infos = DeviceConfigInfos::new();
+        assert_eq!(infos.insert_or_update(&config).unwrap(), 0);
+        assert_eq!(infos.insert_or_update(&other).unwrap(), 1);
+        other.bus_slot_func = config.bus_slot_func.clone();
+        assert!(infos.insert_or_update(&other).is_err());
+    }
+}
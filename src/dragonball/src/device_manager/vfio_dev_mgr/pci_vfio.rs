@@ -0,0 +1,82 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The PCI root bus on which the host devices are passed through to the guest.
+//!
+//! Dragonball has no PCI bus for the emulated devices, which are all virtio-mmio ones, so a
+//! PCI root bus is only created for the VFIO devices. The guest kernel probes the PCI root
+//! bus at boot through the PCI configuration io ports, which are trapped by the PCI root
+//! device.
+
+use std::sync::Arc;
+
+use dbs_device::device_manager::IoManagerContext;
+use dbs_device::DeviceIo;
+use dbs_interrupt::KvmIrqManager;
+use dbs_pci::{create_pci_root_bus, PciBus, PciRootDevice, PciSystemContext};
+
+use super::{Result, VfioDeviceError};
+use crate::device_manager::{DeviceManagerContext, DeviceMgrError, DeviceOpContext};
+
+/// Bus ID of the PCI root bus.
+pub const PCI_ROOT_BUS_ID: u8 = 0;
+
+/// Manager of the PCI root bus, which hosts the VFIO PCI devices.
+#[derive(Clone)]
+pub struct PciSystemManager {
+    irq_manager: Arc<KvmIrqManager>,
+    io_context: DeviceManagerContext,
+    pci_root: Arc<PciRootDevice>,
+    pci_root_bus: Arc<PciBus>,
+}
+
+impl PciSystemManager {
+    /// Create the PCI root bus and its root device.
+    pub fn new(irq_manager: Arc<KvmIrqManager>, io_context: DeviceManagerContext) -> Result<Self> {
+        let pci_root_bus = create_pci_root_bus(PCI_ROOT_BUS_ID).map_err(VfioDeviceError::PciBus)?;
+        let pci_root = PciRootDevice::create(PCI_ROOT_BUS_ID, pci_root_bus.clone())
+            .map_err(VfioDeviceError::PciBus)?;
+
+        Ok(PciSystemManager {
+            irq_manager,
+            io_context,
+            pci_root: Arc::new(pci_root),
+            pci_root_bus,
+        })
+    }
+
+    /// Register the PCI configuration io ports handled by the PCI root device.
+    pub fn register_root_device(&self, ctx: &mut DeviceOpContext) -> Result<()> {
+        let device = self.pci_root.clone() as Arc<dyn DeviceIo>;
+        let resources = device.get_trapped_io_resources();
+
+        let mut tx = ctx.io_context.begin_tx();
+        if let Err(e) = ctx
+            .io_context
+            .register_device_io(&mut tx, device, &resources)
+        {
+            ctx.io_context.cancel_tx(tx);
+            Err(VfioDeviceError::DeviceManager(DeviceMgrError::IoManager(e)))
+        } else {
+            ctx.io_context.commit_tx(tx);
+            Ok(())
+        }
+    }
+
+    /// Get the PCI root bus.
+    pub fn pci_root_bus(&self) -> Arc<PciBus> {
+        self.pci_root_bus.clone()
+    }
+}
+
+impl PciSystemContext for PciSystemManager {
+    type D = DeviceManagerContext;
+
+    fn get_device_manager_context(&self) -> Self::D {
+        self.io_context.clone()
+    }
+
+    fn get_interrupt_manager(&self) -> &KvmIrqManager {
+        &self.irq_manager
+    }
+}
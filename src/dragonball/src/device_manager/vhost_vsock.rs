@@ -0,0 +1,496 @@
+// Copyright (C) 2022 Alibaba Cloud. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Virtio vsock device backed by the vhost-vsock driver of the host kernel.
+//!
+//! The rx and tx queues are handled by the host kernel, so that the guest is reachable from the
+//! host with `AF_VSOCK` sockets on its context identifier. The event queue is left unused, the
+//! transport reset event is only needed when the device is migrated.
+
+use std::any::Any;
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::Arc;
+
+use dbs_device::resources::ResourceConstraint;
+use dbs_utils::epoll_manager::{
+    EpollManager, EventOps, EventSet, Events, MutEventSubscriber, SubscriberId,
+};
+use dbs_virtio_devices::{
+    ActivateError, ActivateResult, Error as VirtioError, Result as VirtioResult, VirtioDevice,
+    VirtioDeviceConfig, VirtioQueueConfig,
+};
+use log::{error, warn};
+use vhost::vhost_kern::VhostKernBackend;
+use vhost::{VhostBackend, VhostUserMemoryRegionInfo, VringConfigData};
+use virtio_queue::{QueueSync, QueueT};
+use vm_memory::{Address, GuestAddressSpace, GuestMemory, GuestMemoryRegion, GuestRegionMmap};
+use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::ioctl::ioctl_with_ref;
+use vmm_sys_util::{ioctl_ioc_nr, ioctl_iow_nr};
+
+use crate::address_space_manager::GuestAddressSpaceImpl;
+
+const VIRTIO_ID_VSOCK: u32 = 19;
+const VIRTIO_F_VERSION_1: u32 = 32;
+// Only the rx and tx queues are handled by the vhost-vsock driver.
+const VHOST_VSOCK_NUM_QUEUES: usize = 2;
+
+// From <linux/vhost.h>
+const VHOST_VIRTIO: u32 = 0xAF;
+ioctl_iow_nr!(VHOST_SET_VRING_KICK, VHOST_VIRTIO, 0x20, VhostVringFile);
+ioctl_iow_nr!(VHOST_SET_VRING_CALL, VHOST_VIRTIO, 0x21, VhostVringFile);
+ioctl_iow_nr!(VHOST_VSOCK_SET_GUEST_CID, VHOST_VIRTIO, 0x60, u64);
+ioctl_iow_nr!(VHOST_VSOCK_SET_RUNNING, VHOST_VIRTIO, 0x61, c_int);
+
+// struct vhost_vring_file of <linux/vhost_types.h>
+#[repr(C)]
+struct VhostVringFile {
+    index: u32,
+    fd: c_int,
+}
+
+// Backend to drive the vhost-vsock instance through the generic vhost ioctls.
+struct VhostVsockBackend {
+    file: File,
+    mem: GuestAddressSpaceImpl,
+}
+
+impl AsRawFd for VhostVsockBackend {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl VhostKernBackend for VhostVsockBackend {
+    type AS = GuestAddressSpaceImpl;
+
+    fn mem(&self) -> &Self::AS {
+        &self.mem
+    }
+}
+
+impl VhostVsockBackend {
+    // The eventfds are passed by the ioctls directly, the vhost crate is built against another
+    // version of vmm-sys-util whose `EventFd` can't be shared with the virtio queues.
+    fn set_vring_file(&self, request: c_ulong, index: usize, fd: &EventFd) -> io::Result<()> {
+        let file = VhostVringFile {
+            index: index as u32,
+            fd: fd.as_raw_fd(),
+        };
+        // SAFETY: the ioctl only reads the struct passed by reference.
+        let ret = unsafe { ioctl_with_ref(&self.file, request, &file) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn set_running(&self, running: bool) -> io::Result<()> {
+        let on: c_int = if running { 1 } else { 0 };
+        // SAFETY: the ioctl only reads the integer passed by reference.
+        let ret = unsafe { ioctl_with_ref(&self.file, VHOST_VSOCK_SET_RUNNING(), &on) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Virtio vsock device whose data path is handled by the host vhost-vsock driver.
+pub struct VhostVsock {
+    file: File,
+    guest_cid: u64,
+    queue_sizes: Arc<Vec<u16>>,
+    avail_features: u64,
+    acked_features: u64,
+    epoll_mgr: EpollManager,
+    backend: Option<VhostVsockBackend>,
+    subscriber_id: Option<SubscriberId>,
+}
+
+impl VhostVsock {
+    /// Create a vhost vsock device from the opened `/dev/vhost-vsock` file `vhost_fd`.
+    ///
+    /// `vhost_fd` is duplicated, the caller keeps its ownership. The guest CID is set again on
+    /// the vhost-vsock instance, so that it fails if the CID is used by another instance.
+    pub fn new(
+        vhost_fd: RawFd,
+        guest_cid: u64,
+        queue_sizes: Arc<Vec<u16>>,
+        epoll_mgr: EpollManager,
+    ) -> VirtioResult<Self> {
+        let fd = nix::unistd::dup(vhost_fd).map_err(|e| VirtioError::IOError(e.into()))?;
+        // SAFETY: the fd has just been duplicated and is owned by nobody else.
+        let file = unsafe { File::from_raw_fd(fd) };
+
+        // SAFETY: the ioctl only reads the CID passed by reference.
+        let ret = unsafe { ioctl_with_ref(&file, VHOST_VSOCK_SET_GUEST_CID(), &guest_cid) };
+        if ret < 0 {
+            return Err(VirtioError::IOError(io::Error::last_os_error()));
+        }
+
+        Ok(VhostVsock {
+            file,
+            guest_cid,
+            queue_sizes,
+            avail_features: 1u64 << VIRTIO_F_VERSION_1,
+            acked_features: 0,
+            epoll_mgr,
+            backend: None,
+            subscriber_id: None,
+        })
+    }
+
+    fn setup_vhost(
+        &self,
+        backend: &VhostVsockBackend,
+        queues: &mut [VirtioQueueConfig<QueueSync>],
+        call_fds: &[EventFd],
+    ) -> vhost::Result<()> {
+        backend.set_owner()?;
+        backend.set_features(self.acked_features)?;
+
+        let mem = backend.mem.memory();
+        let regions: Vec<VhostUserMemoryRegionInfo> = mem
+            .iter()
+            .map(|region| VhostUserMemoryRegionInfo {
+                guest_phys_addr: region.start_addr().raw_value(),
+                memory_size: region.len(),
+                userspace_addr: region.as_ptr() as u64,
+                mmap_offset: 0,
+                mmap_handle: -1,
+            })
+            .collect();
+        backend.set_mem_table(&regions)?;
+
+        for (index, queue) in queues.iter_mut().enumerate() {
+            let eventfd = queue.eventfd.clone();
+            let config_data = VringConfigData {
+                queue_max_size: queue.queue.max_size(),
+                queue_size: queue.queue.size(),
+                flags: 0,
+                desc_table_addr: queue.queue.desc_table(),
+                used_ring_addr: queue.queue.used_ring(),
+                avail_ring_addr: queue.queue.avail_ring(),
+                log_addr: None,
+            };
+            backend.set_vring_num(index, queue.queue.size())?;
+            backend.set_vring_addr(index, &config_data)?;
+            backend.set_vring_base(index, 0)?;
+            backend
+                .set_vring_file(VHOST_SET_VRING_CALL(), index, &call_fds[index])
+                .map_err(vhost::Error::IOError)?;
+            backend
+                .set_vring_file(VHOST_SET_VRING_KICK(), index, &eventfd)
+                .map_err(vhost::Error::IOError)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl VirtioDevice<GuestAddressSpaceImpl, QueueSync, GuestRegionMmap> for VhostVsock {
+    fn device_type(&self) -> u32 {
+        VIRTIO_ID_VSOCK
+    }
+
+    fn queue_max_sizes(&self) -> &[u16] {
+        &self.queue_sizes
+    }
+
+    fn get_avail_features(&self, page: u32) -> u32 {
+        match page {
+            0 => self.avail_features as u32,
+            1 => (self.avail_features >> 32) as u32,
+            _ => 0,
+        }
+    }
+
+    fn set_acked_features(&mut self, page: u32, value: u32) {
+        let mut features = match page {
+            0 => value as u64,
+            1 => (value as u64) << 32,
+            _ => {
+                warn!("vhost-vsock: cannot ack unknown feature page {}", page);
+                0
+            }
+        };
+
+        let unrequested = features & !self.avail_features;
+        if unrequested != 0 {
+            warn!("vhost-vsock: ack unknown features {:x}", unrequested);
+            features &= !unrequested;
+        }
+        self.acked_features |= features;
+    }
+
+    fn read_config(&mut self, offset: u64, data: &mut [u8]) {
+        // The configuration space only holds the guest CID.
+        let config_space = self.guest_cid.to_le_bytes();
+        let offset = offset as usize;
+        if offset >= config_space.len() {
+            warn!("vhost-vsock: read config out of range, offset {}", offset);
+            return;
+        }
+        let end = cmp::min(offset + data.len(), config_space.len());
+        data[..end - offset].copy_from_slice(&config_space[offset..end]);
+    }
+
+    fn write_config(&mut self, offset: u64, _data: &[u8]) {
+        warn!(
+            "vhost-vsock: guest writes read-only config, offset {}",
+            offset
+        );
+    }
+
+    fn activate(
+        &mut self,
+        mut config: VirtioDeviceConfig<GuestAddressSpaceImpl, QueueSync, GuestRegionMmap>,
+    ) -> ActivateResult {
+        if config.queues.len() < VHOST_VSOCK_NUM_QUEUES {
+            error!(
+                "vhost-vsock: expect at least {} queues, got {}",
+                VHOST_VSOCK_NUM_QUEUES,
+                config.queues.len()
+            );
+            return Err(ActivateError::InvalidParam);
+        }
+        config.queues.truncate(VHOST_VSOCK_NUM_QUEUES);
+
+        let file = self.file.try_clone().map_err(|e| {
+            error!("vhost-vsock: failed to clone vhost file, {:?}", e);
+            ActivateError::InternalError
+        })?;
+        let backend = VhostVsockBackend {
+            file,
+            mem: config.vm_as.clone(),
+        };
+
+        // The interrupts are injected by the epoll handler, so that the interrupt status of
+        // the mmio transport is updated.
+        let mut call_fds = Vec::with_capacity(VHOST_VSOCK_NUM_QUEUES);
+        for _ in 0..VHOST_VSOCK_NUM_QUEUES {
+            call_fds.push(EventFd::new(libc::EFD_NONBLOCK).map_err(|e| {
+                error!("vhost-vsock: failed to create call eventfd, {:?}", e);
+                ActivateError::InternalError
+            })?);
+        }
+
+        self.setup_vhost(&backend, &mut config.queues, &call_fds)
+            .map_err(|e| {
+                error!("vhost-vsock: failed to set up vhost, {:?}", e);
+                ActivateError::InternalError
+            })?;
+        backend.set_running(true).map_err(|e| {
+            error!("vhost-vsock: failed to start vhost, {:?}", e);
+            ActivateError::InternalError
+        })?;
+
+        let handler = VhostVsockCallHandler {
+            queues: config.queues,
+            call_fds,
+        };
+        self.subscriber_id = Some(self.epoll_mgr.add_subscriber(Box::new(handler)));
+        self.backend = Some(backend);
+
+        Ok(())
+    }
+
+    fn remove(&mut self) {
+        if let Some(backend) = self.backend.take() {
+            if let Err(e) = backend.set_running(false) {
+                warn!("vhost-vsock: failed to stop vhost, {:?}", e);
+            }
+        }
+        if let Some(subscriber_id) = self.subscriber_id.take() {
+            if let Err(e) = self.epoll_mgr.remove_subscriber(subscriber_id) {
+                warn!("vhost-vsock: failed to remove epoll handler, {:?}", e);
+            }
+        }
+    }
+
+    fn get_resource_requirements(
+        &self,
+        requests: &mut Vec<ResourceConstraint>,
+        use_generic_irq: bool,
+    ) {
+        requests.push(ResourceConstraint::LegacyIrq { irq: None });
+        if use_generic_irq {
+            requests.push(ResourceConstraint::GenericIrq {
+                size: (self.queue_sizes.len() + 1) as u32,
+            });
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Forward the used buffer notifications of the vhost-vsock driver to the guest.
+struct VhostVsockCallHandler {
+    queues: Vec<VirtioQueueConfig<QueueSync>>,
+    call_fds: Vec<EventFd>,
+}
+
+impl MutEventSubscriber for VhostVsockCallHandler {
+    fn process(&mut self, events: Events, _ops: &mut EventOps) {
+        let index = events.data() as usize;
+        let call_fd = match self.call_fds.get(index) {
+            Some(fd) => fd,
+            None => {
+                error!("vhost-vsock: unknown epoll slot number {}", index);
+                return;
+            }
+        };
+
+        if let Err(e) = call_fd.read() {
+            error!(
+                "vhost-vsock: failed to read call eventfd {}, {:?}",
+                index, e
+            );
+            return;
+        }
+        if let Err(e) = self.queues[index].notify() {
+            error!("vhost-vsock: failed to notify queue {}, {:?}", index, e);
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        for (index, call_fd) in self.call_fds.iter().enumerate() {
+            let events = Events::with_data(call_fd, index as u32, EventSet::IN);
+            if let Err(e) = ops.add(events) {
+                error!(
+                    "vhost-vsock: failed to register call eventfd {}, {:?}",
+                    index, e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use test_utils::skip_if_not_root;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const VHOST_VSOCK_DEVICE: &str = "/dev/vhost-vsock";
+
+    fn create_test_device(guest_cid: u64) -> VhostVsock {
+        VhostVsock {
+            file: TempFile::new().unwrap().into_file(),
+            guest_cid,
+            queue_sizes: Arc::new(vec![256; 3]),
+            avail_features: 1u64 << VIRTIO_F_VERSION_1,
+            acked_features: 0,
+            epoll_mgr: EpollManager::default(),
+            backend: None,
+            subscriber_id: None,
+        }
+    }
+
+    #[test]
+    fn test_vhost_vsock_new_invalid_fd() {
+        // a regular file doesn't accept the vhost-vsock ioctls
+        let file = TempFile::new().unwrap();
+        assert!(VhostVsock::new(
+            file.as_file().as_raw_fd(),
+            3,
+            Arc::new(vec![256; 3]),
+            EpollManager::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_vhost_vsock_new() {
+        skip_if_not_root!();
+        if !Path::new(VHOST_VSOCK_DEVICE).exists() {
+            return;
+        }
+
+        let open = || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(VHOST_VSOCK_DEVICE)
+                .unwrap()
+        };
+        let file = open();
+        let guest_cid = 0xdead_beef;
+        let queue_sizes = Arc::new(vec![256; 3]);
+        let device = VhostVsock::new(
+            file.as_raw_fd(),
+            guest_cid,
+            queue_sizes.clone(),
+            EpollManager::default(),
+        )
+        .unwrap();
+        assert_eq!(device.guest_cid, guest_cid);
+        assert_eq!(device.device_type(), VIRTIO_ID_VSOCK);
+        assert_eq!(device.queue_max_sizes(), &[256, 256, 256]);
+
+        // the CID is held by the first vhost-vsock instance
+        let other = open();
+        assert!(VhostVsock::new(
+            other.as_raw_fd(),
+            guest_cid,
+            queue_sizes,
+            EpollManager::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_vhost_vsock_features() {
+        let mut device = create_test_device(3);
+        assert_eq!(device.get_avail_features(0), 0);
+        assert_eq!(device.get_avail_features(1), 1);
+        assert_eq!(device.get_avail_features(2), 0);
+
+        // unknown features and pages are not acked
+        device.set_acked_features(0, 0xff);
+        device.set_acked_features(2, 0xff);
+        assert_eq!(device.acked_features, 0);
+
+        device.set_acked_features(1, 1);
+        assert_eq!(device.acked_features, 1u64 << VIRTIO_F_VERSION_1);
+    }
+
+    #[test]
+    fn test_vhost_vsock_config() {
+        let guest_cid: u64 = 0x0102_0304_0506_0708;
+        let mut device = create_test_device(guest_cid);
+
+        let mut data = [0u8; 8];
+        device.read_config(0, &mut data);
+        assert_eq!(data, guest_cid.to_le_bytes());
+
+        // a read crossing the end of the config space is truncated
+        let mut data = [0u8; 8];
+        device.read_config(4, &mut data);
+        assert_eq!(data, [0x04, 0x03, 0x02, 0x01, 0, 0, 0, 0]);
+
+        let mut data = [0xffu8; 4];
+        device.read_config(8, &mut data);
+        assert_eq!(data, [0xff; 4]);
+
+        // the config space is read-only
+        device.write_config(0, &[0u8; 8]);
+        let mut data = [0u8; 8];
+        device.read_config(0, &mut data);
+        assert_eq!(data, guest_cid.to_le_bytes());
+    }
+}
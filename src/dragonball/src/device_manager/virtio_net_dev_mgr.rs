@@ -7,8 +7,13 @@
 // found in the THIRD-PARTY file.
 
 use std::convert::TryInto;
+#[cfg(all(feature = "hotplug", feature = "dbs-upcall"))]
+use std::sync::mpsc::{channel, Receiver};
 use std::sync::Arc;
 
+#[cfg(all(feature = "hotplug", feature = "dbs-upcall"))]
+use dbs_upcall::{DevMgrResponse, UpcallClientResponse};
+
 use dbs_utils::net::{MacAddr, Tap, TapError};
 use dbs_utils::rate_limiter::BucketUpdate;
 use dbs_virtio_devices as virtio;
@@ -279,6 +284,102 @@ impl VirtioNetDeviceMgr {
         Ok(())
     }
 
+    /// Notify the guest through the upcall channel to detach a virtio net device after boot.
+    ///
+    /// The result of the guest is sent to the returned receiver, or `None` if the upcall channel
+    /// is reset before the guest replies. The device should only be removed by `remove_device`
+    /// once the guest has detached it.
+    #[cfg(all(feature = "hotplug", feature = "dbs-upcall"))]
+    pub fn prepare_remove_device(
+        device_mgr: &DeviceManager,
+        ctx: &DeviceOpContext,
+        iface_id: &str,
+    ) -> std::result::Result<Receiver<Option<i32>>, VirtioNetDeviceError> {
+        let mgr = &device_mgr.virtio_net_manager;
+        let device = mgr
+            .get_index_of_iface_id(iface_id)
+            .and_then(|index| mgr.info_list[index].device.clone())
+            .ok_or_else(|| VirtioNetDeviceError::InvalidIfaceId(iface_id.to_owned()))?;
+
+        slog::info!(
+            ctx.logger(),
+            "detach virtio-net device from guest";
+            "subsystem" => "net_dev_mgr",
+            "id" => iface_id,
+        );
+
+        let (sender, receiver) = channel();
+        let callback = Box::new(move |response| {
+            let result = match response {
+                UpcallClientResponse::DevMgr(DevMgrResponse::Other(resp)) => Some(resp.result),
+                _ => None,
+            };
+            // The receiver may have given up waiting for the guest.
+            let _ = sender.send(result);
+        });
+        ctx.remove_hotplug_mmio_device(&device, Some(callback))
+            .map_err(VirtioNetDeviceError::DeviceManager)?;
+
+        Ok(receiver)
+    }
+
+    /// Remove a virtio net device, it basically is the inverse operation of `insert_device`.
+    ///
+    /// After boot, the guest must have detached the device with `prepare_remove_device` before
+    /// the device is destroyed.
+    pub fn remove_device(
+        device_mgr: &mut DeviceManager,
+        mut ctx: DeviceOpContext,
+        iface_id: &str,
+    ) -> std::result::Result<(), VirtioNetDeviceError> {
+        if !cfg!(feature = "hotplug") && ctx.is_hotplug {
+            return Err(VirtioNetDeviceError::UpdateNotAllowedPostBoot);
+        }
+
+        let mgr = &mut device_mgr.virtio_net_manager;
+        let mut info = match mgr.get_index_of_iface_id(iface_id) {
+            Some(index) => mgr
+                .info_list
+                .remove(index)
+                .ok_or_else(|| VirtioNetDeviceError::InvalidIfaceId(iface_id.to_owned()))?,
+            None => return Err(VirtioNetDeviceError::InvalidIfaceId(iface_id.to_owned())),
+        };
+
+        slog::info!(
+            ctx.logger(),
+            "remove virtio-net device";
+            "subsystem" => "net_dev_mgr",
+            "id" => &info.config.iface_id,
+            "host_dev_name" => &info.config.host_dev_name,
+        );
+
+        if let Some(device) = info.device.take() {
+            DeviceManager::destroy_mmio_virtio_device(device, &mut ctx)
+                .map_err(VirtioNetDeviceError::DeviceManager)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all virtio net devices when shutting down the virtual machine instance.
+    pub fn remove_devices(&mut self, ctx: &mut DeviceOpContext) -> Result<(), DeviceMgrError> {
+        while !self.info_list.is_empty() {
+            if let Some(mut info) = self.info_list.remove(self.info_list.len() - 1) {
+                slog::info!(
+                    ctx.logger(),
+                    "remove virtio-net device";
+                    "subsystem" => "net_dev_mgr",
+                    "id" => &info.config.iface_id,
+                );
+                if let Some(device) = info.device.take() {
+                    DeviceManager::destroy_mmio_virtio_device(device, ctx)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Update the ratelimiter settings of a virtio net device.
     pub fn update_device_ratelimiters(
         device_mgr: &mut DeviceManager,
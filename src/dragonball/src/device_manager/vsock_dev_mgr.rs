@@ -6,8 +6,10 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the THIRD-PARTY file.
 
+use std::os::unix::io::RawFd;
 use std::sync::Arc;
 
+use dbs_utils::epoll_manager::EpollManager;
 use dbs_virtio_devices as virtio;
 use dbs_virtio_devices::mmio::DRAGONBALL_FEATURE_INTR_USED;
 use dbs_virtio_devices::vsock::backend::{
@@ -19,7 +21,10 @@ use serde_derive::{Deserialize, Serialize};
 
 use super::StartMicroVmError;
 use crate::config_manager::{ConfigItem, DeviceConfigInfo, DeviceConfigInfos};
-use crate::device_manager::{DeviceManager, DeviceOpContext};
+use crate::device_manager::{DbsVirtioDevice, DeviceManager, DeviceOpContext};
+
+#[cfg(feature = "vhost-vsock")]
+use super::vhost_vsock::VhostVsock;
 
 pub use dbs_virtio_devices::vsock::QUEUE_SIZES;
 
@@ -67,6 +72,10 @@ pub enum VsockDeviceError {
     /// Inner backend create error
     #[error("vsock inner backend create error: {0}")]
     CreateInnerBackend(#[source] std::io::Error),
+
+    /// The vhost-vsock device is not supported.
+    #[error("vhost-vsock device is not supported, the vhost-vsock feature is disabled")]
+    VhostVsockNotSupported,
 }
 
 /// Configuration information for a vsock device.
@@ -86,6 +95,10 @@ pub struct VsockDeviceConfigInfo {
     pub use_shared_irq: Option<bool>,
     /// Use generic irq
     pub use_generic_irq: Option<bool>,
+    /// File descriptor of an opened `/dev/vhost-vsock`, the host kernel vhost-vsock driver
+    /// handles the device instead of the user-space backends if given. It's duplicated by the
+    /// device manager, the caller keeps its ownership.
+    pub vhost_fd: Option<RawFd>,
 }
 
 impl Default for VsockDeviceConfigInfo {
@@ -98,6 +111,7 @@ impl Default for VsockDeviceConfigInfo {
             queue_size: Vec::from(QUEUE_SIZES),
             use_shared_irq: None,
             use_generic_irq: None,
+            vhost_fd: None,
         }
     }
 }
@@ -179,6 +193,9 @@ impl VsockDeviceMgr {
         if config.guest_cid <= 2 {
             return Err(VsockDeviceError::GuestCIDInvalid(config.guest_cid));
         }
+        if config.vhost_fd.is_some() && cfg!(not(feature = "vhost-vsock")) {
+            return Err(VsockDeviceError::VhostVsockNotSupported);
+        }
 
         slog::info!(
             ctx.logger(),
@@ -216,40 +233,22 @@ impl VsockDeviceMgr {
                 "uds_path" => &info.config.uds_path,
             );
 
-            let mut device = Box::new(
-                Vsock::new(
-                    info.config.guest_cid as u64,
-                    Arc::new(info.config.queue_sizes()),
+            let device = match info.config.vhost_fd {
+                Some(vhost_fd) => {
+                    slog::warn!(
+                        ctx.logger(),
+                        "vhost-vsock device has no inner backend, the upcall won't be available";
+                        "subsystem" => SUBSYSTEM,
+                        "id" => &info.config.id,
+                    );
+                    Self::create_vhost_device(vhost_fd, &info.config, epoll_mgr.clone())?
+                }
+                None => Self::create_device(
+                    &info.config,
                     epoll_mgr.clone(),
-                )
-                .map_err(VirtioError::VirtioVsockError)
-                .map_err(StartMicroVmError::CreateVsockDevice)?,
-            );
-            if let Some(uds_path) = info.config.uds_path.as_ref() {
-                let unix_backend = VsockUnixStreamBackend::new(uds_path.clone())
-                    .map_err(VirtioError::VirtioVsockError)
-                    .map_err(StartMicroVmError::CreateVsockDevice)?;
-                device
-                    .add_backend(Box::new(unix_backend), true)
-                    .map_err(VirtioError::VirtioVsockError)
-                    .map_err(StartMicroVmError::CreateVsockDevice)?;
-            }
-            if let Some(tcp_addr) = info.config.tcp_addr.as_ref() {
-                let tcp_backend = VsockTcpBackend::new(tcp_addr.clone())
-                    .map_err(VirtioError::VirtioVsockError)
-                    .map_err(StartMicroVmError::CreateVsockDevice)?;
-                device
-                    .add_backend(Box::new(tcp_backend), false)
-                    .map_err(VirtioError::VirtioVsockError)
-                    .map_err(StartMicroVmError::CreateVsockDevice)?;
-            }
-            // add inner backend to the the first added vsock device
-            if let Some(inner_backend) = self.default_inner_backend.take() {
-                device
-                    .add_backend(Box::new(inner_backend), false)
-                    .map_err(VirtioError::VirtioVsockError)
-                    .map_err(StartMicroVmError::CreateVsockDevice)?;
-            }
+                    self.default_inner_backend.take(),
+                )?,
+            };
             let device = DeviceManager::create_mmio_virtio_device_with_features(
                 device,
                 ctx,
@@ -264,6 +263,79 @@ impl VsockDeviceMgr {
         Ok(())
     }
 
+    fn create_device(
+        config: &VsockDeviceConfigInfo,
+        epoll_mgr: EpollManager,
+        inner_backend: Option<VsockInnerBackend>,
+    ) -> std::result::Result<DbsVirtioDevice, StartMicroVmError> {
+        let mut device = Box::new(
+            Vsock::new(
+                config.guest_cid as u64,
+                Arc::new(config.queue_sizes()),
+                epoll_mgr,
+            )
+            .map_err(VirtioError::VirtioVsockError)
+            .map_err(StartMicroVmError::CreateVsockDevice)?,
+        );
+        if let Some(uds_path) = config.uds_path.as_ref() {
+            let unix_backend = VsockUnixStreamBackend::new(uds_path.clone())
+                .map_err(VirtioError::VirtioVsockError)
+                .map_err(StartMicroVmError::CreateVsockDevice)?;
+            device
+                .add_backend(Box::new(unix_backend), true)
+                .map_err(VirtioError::VirtioVsockError)
+                .map_err(StartMicroVmError::CreateVsockDevice)?;
+        }
+        if let Some(tcp_addr) = config.tcp_addr.as_ref() {
+            let tcp_backend = VsockTcpBackend::new(tcp_addr.clone())
+                .map_err(VirtioError::VirtioVsockError)
+                .map_err(StartMicroVmError::CreateVsockDevice)?;
+            device
+                .add_backend(Box::new(tcp_backend), false)
+                .map_err(VirtioError::VirtioVsockError)
+                .map_err(StartMicroVmError::CreateVsockDevice)?;
+        }
+        // add inner backend to the the first added vsock device
+        if let Some(inner_backend) = inner_backend {
+            device
+                .add_backend(Box::new(inner_backend), false)
+                .map_err(VirtioError::VirtioVsockError)
+                .map_err(StartMicroVmError::CreateVsockDevice)?;
+        }
+
+        Ok(device)
+    }
+
+    // The inner backend isn't available with the vhost-vsock device, so the upcall can't
+    // connect to the guest if there is no other user-space vsock device.
+    #[cfg(feature = "vhost-vsock")]
+    fn create_vhost_device(
+        vhost_fd: RawFd,
+        config: &VsockDeviceConfigInfo,
+        epoll_mgr: EpollManager,
+    ) -> std::result::Result<DbsVirtioDevice, StartMicroVmError> {
+        let device = VhostVsock::new(
+            vhost_fd,
+            config.guest_cid as u64,
+            Arc::new(config.queue_sizes()),
+            epoll_mgr,
+        )
+        .map_err(StartMicroVmError::CreateVsockDevice)?;
+
+        Ok(Box::new(device))
+    }
+
+    #[cfg(not(feature = "vhost-vsock"))]
+    fn create_vhost_device(
+        _vhost_fd: RawFd,
+        _config: &VsockDeviceConfigInfo,
+        _epoll_mgr: EpollManager,
+    ) -> std::result::Result<DbsVirtioDevice, StartMicroVmError> {
+        Err(StartMicroVmError::CreateVsockDevice(
+            VirtioError::InvalidInput,
+        ))
+    }
+
     // check the default connector is present, or build it.
     fn lazy_make_default_connector(&mut self) -> std::result::Result<(), VsockDeviceError> {
         if self.default_inner_connector.is_none() {
@@ -475,7 +475,7 @@ impl Vcpu {
                     Err(VcpuError::VcpuUnhandledKvmExit)
                 }
                 // Documentation specifies that below kvm exits are considered errors.
-                VcpuExit::FailEntry => {
+                VcpuExit::FailEntry(_, _) => {
                     METRICS.vcpu.failures.inc();
                     error!("Received KVM_EXIT_FAIL_ENTRY signal");
                     Err(VcpuError::VcpuUnhandledKvmExit)
@@ -807,7 +807,7 @@ pub mod tests {
                 EmulationCase::MmioWrite => Ok(VcpuExit::MmioWrite(0, &[])),
                 EmulationCase::Hlt => Ok(VcpuExit::Hlt),
                 EmulationCase::Shutdown => Ok(VcpuExit::Shutdown),
-                EmulationCase::FailEntry => Ok(VcpuExit::FailEntry),
+                EmulationCase::FailEntry => Ok(VcpuExit::FailEntry(0, 0)),
                 EmulationCase::InternalError => Ok(VcpuExit::InternalError),
                 EmulationCase::Unknown => Ok(VcpuExit::Unknown),
                 EmulationCase::SystemEvent(event_type, event_flags) => {
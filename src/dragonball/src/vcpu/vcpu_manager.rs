@@ -434,6 +434,41 @@ impl VcpuManager {
         self.pause_vcpus(&self.present_vcpus())
     }
 
+    /// pause all vcpus and wait until they're paused, so that their states don't change anymore
+    pub fn pause_all_vcpus_sync(&mut self) -> Result<()> {
+        let cpu_indexes = self.present_vcpus();
+
+        // drop the responses to the previous pause and resume requests
+        for cpu_id in cpu_indexes.iter() {
+            if let Some(handle) = &self.vcpu_infos[*cpu_id as usize].handle {
+                while handle.response_receiver().try_recv().is_ok() {}
+            }
+        }
+
+        self.pause_vcpus(&cpu_indexes)?;
+
+        for cpu_id in cpu_indexes.iter() {
+            if let Some(handle) = &self.vcpu_infos[*cpu_id as usize].handle {
+                match handle
+                    .response_receiver()
+                    .recv_timeout(Duration::from_millis(CPU_RECV_TIMEOUT_MS))
+                {
+                    Ok(VcpuResponse::Paused) => {}
+                    Err(e) => {
+                        error!("vCPU pause error! {:?}", e);
+                        return Err(VcpuManagerError::VcpuPause);
+                    }
+                    _ => {
+                        error!("vCPU pause error!");
+                        return Err(VcpuManagerError::VcpuPause);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// resume all vcpus
     pub fn resume_all_vcpus(&mut self) -> Result<()> {
         self.resume_vcpus(&self.present_vcpus())
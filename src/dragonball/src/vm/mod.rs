@@ -449,6 +449,30 @@ impl Vm {
         Ok(())
     }
 
+    /// Pause the running microvm, its vcpus are paused when the method returns.
+    pub fn pause_microvm(&mut self) -> std::result::Result<(), VcpuManagerError> {
+        self.vcpu_manager()?.pause_all_vcpus_sync()?;
+        // Use expect() to crash if the other thread poisoned this lock.
+        self.shared_info
+            .write()
+            .expect("Failed to pause microVM because shared info couldn't be written due to poisoned lock")
+            .state = InstanceState::Paused;
+
+        Ok(())
+    }
+
+    /// Resume the paused microvm.
+    pub fn resume_microvm(&mut self) -> std::result::Result<(), VcpuManagerError> {
+        self.vcpu_manager()?.resume_all_vcpus()?;
+        // Use expect() to crash if the other thread poisoned this lock.
+        self.shared_info
+            .write()
+            .expect("Failed to resume microVM because shared info couldn't be written due to poisoned lock")
+            .state = InstanceState::Running;
+
+        Ok(())
+    }
+
     /// Resume all vcpus and calc the intance downtime
     pub fn resume_all_vcpus_with_downtime(&mut self) -> std::result::Result<(), VcpuManagerError> {
         self.vcpu_manager()?.resume_all_vcpus()?;
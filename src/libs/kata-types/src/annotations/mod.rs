@@ -17,7 +17,9 @@ use crate::config::default::DEFAULT_HYPERVISOR;
 use crate::config::default::DEFAULT_RUNTIME_NAME;
 use crate::config::hypervisor::get_hypervisor_plugin;
 
-use crate::config::TomlConfig;
+use crate::config::{
+    TomlConfig, RUNTIME_NAME_LINUXCONTAINER, RUNTIME_NAME_VIRTCONTAINER, RUNTIME_NAME_WASMCONTAINER,
+};
 use crate::sl;
 
 use self::cri_containerd::{SANDBOX_CPU_PERIOD_KEY, SANDBOX_CPU_QUOTA_KEY, SANDBOX_MEM_KEY};
@@ -881,9 +883,16 @@ impl Annotation {
                     },
                     // update runtime config
                     KATA_ANNO_CFG_RUNTIME_NAME => {
-                        let runtime = vec!["virt-container", "linux-container", "wasm-container"];
-                        if runtime.contains(&value.as_str()) {
-                            config.runtime.name = value.to_string();
+                        let runtime = vec![
+                            RUNTIME_NAME_VIRTCONTAINER,
+                            RUNTIME_NAME_LINUXCONTAINER,
+                            RUNTIME_NAME_WASMCONTAINER,
+                        ];
+                        // runtime handlers are named "xxx_container", also accept
+                        // the "xxx-container" form used by earlier versions.
+                        let name = value.replace('-', "_");
+                        if runtime.contains(&name.as_str()) {
+                            config.runtime.name = name;
                         } else {
                             return Err(io::Error::new(
                                 io::ErrorKind::InvalidData,
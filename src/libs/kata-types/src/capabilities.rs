@@ -17,6 +17,10 @@ pub enum CapabilityBits {
     MultiQueueSupport,
     /// hypervisor supports filesystem share
     FsSharingSupport,
+    /// hypervisor supports resizing the guest memory
+    MemoryResizeSupport,
+    /// hypervisor reports the PCI path of the hotplugged devices
+    DevicePciPathSupport,
 }
 
 /// Capabilities describe a virtcontainers hypervisor capabilities through a bit mask.
@@ -45,6 +49,11 @@ impl Capabilities {
         self.flags = flags;
     }
 
+    /// add CapabilityBits to the current ones
+    pub fn add(&mut self, flags: CapabilityBits) {
+        self.flags |= flags;
+    }
+
     /// is_block_device_supported tells if an hypervisor supports block devices.
     pub fn is_block_device_supported(&self) -> bool {
         self.flags.and(CapabilityBits::BlockDeviceSupport) != 0
@@ -64,6 +73,17 @@ impl Capabilities {
     pub fn is_fs_sharing_supported(&self) -> bool {
         self.flags.and(CapabilityBits::FsSharingSupport) != 0
     }
+
+    /// is_mem_resize_supported tells if an hypervisor supports resizing the guest memory.
+    pub fn is_mem_resize_supported(&self) -> bool {
+        self.flags.and(CapabilityBits::MemoryResizeSupport) != 0
+    }
+
+    /// is_device_pci_path_supported tells if an hypervisor reports the PCI path of the
+    /// hotplugged devices.
+    pub fn is_device_pci_path_supported(&self) -> bool {
+        self.flags.and(CapabilityBits::DevicePciPathSupport) != 0
+    }
 }
 
 #[cfg(test)]
@@ -102,6 +122,17 @@ mod tests {
                 | CapabilityBits::MultiQueueSupport
                 | CapabilityBits::FsSharingSupport,
         );
-        assert!(cap.is_fs_sharing_supported())
+        assert!(cap.is_fs_sharing_supported());
+        assert!(!cap.is_mem_resize_supported());
+
+        // test add memory resize support
+        cap.add(CapabilityBits::MemoryResizeSupport);
+        assert!(cap.is_mem_resize_supported());
+        assert!(cap.is_fs_sharing_supported());
+        assert!(!cap.is_device_pci_path_supported());
+
+        // test add device pci path support
+        cap.add(CapabilityBits::DevicePciPathSupport);
+        assert!(cap.is_device_pci_path_supported());
     }
 }
@@ -424,6 +424,15 @@ pub struct DeviceInfo {
     /// Enabling this will result in the VM device having iommu_platform=on set
     #[serde(default)]
     pub enable_iommu_platform: bool,
+
+    /// Use the vhost-vsock driver of the host kernel for the vsock device, default false
+    ///
+    /// The guest is then reached through a standard vsock, with a guest CID allocated by the
+    /// runtime, rather than a hybrid vsock backed by a unix domain socket. Dragonball's upcall
+    /// channel relies on the hybrid vsock, so the vCPU and device hotplug of dragonball are not
+    /// available when it is enabled.
+    #[serde(default)]
+    pub use_vhost_vsock: bool,
 }
 
 impl DeviceInfo {
@@ -523,6 +532,13 @@ pub struct MemoryInfo {
     #[serde(default)]
     pub default_memory: u32,
 
+    /// Max memory size in MiB the SB/VM can be resized to.
+    ///
+    /// The memory of the VM is resized according to the memory limits of the containers, up to
+    /// this size. The default 0 means the memory size can't be changed after boot.
+    #[serde(default)]
+    pub default_maxmemory: u32,
+
     /// Default memory slots per SB/VM.
     ///
     /// This is will determine the times that memory will be hotadded to sandbox/VM.
@@ -584,6 +600,12 @@ pub struct MemoryInfo {
     /// If swap_in_bytes and memory_limit_in_bytes is not set, the size should be default_memory.
     #[serde(default)]
     pub enable_guest_swap: bool,
+
+    /// Return the guest memory freed by the guest to the host. Default false.
+    ///
+    /// The guest reports its free pages through the balloon device, and the host releases them.
+    #[serde(default)]
+    pub reclaim_guest_freed_memory: bool,
 }
 
 impl MemoryInfo {
@@ -608,6 +630,13 @@ impl MemoryInfo {
         if self.memory_slots == 0 {
             return Err(eother!("Configured memory slots for guest VM are zero"));
         }
+        if self.default_maxmemory != 0 && self.default_maxmemory < self.default_memory {
+            return Err(eother!(
+                "Configured max memory size {} MiB is less than the memory size {} MiB",
+                self.default_maxmemory,
+                self.default_memory
+            ));
+        }
 
         Ok(())
     }
@@ -30,7 +30,10 @@ pub use self::hypervisor::{
 };
 
 mod runtime;
-pub use self::runtime::{Runtime, RuntimeVendor, RUNTIME_NAME_VIRTCONTAINER};
+pub use self::runtime::{
+    Runtime, RuntimeVendor, RUNTIME_NAME_LINUXCONTAINER, RUNTIME_NAME_VIRTCONTAINER,
+    RUNTIME_NAME_WASMCONTAINER,
+};
 
 pub use self::agent::AGENT_NAME_KATA;
 
@@ -177,7 +180,8 @@ impl TomlConfig {
             if cfg.debug {
                 kv.insert(LOG_LEVEL_OPTION.to_string(), LOG_LEVEL_DEBUG.to_string());
             }
-            if cfg.enable_tracing {
+            // the agent exports its spans too when the runtime is traced
+            if cfg.enable_tracing || self.runtime.enable_tracing {
                 kv.insert(TRACE_MODE_OPTION.to_string(), TRACE_MODE_ENABLE.to_string());
             }
             if cfg.container_pipe_size > 0 {
@@ -370,5 +374,14 @@ mod tests {
         assert_eq!(kv.get("agent.container_pipe_size").unwrap(), "20");
         kv.get("agent.debug_console").unwrap();
         assert_eq!(kv.get("agent.debug_console_vport").unwrap(), "1026"); // 1026 is the default port
+
+        let agent_config = config.agent.get_mut(agent_name).unwrap();
+        agent_config.enable_tracing = false;
+        let kv = config.get_agent_kernel_params().unwrap();
+        assert!(kv.get("agent.trace").is_none());
+
+        config.runtime.enable_tracing = true;
+        let kv = config.get_agent_kernel_params().unwrap();
+        assert_eq!(kv.get("agent.trace").unwrap(), "true");
     }
 }
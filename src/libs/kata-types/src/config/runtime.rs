@@ -12,6 +12,10 @@ use crate::{eother, resolve_path, validate_path};
 
 /// Type of runtime VirtContainer.
 pub const RUNTIME_NAME_VIRTCONTAINER: &str = "virt_container";
+/// Type of runtime LinuxContainer.
+pub const RUNTIME_NAME_LINUXCONTAINER: &str = "linux_container";
+/// Type of runtime WasmContainer.
+pub const RUNTIME_NAME_WASMCONTAINER: &str = "wasm_container";
 
 /// Kata runtime configuration information.
 #[derive(Debug, Default, Deserialize, Serialize)]
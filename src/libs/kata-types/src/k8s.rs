@@ -16,6 +16,8 @@ const K8S_EMPTY_DIR: &str = "kubernetes.io~empty-dir";
 const K8S_CONFIGMAP: &str = "kubernetes.io~configmap";
 // K8S_SECRET is the K8s specific path for `secret` volumes
 const K8S_SECRET: &str = "kubernetes.io~secret";
+// K8S_DOWNWARD_API is the K8s specific path for `downward-api` volumes
+const K8S_DOWNWARD_API: &str = "kubernetes.io~downward-api";
 
 /// Check whether the path is a K8s empty directory.
 pub fn is_empty_dir<P: AsRef<Path>>(path: P) -> bool {
@@ -32,6 +34,11 @@ pub fn is_secret<P: AsRef<Path>>(path: P) -> bool {
     is_special_dir(path, K8S_SECRET)
 }
 
+/// Check whether the path is a K8s downward API volume.
+pub fn is_downward_api<P: AsRef<Path>>(path: P) -> bool {
+    is_special_dir(path, K8S_DOWNWARD_API)
+}
+
 /// Check whether the path is a K8s empty directory, configmap, or secret.
 ///
 /// For example, given a K8s EmptyDir, Kubernetes mounts
@@ -261,6 +268,18 @@ mod tests {
         assert!(!is_secret(path));
     }
 
+    #[test]
+    fn test_is_downward_api() {
+        let path = "/volumes/kubernetes.io~downward-api/podinfo";
+        assert!(is_downward_api(path));
+
+        let path = "/volumes/kubernetes.io~downward-api-test/podinfo";
+        assert!(!is_downward_api(path));
+
+        let path = "/volumes/kubernetes.io~downward-api";
+        assert!(!is_downward_api(path));
+    }
+
     #[test]
     fn test_container_type() {
         let sid = "sid".to_string();
@@ -91,6 +91,19 @@ pub fn is_kata_host_dir_volume(ty: &str) -> bool {
     ty == KATA_HOST_DIR_VOLUME_TYPE
 }
 
+/// Join the user provided direct volume path to the path prefix.
+///
+/// The volume path is base64-encoded and then safely joined to the end of the prefix, which
+/// must exist.
+pub fn join_path(prefix: &str, volume_path: &str) -> Result<PathBuf> {
+    if volume_path.is_empty() {
+        return Err(anyhow!("volume path must not be empty"));
+    }
+    let b64_encoded_path = base64::encode(volume_path.as_bytes());
+
+    Ok(safe_path::scoped_join(prefix, b64_encoded_path)?)
+}
+
 /// Nydus extra options
 #[derive(Debug, serde::Deserialize)]
 pub struct NydusExtraOptions {
@@ -131,6 +144,8 @@ impl NydusExtraOptions {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::tempdir;
+
     #[test]
     fn test_is_kata_special_volume() {
         assert!(is_kata_special_volume("kata:guest-mount:nfs"));
@@ -178,4 +193,54 @@ mod tests {
         );
         assert_eq!(extra_option.fs_version, "v6");
     }
+
+    #[test]
+    fn test_path_join() {
+        #[derive(Debug)]
+        struct TestData<'a> {
+            rootfs: &'a str,
+            volume_path: &'a str,
+            result: Result<PathBuf>,
+        }
+        // the safe_path::scoped_join requires the prefix path to exist on testing machine
+        let root_fs = tempdir().expect("failed to create tmpdir").into_path();
+        let root_fs_str = root_fs.to_str().unwrap();
+
+        let relative_secret_path = "../../etc/passwd";
+        let b64_relative_secret_path = base64::encode(relative_secret_path);
+
+        // this byte array b64encodes to "/abcdddd"
+        let b64_abs_path = vec![253, 166, 220, 117, 215, 93];
+        let converted_relative_path = "abcdddd";
+
+        let tests = &[
+            TestData {
+                rootfs: root_fs_str,
+                volume_path: "",
+                result: Err(anyhow!("volume path must not be empty")),
+            },
+            TestData {
+                rootfs: root_fs_str,
+                volume_path: relative_secret_path,
+                result: Ok(root_fs.join(b64_relative_secret_path)),
+            },
+            TestData {
+                rootfs: root_fs_str,
+                volume_path: unsafe { std::str::from_utf8_unchecked(&b64_abs_path) },
+                result: Ok(root_fs.join(converted_relative_path)),
+            },
+        ];
+        for (i, d) in tests.iter().enumerate() {
+            let msg = format!("test[{}]: {:?}", i, d);
+            let result = join_path(d.rootfs, d.volume_path);
+            let msg = format!("{}, result: {:?}", msg, result);
+            if let Ok(expected) = &d.result {
+                assert!(result.as_ref().unwrap() == expected, "{}", msg);
+                continue;
+            }
+            let expected_error = format!("{}", d.result.as_ref().unwrap_err());
+            let actual_error = format!("{}", result.unwrap_err());
+            assert!(actual_error == expected_error, "{}", msg);
+        }
+    }
 }
@@ -472,6 +472,25 @@ mod tests {
         assert!(anno.update_config_by_annotation(&mut config).is_err());
     }
 
+    #[test]
+    fn test_change_runtime_name() {
+        let content = include_str!("texture/configuration-anno-0.toml");
+
+        let qemu = QemuConfig::new();
+        qemu.register();
+
+        let config = TomlConfig::load(content).unwrap();
+        KataConfig::set_active_config(Some(config), "qemu", "agent0");
+        for name in ["wasm_container", "wasm-container"] {
+            let mut anno_hash = HashMap::new();
+            anno_hash.insert(KATA_ANNO_CFG_RUNTIME_NAME.to_string(), name.to_string());
+            let anno = Annotation::new(anno_hash);
+            let mut config = TomlConfig::load(content).unwrap();
+            assert!(anno.update_config_by_annotation(&mut config).is_ok());
+            assert_eq!(&config.runtime.name, "wasm_container");
+        }
+    }
+
     #[test]
     fn test_fail_to_change_runtime_name() {
         let content = include_str!("texture/configuration-anno-0.toml");
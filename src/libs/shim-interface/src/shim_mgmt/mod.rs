@@ -18,6 +18,8 @@ pub const AGENT_URL: &str = "/agent-url";
 pub const IP_TABLE_URL: &str = "/iptables";
 /// URL for operation on guest iptable (ipv6)
 pub const IP6_TABLE_URL: &str = "/ip6tables";
+/// URL for rescanning the sandbox network namespace
+pub const NETWORK_URL: &str = "/network";
 /// URL for querying metrics inside shim
 pub const METRICS_URL: &str = "/metrics";
 
@@ -4,20 +4,36 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use opentelemetry::global;
+use tracing::{instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use ttrpc::context as ttrpc_ctx;
 
 use kata_types::config::Agent as AgentConfig;
 
-use crate::{kata::KataAgent, Agent, AgentManager, HealthService};
+use crate::{kata::KataAgent, Agent, AgentManager, HealthService, ImageService};
 
 /// millisecond to nanosecond
 const MILLISECOND_TO_NANOSECOND: i64 = 1_000_000;
 
-/// new ttrpc context with timeout
+/// new ttrpc context with timeout, the trace context of the current span is carried
+/// in the metadata so that the agent can link its spans to the runtime ones
 fn new_ttrpc_ctx(timeout: i64) -> ttrpc_ctx::Context {
-    ttrpc_ctx::with_timeout(timeout)
+    let mut ctx = ttrpc_ctx::with_timeout(timeout);
+
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut carrier)
+    });
+    for (k, v) in carrier {
+        ctx.add(k, v);
+    }
+
+    ctx
 }
 
 #[async_trait]
@@ -69,11 +85,37 @@ impl_health_service!(
     version | crate::CheckRequest | crate::VersionCheckResponse
 );
 
+// implement for image service
+macro_rules! impl_image_service {
+    ($($name: tt | $req: ty | $resp: ty | $new_timeout: expr),*) => {
+        #[async_trait]
+        impl ImageService for KataAgent {
+            $(#[instrument(skip_all)]
+            async fn $name(&self, req: $req) -> Result<$resp> {
+                let r = req.into();
+                let (mut client, mut timeout, _) = self.get_image_client().await.context("get image client")?;
+
+                // update new timeout
+                if let Some(v) = $new_timeout {
+                    timeout = v;
+                }
+
+                let resp = client.$name(new_ttrpc_ctx(timeout * MILLISECOND_TO_NANOSECOND), &r).await?;
+                Ok(resp.into())
+            })*
+        }
+    };
+}
+
+// pulling an image may take a long time, so no timeout is set for it
+impl_image_service!(pull_image | crate::PullImageRequest | crate::PullImageResponse | Some(0));
+
 macro_rules! impl_agent {
     ($($name: tt | $req: ty | $resp: ty | $new_timeout: expr),*) => {
         #[async_trait]
         impl Agent for KataAgent {
-            $(async fn $name(&self, req: $req) -> Result<$resp> {
+            $(#[instrument(skip_all)]
+            async fn $name(&self, req: $req) -> Result<$resp> {
                 let r = req.into();
                 let (mut client, mut timeout, _) = self.get_agent_client().await.context("get client")?;
 
@@ -112,8 +154,17 @@ impl_agent!(
     list_routes | crate::Empty | crate::Routes | None,
     create_sandbox | crate::CreateSandboxRequest | crate::Empty | None,
     destroy_sandbox | crate::Empty | crate::Empty | None,
+    get_guest_details | crate::GetGuestDetailsRequest | crate::GuestDetailsResponse | None,
+    online_cpu_mem | crate::OnlineCPUMemRequest | crate::Empty | None,
+    mem_hotplug_by_probe | crate::MemHotplugByProbeRequest | crate::Empty | None,
     copy_file | crate::CopyFileRequest | crate::Empty | None,
     get_oom_event | crate::Empty | crate::OomEventResponse | Some(0),
+    get_metrics | crate::Empty | crate::MetricsResponse | None,
     get_ip_tables | crate::GetIPTablesRequest | crate::GetIPTablesResponse | None,
-    set_ip_tables | crate::SetIPTablesRequest | crate::SetIPTablesResponse | None
+    set_ip_tables | crate::SetIPTablesRequest | crate::SetIPTablesResponse | None,
+    reseed_random_dev | crate::ReseedRandomDevRequest | crate::Empty | None,
+    set_guest_date_time | crate::SetGuestDateTimeRequest | crate::Empty | None,
+    add_swap | crate::AddSwapRequest | crate::Empty | None,
+    get_volume_stats | crate::VolumeStatsRequest | crate::VolumeStatsResponse | None,
+    resize_volume | crate::ResizeVolumeRequest | crate::Empty | None
 );
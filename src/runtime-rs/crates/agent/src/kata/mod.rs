@@ -14,7 +14,10 @@ use std::{
 
 use anyhow::{Context, Result};
 use kata_types::config::Agent as AgentConfig;
-use protocols::{agent_ttrpc_async as agent_ttrpc, health_ttrpc_async as health_ttrpc};
+use protocols::{
+    agent_ttrpc_async as agent_ttrpc, health_ttrpc_async as health_ttrpc,
+    image_ttrpc_async as image_ttrpc,
+};
 use tokio::sync::RwLock;
 use ttrpc::asynchronous::Client;
 
@@ -83,6 +86,17 @@ impl KataAgent {
         })
     }
 
+    pub async fn get_image_client(&self) -> Option<(image_ttrpc::ImageClient, i64, RawFd)> {
+        let inner = self.inner.read().await;
+        inner.client.as_ref().map(|c| {
+            (
+                image_ttrpc::ImageClient::new(c.clone()),
+                inner.config.request_timeout_ms as i64,
+                inner.client_fd,
+            )
+        })
+    }
+
     pub(crate) async fn set_socket_address(&self, address: &str) -> Result<()> {
         let mut inner = self.inner.write().await;
         inner.socket_address = address.to_string();
@@ -8,25 +8,27 @@ use std::convert::Into;
 
 use protocols::{
     agent::{self, OOMEvent},
-    empty, health, types,
+    csi, empty, health, image, types,
 };
 
 use crate::{
     types::{
-        ARPNeighbor, ARPNeighbors, AddArpNeighborRequest, AgentDetails, BlkioStats,
+        ARPNeighbor, ARPNeighbors, AddArpNeighborRequest, AddSwapRequest, AgentDetails, BlkioStats,
         BlkioStatsEntry, CgroupStats, CheckRequest, CloseStdinRequest, ContainerID,
         CopyFileRequest, CpuStats, CpuUsage, CreateContainerRequest, CreateSandboxRequest, Device,
-        Empty, ExecProcessRequest, FSGroup, FSGroupChangePolicy, GetIPTablesRequest,
-        GetIPTablesResponse, GuestDetailsResponse, HealthCheckResponse, HugetlbStats, IPAddress,
-        IPFamily, Interface, Interfaces, KernelModule, MemHotplugByProbeRequest, MemoryData,
-        MemoryStats, NetworkStats, OnlineCPUMemRequest, PidsStats, ReadStreamRequest,
-        ReadStreamResponse, RemoveContainerRequest, ReseedRandomDevRequest, Route, Routes,
+        Empty, ExecProcessRequest, FSGroup, FSGroupChangePolicy, GetGuestDetailsRequest,
+        GetIPTablesRequest, GetIPTablesResponse, GuestDetailsResponse, HealthCheckResponse,
+        HugetlbStats, IPAddress, IPFamily, Interface, Interfaces, KernelModule,
+        MemHotplugByProbeRequest, MemoryData, MemoryStats, NetworkStats, OnlineCPUMemRequest,
+        PidsStats, PullImageRequest, PullImageResponse, ReadStreamRequest, ReadStreamResponse,
+        RemoveContainerRequest, ReseedRandomDevRequest, ResizeVolumeRequest, Route, Routes,
         SetGuestDateTimeRequest, SetIPTablesRequest, SetIPTablesResponse, SignalProcessRequest,
         StatsContainerResponse, Storage, StringUser, ThrottlingData, TtyWinResizeRequest,
         UpdateContainerRequest, UpdateInterfaceRequest, UpdateRoutesRequest, VersionCheckResponse,
+        VolumeCondition, VolumeStatsRequest, VolumeStatsResponse, VolumeUsage, VolumeUsageUnit,
         WaitProcessRequest, WriteStreamRequest,
     },
-    OomEventResponse, WaitProcessResponse, WriteStreamResponse,
+    MetricsResponse, OomEventResponse, WaitProcessResponse, WriteStreamResponse,
 };
 
 fn from_vec<F: Into<T>, T: Sized>(from: Vec<F>) -> ::protobuf::RepeatedField<T> {
@@ -424,6 +426,64 @@ impl From<agent::SetIPTablesResponse> for SetIPTablesResponse {
     }
 }
 
+impl From<VolumeStatsRequest> for agent::VolumeStatsRequest {
+    fn from(from: VolumeStatsRequest) -> Self {
+        Self {
+            volume_guest_path: from.volume_guest_path,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<csi::VolumeUsage_Unit> for VolumeUsageUnit {
+    fn from(from: csi::VolumeUsage_Unit) -> Self {
+        match from {
+            csi::VolumeUsage_Unit::UNKNOWN => VolumeUsageUnit::Unknown,
+            csi::VolumeUsage_Unit::BYTES => VolumeUsageUnit::Bytes,
+            csi::VolumeUsage_Unit::INODES => VolumeUsageUnit::Inodes,
+        }
+    }
+}
+
+impl From<csi::VolumeUsage> for VolumeUsage {
+    fn from(from: csi::VolumeUsage) -> Self {
+        Self {
+            available: from.available,
+            total: from.total,
+            used: from.used,
+            unit: from.unit.into(),
+        }
+    }
+}
+
+impl From<csi::VolumeCondition> for VolumeCondition {
+    fn from(from: csi::VolumeCondition) -> Self {
+        Self {
+            abnormal: from.abnormal,
+            message: from.message,
+        }
+    }
+}
+
+impl From<csi::VolumeStatsResponse> for VolumeStatsResponse {
+    fn from(from: csi::VolumeStatsResponse) -> Self {
+        Self {
+            usage: into_vec(from.usage),
+            volume_condition: into_option(from.volume_condition),
+        }
+    }
+}
+
+impl From<ResizeVolumeRequest> for agent::ResizeVolumeRequest {
+    fn from(from: ResizeVolumeRequest) -> Self {
+        Self {
+            volume_guest_path: from.volume_guest_path,
+            size: from.size,
+            ..Default::default()
+        }
+    }
+}
+
 impl From<ExecProcessRequest> for agent::ExecProcessRequest {
     fn from(from: ExecProcessRequest) -> Self {
         Self {
@@ -756,6 +816,27 @@ impl From<SetGuestDateTimeRequest> for agent::SetGuestDateTimeRequest {
     }
 }
 
+impl From<GetGuestDetailsRequest> for agent::GuestDetailsRequest {
+    fn from(from: GetGuestDetailsRequest) -> Self {
+        Self {
+            mem_block_size: from.mem_block_size,
+            mem_hotplug_probe: from.mem_hotplug_probe,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+impl From<AddSwapRequest> for agent::AddSwapRequest {
+    fn from(from: AddSwapRequest) -> Self {
+        Self {
+            PCIPath: from.pci_path,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
 impl From<agent::AgentDetails> for AgentDetails {
     fn from(src: agent::AgentDetails) -> Self {
         Self {
@@ -846,3 +927,40 @@ impl From<agent::OOMEvent> for OomEventResponse {
         }
     }
 }
+
+impl From<Empty> for agent::GetMetricsRequest {
+    fn from(_: Empty) -> Self {
+        Self {
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+impl From<agent::Metrics> for MetricsResponse {
+    fn from(from: agent::Metrics) -> Self {
+        Self {
+            metrics: from.metrics,
+        }
+    }
+}
+
+impl From<PullImageRequest> for image::PullImageRequest {
+    fn from(from: PullImageRequest) -> Self {
+        Self {
+            image: from.image,
+            container_id: from.container_id,
+            source_creds: from.source_creds,
+            unknown_fields: Default::default(),
+            cached_size: Default::default(),
+        }
+    }
+}
+
+impl From<image::PullImageResponse> for PullImageResponse {
+    fn from(from: image::PullImageResponse) -> Self {
+        Self {
+            image_ref: from.image_ref,
+        }
+    }
+}
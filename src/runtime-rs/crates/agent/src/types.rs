@@ -504,6 +504,11 @@ pub struct SetGuestDateTimeRequest {
     pub usec: i64,
 }
 
+#[derive(PartialEq, Clone, Default)]
+pub struct AddSwapRequest {
+    pub pci_path: Vec<u32>,
+}
+
 #[derive(PartialEq, Clone, Default)]
 pub struct AgentDetails {
     pub version: String,
@@ -561,6 +566,11 @@ pub struct OomEventResponse {
     pub container_id: String,
 }
 
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct MetricsResponse {
+    pub metrics: String,
+}
+
 // ResizeVolumeRequest is also the common struct for serialization and deserialization with json
 // between shim-client HTTP calls to the shim-mgmt-server
 #[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug)]
@@ -569,6 +579,58 @@ pub struct ResizeVolumeRequest {
     pub size: u64,
 }
 
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct VolumeStatsRequest {
+    pub volume_guest_path: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub enum VolumeUsageUnit {
+    Unknown,
+    Bytes,
+    Inodes,
+}
+
+impl Default for VolumeUsageUnit {
+    fn default() -> Self {
+        VolumeUsageUnit::Unknown
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug)]
+pub struct VolumeUsage {
+    pub available: u64,
+    pub total: u64,
+    pub used: u64,
+    pub unit: VolumeUsageUnit,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug)]
+pub struct VolumeCondition {
+    pub abnormal: bool,
+    pub message: String,
+}
+
+// VolumeStatsResponse is also the common struct for serialization and deserialization with json
+// between shim-client HTTP calls to the shim-mgmt-server
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default, Debug)]
+pub struct VolumeStatsResponse {
+    pub usage: Vec<VolumeUsage>,
+    pub volume_condition: Option<VolumeCondition>,
+}
+
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct PullImageRequest {
+    pub image: String,
+    pub container_id: String,
+    pub source_creds: String,
+}
+
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct PullImageResponse {
+    pub image_ref: String,
+}
+
 #[cfg(test)]
 mod test {
     use std::convert::TryFrom;
@@ -0,0 +1,561 @@
+// Copyright (c) 2022 Red Hat
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Asynchronous client for the QEMU Machine Protocol (QMP).
+//!
+//! QMP is a line based JSON protocol served by QEMU on a socket. After the
+//! connection is established, the server sends a greeting and waits for the
+//! `qmp_capabilities` command before accepting any other command. Command
+//! responses are correlated with their requests through the `id` member,
+//! while asynchronous events (e.g. `SHUTDOWN`, `DEVICE_DELETED`) may be
+//! interleaved with them at any time.
+//!
+//! [`Qmp`] owns a background task reading the socket: responses are routed
+//! to the pending command they belong to and events are broadcast to every
+//! subscriber.
+
+use std::collections::HashMap;
+use std::io::{self, IoSlice};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Interest};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+
+/// Emitted when the virtual machine has shut down.
+pub const EVENT_SHUTDOWN: &str = "SHUTDOWN";
+/// Emitted when the guest has acknowledged the removal of a device.
+pub const EVENT_DEVICE_DELETED: &str = "DEVICE_DELETED";
+/// Emitted when the guest kernel has panicked.
+pub const EVENT_GUEST_PANICKED: &str = "GUEST_PANICKED";
+
+/// Default time to wait for the response of a command.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of events buffered for slow subscribers before older ones are
+/// dropped.
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Number of milliseconds to wait before retrying to connect to the socket.
+const CONNECT_POLL_TIME_MS: u64 = 50;
+
+type PendingCommands = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+
+/// Timestamp of a QMP event.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Timestamp {
+    pub seconds: u64,
+    pub microseconds: u64,
+}
+
+/// Asynchronous event sent by QEMU.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Event {
+    pub event: String,
+    #[serde(default)]
+    pub data: Value,
+    #[serde(default)]
+    pub timestamp: Timestamp,
+}
+
+impl Event {
+    /// Device id carried by a `DEVICE_DELETED` event, if any.
+    pub fn device(&self) -> Option<&str> {
+        self.data.get("device").and_then(Value::as_str)
+    }
+}
+
+/// Error returned by QEMU for a failed command.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CommandError {
+    pub class: String,
+    pub desc: String,
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.class, self.desc)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// QMP client connected to a QEMU instance.
+pub struct Qmp {
+    writer: tokio::sync::Mutex<OwnedWriteHalf>,
+    pending: PendingCommands,
+    events: broadcast::Sender<Event>,
+    next_id: AtomicU64,
+    reader_task: JoinHandle<()>,
+    timeout: Duration,
+}
+
+impl Qmp {
+    /// Connect to the QMP socket at `path` and negotiate the capabilities.
+    /// The connection is retried until `timeout` expires, as QEMU may not
+    /// have created the socket yet.
+    pub async fn connect<P: AsRef<Path>>(path: P, timeout: Duration) -> Result<Self> {
+        let path = path.as_ref();
+        let stream = tokio::time::timeout(timeout, async {
+            loop {
+                match UnixStream::connect(path).await {
+                    Ok(stream) => return stream,
+                    Err(_) => tokio::time::sleep(Duration::from_millis(CONNECT_POLL_TIME_MS)).await,
+                }
+            }
+        })
+        .await
+        .with_context(|| {
+            format!(
+                "QMP socket {} connect timed out after {:?}",
+                path.display(),
+                timeout
+            )
+        })?;
+
+        Self::from_stream(stream, timeout).await
+    }
+
+    /// Set up a client on an already connected stream, waiting at most
+    /// `timeout` for the greeting.
+    pub async fn from_stream(stream: UnixStream, timeout: Duration) -> Result<Self> {
+        let (read_half, write_half) = stream.into_split();
+        let mut reader = BufReader::new(read_half);
+
+        let greeting = tokio::time::timeout(timeout, read_message(&mut reader))
+            .await
+            .context("QMP greeting timed out")?
+            .context("read QMP greeting")?;
+        if greeting.get("QMP").is_none() {
+            return Err(anyhow!("unexpected QMP greeting {}", greeting));
+        }
+
+        let pending: PendingCommands = Arc::new(Mutex::new(HashMap::new()));
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let reader_task = tokio::spawn(read_loop(reader, pending.clone(), events.clone()));
+
+        let qmp = Self {
+            writer: tokio::sync::Mutex::new(write_half),
+            pending,
+            events,
+            next_id: AtomicU64::new(0),
+            reader_task,
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+        };
+
+        qmp.execute("qmp_capabilities", None)
+            .await
+            .context("negotiate QMP capabilities")?;
+
+        Ok(qmp)
+    }
+
+    /// Set the time to wait for the response of a command.
+    pub fn set_command_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Subscribe to the events sent by QEMU from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Execute a command and return its `return` value.
+    pub async fn execute(&self, command: &str, arguments: Option<Value>) -> Result<Value> {
+        self.send_command(command, arguments, None).await
+    }
+
+    /// Execute a command passing the file descriptor `fd` along, as
+    /// `getfd` expects, and return its `return` value. QEMU receives a copy
+    /// of the file descriptor, the caller still owns `fd`.
+    pub async fn execute_with_fd(
+        &self,
+        command: &str,
+        arguments: Option<Value>,
+        fd: RawFd,
+    ) -> Result<Value> {
+        self.send_command(command, arguments, Some(fd)).await
+    }
+
+    async fn send_command(
+        &self,
+        command: &str,
+        arguments: Option<Value>,
+        fd: Option<RawFd>,
+    ) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut request = json!({ "execute": command, "id": id });
+        if let Some(arguments) = arguments {
+            request["arguments"] = arguments;
+        }
+        let mut line = serde_json::to_vec(&request).context("serialize QMP request")?;
+        line.push(b'\n');
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let result = async {
+            let mut writer = self.writer.lock().await;
+            // the file descriptor is attached to the first bytes of the command
+            let sent = match fd {
+                Some(fd) => send_with_fd(&writer, &line, fd)
+                    .await
+                    .with_context(|| format!("send QMP command {} with fd {}", command, fd))?,
+                None => 0,
+            };
+            writer
+                .write_all(&line[sent..])
+                .await
+                .with_context(|| format!("send QMP command {}", command))?;
+            drop(writer);
+
+            tokio::time::timeout(self.timeout, rx)
+                .await
+                .with_context(|| format!("QMP command {} timed out", command))?
+                .map_err(|_| anyhow!("QMP connection closed"))?
+        }
+        .await;
+
+        self.pending.lock().unwrap().remove(&id);
+        result.with_context(|| format!("QMP command {}", command))
+    }
+
+    /// Execute a command, then wait for the first event accepted by
+    /// `filter`. Subscribing before sending the command guarantees that the
+    /// event cannot be missed.
+    pub async fn execute_and_wait_event<F>(
+        &self,
+        command: &str,
+        arguments: Option<Value>,
+        timeout: Duration,
+        filter: F,
+    ) -> Result<Event>
+    where
+        F: Fn(&Event) -> bool,
+    {
+        let mut events = self.subscribe();
+        self.execute(command, arguments).await?;
+
+        tokio::time::timeout(timeout, async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if filter(&event) => return Ok(event),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(anyhow!("QMP connection closed"))
+                    }
+                }
+            }
+        })
+        .await
+        .with_context(|| format!("wait for event after {} timed out", command))?
+    }
+
+    /// Remove a device and wait for the guest to release it.
+    pub async fn device_del(&self, id: &str, timeout: Duration) -> Result<()> {
+        self.execute_and_wait_event("device_del", Some(json!({ "id": id })), timeout, |event| {
+            event.event == EVENT_DEVICE_DELETED && event.device() == Some(id)
+        })
+        .await
+        .map(|_| ())
+    }
+}
+
+impl Drop for Qmp {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+// Send the first bytes of `data` that fit in the socket, with `fd` as
+// SCM_RIGHTS ancillary data, and return the number of bytes sent.
+async fn send_with_fd(writer: &OwnedWriteHalf, data: &[u8], fd: RawFd) -> io::Result<usize> {
+    let stream: &UnixStream = writer.as_ref();
+    let fds = [fd];
+    loop {
+        stream.writable().await?;
+        match stream.try_io(Interest::WRITABLE, || {
+            sendmsg::<()>(
+                stream.as_raw_fd(),
+                &[IoSlice::new(data)],
+                &[ControlMessage::ScmRights(&fds)],
+                MsgFlags::empty(),
+                None,
+            )
+            .map_err(io::Error::from)
+        }) {
+            Ok(n) => return Ok(n),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+async fn read_message(reader: &mut BufReader<OwnedReadHalf>) -> Result<Value> {
+    let mut line = String::new();
+    let n = reader
+        .read_line(&mut line)
+        .await
+        .context("read QMP socket")?;
+    if n == 0 {
+        return Err(anyhow!("QMP socket closed"));
+    }
+    serde_json::from_str(&line).with_context(|| format!("parse QMP message {}", line))
+}
+
+// Dispatch the messages received from QEMU until the connection is closed.
+async fn read_loop(
+    mut reader: BufReader<OwnedReadHalf>,
+    pending: PendingCommands,
+    events: broadcast::Sender<Event>,
+) {
+    loop {
+        let message = match read_message(&mut reader).await {
+            Ok(message) => message,
+            Err(_) => break,
+        };
+
+        if message.get("event").is_some() {
+            if let Ok(event) = serde_json::from_value::<Event>(message) {
+                // no subscriber is not an error
+                let _ = events.send(event);
+            }
+            continue;
+        }
+
+        let id = match message.get("id").and_then(Value::as_u64) {
+            Some(id) => id,
+            None => continue,
+        };
+        let result = if let Some(error) = message.get("error") {
+            match serde_json::from_value::<CommandError>(error.clone()) {
+                Ok(error) => Err(error.into()),
+                Err(_) => Err(anyhow!("QMP error {}", error)),
+            }
+        } else {
+            Ok(message.get("return").cloned().unwrap_or(Value::Null))
+        };
+
+        if let Some(tx) = pending.lock().unwrap().remove(&id) {
+            let _ = tx.send(result);
+        }
+    }
+
+    // fail the commands still waiting for a response
+    pending.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // A fake QMP server accepting a single connection: it sends the
+    // greeting, answers `qmp_capabilities`, then hands every other command
+    // to `handler` which returns the lines to send back.
+    async fn fake_server<F>(listener: UnixListener, handler: F)
+    where
+        F: Fn(&Value) -> Vec<Value> + Send + 'static,
+    {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut reader = BufReader::new(read_half);
+
+        let greeting = json!({"QMP": {"version": {}, "capabilities": []}});
+        write_half
+            .write_all(format!("{}\n", greeting).as_bytes())
+            .await
+            .unwrap();
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let replies = if request["execute"] == "qmp_capabilities" {
+                vec![json!({"return": {}, "id": request["id"]})]
+            } else {
+                handler(&request)
+            };
+            for reply in replies {
+                write_half
+                    .write_all(format!("{}\n", reply).as_bytes())
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    async fn setup<F>(handler: F) -> (tempfile::TempDir, Qmp)
+    where
+        F: Fn(&Value) -> Vec<Value> + Send + 'static,
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(fake_server(listener, handler));
+
+        let qmp = Qmp::connect(&path, TIMEOUT).await.unwrap();
+        (dir, qmp)
+    }
+
+    fn shutdown_event() -> Value {
+        json!({
+            "event": EVENT_SHUTDOWN,
+            "data": {"guest": true, "reason": "guest-shutdown"},
+            "timestamp": {"seconds": 1, "microseconds": 2},
+        })
+    }
+
+    #[tokio::test]
+    async fn test_execute() {
+        let (_dir, qmp) = setup(|request| match request["execute"].as_str() {
+            Some("query-status") => vec![
+                // an event interleaved with the response must be skipped
+                shutdown_event(),
+                json!({"return": {"status": "running"}, "id": request["id"]}),
+            ],
+            _ => vec![json!({
+                "error": {"class": "CommandNotFound", "desc": "not found"},
+                "id": request["id"],
+            })],
+        })
+        .await;
+
+        let status = qmp.execute("query-status", None).await.unwrap();
+        assert_eq!(status["status"], "running");
+
+        let err = qmp.execute("foo", None).await.unwrap_err();
+        let err = err.downcast_ref::<CommandError>().unwrap();
+        assert_eq!(err.class, "CommandNotFound");
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let (_dir, qmp) =
+            setup(|request| vec![json!({"return": {}, "id": request["id"]}), shutdown_event()])
+                .await;
+
+        let mut events = qmp.subscribe();
+        qmp.execute("system_powerdown", None).await.unwrap();
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event, EVENT_SHUTDOWN);
+        assert_eq!(
+            event.timestamp,
+            Timestamp {
+                seconds: 1,
+                microseconds: 2
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_device_del() {
+        let (_dir, qmp) = setup(|request| {
+            let id = request["arguments"]["id"].clone();
+            vec![
+                json!({"return": {}, "id": request["id"]}),
+                json!({"event": EVENT_DEVICE_DELETED, "data": {"device": "other"}}),
+                json!({"event": EVENT_DEVICE_DELETED, "data": {"device": id}}),
+            ]
+        })
+        .await;
+
+        qmp.device_del("virtio-drive0", TIMEOUT).await.unwrap();
+    }
+
+    // A fake QMP server answering every command on a blocking stream,
+    // which returns the file descriptor received with the `getfd` command.
+    fn fake_fd_server(mut stream: std::os::unix::net::UnixStream) -> RawFd {
+        use nix::sys::socket::{recvmsg, ControlMessageOwned};
+        use std::io::{IoSliceMut, Write};
+
+        let greeting = json!({"QMP": {"version": {}, "capabilities": []}});
+        writeln!(stream, "{}", greeting).unwrap();
+
+        loop {
+            let mut buf = [0u8; 1024];
+            let mut cmsg = nix::cmsg_space!([RawFd; 1]);
+            let mut iov = [IoSliceMut::new(&mut buf)];
+            let msg = recvmsg::<()>(
+                stream.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg),
+                MsgFlags::empty(),
+            )
+            .unwrap();
+            let fd = msg.cmsgs().find_map(|cmsg| match cmsg {
+                ControlMessageOwned::ScmRights(fds) => fds.first().cloned(),
+                _ => None,
+            });
+            let len = msg.bytes;
+
+            let request: Value = serde_json::from_slice(&buf[..len]).unwrap();
+            writeln!(stream, "{}", json!({"return": {}, "id": request["id"]})).unwrap();
+            if request["execute"] == "getfd" {
+                return fd.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_fd() {
+        use std::io::{Read, Seek, Write};
+        use std::os::unix::io::FromRawFd;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = stream.into_std().unwrap();
+            stream.set_nonblocking(false).unwrap();
+            tokio::task::spawn_blocking(move || fake_fd_server(stream))
+                .await
+                .unwrap()
+        });
+
+        let qmp = Qmp::connect(&path, TIMEOUT).await.unwrap();
+        let mut file = tempfile::tempfile().unwrap();
+        qmp.execute_with_fd("getfd", Some(json!({"fdname": "fd0"})), file.as_raw_fd())
+            .await
+            .unwrap();
+
+        // the received file descriptor refers to the same file
+        let mut received = unsafe { std::fs::File::from_raw_fd(server.await.unwrap()) };
+        received.write_all(b"state").unwrap();
+        let mut data = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "state");
+    }
+
+    #[tokio::test]
+    async fn test_connection_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qmp.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // close the connection instead of sending the greeting
+            stream.shutdown().await.unwrap();
+        });
+
+        assert!(Qmp::connect(&path, TIMEOUT).await.is_err());
+    }
+}
@@ -12,6 +12,7 @@ use kata_types::capabilities::{Capabilities, CapabilityBits};
 use kata_types::config::hypervisor::Hypervisor as HypervisorConfig;
 use kata_types::config::hypervisor::HYPERVISOR_NAME_CH;
 use persist::sandbox_persist::Persist;
+use std::collections::HashMap;
 use std::os::unix::net::UnixStream;
 use tokio::process::Child;
 use tokio::sync::watch::{channel, Receiver, Sender};
@@ -22,6 +23,9 @@ pub struct CloudHypervisorInner {
     pub(crate) state: VmmState,
     pub(crate) id: String,
 
+    /// Whether the running VM has been paused
+    pub(crate) paused: bool,
+
     pub(crate) api_socket: Option<UnixStream>,
     pub(crate) extra_args: Option<Vec<String>>,
 
@@ -46,7 +50,10 @@ pub struct CloudHypervisorInner {
     /// List of devices that will be added to the VM once it boots
     pub(crate) pending_devices: Option<Vec<Device>>,
 
-    pub(crate) _capabilities: Capabilities,
+    pub(crate) capabilities: Capabilities,
+
+    /// Guest PCI paths of the hotplugged devices, indexed by the device id
+    pub(crate) device_pci_paths: HashMap<String, String>,
 
     pub(crate) shutdown_tx: Option<Sender<bool>>,
     pub(crate) shutdown_rx: Option<Receiver<bool>>,
@@ -64,7 +71,8 @@ impl CloudHypervisorInner {
         capabilities.set(
             CapabilityBits::BlockDeviceSupport
                 | CapabilityBits::BlockDeviceHotplugSupport
-                | CapabilityBits::FsSharingSupport,
+                | CapabilityBits::FsSharingSupport
+                | CapabilityBits::DevicePciPathSupport,
         );
 
         let (tx, rx) = channel(true);
@@ -78,6 +86,7 @@ impl CloudHypervisorInner {
 
             config: None,
             state: VmmState::NotReady,
+            paused: false,
             timeout_secs: CH_DEFAULT_TIMEOUT_SECS as i32,
             id: String::default(),
             jailer_root: String::default(),
@@ -85,7 +94,8 @@ impl CloudHypervisorInner {
             run_dir: String::default(),
             netns: None,
             pending_devices: None,
-            _capabilities: capabilities,
+            capabilities,
+            device_pci_paths: HashMap::new(),
             shutdown_tx: Some(tx),
             shutdown_rx: Some(rx),
             tasks: None,
@@ -124,6 +134,7 @@ impl Persist for CloudHypervisorInner {
             config: self.hypervisor_config(),
             run_dir: self.run_dir.clone(),
             cached_block_devices: Default::default(),
+            device_pci_paths: self.device_pci_paths.clone(),
             ..Default::default()
         })
     }
@@ -139,6 +150,7 @@ impl Persist for CloudHypervisorInner {
             id: hypervisor_state.id,
             vm_path: hypervisor_state.vm_path,
             run_dir: hypervisor_state.run_dir,
+            device_pci_paths: hypervisor_state.device_pci_paths,
 
             ..Default::default()
         };
@@ -6,17 +6,26 @@
 
 use super::inner::CloudHypervisorInner;
 use crate::device::{Device, ShareFsDeviceConfig};
-use crate::HybridVsockConfig;
-use crate::VmmState;
+use crate::{BlockConfig, HybridVsockConfig, NetworkConfig, VfioBusMode, VfioConfig, VmmState};
 use anyhow::{anyhow, Context, Result};
-use ch_config::ch_api::cloud_hypervisor_vm_fs_add;
-use ch_config::{FsConfig, PmemConfig};
+use ch_config::ch_api::{
+    cloud_hypervisor_vm_blockdev_add, cloud_hypervisor_vm_device_add,
+    cloud_hypervisor_vm_device_remove, cloud_hypervisor_vm_fs_add, cloud_hypervisor_vm_netdev_add,
+};
+use ch_config::{
+    DeviceConfig, DiskConfig, FsConfig, MacAddr, NetConfig, PmemConfig, VmRemoveDeviceData,
+};
 use safe_path::scoped_join;
 use std::convert::TryFrom;
 use std::path::PathBuf;
 
 const VIRTIO_FS: &str = "virtio-fs";
 
+const DEFAULT_DISK_QUEUES: usize = 1;
+const DEFAULT_DISK_QUEUE_SIZE: u16 = 128;
+
+const SYS_PCI_DEVICES_PATH: &str = "/sys/bus/pci/devices";
+
 impl CloudHypervisorInner {
     pub(crate) async fn add_device(&mut self, device: Device) -> Result<()> {
         if self.state != VmmState::VmRunning {
@@ -42,13 +51,15 @@ impl CloudHypervisorInner {
         match device {
             Device::ShareFsDevice(cfg) => self.handle_share_fs_device(cfg).await,
             Device::HybridVsock(cfg) => self.handle_hvsock_device(&cfg).await,
+            Device::Network(cfg) => self.handle_network_device(&cfg).await,
+            Device::Block(cfg) => self.handle_block_device(&cfg).await,
+            Device::Vfio(cfg) => self.handle_vfio_device(&cfg).await,
             _ => return Err(anyhow!("unhandled device: {:?}", device)),
         }
     }
 
     /// Add the device that were requested to be added before the VMM was
     /// started.
-    #[allow(dead_code)]
     pub(crate) async fn handle_pending_devices_after_boot(&mut self) -> Result<()> {
         if self.state != VmmState::VmRunning {
             return Err(anyhow!(
@@ -66,7 +77,41 @@ impl CloudHypervisorInner {
         Ok(())
     }
 
-    pub(crate) async fn remove_device(&mut self, _device: Device) -> Result<()> {
+    pub(crate) async fn remove_device(&mut self, device: Device) -> Result<()> {
+        let id = match &device {
+            Device::Network(cfg) => cfg.id.clone(),
+            Device::Block(cfg) => cfg.id.clone(),
+            Device::Vfio(cfg) => cfg.id.clone(),
+            _ => return Err(anyhow!("unsupported device {:?}", device)),
+        };
+
+        // A device which has not been added to the VM yet is only dropped
+        // from the pending list.
+        if let Some(devices) = self.pending_devices.as_mut() {
+            if let Some(index) = devices.iter().position(|d| is_same_device(d, &device)) {
+                devices.remove(index);
+                return Ok(());
+            }
+        }
+
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let response = cloud_hypervisor_vm_device_remove(
+            socket.try_clone().context("failed to clone socket")?,
+            VmRemoveDeviceData { id: id.clone() },
+        )
+        .await
+        .with_context(|| format!("remove device {}", device))?;
+
+        if let Some(detail) = response {
+            debug!(sl!(), "device remove response: {:?}", detail);
+        }
+        self.device_pci_paths.remove(&id);
+
         Ok(())
     }
 
@@ -124,12 +169,117 @@ impl CloudHypervisorInner {
         Ok(())
     }
 
+    async fn handle_network_device(&mut self, cfg: &NetworkConfig) -> Result<()> {
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let net_config = NetConfig {
+            tap: Some(cfg.host_dev_name.clone()),
+            mac: cfg
+                .guest_mac
+                .as_ref()
+                .map(|mac| MacAddr { bytes: mac.0 })
+                .unwrap_or_default(),
+            id: Some(cfg.id.clone()),
+            ..Default::default()
+        };
+
+        let response = cloud_hypervisor_vm_netdev_add(
+            socket.try_clone().context("failed to clone socket")?,
+            net_config,
+        )
+        .await?;
+
+        if let Some(detail) = response {
+            debug!(sl!(), "net add response: {:?}", detail);
+        }
+
+        Ok(())
+    }
+
+    async fn handle_block_device(&mut self, cfg: &BlockConfig) -> Result<()> {
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let direct = self
+            .config
+            .as_ref()
+            .map(|c| c.blockdev_info.block_device_cache_direct)
+            .unwrap_or_default();
+
+        let response = cloud_hypervisor_vm_blockdev_add(
+            socket.try_clone().context("failed to clone socket")?,
+            disk_config(cfg, direct),
+        )
+        .await?;
+
+        if let Some(detail) = response {
+            debug!(sl!(), "blockdev add response: {:?}", detail);
+            let pci_path = pci_path_from_response(&detail).context("get block device PCI path")?;
+            self.device_pci_paths.insert(cfg.id.clone(), pci_path);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn get_device_pci_path(&self, id: &str) -> Result<String> {
+        self.device_pci_paths
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("device {} is not hotplugged", id))
+    }
+
+    pub(crate) async fn resize_block_device(&self, id: &str, _new_size: u64) -> Result<()> {
+        Err(anyhow!(
+            "resizing block device {} is not supported by cloud hypervisor",
+            id
+        ))
+    }
+
+    async fn handle_vfio_device(&mut self, cfg: &VfioConfig) -> Result<()> {
+        // CH only passes through PCI devices
+        if let VfioBusMode::MMIO = cfg.mode {
+            return Err(anyhow!(
+                "vfio device {} in mmio mode is not supported",
+                cfg.id
+            ));
+        }
+
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let response = cloud_hypervisor_vm_device_add(
+            socket.try_clone().context("failed to clone socket")?,
+            vfio_device_config(cfg),
+        )
+        .await?;
+
+        if let Some(detail) = response {
+            debug!(sl!(), "vfio add response: {:?}", detail);
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn get_shared_fs_devices(&mut self) -> Result<Option<Vec<FsConfig>>> {
         let pending_root_devices = self.pending_devices.take();
 
         let mut root_devices = Vec::<FsConfig>::new();
 
         if let Some(devices) = pending_root_devices {
+            // The devices which can be hotplugged are kept pending, they're
+            // added once the VM is running.
+            let mut hotplug_devices = Vec::<Device>::new();
+
             for dev in devices {
                 match dev {
                     Device::ShareFsDevice(dev) => {
@@ -139,10 +289,17 @@ impl CloudHypervisorInner {
 
                         root_devices.push(fs_cfg);
                     }
+                    Device::Network(_) | Device::Block(_) | Device::Vfio(_) => {
+                        hotplug_devices.push(dev)
+                    }
                     _ => continue,
                 };
             }
 
+            if !hotplug_devices.is_empty() {
+                self.pending_devices = Some(hotplug_devices);
+            }
+
             Ok(Some(root_devices))
         } else {
             Ok(None)
@@ -233,3 +390,185 @@ impl TryFrom<ShareFsSettings> for FsConfig {
         Ok(fs_cfg)
     }
 }
+
+fn is_same_device(a: &Device, b: &Device) -> bool {
+    match (a, b) {
+        (Device::Network(a), Device::Network(b)) => a.id == b.id,
+        (Device::Block(a), Device::Block(b)) => a.id == b.id,
+        (Device::Vfio(a), Device::Vfio(b)) => a.id == b.id,
+        _ => false,
+    }
+}
+
+fn disk_config(cfg: &BlockConfig, direct: bool) -> DiskConfig {
+    DiskConfig {
+        path: Some(PathBuf::from(&cfg.path_on_host)),
+        readonly: cfg.is_readonly,
+        direct,
+        num_queues: DEFAULT_DISK_QUEUES,
+        queue_size: DEFAULT_DISK_QUEUE_SIZE,
+        id: Some(cfg.id.clone()),
+        ..Default::default()
+    }
+}
+
+fn vfio_device_config(cfg: &VfioConfig) -> DeviceConfig {
+    let sysfs_path = if cfg.sysfs_path.is_empty() {
+        format!("{}/{}", SYS_PCI_DEVICES_PATH, cfg.bus_slot_func)
+    } else {
+        cfg.sysfs_path.clone()
+    };
+
+    DeviceConfig {
+        path: PathBuf::from(sysfs_path),
+        id: Some(cfg.id.clone()),
+        ..Default::default()
+    }
+}
+
+// The response of a device hotplug is the PciDeviceInfo of the device, e.g.
+// {"id":"drive_1","bdf":"0000:00:05.0"}. CH plugs the devices on the root bus, the guest PCI
+// path is the slot of the device.
+fn pci_path_from_response(response: &str) -> Result<String> {
+    let info: serde_json::Value =
+        serde_json::from_str(response).context("parse PCI device info")?;
+    let bdf = info["bdf"]
+        .as_str()
+        .ok_or_else(|| anyhow!("no bdf in PCI device info {}", response))?;
+    bdf.rsplit(':')
+        .next()
+        .and_then(|dev_func| dev_func.split('.').next())
+        .filter(|slot| !slot.is_empty())
+        .map(|slot| slot.to_string())
+        .ok_or_else(|| anyhow!("invalid bdf {}", bdf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_pci_path_from_response() {
+        assert_eq!(
+            pci_path_from_response(r#"{"id":"drive_1","bdf":"0000:00:05.0"}"#).unwrap(),
+            "05"
+        );
+        assert!(pci_path_from_response(r#"{"id":"drive_1"}"#).is_err());
+        assert!(pci_path_from_response("not json").is_err());
+    }
+
+    fn block_device(id: &str) -> Device {
+        Device::Block(BlockConfig {
+            id: id.to_string(),
+            path_on_host: format!("/dev/{}", id),
+            is_readonly: false,
+            no_drop: false,
+            index: 0,
+        })
+    }
+
+    fn network_device(id: &str) -> Device {
+        Device::Network(NetworkConfig {
+            id: id.to_string(),
+            host_dev_name: format!("tap-{}", id),
+            guest_mac: None,
+        })
+    }
+
+    fn vfio_config(id: &str, mode: VfioBusMode) -> VfioConfig {
+        VfioConfig {
+            id: id.to_string(),
+            sysfs_path: String::new(),
+            bus_slot_func: "0000:01:00.0".to_string(),
+            mode,
+        }
+    }
+
+    fn pending_ids(ch: &CloudHypervisorInner) -> Vec<String> {
+        ch.pending_devices
+            .iter()
+            .flatten()
+            .map(|d| match d {
+                Device::Block(cfg) => cfg.id.clone(),
+                Device::Network(cfg) => cfg.id.clone(),
+                Device::Vfio(cfg) => cfg.id.clone(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_add_device_before_boot() {
+        let mut ch = CloudHypervisorInner::new();
+
+        block_on(ch.add_device(block_device("blk0"))).unwrap();
+        block_on(ch.add_device(network_device("net0"))).unwrap();
+        // the devices are popped from the end of the list, in the order they were added
+        assert_eq!(pending_ids(&ch), vec!["net0", "blk0"]);
+
+        assert!(block_on(ch.handle_pending_devices_after_boot()).is_err());
+    }
+
+    #[test]
+    fn test_remove_pending_device() {
+        let mut ch = CloudHypervisorInner::new();
+        block_on(ch.add_device(block_device("dev0"))).unwrap();
+        block_on(ch.add_device(network_device("dev0"))).unwrap();
+
+        // no API call for a pending device, there is no API socket
+        block_on(ch.remove_device(block_device("dev0"))).unwrap();
+        assert_eq!(pending_ids(&ch), vec!["dev0"]);
+        assert!(matches!(
+            ch.pending_devices.as_ref().unwrap()[0],
+            Device::Network(_)
+        ));
+
+        // a device which is not pending is removed with the API
+        let err = block_on(ch.remove_device(block_device("dev1"))).unwrap_err();
+        assert!(err.to_string().contains("missing socket"));
+    }
+
+    #[test]
+    fn test_hotplug_vfio_device() {
+        let mut ch = CloudHypervisorInner::new();
+        ch.state = VmmState::VmRunning;
+
+        let err = block_on(ch.add_device(Device::Vfio(vfio_config("vfio0", VfioBusMode::MMIO))))
+            .unwrap_err();
+        assert!(err.to_string().contains("mmio mode is not supported"));
+
+        let mut cfg = vfio_config("vfio0", VfioBusMode::PCI);
+        let device_config = vfio_device_config(&cfg);
+        assert_eq!(
+            device_config.path,
+            PathBuf::from("/sys/bus/pci/devices/0000:01:00.0")
+        );
+        assert_eq!(device_config.id, Some("vfio0".to_string()));
+
+        cfg.sysfs_path = "/sys/devices/pci0000:00/0000:00:02.0".to_string();
+        assert_eq!(
+            vfio_device_config(&cfg).path,
+            PathBuf::from("/sys/devices/pci0000:00/0000:00:02.0")
+        );
+    }
+
+    #[test]
+    fn test_hotplug_block_device() {
+        let cfg = BlockConfig {
+            id: "blk0".to_string(),
+            path_on_host: "/dev/sdb".to_string(),
+            is_readonly: true,
+            no_drop: false,
+            index: 1,
+        };
+
+        let disk = disk_config(&cfg, true);
+        assert_eq!(disk.path, Some(PathBuf::from("/dev/sdb")));
+        assert!(disk.readonly);
+        assert!(disk.direct);
+        assert_eq!(disk.num_queues, DEFAULT_DISK_QUEUES);
+        assert_eq!(disk.queue_size, DEFAULT_DISK_QUEUE_SIZE);
+        assert_eq!(disk.id, Some("blk0".to_string()));
+    }
+}
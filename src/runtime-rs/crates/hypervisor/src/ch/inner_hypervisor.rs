@@ -6,19 +6,25 @@
 use super::inner::CloudHypervisorInner;
 use crate::ch::utils::get_api_socket_path;
 use crate::ch::utils::{get_jailer_root, get_sandbox_path, get_vsock_path};
+use crate::utils::get_child_threads;
 use crate::Device;
 use crate::VsockConfig;
 use crate::{VcpuThreadIds, VmmState};
 use anyhow::{anyhow, Context, Result};
 use ch_config::ch_api::{
-    cloud_hypervisor_vm_create, cloud_hypervisor_vm_start, cloud_hypervisor_vmm_ping,
+    cloud_hypervisor_vm_create, cloud_hypervisor_vm_pause, cloud_hypervisor_vm_resume,
+    cloud_hypervisor_vm_snapshot, cloud_hypervisor_vm_start, cloud_hypervisor_vmm_ping,
     cloud_hypervisor_vmm_shutdown,
 };
+use ch_config::VmSnapshotConfig;
 use core::future::poll_fn;
 use futures::executor::block_on;
 use futures::future::join_all;
-use kata_types::capabilities::{Capabilities, CapabilityBits};
-use std::fs::create_dir_all;
+use kata_types::capabilities::Capabilities;
+use nix::sched::{setns, CloneFlags};
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, File};
+use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
 use std::path::Path;
 use std::process::Stdio;
@@ -35,6 +41,12 @@ const CH_NAME: &str = "cloud-hypervisor";
 /// Number of milliseconds to wait before retrying a CH operation.
 const CH_POLL_TIME_MS: u64 = 50;
 
+/// CH names its vCPU threads "vcpu<index>".
+const CH_VCPU_THREAD_PREFIX: &str = "vcpu";
+
+/// Subdirectory of the VM path where the snapshot is saved.
+const CH_SNAPSHOT_DIR: &str = "snapshot";
+
 impl CloudHypervisorInner {
     async fn start_hypervisor(&mut self, timeout_secs: i32) -> Result<()> {
         self.cloud_hypervisor_launch(timeout_secs)
@@ -94,6 +106,10 @@ impl CloudHypervisorInner {
 
         self.state = VmmState::VmRunning;
 
+        self.handle_pending_devices_after_boot()
+            .await
+            .context("add pending devices")?;
+
         Ok(())
     }
 
@@ -190,6 +206,17 @@ impl CloudHypervisorInner {
 
         let path = Path::new(&binary_path).canonicalize()?;
 
+        // The network devices are created in the netns of the sandbox, CH
+        // must run there to open their tap devices.
+        let netns_file = match &self.netns {
+            Some(netns_path) => Some(
+                File::open(netns_path)
+                    .with_context(|| format!("open netns path {}", netns_path))?,
+            ),
+            None => None,
+        };
+        let netns_fd = netns_file.as_ref().map(|f| f.as_raw_fd());
+
         let mut cmd = Command::new(path);
 
         cmd.current_dir("/");
@@ -214,6 +241,18 @@ impl CloudHypervisorInner {
             cmd.args(["--seccomp", "false"]);
         }
 
+        // SAFETY: the closure runs in the forked child before exec, it only
+        // calls setns(2), which is async-signal-safe, and allocates nothing.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(fd) = netns_fd {
+                    setns(fd, CloneFlags::CLONE_NEWNET)
+                        .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+                }
+                Ok(())
+            });
+        }
+
         let child = cmd.spawn().context(format!("{} spawn failed", CH_NAME))?;
 
         // Save process PID
@@ -377,18 +416,92 @@ impl CloudHypervisorInner {
         Ok(())
     }
 
-    pub(crate) fn pause_vm(&self) -> Result<()> {
+    pub(crate) async fn pause_vm(&mut self) -> Result<()> {
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let response =
+            cloud_hypervisor_vm_pause(socket.try_clone().context("failed to clone socket")?)
+                .await
+                .context("pause vm")?;
+
+        if let Some(detail) = response {
+            debug!(sl!(), "vm pause response: {:?}", detail);
+        }
+        self.paused = true;
+
         Ok(())
     }
 
-    pub(crate) fn resume_vm(&self) -> Result<()> {
+    pub(crate) async fn resume_vm(&mut self) -> Result<()> {
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let response =
+            cloud_hypervisor_vm_resume(socket.try_clone().context("failed to clone socket")?)
+                .await
+                .context("resume vm")?;
+
+        if let Some(detail) = response {
+            debug!(sl!(), "vm resume response: {:?}", detail);
+        }
+        self.paused = false;
+
         Ok(())
     }
 
-    pub(crate) async fn save_vm(&self) -> Result<()> {
+    fn is_vm_running(&self) -> bool {
+        self.state == VmmState::VmRunning && !self.paused
+    }
+
+    // save_vm saves a snapshot of the VM into the VM directory, CH requires
+    // the VM to be paused while the snapshot is created. A running VM is
+    // paused for the snapshot and resumed afterwards, a paused VM is left
+    // paused.
+    pub(crate) async fn save_vm(&mut self) -> Result<()> {
+        let socket = self
+            .api_socket
+            .as_ref()
+            .ok_or("missing socket")
+            .map_err(|e| anyhow!(e))?;
+
+        let snapshot_dir = [self.vm_path.as_str(), CH_SNAPSHOT_DIR].join("/");
+        create_dir_all(&snapshot_dir)
+            .with_context(|| format!("failed to create snapshot directory {}", snapshot_dir))?;
+
+        let snapshot_config = VmSnapshotConfig {
+            destination_url: format!("file://{}", snapshot_dir),
+        };
+        let socket = socket.try_clone().context("failed to clone socket")?;
+
+        let was_running = self.is_vm_running();
+        if was_running {
+            self.pause_vm().await?;
+        }
+        let result = cloud_hypervisor_vm_snapshot(socket, snapshot_config)
+            .await
+            .context("snapshot vm");
+        if was_running {
+            self.resume_vm().await?;
+        }
+
+        if let Some(detail) = result? {
+            debug!(sl!(), "vm snapshot response: {:?}", detail);
+        }
+
         Ok(())
     }
 
+    pub(crate) async fn resize_memory(&self, _new_mem_mb: u32) -> Result<u32> {
+        Err(anyhow!("CH hypervisor doesn't support memory resize yet"))
+    }
+
     pub(crate) async fn get_agent_socket(&self) -> Result<String> {
         const HYBRID_VSOCK_SCHEME: &str = "hvsock";
 
@@ -404,7 +517,32 @@ impl CloudHypervisorInner {
     }
 
     pub(crate) async fn get_thread_ids(&self) -> Result<VcpuThreadIds> {
-        Ok(VcpuThreadIds::default())
+        let pid = self.pid.ok_or("no CH process").map_err(|e| anyhow!(e))?;
+
+        let mut vcpu_thread_ids = VcpuThreadIds {
+            vcpus: HashMap::new(),
+        };
+
+        // The CH API doesn't report the vCPU threads, find them by name.
+        for tid in get_child_threads(pid) {
+            let comm_path = format!("/proc/{}/task/{}/comm", pid, tid);
+            let comm = match std::fs::read_to_string(&comm_path) {
+                Ok(comm) => comm,
+                // the thread may have exited in the meantime
+                Err(_) => continue,
+            };
+
+            if let Some(index) = comm
+                .trim()
+                .strip_prefix(CH_VCPU_THREAD_PREFIX)
+                .and_then(|index| index.parse::<u32>().ok())
+            {
+                vcpu_thread_ids.vcpus.insert(index, tid);
+            }
+        }
+
+        info!(sl!(), "get thread ids {:?}", vcpu_thread_ids);
+        Ok(vcpu_thread_ids)
     }
 
     pub(crate) async fn cleanup(&self) -> Result<()> {
@@ -412,7 +550,25 @@ impl CloudHypervisorInner {
     }
 
     pub(crate) async fn get_pids(&self) -> Result<Vec<u32>> {
-        Ok(Vec::<u32>::new())
+        let pid = match self.pid {
+            Some(pid) => pid,
+            None => return Ok(Vec::<u32>::new()),
+        };
+
+        let mut pids = HashSet::new();
+        pids.insert(pid);
+
+        for tid in get_child_threads(pid) {
+            pids.insert(tid);
+        }
+
+        // remove vcpus
+        for tid in self.get_thread_ids().await?.vcpus.values() {
+            pids.remove(tid);
+        }
+
+        info!(sl!(), "get pids {:?}", pids);
+        Ok(pids.into_iter().collect())
     }
 
     pub(crate) async fn check(&self) -> Result<()> {
@@ -428,9 +584,11 @@ impl CloudHypervisorInner {
     }
 
     pub(crate) async fn capabilities(&self) -> Result<Capabilities> {
-        let mut caps = Capabilities::default();
-        caps.set(CapabilityBits::FsSharingSupport);
-        Ok(caps)
+        Ok(self.capabilities.clone())
+    }
+
+    pub(crate) async fn get_hypervisor_metrics(&self) -> Result<String> {
+        Err(anyhow!("CH hypervisor doesn't support metrics yet"))
     }
 }
 
@@ -484,3 +642,37 @@ async fn cloud_hypervisor_log_output(mut child: Child, mut shutdown: Receiver<bo
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_vm_running() {
+        let mut ch = CloudHypervisorInner::new();
+        assert!(!ch.is_vm_running());
+
+        ch.state = VmmState::VmRunning;
+        assert!(ch.is_vm_running());
+
+        ch.paused = true;
+        assert!(!ch.is_vm_running());
+    }
+
+    #[test]
+    fn test_pause_resume_state() {
+        let mut ch = CloudHypervisorInner::new();
+        ch.state = VmmState::VmRunning;
+
+        // the paused state only changes when the API call succeeds
+        assert!(block_on(ch.pause_vm()).is_err());
+        assert!(!ch.paused);
+
+        ch.paused = true;
+        assert!(block_on(ch.resume_vm()).is_err());
+        assert!(ch.paused);
+
+        assert!(block_on(ch.save_vm()).is_err());
+        assert!(ch.paused);
+    }
+}
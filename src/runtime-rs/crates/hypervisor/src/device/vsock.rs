@@ -50,17 +50,21 @@ nix::ioctl_write_ptr!(
 
 const CID_RETRY_COUNT: u32 = 50;
 
+async fn open_vhost_vsock() -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(VHOST_VSOCK_DEVICE)
+        .await
+        .context(format!(
+            "failed to open {}, try to run modprobe vhost_vsock.",
+            VHOST_VSOCK_DEVICE
+        ))
+}
+
 impl VsockConfig {
     pub async fn new(id: String) -> Result<Self> {
-        let vhost_fd = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(VHOST_VSOCK_DEVICE)
-            .await
-            .context(format!(
-                "failed to open {}, try to run modprobe vhost_vsock.",
-                VHOST_VSOCK_DEVICE
-            ))?;
+        let vhost_fd = open_vhost_vsock().await?;
         let mut rng = rand::thread_rng();
 
         // Try 50 times to find a context ID that is not in use.
@@ -92,4 +96,17 @@ impl VsockConfig {
             CID_RETRY_COUNT
         );
     }
+
+    /// Claim the given guest CID again, e.g. the one of a restored sandbox.
+    pub async fn with_guest_cid(id: String, guest_cid: u32) -> Result<Self> {
+        let vhost_fd = open_vhost_vsock().await?;
+        unsafe { vhost_vsock_set_guest_cid(vhost_fd.as_raw_fd(), &(guest_cid as u64)) }
+            .with_context(|| format!("failed to set guest CID {}", guest_cid))?;
+
+        Ok(VsockConfig {
+            id,
+            guest_cid,
+            vhost_fd,
+        })
+    }
 }
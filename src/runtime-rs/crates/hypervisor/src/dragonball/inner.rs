@@ -7,12 +7,12 @@
 use super::vmm_instance::VmmInstance;
 use crate::{
     device::Device, hypervisor_persist::HypervisorState, kernel_param::KernelParams, VmmState,
-    DEV_HUGEPAGES, HUGETLBFS, HYPERVISOR_DRAGONBALL, SHMEM, VM_ROOTFS_DRIVER_BLK,
+    VsockConfig, DEV_HUGEPAGES, HUGETLBFS, HYPERVISOR_DRAGONBALL, SHMEM, VM_ROOTFS_DRIVER_BLK,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use dragonball::{
-    api::v1::{BlockDeviceConfigInfo, BootSourceConfig},
+    api::v1::{BalloonDeviceConfigInfo, BlockDeviceConfigInfo, BootSourceConfig},
     vm::VmConfigInfo,
 };
 use kata_sys_util::mount;
@@ -26,6 +26,7 @@ use std::{collections::HashSet, fs::create_dir_all, path::PathBuf};
 
 const DRAGONBALL_KERNEL: &str = "vmlinux";
 const DRAGONBALL_ROOT_FS: &str = "rootfs";
+const DRAGONBALL_BALLOON: &str = "balloon0";
 
 unsafe impl Sync for DragonballInner {}
 pub struct DragonballInner {
@@ -64,6 +65,12 @@ pub struct DragonballInner {
 
     /// dragonball capabilities
     pub(crate) capabilities: Capabilities,
+
+    /// guest CID of the vhost-vsock device
+    pub(crate) guest_cid: u32,
+
+    /// vhost-vsock device, hold to ensure the guest CID is not used by other VM
+    pub(crate) vhost_vsock: Option<VsockConfig>,
 }
 
 impl DragonballInner {
@@ -87,6 +94,8 @@ impl DragonballInner {
             run_dir: "".to_string(),
             cached_block_devices: Default::default(),
             capabilities,
+            guest_cid: 0,
+            vhost_vsock: None,
         }
     }
 
@@ -140,6 +149,11 @@ impl DragonballInner {
             self.add_device(dev).await.context("add_device")?;
         }
 
+        // the balloon holds the memory above default_memory until it's resized
+        if self.is_mem_resize_enabled() {
+            self.set_vm_balloon().context("set vm balloon")?;
+        }
+
         // start vmm and wait ready
         self.start_vmm_instance().context("start vmm instance")?;
         self.wait_vmm_ready(timeout).context("wait vmm")?;
@@ -194,9 +208,16 @@ impl DragonballInner {
         } else {
             (String::from(SHMEM), String::from(""))
         };
+        // boot with the max memory when the memory can be resized, the balloon gives the memory
+        // to the guest on demand
+        let mem_size_mib = if self.is_mem_resize_enabled() {
+            self.config.memory_info.default_maxmemory
+        } else {
+            self.config.memory_info.default_memory
+        };
         let vm_config = VmConfigInfo {
             serial_path: Some(serial_path),
-            mem_size_mib: self.config.memory_info.default_memory as usize,
+            mem_size_mib: mem_size_mib as usize,
             vcpu_count: self.config.cpu_info.default_vcpus as u8,
             max_vcpu_count: self.config.cpu_info.default_maxvcpus as u8,
             mem_type,
@@ -291,6 +312,26 @@ impl DragonballInner {
         }
     }
 
+    fn set_vm_balloon(&mut self) -> Result<()> {
+        let memory_info = &self.config.memory_info;
+        let balloon_cfg = BalloonDeviceConfigInfo {
+            balloon_id: DRAGONBALL_BALLOON.to_string(),
+            size_mib: (memory_info.default_maxmemory - memory_info.default_memory) as u64,
+            f_deflate_on_oom: true,
+            f_reporting: memory_info.reclaim_guest_freed_memory,
+            ..Default::default()
+        };
+        info!(sl!(), "set vm balloon {:?}", balloon_cfg);
+
+        self.vmm_instance
+            .insert_balloon(balloon_cfg)
+            .context("insert balloon device")
+    }
+
+    pub(crate) fn is_mem_resize_enabled(&self) -> bool {
+        self.config.memory_info.default_maxmemory > self.config.memory_info.default_memory
+    }
+
     fn start_vmm_instance(&mut self) -> Result<()> {
         info!(sl!(), "Starting VM");
         self.vmm_instance
@@ -329,6 +370,9 @@ impl DragonballInner {
 
     pub fn set_hypervisor_config(&mut self, config: HypervisorConfig) {
         self.config = config;
+        if self.is_mem_resize_enabled() {
+            self.capabilities.add(CapabilityBits::MemoryResizeSupport);
+        }
     }
 
     pub fn hypervisor_config(&self) -> HypervisorConfig {
@@ -353,6 +397,7 @@ impl Persist for DragonballInner {
             config: self.hypervisor_config(),
             run_dir: self.run_dir.clone(),
             cached_block_devices: self.cached_block_devices.clone(),
+            guest_cid: self.guest_cid,
             ..Default::default()
         })
     }
@@ -362,6 +407,22 @@ impl Persist for DragonballInner {
         _hypervisor_args: Self::ConstructorArgs,
         hypervisor_state: Self::State,
     ) -> Result<Self> {
+        // hold the guest CID again, so that it's not reused before the sandbox is cleaned up
+        let vhost_vsock = if hypervisor_state.config.device_info.use_vhost_vsock
+            && hypervisor_state.guest_cid > 2
+        {
+            let id = format!("vsock-{}", &hypervisor_state.id);
+            match VsockConfig::with_guest_cid(id, hypervisor_state.guest_cid).await {
+                Ok(vsock) => Some(vsock),
+                Err(err) => {
+                    warn!(sl!(), "failed to restore vhost-vsock: {:?}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         Ok(DragonballInner {
             id: hypervisor_state.id,
             vm_path: hypervisor_state.vm_path,
@@ -375,6 +436,8 @@ impl Persist for DragonballInner {
             pending_devices: vec![],
             cached_block_devices: hypervisor_state.cached_block_devices,
             capabilities: Capabilities::new(),
+            guest_cid: hypervisor_state.guest_cid,
+            vhost_vsock,
         })
     }
 }
@@ -4,10 +4,12 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use std::path::PathBuf;
+use std::{os::unix::io::AsRawFd, path::PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use dbs_utils::net::MacAddr;
+#[cfg(target_arch = "x86_64")]
+use dragonball::api::v1::VfioDeviceConfigInfo;
 use dragonball::api::v1::{
     BlockDeviceConfigInfo, FsDeviceConfigInfo, FsMountConfigInfo, VirtioNetDeviceConfigInfo,
     VsockDeviceConfigInfo,
@@ -16,8 +18,10 @@ use dragonball::api::v1::{
 use super::DragonballInner;
 use crate::{
     device::Device, HybridVsockConfig, NetworkConfig, ShareFsDeviceConfig, ShareFsMountConfig,
-    ShareFsMountType, ShareFsOperation, VmmState,
+    ShareFsMountType, ShareFsOperation, VmmState, VsockConfig,
 };
+#[cfg(target_arch = "x86_64")]
+use crate::{VfioBusMode, VfioConfig};
 
 const MB_TO_B: u32 = 1024 * 1024;
 const DEFAULT_VIRTIO_FS_NUM_QUEUES: i32 = 1;
@@ -45,9 +49,10 @@ impl DragonballInner {
         info!(sl!(), "dragonball add device {:?}", &device);
         match device {
             Device::Network(config) => self.add_net_device(&config).context("add net device"),
-            Device::Vfio(_config) => {
-                todo!()
-            }
+            #[cfg(target_arch = "x86_64")]
+            Device::Vfio(config) => self.add_vfio_device(&config).context("add vfio device"),
+            #[cfg(not(target_arch = "x86_64"))]
+            Device::Vfio(_) => Err(anyhow!("vfio is only supported on x86_64")),
             Device::Block(config) => self
                 .add_block_device(
                     config.path_on_host.as_str(),
@@ -63,9 +68,7 @@ impl DragonballInner {
             Device::ShareFsMount(config) => self
                 .add_share_fs_mount(&config)
                 .context("add share fs mount"),
-            Device::Vsock(_) => {
-                todo!()
-            }
+            Device::Vsock(config) => self.add_vsock(config).context("add vsock"),
         }
     }
 
@@ -78,13 +81,35 @@ impl DragonballInner {
                 self.remove_block_drive(drive_id.as_str())
                     .context("remove block drive")
             }
-            Device::Vfio(_config) => {
-                todo!()
-            }
+            #[cfg(target_arch = "x86_64")]
+            Device::Vfio(config) => self
+                .vmm_instance
+                .remove_vfio_device(&config.id)
+                .context("remove vfio device"),
+            Device::Network(config) => self
+                .vmm_instance
+                .remove_network_device(&config.id)
+                .context("remove network device"),
             _ => Err(anyhow!("unsupported device {:?}", device)),
         }
     }
 
+    pub(crate) fn get_device_pci_path(&self, id: &str) -> Result<String> {
+        // the block and network devices of dragonball are virtio-mmio devices
+        Err(anyhow!(
+            "device {} has no PCI path, dragonball plugs the devices on the mmio bus",
+            id
+        ))
+    }
+
+    pub(crate) async fn resize_block_device(&self, id: &str, _new_size: u64) -> Result<()> {
+        // the capacity of the virtio block devices is fixed once they are created
+        Err(anyhow!(
+            "resizing block device {} is not supported by dragonball",
+            id
+        ))
+    }
+
     fn add_block_device(
         &mut self,
         path: &str,
@@ -121,6 +146,33 @@ impl DragonballInner {
         Ok(())
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn add_vfio_device(&mut self, config: &VfioConfig) -> Result<()> {
+        // dragonball only passes through the host devices on its PCI root bus
+        if let VfioBusMode::MMIO = config.mode {
+            return Err(anyhow!(
+                "vfio device {} in mmio mode is not supported",
+                config.id
+            ));
+        }
+
+        let vfio_cfg = VfioDeviceConfigInfo {
+            hostdev_id: config.id.clone(),
+            bus_slot_func: config.bus_slot_func.clone(),
+            sysfs_path: config.sysfs_path.clone(),
+            ..Default::default()
+        };
+
+        info!(
+            sl!(),
+            "add vfio device {} from {}", vfio_cfg.hostdev_id, vfio_cfg.bus_slot_func
+        );
+
+        self.vmm_instance
+            .insert_vfio_device(vfio_cfg)
+            .context("insert vfio device")
+    }
+
     fn add_net_device(&mut self, config: &NetworkConfig) -> Result<()> {
         let iface_cfg = VirtioNetDeviceConfigInfo {
             iface_id: config.id.clone(),
@@ -155,6 +207,22 @@ impl DragonballInner {
             .context("insert vsock")
     }
 
+    fn add_vsock(&mut self, config: VsockConfig) -> Result<()> {
+        let vsock_cfg = VsockDeviceConfigInfo {
+            id: String::from("root"),
+            guest_cid: config.guest_cid,
+            vhost_fd: Some(config.vhost_fd.as_raw_fd()),
+            ..Default::default()
+        };
+
+        self.vmm_instance
+            .insert_vsock(vsock_cfg)
+            .context("insert vsock")?;
+        // dragonball duplicates the vhost fd when the device is created at boot
+        self.vhost_vsock = Some(config);
+        Ok(())
+    }
+
     fn parse_inline_virtiofs_args(&self, fs_cfg: &mut FsDeviceConfigInfo) -> Result<()> {
         let mut debug = false;
         let mut opt_list = String::new();
@@ -16,6 +16,8 @@ use super::inner::DragonballInner;
 use crate::{utils, VcpuThreadIds, VmmState};
 use shim_interface::KATA_PATH;
 const DEFAULT_HYBRID_VSOCK_NAME: &str = "kata.hvsock";
+const VSOCK_SCHEME: &str = "vsock";
+const VSOCK_AGENT_PORT: u32 = 1024;
 
 fn get_vsock_path(root: &str) -> String {
     [root, DEFAULT_HYBRID_VSOCK_NAME].join("/")
@@ -31,12 +33,24 @@ impl DragonballInner {
         self.netns = netns;
 
         // prepare vsock
-        let uds_path = [&self.jailer_root, DEFAULT_HYBRID_VSOCK_NAME].join("/");
-        let d = crate::device::Device::HybridVsock(crate::device::HybridVsockConfig {
-            id: format!("vsock-{}", &self.id),
-            guest_cid: 3,
-            uds_path,
-        });
+        let d = if self.config.device_info.use_vhost_vsock {
+            warn!(
+                sl!(),
+                "vhost-vsock is enabled, vCPU and device hotplug are unavailable without the upcall"
+            );
+            let vsock = crate::device::VsockConfig::new(format!("vsock-{}", &self.id))
+                .await
+                .context("new vsock config")?;
+            self.guest_cid = vsock.guest_cid;
+            crate::device::Device::Vsock(vsock)
+        } else {
+            let uds_path = [&self.jailer_root, DEFAULT_HYBRID_VSOCK_NAME].join("/");
+            crate::device::Device::HybridVsock(crate::device::HybridVsockConfig {
+                id: format!("vsock-{}", &self.id),
+                guest_cid: 3,
+                uds_path,
+            })
+        };
 
         self.add_device(d).await.context("add device")?;
         Ok(())
@@ -75,11 +89,40 @@ impl DragonballInner {
         Ok(())
     }
 
+    // save_vm isn't supported, as the virtio devices of dragonball can't save their queue
+    // states, a VM restored without them would lose its block, network, vsock and fs devices.
     pub(crate) async fn save_vm(&self) -> Result<()> {
-        todo!()
+        Err(anyhow::anyhow!("dragonball doesn't support saving the vm"))
+    }
+
+    // resize_memory changes the memory available to the guest by resizing the balloon, the new
+    // size is kept between default_memory and default_maxmemory.
+    pub(crate) fn resize_memory(&self, new_mem_mb: u32) -> Result<u32> {
+        if !self.is_mem_resize_enabled() {
+            return Err(anyhow::anyhow!(
+                "memory resize isn't enabled, default_maxmemory isn't set"
+            ));
+        }
+
+        let memory_info = &self.config.memory_info;
+        let new_mem_mb =
+            new_mem_mb.clamp(memory_info.default_memory, memory_info.default_maxmemory);
+        info!(sl!(), "resize memory to {} MiB", new_mem_mb);
+        self.vmm_instance
+            .resize_memory(new_mem_mb as u64)
+            .context("resize memory")?;
+
+        Ok(new_mem_mb)
     }
 
     pub(crate) async fn get_agent_socket(&self) -> Result<String> {
+        if self.config.device_info.use_vhost_vsock {
+            return Ok(format!(
+                "{}://{}:{}",
+                VSOCK_SCHEME, self.guest_cid, VSOCK_AGENT_PORT
+            ));
+        }
+
         const HYBRID_VSOCK_SCHEME: &str = "hvsock";
         Ok(format!(
             "{}://{}",
@@ -138,4 +181,10 @@ impl DragonballInner {
     pub(crate) async fn capabilities(&self) -> Result<Capabilities> {
         Ok(self.capabilities.clone())
     }
+
+    // get_hypervisor_metrics returns the metrics of dragonball in json, the counters are the
+    // increments since the last call.
+    pub(crate) async fn get_hypervisor_metrics(&self) -> Result<String> {
+        serde_json::to_string(&*dragonball::metric::METRICS).context("serialize metrics")
+    }
 }
@@ -19,6 +19,7 @@ use async_trait::async_trait;
 use kata_types::capabilities::Capabilities;
 use kata_types::config::hypervisor::Hypervisor as HypervisorConfig;
 use tokio::sync::RwLock;
+use tracing::instrument;
 
 use crate::{device::Device, Hypervisor, VcpuThreadIds};
 
@@ -47,94 +48,136 @@ impl Dragonball {
 
 #[async_trait]
 impl Hypervisor for Dragonball {
+    #[instrument(skip_all)]
     async fn prepare_vm(&self, id: &str, netns: Option<String>) -> Result<()> {
         let mut inner = self.inner.write().await;
         inner.prepare_vm(id, netns).await
     }
 
+    #[instrument(skip_all)]
     async fn start_vm(&self, timeout: i32) -> Result<()> {
         let mut inner = self.inner.write().await;
         inner.start_vm(timeout).await
     }
 
+    #[instrument(skip_all)]
     async fn stop_vm(&self) -> Result<()> {
         let mut inner = self.inner.write().await;
         inner.stop_vm()
     }
 
+    #[instrument(skip_all)]
     async fn pause_vm(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.pause_vm()
     }
 
+    #[instrument(skip_all)]
     async fn resume_vm(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.resume_vm()
     }
 
+    #[instrument(skip_all)]
     async fn save_vm(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.save_vm().await
     }
 
+    #[instrument(skip_all)]
+    async fn resize_memory(&self, new_mem_mb: u32) -> Result<u32> {
+        let inner = self.inner.read().await;
+        inner.resize_memory(new_mem_mb)
+    }
+
+    #[instrument(skip_all)]
     async fn add_device(&self, device: Device) -> Result<()> {
         let mut inner = self.inner.write().await;
         inner.add_device(device).await
     }
 
+    #[instrument(skip_all)]
     async fn remove_device(&self, device: Device) -> Result<()> {
         let mut inner = self.inner.write().await;
         inner.remove_device(device).await
     }
 
+    #[instrument(skip_all)]
+    async fn get_device_pci_path(&self, id: &str) -> Result<String> {
+        let inner = self.inner.read().await;
+        inner.get_device_pci_path(id)
+    }
+
+    #[instrument(skip_all)]
+    async fn resize_block_device(&self, id: &str, new_size: u64) -> Result<()> {
+        let inner = self.inner.read().await;
+        inner.resize_block_device(id, new_size).await
+    }
+
+    #[instrument(skip_all)]
     async fn get_agent_socket(&self) -> Result<String> {
         let inner = self.inner.read().await;
         inner.get_agent_socket().await
     }
 
+    #[instrument(skip_all)]
     async fn disconnect(&self) {
         let mut inner = self.inner.write().await;
         inner.disconnect().await
     }
 
+    #[instrument(skip_all)]
     async fn hypervisor_config(&self) -> HypervisorConfig {
         let inner = self.inner.read().await;
         inner.hypervisor_config()
     }
 
+    #[instrument(skip_all)]
     async fn get_thread_ids(&self) -> Result<VcpuThreadIds> {
         let inner = self.inner.read().await;
         inner.get_thread_ids().await
     }
 
+    #[instrument(skip_all)]
     async fn cleanup(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.cleanup().await
     }
 
+    #[instrument(skip_all)]
     async fn get_pids(&self) -> Result<Vec<u32>> {
         let inner = self.inner.read().await;
         inner.get_pids().await
     }
 
+    #[instrument(skip_all)]
     async fn check(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.check().await
     }
 
+    #[instrument(skip_all)]
     async fn get_jailer_root(&self) -> Result<String> {
         let inner = self.inner.read().await;
         inner.get_jailer_root().await
     }
 
+    #[instrument(skip_all)]
     async fn save_state(&self) -> Result<HypervisorState> {
         self.save().await
     }
 
+    #[instrument(skip_all)]
     async fn capabilities(&self) -> Result<Capabilities> {
         let inner = self.inner.read().await;
         inner.capabilities().await
     }
+
+    #[instrument(skip_all)]
+    async fn get_hypervisor_metrics(&self) -> Result<String> {
+        let inner = self.inner.read().await;
+        inner.get_hypervisor_metrics().await
+    }
 }
 
 #[async_trait]
@@ -12,14 +12,18 @@ use std::{
         Arc, Mutex, RwLock,
     },
     thread,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context, Result};
+#[cfg(target_arch = "x86_64")]
+use dragonball::api::v1::VfioDeviceConfigInfo;
 use dragonball::{
     api::v1::{
-        BlockDeviceConfigInfo, BootSourceConfig, FsDeviceConfigInfo, FsMountConfigInfo,
-        InstanceInfo, InstanceState, VirtioNetDeviceConfigInfo, VmmAction, VmmActionError, VmmData,
-        VmmRequest, VmmResponse, VmmService, VsockDeviceConfigInfo,
+        BalloonDeviceConfigInfo, BlockDeviceConfigInfo, BootSourceConfig, FsDeviceConfigInfo,
+        FsMountConfigInfo, InstanceInfo, InstanceState, MemoryResizeInfo,
+        VirtioNetDeviceConfigInfo, VmmAction, VmmActionError, VmmData, VmmRequest, VmmResponse,
+        VmmService, VsockDeviceConfigInfo,
     },
     vm::VmConfigInfo,
     Vmm,
@@ -37,6 +41,7 @@ pub enum Request {
 const DRAGONBALL_VERSION: &str = env!("CARGO_PKG_VERSION");
 const REQUEST_RETRY: u32 = 500;
 const KVM_DEVICE: &str = "/dev/kvm";
+const HOT_UNPLUG_TIMEOUT_MS: u64 = 5000;
 
 pub struct VmmInstance {
     /// VMM instance info directly accessible from runtime
@@ -192,6 +197,23 @@ impl VmmInstance {
         Ok(())
     }
 
+    #[cfg(target_arch = "x86_64")]
+    pub fn insert_vfio_device(&self, device_cfg: VfioDeviceConfigInfo) -> Result<()> {
+        self.handle_request_with_retry(Request::Sync(VmmAction::InsertVfioDevice(
+            device_cfg.clone(),
+        )))
+        .with_context(|| format!("Failed to insert vfio device {:?}", device_cfg))?;
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn remove_vfio_device(&self, id: &str) -> Result<()> {
+        info!(sl!(), "remove vfio device {}", id);
+        self.handle_request(Request::Sync(VmmAction::RemoveVfioDevice(id.to_string())))
+            .with_context(|| format!("Failed to remove vfio device {:?}", id))?;
+        Ok(())
+    }
+
     pub fn set_vm_configuration(&self, vm_config: VmConfigInfo) -> Result<()> {
         self.handle_request(Request::Sync(VmmAction::SetVmConfiguration(
             vm_config.clone(),
@@ -208,6 +230,48 @@ impl VmmInstance {
         Ok(())
     }
 
+    pub fn remove_network_device(&self, id: &str) -> Result<()> {
+        info!(sl!(), "remove network device {}", id);
+        // the guest has to detach the device before it is destroyed
+        if self.is_running().is_ok() {
+            let data = self
+                .handle_request_with_retry(Request::Sync(VmmAction::PrepareRemoveNetworkDevice(
+                    id.to_string(),
+                )))
+                .with_context(|| format!("Failed to detach network device {:?}", id))?;
+            if let VmmData::SyncHotplug(receiver) = data {
+                match receiver.recv_timeout(Duration::from_millis(HOT_UNPLUG_TIMEOUT_MS)) {
+                    Ok(Some(0)) => {}
+                    Ok(Some(result)) => {
+                        return Err(anyhow!(
+                            "guest failed to detach network device {}: {}",
+                            id,
+                            result
+                        ))
+                    }
+                    Ok(None) => {
+                        return Err(anyhow!(
+                            "upcall reset while detaching network device {}",
+                            id
+                        ))
+                    }
+                    Err(e) => {
+                        return Err(anyhow!(
+                            "wait for guest to detach network device {}: {}",
+                            id,
+                            e
+                        ))
+                    }
+                }
+            }
+        }
+        self.handle_request(Request::Sync(VmmAction::RemoveNetworkDevice(
+            id.to_string(),
+        )))
+        .with_context(|| format!("Failed to remove network device {:?}", id))?;
+        Ok(())
+    }
+
     pub fn insert_vsock(&self, vsock_cfg: VsockDeviceConfigInfo) -> Result<()> {
         self.handle_request(Request::Sync(VmmAction::InsertVsockDevice(
             vsock_cfg.clone(),
@@ -233,12 +297,32 @@ impl VmmInstance {
         Ok(())
     }
 
+    pub fn insert_balloon(&self, balloon_cfg: BalloonDeviceConfigInfo) -> Result<()> {
+        self.handle_request(Request::Sync(VmmAction::InsertBalloonDevice(
+            balloon_cfg.clone(),
+        )))
+        .with_context(|| format!("Failed to insert balloon device {:?}", balloon_cfg))?;
+        Ok(())
+    }
+
+    pub fn resize_memory(&self, mem_size_mib: u64) -> Result<()> {
+        self.handle_request(Request::Sync(VmmAction::ResizeMemory(MemoryResizeInfo {
+            mem_size_mib,
+        })))
+        .with_context(|| format!("Failed to resize memory to {} MiB", mem_size_mib))?;
+        Ok(())
+    }
+
     pub fn pause(&self) -> Result<()> {
-        todo!()
+        self.handle_request(Request::Sync(VmmAction::PauseMicroVm))
+            .context("Failed to pause MicroVM")?;
+        Ok(())
     }
 
     pub fn resume(&self) -> Result<()> {
-        todo!()
+        self.handle_request(Request::Sync(VmmAction::ResumeMicroVm))
+            .context("Failed to resume MicroVM")?;
+        Ok(())
     }
 
     pub fn pid(&self) -> u32 {
@@ -6,7 +6,7 @@
 
 use crate::HypervisorConfig;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct HypervisorState {
@@ -33,4 +33,13 @@ pub struct HypervisorState {
     /// cached block device
     pub cached_block_devices: HashSet<String>,
     pub virtiofs_daemon_pid: i32,
+    /// guest CID of the vhost-vsock device
+    #[serde(default)]
+    pub guest_cid: u32,
+    /// PCI paths of the hotplugged devices by device id
+    #[serde(default)]
+    pub device_pci_paths: HashMap<String, String>,
+    /// qemu specific: PCI bridge and slot of the hotplugged devices by device id
+    #[serde(default)]
+    pub pci_slots: HashMap<String, (u32, u32)>,
 }
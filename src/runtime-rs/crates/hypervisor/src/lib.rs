@@ -76,10 +76,13 @@ pub trait Hypervisor: Send + Sync {
     async fn pause_vm(&self) -> Result<()>;
     async fn save_vm(&self) -> Result<()>;
     async fn resume_vm(&self) -> Result<()>;
+    async fn resize_memory(&self, new_mem_mb: u32) -> Result<u32>;
 
     // device manager
     async fn add_device(&self, device: device::Device) -> Result<()>;
     async fn remove_device(&self, device: device::Device) -> Result<()>;
+    async fn get_device_pci_path(&self, id: &str) -> Result<String>;
+    async fn resize_block_device(&self, id: &str, new_size: u64) -> Result<()>;
 
     // utils
     async fn get_agent_socket(&self) -> Result<String>;
@@ -92,4 +95,5 @@ pub trait Hypervisor: Send + Sync {
     async fn get_jailer_root(&self) -> Result<String>;
     async fn save_state(&self) -> Result<HypervisorState>;
     async fn capabilities(&self) -> Result<Capabilities>;
+    async fn get_hypervisor_metrics(&self) -> Result<String>;
 }
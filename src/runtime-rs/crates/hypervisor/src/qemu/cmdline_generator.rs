@@ -0,0 +1,480 @@
+// Copyright (c) 2022 Red Hat
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    kernel_param::KernelParams, BlockConfig, HypervisorConfig, NetworkConfig, ShareFsDeviceConfig,
+    VfioConfig, DEV_HUGEPAGES, VM_ROOTFS_DRIVER_BLK, VM_ROOTFS_DRIVER_PMEM,
+};
+
+// Identifiers used on the QEMU command line for the boot image and the
+// shared guest memory backend.
+const IMAGE_DRIVE_ID: &str = "image-rootfs";
+const IMAGE_PMEM_ID: &str = "mem-rootfs";
+const SHARED_MEMORY_ID: &str = "dimm1";
+const DEV_SHM: &str = "/dev/shm";
+
+const CONSOLE_DEVICE_ID: &str = "charconsole0";
+
+// QEMU machine types supported by the command line generator, together
+// with the name of their root PCI bus.
+const MACHINE_TYPE_Q35: &str = "q35";
+const MACHINE_TYPE_PC: &str = "pc";
+const MACHINE_TYPE_VIRT: &str = "virt";
+const ROOT_BUS_PCIE: &str = "pcie.0";
+const ROOT_BUS_PCI: &str = "pci.0";
+
+// The first slots of the root bus are used by the chipset, bridges start
+// right after them.
+const BRIDGE_FIRST_ADDR: u32 = 2;
+
+pub(crate) const BRIDGE_ID_PREFIX: &str = "pci-bridge-";
+
+pub(crate) fn bridge_id(index: u32) -> String {
+    format!("{}{}", BRIDGE_ID_PREFIX, index)
+}
+
+pub(crate) fn bridge_addr(index: u32) -> u32 {
+    BRIDGE_FIRST_ADDR + index
+}
+
+pub(crate) fn root_bus(machine_type: &str) -> Result<&'static str> {
+    match machine_type {
+        MACHINE_TYPE_Q35 | MACHINE_TYPE_VIRT => Ok(ROOT_BUS_PCIE),
+        MACHINE_TYPE_PC => Ok(ROOT_BUS_PCI),
+        _ => Err(anyhow!("unsupported machine type {}", machine_type)),
+    }
+}
+
+pub(crate) fn vm_rootfs_driver(config: &HypervisorConfig) -> &'static str {
+    if !config.boot_info.image.is_empty() && !config.blockdev_info.disable_image_nvdimm {
+        VM_ROOTFS_DRIVER_PMEM
+    } else {
+        VM_ROOTFS_DRIVER_BLK
+    }
+}
+
+/// Builder of the QEMU command line arguments for a sandbox VM.
+pub(crate) struct QemuCmdLine<'a> {
+    id: &'a str,
+    config: &'a HypervisorConfig,
+    run_dir: &'a str,
+    devices: Vec<String>,
+    need_shared_memory: bool,
+}
+
+impl<'a> QemuCmdLine<'a> {
+    pub(crate) fn new(id: &'a str, config: &'a HypervisorConfig, run_dir: &'a str) -> Self {
+        Self {
+            id,
+            config,
+            run_dir,
+            devices: vec![],
+            need_shared_memory: config.memory_info.enable_hugepages,
+        }
+    }
+
+    pub(crate) fn qmp_socket_path(run_dir: &str) -> String {
+        [run_dir, "qmp.sock"].join("/")
+    }
+
+    pub(crate) fn console_socket_path(run_dir: &str) -> String {
+        [run_dir, "console.sock"].join("/")
+    }
+
+    pub(crate) fn add_vsock(&mut self, guest_cid: u32, vhost_fd: i32) {
+        self.devices.push("-device".to_string());
+        self.devices.push(format!(
+            "vhost-vsock-pci,id=vsock-{},guest-cid={},vhostfd={}",
+            guest_cid, guest_cid, vhost_fd
+        ));
+    }
+
+    pub(crate) fn add_share_fs_device(&mut self, config: &ShareFsDeviceConfig) {
+        let chardev_id = format!("char-{}", config.mount_tag);
+        self.devices.push("-chardev".to_string());
+        self.devices.push(format!(
+            "socket,id={},path={}",
+            chardev_id, config.sock_path
+        ));
+        self.devices.push("-device".to_string());
+        self.devices.push(format!(
+            "vhost-user-fs-pci,chardev={},tag={},queue-size={}",
+            chardev_id, config.mount_tag, config.queue_size
+        ));
+
+        // vhost-user devices require the guest memory to be shared with
+        // the backend daemon.
+        self.need_shared_memory = true;
+    }
+
+    pub(crate) fn add_block_device(&mut self, config: &BlockConfig) {
+        self.devices.push("-drive".to_string());
+        self.devices.push(format!(
+            "id={},file={},aio=threads,format=raw,if=none,readonly={},cache.direct={}",
+            config.id,
+            config.path_on_host,
+            on_off(config.is_readonly),
+            on_off(self.config.blockdev_info.block_device_cache_direct)
+        ));
+        self.devices.push("-device".to_string());
+        self.devices.push(format!(
+            "virtio-blk-pci,id=virtio-{},drive={},serial={}",
+            config.id, config.id, config.id
+        ));
+    }
+
+    pub(crate) fn add_network_device(&mut self, config: &NetworkConfig) {
+        let netdev_id = format!("network-{}", config.id);
+        self.devices.push("-netdev".to_string());
+        self.devices.push(format!(
+            "tap,id={},ifname={},script=no,downscript=no,vhost={}",
+            netdev_id,
+            config.host_dev_name,
+            on_off(!self.config.network_info.disable_vhost_net)
+        ));
+
+        let mut device = format!(
+            "virtio-net-pci,id=virtio-{},netdev={}",
+            config.id, netdev_id
+        );
+        if let Some(mac) = &config.guest_mac {
+            device.push_str(&format!(",mac={:?}", mac));
+        }
+        self.devices.push("-device".to_string());
+        self.devices.push(device);
+    }
+
+    pub(crate) fn add_vfio_device(&mut self, config: &VfioConfig) {
+        self.devices.push("-device".to_string());
+        self.devices.push(format!(
+            "vfio-pci,id={},host={}",
+            config.id, config.bus_slot_func
+        ));
+    }
+
+    pub(crate) fn build(&self) -> Result<Vec<String>> {
+        let mut args = vec![
+            "-name".to_string(),
+            format!("sandbox-{}", self.id),
+            "-nodefaults".to_string(),
+            "-nographic".to_string(),
+            "-no-user-config".to_string(),
+            "-vga".to_string(),
+            "none".to_string(),
+            "-rtc".to_string(),
+            "base=utc,driftfix=slew,clock=host".to_string(),
+        ];
+
+        args.append(&mut self.machine_args()?);
+        args.append(&mut self.cpu_args());
+        args.append(&mut self.memory_args()?);
+        args.append(&mut self.qmp_args());
+        args.append(&mut self.console_args());
+        args.append(&mut self.bridge_args()?);
+        args.append(&mut self.kernel_args()?);
+        args.append(&mut self.rootfs_args()?);
+        args.append(&mut self.devices.clone());
+
+        Ok(args)
+    }
+
+    fn machine_args(&self) -> Result<Vec<String>> {
+        let machine_info = &self.config.machine_info;
+        // make sure the machine type is one we know how to drive
+        root_bus(&machine_info.machine_type)?;
+
+        let mut machine = format!("{},accel=kvm", machine_info.machine_type);
+        if !machine_info.machine_accelerators.is_empty() {
+            machine.push(',');
+            machine.push_str(&machine_info.machine_accelerators);
+        }
+        if vm_rootfs_driver(self.config) == VM_ROOTFS_DRIVER_PMEM {
+            machine.push_str(",nvdimm=on");
+        }
+
+        Ok(vec!["-machine".to_string(), machine])
+    }
+
+    fn cpu_args(&self) -> Vec<String> {
+        let cpu_info = &self.config.cpu_info;
+
+        let mut cpu = "host".to_string();
+        if !cpu_info.cpu_features.is_empty() {
+            cpu.push(',');
+            cpu.push_str(&cpu_info.cpu_features);
+        }
+
+        vec![
+            "-cpu".to_string(),
+            cpu,
+            "-smp".to_string(),
+            format!(
+                "{},cores=1,threads=1,sockets={},maxcpus={}",
+                cpu_info.default_vcpus, cpu_info.default_maxvcpus, cpu_info.default_maxvcpus
+            ),
+        ]
+    }
+
+    fn memory_args(&self) -> Result<Vec<String>> {
+        let memory_info = &self.config.memory_info;
+
+        let max_memory_mb = host_memory_mb().context("get host memory")?;
+        let mut args = vec![
+            "-m".to_string(),
+            format!(
+                "{}M,slots={},maxmem={}M",
+                memory_info.default_memory, memory_info.memory_slots, max_memory_mb
+            ),
+        ];
+
+        if self.need_shared_memory {
+            let mem_path = if memory_info.enable_hugepages {
+                DEV_HUGEPAGES
+            } else {
+                DEV_SHM
+            };
+            args.push("-object".to_string());
+            args.push(format!(
+                "memory-backend-file,id={},size={}M,mem-path={},share=on,prealloc={}",
+                SHARED_MEMORY_ID,
+                memory_info.default_memory,
+                mem_path,
+                on_off(memory_info.enable_mem_prealloc)
+            ));
+            args.push("-numa".to_string());
+            args.push(format!("node,memdev={}", SHARED_MEMORY_ID));
+        } else if memory_info.enable_mem_prealloc {
+            args.push("-mem-prealloc".to_string());
+        }
+
+        Ok(args)
+    }
+
+    fn qmp_args(&self) -> Vec<String> {
+        vec![
+            "-qmp".to_string(),
+            format!(
+                "unix:{},server=on,wait=off",
+                Self::qmp_socket_path(self.run_dir)
+            ),
+        ]
+    }
+
+    fn console_args(&self) -> Vec<String> {
+        vec![
+            "-device".to_string(),
+            "virtio-serial-pci,id=serial0".to_string(),
+            "-device".to_string(),
+            format!("virtconsole,chardev={}", CONSOLE_DEVICE_ID),
+            "-chardev".to_string(),
+            format!(
+                "socket,id={},path={},server=on,wait=off",
+                CONSOLE_DEVICE_ID,
+                Self::console_socket_path(self.run_dir)
+            ),
+        ]
+    }
+
+    fn bridge_args(&self) -> Result<Vec<String>> {
+        let bus = root_bus(&self.config.machine_info.machine_type)?;
+
+        let mut args = vec![];
+        for i in 0..self.config.device_info.default_bridges {
+            args.push("-device".to_string());
+            args.push(format!(
+                "pci-bridge,bus={},id={},chassis_nr={},shpc=on,addr={:x}",
+                bus,
+                bridge_id(i),
+                i + 1,
+                bridge_addr(i)
+            ));
+        }
+
+        Ok(args)
+    }
+
+    fn kernel_args(&self) -> Result<Vec<String>> {
+        let boot_info = &self.config.boot_info;
+
+        let mut kernel_params = KernelParams::new(self.config.debug_info.enable_debug);
+        kernel_params.append(&mut KernelParams::from_string("console=hvc0"));
+        if boot_info.initrd.is_empty() {
+            kernel_params.append(&mut KernelParams::new_rootfs_kernel_params(
+                vm_rootfs_driver(self.config),
+                &boot_info.rootfs_type,
+            )?);
+        }
+        kernel_params.append(&mut KernelParams::from_string(&boot_info.kernel_params));
+        info!(sl!(), "prepared kernel_params={:?}", kernel_params);
+
+        let mut args = vec![
+            "-kernel".to_string(),
+            boot_info.kernel.clone(),
+            "-append".to_string(),
+            kernel_params
+                .to_string()
+                .context("kernel params to string")?,
+        ];
+
+        if !boot_info.firmware.is_empty() {
+            args.push("-bios".to_string());
+            args.push(boot_info.firmware.clone());
+        }
+
+        Ok(args)
+    }
+
+    fn rootfs_args(&self) -> Result<Vec<String>> {
+        let boot_info = &self.config.boot_info;
+
+        if !boot_info.initrd.is_empty() {
+            return Ok(vec!["-initrd".to_string(), boot_info.initrd.clone()]);
+        }
+
+        if boot_info.image.is_empty() {
+            return Err(anyhow!("failed to get image"));
+        }
+
+        match vm_rootfs_driver(self.config) {
+            VM_ROOTFS_DRIVER_PMEM => {
+                let image_size = std::fs::metadata(&boot_info.image)
+                    .with_context(|| format!("stat image {}", &boot_info.image))?
+                    .len();
+                Ok(vec![
+                    "-object".to_string(),
+                    format!(
+                        "memory-backend-file,id={},mem-path={},size={},readonly=on",
+                        IMAGE_PMEM_ID, boot_info.image, image_size
+                    ),
+                    "-device".to_string(),
+                    format!("nvdimm,id=nv0,memdev={},unarmed=on", IMAGE_PMEM_ID),
+                ])
+            }
+            _ => Ok(vec![
+                "-drive".to_string(),
+                format!(
+                    "id={},file={},aio=threads,format=raw,if=none,readonly=on",
+                    IMAGE_DRIVE_ID, boot_info.image
+                ),
+                "-device".to_string(),
+                format!(
+                    "virtio-blk-pci,drive={},scsi=off,config-wce=off,share-rw=on,serial={}",
+                    IMAGE_DRIVE_ID, IMAGE_DRIVE_ID
+                ),
+            ]),
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn host_memory_mb() -> Result<u64> {
+    let info = nix::sys::sysinfo::sysinfo().context("sysinfo")?;
+    Ok(info.ram_total() / (1024 * 1024))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Address;
+
+    fn test_config() -> HypervisorConfig {
+        let mut config = HypervisorConfig {
+            path: "/usr/bin/qemu-system-x86_64".to_string(),
+            ..Default::default()
+        };
+        config.machine_info.machine_type = MACHINE_TYPE_Q35.to_string();
+        config.cpu_info.default_vcpus = 1;
+        config.cpu_info.default_maxvcpus = 4;
+        config.memory_info.default_memory = 2048;
+        config.memory_info.memory_slots = 10;
+        config.device_info.default_bridges = 1;
+        config.boot_info.kernel = "/usr/share/kata-containers/vmlinux".to_string();
+        config.boot_info.initrd = "/usr/share/kata-containers/kata-initrd.img".to_string();
+        config
+    }
+
+    fn arg_value<'a>(args: &'a [String], key: &str) -> Vec<&'a str> {
+        args.windows(2)
+            .filter(|w| w[0] == key)
+            .map(|w| w[1].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_basic_cmdline() {
+        let config = test_config();
+        let cmdline = QemuCmdLine::new("sid", &config, "/run/kata/sid");
+        let args = cmdline.build().unwrap();
+
+        assert_eq!(arg_value(&args, "-machine"), vec!["q35,accel=kvm"]);
+        assert_eq!(
+            arg_value(&args, "-smp"),
+            vec!["1,cores=1,threads=1,sockets=4,maxcpus=4"]
+        );
+        assert_eq!(
+            arg_value(&args, "-qmp"),
+            vec!["unix:/run/kata/sid/qmp.sock,server=on,wait=off"]
+        );
+        assert_eq!(
+            arg_value(&args, "-initrd"),
+            vec!["/usr/share/kata-containers/kata-initrd.img"]
+        );
+        assert!(arg_value(&args, "-device")
+            .contains(&"pci-bridge,bus=pcie.0,id=pci-bridge-0,chassis_nr=1,shpc=on,addr=2"));
+        assert!(arg_value(&args, "-m")[0].starts_with("2048M,slots=10,maxmem="));
+        // no shared memory backend without vhost-user devices or hugepages
+        assert!(arg_value(&args, "-numa").is_empty());
+    }
+
+    #[test]
+    fn test_build_cmdline_with_devices() {
+        let config = test_config();
+        let mut cmdline = QemuCmdLine::new("sid", &config, "/run/kata/sid");
+        cmdline.add_vsock(5, 10);
+        cmdline.add_share_fs_device(&ShareFsDeviceConfig {
+            fs_type: "virtio-fs".to_string(),
+            sock_path: "/run/kata/sid/root/virtiofsd.sock".to_string(),
+            mount_tag: "kataShared".to_string(),
+            host_path: "/run/kata/shared/sid".to_string(),
+            queue_size: 1024,
+            queue_num: 1,
+        });
+        cmdline.add_network_device(&NetworkConfig {
+            id: "eth0".to_string(),
+            host_dev_name: "tap0_kata".to_string(),
+            guest_mac: Some(Address([0x02, 0x42, 0xac, 0x11, 0x00, 0x02])),
+        });
+        let args = cmdline.build().unwrap();
+        let devices = arg_value(&args, "-device");
+
+        assert!(devices.contains(&"vhost-vsock-pci,id=vsock-5,guest-cid=5,vhostfd=10"));
+        assert!(devices
+            .contains(&"vhost-user-fs-pci,chardev=char-kataShared,tag=kataShared,queue-size=1024"));
+        assert!(devices
+            .contains(&"virtio-net-pci,id=virtio-eth0,netdev=network-eth0,mac=02:42:ac:11:00:02"));
+        assert_eq!(
+            arg_value(&args, "-netdev"),
+            vec!["tap,id=network-eth0,ifname=tap0_kata,script=no,downscript=no,vhost=on"]
+        );
+        assert_eq!(arg_value(&args, "-numa"), vec!["node,memdev=dimm1"]);
+    }
+
+    #[test]
+    fn test_build_cmdline_unsupported_machine() {
+        let mut config = test_config();
+        config.machine_info.machine_type = "microvm".to_string();
+        let cmdline = QemuCmdLine::new("sid", &config, "/run/kata/sid");
+        assert!(cmdline.build().is_err());
+    }
+}
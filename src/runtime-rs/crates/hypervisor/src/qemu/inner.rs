@@ -3,117 +3,488 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::iter::FromIterator;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::process::Stdio;
+use std::sync::Arc;
 
-use crate::{HypervisorConfig, VcpuThreadIds};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::sched::{setns, CloneFlags};
+use persist::sandbox_persist::Persist;
+use qmp::{Qmp, EVENT_GUEST_PANICKED, EVENT_SHUTDOWN};
+use serde_json::{json, Value};
+use shim_interface::KATA_PATH;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+use super::cmdline_generator::QemuCmdLine;
+use super::inner_device::PciSlots;
+use crate::{
+    device::Device, hypervisor_persist::HypervisorState, utils, HypervisorConfig, VcpuThreadIds,
+    VmmState, VsockConfig, HYPERVISOR_QEMU,
+};
 use kata_types::capabilities::{Capabilities, CapabilityBits};
 
 const VSOCK_SCHEME: &str = "vsock";
-const VSOCK_AGENT_CID: u32 = 3;
 const VSOCK_AGENT_PORT: u32 = 1024;
 
+/// Seconds to wait for QEMU to exit after a `quit` command before killing it.
+const QEMU_STOP_TIMEOUT_SECS: u64 = 5;
+
+/// Seconds to wait for the QMP socket of an already running QEMU.
+const QMP_CONNECT_TIMEOUT_SECS: u64 = 5;
+
+/// Name of the file the VM state is migrated to by `save_vm`.
+const QEMU_STATE_FILE: &str = "state";
+
+/// Name of the file descriptor of the state file passed to QEMU by `save_vm`.
+const QEMU_STATE_FD_NAME: &str = "kata-state";
+
+/// Number of milliseconds to wait before polling the migration status again.
+const QEMU_MIGRATE_POLL_TIME_MS: u64 = 100;
+
 unsafe impl Send for QemuInner {}
 unsafe impl Sync for QemuInner {}
 
 pub struct QemuInner {
-    config: HypervisorConfig,
+    /// sandbox id
+    pub(crate) id: String,
+
+    /// vm path
+    pub(crate) vm_path: String,
+
+    /// directory holding the sockets exposed to other components
+    pub(crate) jailer_root: String,
+
+    /// netns
+    pub(crate) netns: Option<String>,
+
+    /// hypervisor config
+    pub(crate) config: HypervisorConfig,
+
+    /// vmm state
+    pub(crate) state: VmmState,
+
+    /// QEMU process, only available to the shim which started the VM
+    pub(crate) process: Option<Child>,
+
+    /// QEMU process id
+    pub(crate) pid: Option<u32>,
+
+    /// guest CID of the vhost-vsock device used by the agent
+    pub(crate) guest_cid: u32,
+
+    /// QMP connection, established on first use
+    pub(crate) qmp: Mutex<Option<Arc<Qmp>>>,
+
+    /// pending devices, cold plugged on the command line when the VM starts
+    pub(crate) pending_devices: Vec<Device>,
+
+    /// PCI slots used by hotplugged devices
+    pub(crate) pci_slots: PciSlots,
+
+    /// qemu capabilities
+    pub(crate) capabilities: Capabilities,
 }
 
 impl QemuInner {
     pub fn new() -> QemuInner {
+        let mut capabilities = Capabilities::new();
+        capabilities.set(
+            CapabilityBits::BlockDeviceSupport
+                | CapabilityBits::BlockDeviceHotplugSupport
+                | CapabilityBits::FsSharingSupport
+                | CapabilityBits::DevicePciPathSupport,
+        );
         QemuInner {
+            id: "".to_string(),
+            vm_path: "".to_string(),
+            jailer_root: "".to_string(),
+            netns: None,
             config: Default::default(),
+            state: VmmState::NotReady,
+            process: None,
+            pid: None,
+            guest_cid: 0,
+            qmp: Mutex::new(None),
+            pending_devices: vec![],
+            pci_slots: PciSlots::default(),
+            capabilities,
         }
     }
 
-    pub(crate) async fn prepare_vm(&mut self, _id: &str, _netns: Option<String>) -> Result<()> {
+    pub(crate) async fn prepare_vm(&mut self, id: &str, netns: Option<String>) -> Result<()> {
         info!(sl!(), "Preparing QEMU VM");
+        self.id = id.to_string();
+        self.state = VmmState::NotReady;
+
+        self.vm_path = [KATA_PATH, id].join("/");
+        self.jailer_root = [self.vm_path.as_str(), "root"].join("/");
+        self.netns = netns;
+
+        create_dir_all(&self.jailer_root)
+            .with_context(|| format!("failed to create dir {}", &self.jailer_root))?;
+
+        // prepare vsock
+        let vsock = VsockConfig::new(format!("vsock-{}", &self.id))
+            .await
+            .context("new vsock config")?;
+        self.guest_cid = vsock.guest_cid;
+        self.add_device(Device::Vsock(vsock))
+            .await
+            .context("add vsock device")?;
+
         Ok(())
     }
 
-    pub(crate) async fn start_vm(&mut self, _timeout: i32) -> Result<()> {
+    pub(crate) async fn start_vm(&mut self, timeout: i32) -> Result<()> {
         info!(sl!(), "Starting QEMU VM");
+        if timeout < 0 {
+            return Err(anyhow!("Invalid param timeout {}", timeout));
+        }
+        if let Some(pid) = self.pid {
+            return Err(anyhow!("QEMU already running with PID {}", pid));
+        }
+
+        self.launch_qemu().await.context("launch qemu")?;
 
-        let mut command = std::process::Command::new(&self.config.path);
+        // QEMU creates the QMP socket during its initialization, so wait
+        // for it to become available.
+        let qmp = match self
+            .connect_qmp(Duration::from_millis(timeout as u64))
+            .await
+        {
+            Ok(qmp) => qmp,
+            Err(err) => {
+                error!(sl!(), "failed to connect QMP {:?}", err);
+                if let Some(mut child) = self.process.take() {
+                    if let Err(err) = child.kill().await {
+                        warn!(sl!(), "failed to kill qemu {:?}", err);
+                    }
+                }
+                self.pid = None;
+                return Err(err);
+            }
+        };
+        *self.qmp.lock().await = Some(qmp);
 
-        command
-            .arg("-kernel")
-            .arg(&self.config.boot_info.kernel)
-            .arg("-m")
-            .arg(format!("{}M", &self.config.memory_info.default_memory))
-            .arg("-initrd")
-            .arg(&self.config.boot_info.initrd)
-            .arg("-vga")
-            .arg("none")
-            .arg("-nodefaults")
-            .arg("-nographic");
+        self.state = VmmState::VmRunning;
+        Ok(())
+    }
 
-        command.spawn()?;
+    async fn launch_qemu(&mut self) -> Result<()> {
+        let mut cmdline = QemuCmdLine::new(&self.id, &self.config, &self.vm_path);
+
+        // The vhost-vsock fds must stay open until QEMU has inherited them,
+        // as they are holding the guest CIDs.
+        let mut vhost_fds: Vec<RawFd> = vec![];
+        let pending_devices = std::mem::take(&mut self.pending_devices);
+        for device in pending_devices.iter() {
+            match device {
+                Device::Vsock(config) => {
+                    let fd = config.vhost_fd.as_raw_fd();
+                    cmdline.add_vsock(config.guest_cid, fd);
+                    vhost_fds.push(fd);
+                }
+                Device::ShareFsDevice(config) => cmdline.add_share_fs_device(config),
+                Device::Block(config) => cmdline.add_block_device(config),
+                Device::Network(config) => cmdline.add_network_device(config),
+                Device::Vfio(config) => cmdline.add_vfio_device(config),
+                _ => {
+                    return Err(anyhow!("unsupported device {}", device));
+                }
+            }
+        }
+
+        let args = cmdline.build().context("build qemu command line")?;
+        info!(sl!(), "qemu args {:?}", args);
+
+        let netns_file = match &self.netns {
+            Some(netns_path) => Some(
+                File::open(netns_path)
+                    .with_context(|| format!("open netns path {}", netns_path))?,
+            ),
+            None => None,
+        };
+        let netns_fd = netns_file.as_ref().map(|f| f.as_raw_fd());
+
+        let mut cmd = Command::new(&self.config.path);
+        cmd.args(&args)
+            .current_dir("/")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        // SAFETY: the closure runs in the forked child before exec, it only
+        // calls setns(2) and fcntl(2), which are async-signal-safe, and
+        // allocates nothing.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(fd) = netns_fd {
+                    setns(fd, CloneFlags::CLONE_NEWNET)
+                        .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+                }
+                for fd in vhost_fds.iter() {
+                    fcntl(*fd, FcntlArg::F_SETFD(FdFlag::empty()))
+                        .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+                }
+                Ok(())
+            });
+        }
+
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("spawn {}", &self.config.path))?;
+        self.pid = child.id();
+
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(log_qemu_output(stderr, "stderr"));
+        }
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(log_qemu_output(stdout, "stdout"));
+        }
+        self.process = Some(child);
 
         Ok(())
     }
 
-    pub(crate) fn stop_vm(&mut self) -> Result<()> {
+    async fn connect_qmp(&self, timeout: Duration) -> Result<Arc<Qmp>> {
+        let qmp_path = QemuCmdLine::qmp_socket_path(&self.vm_path);
+        let qmp = Qmp::connect(&qmp_path, timeout)
+            .await
+            .context("connect qmp")?;
+        tokio::spawn(monitor_qemu_events(qmp.subscribe()));
+        Ok(Arc::new(qmp))
+    }
+
+    /// Get the QMP client, connecting to the QMP socket if needed, which is
+    /// the case for a restored hypervisor.
+    pub(crate) async fn qmp(&self) -> Result<Arc<Qmp>> {
+        let mut qmp = self.qmp.lock().await;
+        if let Some(qmp) = qmp.as_ref() {
+            return Ok(qmp.clone());
+        }
+
+        let client = self
+            .connect_qmp(Duration::from_secs(QMP_CONNECT_TIMEOUT_SECS))
+            .await?;
+        *qmp = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Execute a QMP command.
+    pub(crate) async fn qmp_execute(
+        &self,
+        command: &str,
+        arguments: Option<Value>,
+    ) -> Result<Value> {
+        self.qmp().await?.execute(command, arguments).await
+    }
+
+    pub(crate) async fn stop_vm(&mut self) -> Result<()> {
         info!(sl!(), "Stopping QEMU VM");
-        todo!()
+        if self.state == VmmState::NotReady && self.pid.is_none() {
+            return Ok(());
+        }
+
+        if let Err(err) = self.qmp_execute("quit", None).await {
+            warn!(sl!(), "failed to quit qemu through QMP {:?}", err);
+        }
+        *self.qmp.lock().await = None;
+
+        if let Some(mut child) = self.process.take() {
+            let wait =
+                tokio::time::timeout(Duration::from_secs(QEMU_STOP_TIMEOUT_SECS), child.wait())
+                    .await;
+            if wait.is_err() {
+                warn!(sl!(), "qemu did not exit in time, killing it");
+                // Note that this kills _and_ waits for the process!
+                child.kill().await.context("kill qemu")?;
+            }
+        } else if let Some(pid) = self.pid {
+            // the process is not our child after a restore
+            let pid = nix::unistd::Pid::from_raw(pid as i32);
+            if let Err(err) = nix::sys::signal::kill(pid, nix::sys::signal::SIGKILL) {
+                if err != nix::Error::ESRCH {
+                    return Err(anyhow!("failed to kill qemu pid {} {}", pid, err));
+                }
+            }
+        }
+
+        self.pid = None;
+        self.state = VmmState::NotReady;
+        Ok(())
     }
 
-    pub(crate) fn pause_vm(&self) -> Result<()> {
+    pub(crate) async fn pause_vm(&self) -> Result<()> {
         info!(sl!(), "Pausing QEMU VM");
-        todo!()
+        self.qmp_execute("stop", None).await.context("pause vm")?;
+        Ok(())
     }
 
-    pub(crate) fn resume_vm(&self) -> Result<()> {
+    pub(crate) async fn resume_vm(&self) -> Result<()> {
         info!(sl!(), "Resuming QEMU VM");
-        todo!()
+        self.qmp_execute("cont", None).await.context("resume vm")?;
+        Ok(())
     }
 
+    /// Save the VM state to a file under the VM path by migrating it. The
+    /// VM is paused first and stays paused.
     pub(crate) async fn save_vm(&self) -> Result<()> {
-        todo!()
+        info!(sl!(), "Saving QEMU VM");
+        let state_path = [self.vm_path.as_str(), QEMU_STATE_FILE].join("/");
+
+        // open the state file here and pass it to QEMU, so that the path is never
+        // interpreted by QEMU
+        let state_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&state_path)
+            .with_context(|| format!("open vm state file {}", state_path))?;
+
+        self.qmp_execute("stop", None).await.context("pause vm")?;
+        let qmp = self.qmp().await?;
+        qmp.execute_with_fd(
+            "getfd",
+            Some(json!({ "fdname": QEMU_STATE_FD_NAME })),
+            state_file.as_raw_fd(),
+        )
+        .await
+        .context("pass vm state file")?;
+        drop(state_file);
+        if let Err(err) = qmp
+            .execute(
+                "migrate",
+                Some(json!({ "uri": format!("fd:{}", QEMU_STATE_FD_NAME) })),
+            )
+            .await
+        {
+            // the file descriptor is only consumed by a started migration
+            let _ = qmp
+                .execute("closefd", Some(json!({ "fdname": QEMU_STATE_FD_NAME })))
+                .await;
+            return Err(err).context("migrate vm");
+        }
+
+        loop {
+            let status = self
+                .qmp_execute("query-migrate", None)
+                .await
+                .context("query migrate")?;
+            match status.get("status").and_then(Value::as_str) {
+                Some("completed") => break,
+                Some("failed") | Some("cancelled") => {
+                    return Err(anyhow!("failed to save vm state {}", status));
+                }
+                _ => tokio::time::sleep(Duration::from_millis(QEMU_MIGRATE_POLL_TIME_MS)).await,
+            }
+        }
+
+        info!(sl!(), "QEMU VM saved to {}", state_path);
+        Ok(())
+    }
+
+    pub(crate) async fn resize_memory(&self, _new_mem_mb: u32) -> Result<u32> {
+        Err(anyhow!("QEMU doesn't support memory resize yet"))
     }
 
-    /// TODO: using a single hardcoded CID is clearly not adequate in the long
-    /// run. Use the recently added VsockConfig infrastructure to fix this.
     pub(crate) async fn get_agent_socket(&self) -> Result<String> {
         info!(sl!(), "QemuInner::get_agent_socket()");
         Ok(format!(
             "{}://{}:{}",
-            VSOCK_SCHEME, VSOCK_AGENT_CID, VSOCK_AGENT_PORT
+            VSOCK_SCHEME, self.guest_cid, VSOCK_AGENT_PORT
         ))
     }
 
     pub(crate) async fn disconnect(&mut self) {
         info!(sl!(), "QemuInner::disconnect()");
-        todo!()
+        self.state = VmmState::NotReady;
     }
 
     pub(crate) async fn get_thread_ids(&self) -> Result<VcpuThreadIds> {
         info!(sl!(), "QemuInner::get_thread_ids()");
-        todo!()
+        let cpus = self
+            .qmp_execute("query-cpus-fast", None)
+            .await
+            .context("query cpus")?;
+
+        let mut vcpu_thread_ids = VcpuThreadIds {
+            vcpus: HashMap::new(),
+        };
+        for cpu in cpus.as_array().into_iter().flatten() {
+            let index = cpu.get("cpu-index").and_then(Value::as_u64);
+            let tid = cpu.get("thread-id").and_then(Value::as_u64);
+            if let (Some(index), Some(tid)) = (index, tid) {
+                vcpu_thread_ids.vcpus.insert(index as u32, tid as u32);
+            }
+        }
+        info!(sl!(), "get thread ids {:?}", vcpu_thread_ids);
+        Ok(vcpu_thread_ids)
     }
 
     pub(crate) async fn cleanup(&self) -> Result<()> {
         info!(sl!(), "QemuInner::cleanup()");
-        todo!()
+        if self.vm_path.is_empty() {
+            return Ok(());
+        }
+        std::fs::remove_dir_all(&self.vm_path)
+            .map_err(|err| {
+                error!(sl!(), "failed to remove dir all for {}", &self.vm_path);
+                err
+            })
+            .ok();
+        Ok(())
     }
 
     pub(crate) async fn get_pids(&self) -> Result<Vec<u32>> {
         info!(sl!(), "QemuInner::get_pids()");
-        todo!()
+        let pid = self.pid.ok_or_else(|| anyhow!("qemu not running"))?;
+
+        let mut pids = HashSet::new();
+        pids.insert(pid);
+        for tid in utils::get_child_threads(pid) {
+            pids.insert(tid);
+        }
+
+        // remove vcpus
+        for tid in self.get_thread_ids().await?.vcpus.values() {
+            pids.remove(tid);
+        }
+
+        info!(sl!(), "get pids {:?}", pids);
+        Ok(Vec::from_iter(pids.into_iter()))
     }
 
     pub(crate) async fn check(&self) -> Result<()> {
-        todo!()
+        let status = self
+            .qmp_execute("query-status", None)
+            .await
+            .context("query status")?;
+        debug!(sl!(), "qemu status {}", status);
+        Ok(())
     }
 
     pub(crate) async fn get_jailer_root(&self) -> Result<String> {
-        todo!()
+        create_dir_all(&self.jailer_root)
+            .with_context(|| format!("failed to create dir {}", &self.jailer_root))?;
+        Ok(self.jailer_root.clone())
     }
 
     pub(crate) async fn capabilities(&self) -> Result<Capabilities> {
-        let mut caps = Capabilities::default();
-        caps.set(CapabilityBits::FsSharingSupport);
-        Ok(caps)
+        Ok(self.capabilities.clone())
+    }
+
+    pub(crate) async fn get_hypervisor_metrics(&self) -> Result<String> {
+        Err(anyhow!("qemu hypervisor doesn't support metrics yet"))
     }
 
     pub fn set_hypervisor_config(&mut self, config: HypervisorConfig) {
@@ -126,17 +497,72 @@ impl QemuInner {
     }
 }
 
-use crate::device::Device;
+// Log the QEMU events worth reporting until the QMP connection is closed.
+async fn monitor_qemu_events(mut events: tokio::sync::broadcast::Receiver<qmp::Event>) {
+    use tokio::sync::broadcast::error::RecvError;
 
-// device manager part of Hypervisor
-impl QemuInner {
-    pub(crate) async fn add_device(&mut self, device: Device) -> Result<()> {
-        info!(sl!(), "QemuInner::add_device() {}", device);
-        todo!()
+    loop {
+        match events.recv().await {
+            Ok(event) => match event.event.as_str() {
+                EVENT_SHUTDOWN => info!(sl!(), "qemu shutdown {}", event.data),
+                EVENT_GUEST_PANICKED => error!(sl!(), "guest panicked {}", event.data),
+                _ => debug!(sl!(), "qemu event {:?}", event),
+            },
+            Err(RecvError::Lagged(n)) => warn!(sl!(), "missed {} qemu events", n),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn log_qemu_output<R>(reader: R, stream: &'static str) -> Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await.context("read next line")? {
+        info!(sl!(), "{}", line; "stream" => stream, "source" => "qemu");
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Persist for QemuInner {
+    type State = HypervisorState;
+    type ConstructorArgs = ();
+
+    /// Save a state of hypervisor
+    async fn save(&self) -> Result<Self::State> {
+        Ok(HypervisorState {
+            hypervisor_type: HYPERVISOR_QEMU.to_string(),
+            pid: self.pid.map(|pid| pid as i32),
+            id: self.id.clone(),
+            vm_path: self.vm_path.clone(),
+            jailer_root: self.jailer_root.clone(),
+            netns: self.netns.clone(),
+            config: self.hypervisor_config(),
+            run_dir: self.vm_path.clone(),
+            guest_cid: self.guest_cid,
+            pci_slots: self.pci_slots.save(),
+            ..Default::default()
+        })
     }
 
-    pub(crate) async fn remove_device(&mut self, device: Device) -> Result<()> {
-        info!(sl!(), "QemuInner::remove_device() {} ", device);
-        todo!()
+    /// Restore hypervisor
+    async fn restore(
+        _hypervisor_args: Self::ConstructorArgs,
+        hypervisor_state: Self::State,
+    ) -> Result<Self> {
+        Ok(QemuInner {
+            id: hypervisor_state.id,
+            vm_path: hypervisor_state.vm_path,
+            jailer_root: hypervisor_state.jailer_root,
+            netns: hypervisor_state.netns,
+            config: hypervisor_state.config,
+            state: VmmState::VmRunning,
+            pid: hypervisor_state.pid.map(|pid| pid as u32),
+            guest_cid: hypervisor_state.guest_cid,
+            pci_slots: PciSlots::restore(hypervisor_state.pci_slots),
+            ..QemuInner::new()
+        })
     }
 }
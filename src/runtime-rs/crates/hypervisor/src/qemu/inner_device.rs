@@ -0,0 +1,338 @@
+// Copyright (c) 2022 Red Hat
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use tokio::time::Duration;
+
+use super::cmdline_generator::{bridge_addr, bridge_id};
+use super::inner::QemuInner;
+use crate::{device::Device, BlockConfig, NetworkConfig, VfioConfig, VmmState};
+
+// Slot 0 of a PCI bridge is reserved for the SHPC controller.
+const BRIDGE_FIRST_SLOT: u32 = 1;
+const BRIDGE_MAX_SLOT: u32 = 31;
+
+/// Seconds to wait for the guest to acknowledge the removal of a device.
+const DEVICE_DEL_TIMEOUT_SECS: u64 = 5;
+
+/// Location of a hotplugged device on one of the PCI bridges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PciSlot {
+    pub bridge: u32,
+    pub slot: u32,
+}
+
+impl PciSlot {
+    /// Guest PCI path of the device, "<bridge addr>/<slot>", in the format
+    /// expected by the agent.
+    pub(crate) fn pci_path(&self) -> String {
+        format!("{:02x}/{:02x}", bridge_addr(self.bridge), self.slot)
+    }
+}
+
+/// Allocator of the PCI bridge slots used by hotplugged devices.
+#[derive(Debug, Default)]
+pub(crate) struct PciSlots {
+    used: HashMap<String, PciSlot>,
+}
+
+impl PciSlots {
+    pub(crate) fn allocate(&mut self, id: &str, bridges: u32) -> Result<PciSlot> {
+        if self.used.contains_key(id) {
+            return Err(anyhow!("device {} already plugged", id));
+        }
+
+        for bridge in 0..bridges {
+            for slot in BRIDGE_FIRST_SLOT..=BRIDGE_MAX_SLOT {
+                let candidate = PciSlot { bridge, slot };
+                if !self.used.values().any(|s| *s == candidate) {
+                    self.used.insert(id.to_string(), candidate);
+                    return Ok(candidate);
+                }
+            }
+        }
+
+        Err(anyhow!("no free PCI slot on {} bridges", bridges))
+    }
+
+    pub(crate) fn release(&mut self, id: &str) -> Option<PciSlot> {
+        self.used.remove(id)
+    }
+
+    pub(crate) fn get(&self, id: &str) -> Option<PciSlot> {
+        self.used.get(id).copied()
+    }
+
+    /// Bridge and slot of every allocated device, as saved in the hypervisor state.
+    pub(crate) fn save(&self) -> HashMap<String, (u32, u32)> {
+        self.used
+            .iter()
+            .map(|(id, s)| (id.clone(), (s.bridge, s.slot)))
+            .collect()
+    }
+
+    pub(crate) fn restore(slots: HashMap<String, (u32, u32)>) -> Self {
+        PciSlots {
+            used: slots
+                .into_iter()
+                .map(|(id, (bridge, slot))| (id, PciSlot { bridge, slot }))
+                .collect(),
+        }
+    }
+}
+
+fn frontend_id(id: &str) -> String {
+    format!("virtio-{}", id)
+}
+
+fn netdev_id(id: &str) -> String {
+    format!("network-{}", id)
+}
+
+impl QemuInner {
+    pub(crate) async fn add_device(&mut self, device: Device) -> Result<()> {
+        if self.state == VmmState::NotReady {
+            info!(sl!(), "VMM not ready, queueing device {}", device);
+            self.pending_devices.push(device);
+            return Ok(());
+        }
+
+        info!(sl!(), "QemuInner::add_device() {}", device);
+        match device {
+            Device::Block(config) => self
+                .hotplug_block_device(&config)
+                .await
+                .context("add block"),
+            Device::Network(config) => self
+                .hotplug_network_device(&config)
+                .await
+                .context("add network"),
+            Device::Vfio(config) => self.hotplug_vfio_device(&config).await.context("add vfio"),
+            _ => Err(anyhow!(
+                "device {} can only be added before the VM starts",
+                device
+            )),
+        }
+    }
+
+    pub(crate) async fn remove_device(&mut self, device: Device) -> Result<()> {
+        info!(sl!(), "QemuInner::remove_device() {} ", device);
+        match device {
+            Device::Block(config) => {
+                self.unplug_device(&frontend_id(&config.id))
+                    .await
+                    .context("remove block frontend")?;
+                self.qmp_execute("blockdev-del", Some(json!({ "node-name": config.id })))
+                    .await
+                    .context("remove block backend")?;
+                Ok(())
+            }
+            Device::Network(config) => {
+                self.unplug_device(&frontend_id(&config.id))
+                    .await
+                    .context("remove network frontend")?;
+                self.qmp_execute("netdev_del", Some(json!({ "id": netdev_id(&config.id) })))
+                    .await
+                    .context("remove network backend")?;
+                Ok(())
+            }
+            Device::Vfio(config) => self.unplug_device(&config.id).await.context("remove vfio"),
+            _ => Err(anyhow!("unsupported device {:?}", device)),
+        }
+    }
+
+    /// Guest PCI path of a hotplugged device, the block and network devices are looked up by
+    /// the id of their backend.
+    pub(crate) fn get_device_pci_path(&self, id: &str) -> Result<String> {
+        self.pci_slots
+            .get(&frontend_id(id))
+            .or_else(|| self.pci_slots.get(id))
+            .map(|slot| slot.pci_path())
+            .ok_or_else(|| anyhow!("device {} is not hotplugged", id))
+    }
+
+    /// Grow the hotplugged block device to the new size of its backing device on the host, the
+    /// guest is notified of the new capacity.
+    pub(crate) async fn resize_block_device(&self, id: &str, new_size: u64) -> Result<()> {
+        self.qmp_execute(
+            "block_resize",
+            Some(json!({ "node-name": id, "size": new_size })),
+        )
+        .await
+        .with_context(|| format!("resize block device {} to {} bytes", id, new_size))?;
+        Ok(())
+    }
+
+    fn allocate_slot(&mut self, id: &str) -> Result<PciSlot> {
+        let bridges = self.config.device_info.default_bridges;
+        self.pci_slots.allocate(id, bridges)
+    }
+
+    async fn hotplug_block_device(&mut self, config: &BlockConfig) -> Result<()> {
+        self.qmp_execute(
+            "blockdev-add",
+            Some(json!({
+                "driver": "raw",
+                "node-name": config.id,
+                "read-only": config.is_readonly,
+                "cache": {
+                    "direct": self.config.blockdev_info.block_device_cache_direct,
+                },
+                "file": {
+                    "driver": "file",
+                    "filename": config.path_on_host,
+                },
+            })),
+        )
+        .await
+        .context("blockdev-add")?;
+
+        let id = frontend_id(&config.id);
+        let slot = self.allocate_slot(&id)?;
+        let args = json!({
+            "driver": "virtio-blk-pci",
+            "drive": config.id,
+            "serial": config.id,
+        });
+        if let Err(err) = self.device_add(&id, slot, args).await {
+            let _ = self
+                .qmp_execute("blockdev-del", Some(json!({ "node-name": config.id })))
+                .await;
+            return Err(err);
+        }
+
+        info!(
+            sl!(),
+            "block device {} plugged at {}",
+            config.id,
+            slot.pci_path()
+        );
+        Ok(())
+    }
+
+    async fn hotplug_network_device(&mut self, config: &NetworkConfig) -> Result<()> {
+        let netdev = netdev_id(&config.id);
+        self.qmp_execute(
+            "netdev_add",
+            Some(json!({
+                "type": "tap",
+                "id": netdev,
+                "ifname": config.host_dev_name,
+                "script": "no",
+                "downscript": "no",
+                "vhost": !self.config.network_info.disable_vhost_net,
+            })),
+        )
+        .await
+        .context("netdev_add")?;
+
+        let mut args = json!({
+            "driver": "virtio-net-pci",
+            "netdev": netdev,
+        });
+        if let Some(mac) = &config.guest_mac {
+            args["mac"] = json!(format!("{:?}", mac));
+        }
+
+        let id = frontend_id(&config.id);
+        let slot = self.allocate_slot(&id)?;
+        if let Err(err) = self.device_add(&id, slot, args).await {
+            let _ = self
+                .qmp_execute("netdev_del", Some(json!({ "id": netdev })))
+                .await;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    async fn hotplug_vfio_device(&mut self, config: &VfioConfig) -> Result<()> {
+        let slot = self.allocate_slot(&config.id)?;
+        let args = json!({
+            "driver": "vfio-pci",
+            "host": config.bus_slot_func,
+        });
+        self.device_add(&config.id, slot, args).await
+    }
+
+    /// Plug a device frontend into `slot`, releasing the slot on failure.
+    async fn device_add(
+        &mut self,
+        id: &str,
+        slot: PciSlot,
+        mut args: serde_json::Value,
+    ) -> Result<()> {
+        args["id"] = json!(id);
+        args["bus"] = json!(bridge_id(slot.bridge));
+        args["addr"] = json!(format!("{:x}", slot.slot));
+
+        if let Err(err) = self.qmp_execute("device_add", Some(args)).await {
+            self.pci_slots.release(id);
+            return Err(err).with_context(|| format!("device_add {}", id));
+        }
+        Ok(())
+    }
+
+    /// Unplug a device frontend and wait for the guest to release it, so
+    /// that its backend can be deleted right after.
+    async fn unplug_device(&mut self, id: &str) -> Result<()> {
+        self.qmp()
+            .await?
+            .device_del(id, Duration::from_secs(DEVICE_DEL_TIMEOUT_SECS))
+            .await
+            .with_context(|| format!("device_del {}", id))?;
+        self.pci_slots.release(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pci_slots() {
+        let mut slots = PciSlots::default();
+
+        let s1 = slots.allocate("dev1", 1).unwrap();
+        assert_eq!(s1, PciSlot { bridge: 0, slot: 1 });
+        assert_eq!(s1.pci_path(), "02/01");
+        assert!(slots.allocate("dev1", 1).is_err());
+
+        let s2 = slots.allocate("dev2", 1).unwrap();
+        assert_eq!(s2, PciSlot { bridge: 0, slot: 2 });
+
+        assert_eq!(slots.release("dev1"), Some(s1));
+        assert_eq!(slots.allocate("dev3", 1).unwrap(), s1);
+
+        // fill the first bridge, the next device goes to the second one
+        for i in 0..(BRIDGE_MAX_SLOT - 2) {
+            slots.allocate(&format!("fill{}", i), 2).unwrap();
+        }
+        assert!(slots.allocate("dev4", 1).is_err());
+        assert_eq!(
+            slots.allocate("dev4", 2).unwrap(),
+            PciSlot { bridge: 1, slot: 1 }
+        );
+    }
+
+    #[test]
+    fn test_pci_slots_save_restore() {
+        let mut slots = PciSlots::default();
+        let s1 = slots.allocate("dev1", 2).unwrap();
+        let s2 = slots.allocate("dev2", 2).unwrap();
+
+        let mut restored = PciSlots::restore(slots.save());
+        assert_eq!(restored.get("dev1"), Some(s1));
+        assert_eq!(restored.get("dev2"), Some(s2));
+
+        // the restored slots are not handed out again
+        let s3 = restored.allocate("dev3", 2).unwrap();
+        assert_ne!(s3, s1);
+        assert_ne!(s3, s2);
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    os::unix::fs::FileTypeExt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use agent::Storage;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use hypervisor::{BlockConfig, Device, Hypervisor};
+use persist::sandbox_persist::Persist;
+
+// The index 0 is taken by the guest rootfs image, i.e. /dev/vda.
+const FIRST_BLOCK_INDEX: u64 = 1;
+const MAX_BLOCK_INDEX: u64 = 1 << 16;
+
+// The block device drivers of the hypervisor configuration
+const VIRTIO_BLK: &str = "virtio-blk";
+const VIRTIO_BLK_MMIO: &str = "virtio-mmio";
+
+// The storage drivers of the agent
+const DRIVER_BLK_TYPE: &str = "blk";
+const DRIVER_MMIO_BLK_TYPE: &str = "mmioblk";
+
+/// Allocator of the indexes of the block devices plugged into the VM, the index of a block
+/// device decides its name in the guest.
+#[derive(Debug, Default)]
+pub struct BlockIndexAllocator {
+    used: Mutex<BTreeSet<u64>>,
+}
+
+impl BlockIndexAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocate the lowest free index, the guest kernel names the new virtio block device the
+    /// same way.
+    pub(crate) fn allocate(&self) -> Result<u64> {
+        let mut used = self
+            .used
+            .lock()
+            .map_err(|e| anyhow!("failed to lock block indexes {:?}", e))?;
+        let index = (FIRST_BLOCK_INDEX..MAX_BLOCK_INDEX)
+            .find(|i| !used.contains(i))
+            .ok_or_else(|| anyhow!("no free block device index"))?;
+        used.insert(index);
+        Ok(index)
+    }
+
+    pub(crate) fn release(&self, index: u64) -> Result<()> {
+        let mut used = self
+            .used
+            .lock()
+            .map_err(|e| anyhow!("failed to lock block indexes {:?}", e))?;
+        used.remove(&index);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Persist for BlockIndexAllocator {
+    type State = Vec<u64>;
+    type ConstructorArgs = ();
+
+    /// Save the indexes in use.
+    async fn save(&self) -> Result<Self::State> {
+        let used = self
+            .used
+            .lock()
+            .map_err(|e| anyhow!("failed to lock block indexes {:?}", e))?;
+        Ok(used.iter().copied().collect())
+    }
+
+    /// Restore the allocator with the indexes in use.
+    async fn restore(_args: Self::ConstructorArgs, state: Self::State) -> Result<Self> {
+        Ok(Self {
+            used: Mutex::new(state.into_iter().collect()),
+        })
+    }
+}
+
+/// Get the name of a virtio block device in the guest from its index, e.g. vda for 0, vdaa for
+/// 26, the same way as the kernel names them.
+pub(crate) fn get_virt_drive_name(mut index: u64) -> String {
+    let mut suffix = Vec::new();
+    loop {
+        suffix.insert(0, b'a' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    format!("vd{}", String::from_utf8_lossy(&suffix))
+}
+
+pub(crate) fn is_block_device(path: &str) -> bool {
+    fs::metadata(path)
+        .map(|md| md.file_type().is_block_device())
+        .unwrap_or(false)
+}
+
+pub(crate) fn block_device_id(index: u64) -> String {
+    format!("drive_{}", index)
+}
+
+/// A block device of the host plugged into the VM.
+#[derive(Debug)]
+pub(crate) struct PluggedBlockDevice {
+    pub(crate) index: u64,
+    pub(crate) id: String,
+    pub(crate) path_on_host: String,
+    pub(crate) read_only: bool,
+    /// The driver and source of the agent storage to mount the device in the guest
+    pub(crate) driver: String,
+    pub(crate) source: String,
+    unplugged: AtomicBool,
+}
+
+impl PluggedBlockDevice {
+    /// Hotplug the block device at path into the VM. The device is plugged on the PCI bus with
+    /// the virtio-blk driver when the hypervisor reports the PCI path of the devices, on the
+    /// mmio bus otherwise.
+    pub(crate) async fn plug(
+        h: &dyn Hypervisor,
+        indexes: &BlockIndexAllocator,
+        path: &str,
+        read_only: bool,
+    ) -> Result<Self> {
+        let capabilities = h.capabilities().await.context("get capabilities")?;
+        if !capabilities.is_block_device_hotplug_supported() {
+            return Err(anyhow!("hypervisor doesn't support block device hotplug"));
+        }
+
+        let blockdev_info = h.hypervisor_config().await.blockdev_info;
+        if blockdev_info.disable_block_device_use {
+            return Err(anyhow!("block device use is disabled"));
+        }
+        let driver = blockdev_info.block_device_driver.as_str();
+        if driver != VIRTIO_BLK && driver != VIRTIO_BLK_MMIO {
+            return Err(anyhow!("unsupported block device driver {}", driver));
+        }
+
+        let index = indexes.allocate().context("allocate block index")?;
+        let id = block_device_id(index);
+        if let Err(e) = h
+            .add_device(Device::Block(BlockConfig {
+                id: id.clone(),
+                path_on_host: path.to_string(),
+                is_readonly: read_only,
+                no_drop: false,
+                index,
+            }))
+            .await
+        {
+            indexes.release(index)?;
+            return Err(e).with_context(|| format!("add block device {}", path));
+        }
+
+        let mut device = Self {
+            index,
+            id,
+            path_on_host: path.to_string(),
+            read_only,
+            driver: DRIVER_MMIO_BLK_TYPE.to_string(),
+            source: format!("/dev/{}", get_virt_drive_name(index)),
+            unplugged: AtomicBool::new(false),
+        };
+        // dragonball doesn't report PCI path, its virtio block devices are always mmio ones
+        if driver == VIRTIO_BLK && capabilities.is_device_pci_path_supported() {
+            match h.get_device_pci_path(&device.id).await {
+                Ok(pci_path) => {
+                    device.driver = DRIVER_BLK_TYPE.to_string();
+                    device.source = pci_path;
+                }
+                Err(e) => {
+                    if let Err(e) = device.unplug(h, indexes).await {
+                        warn!(sl!(), "failed to unplug block device {}: {:?}", path, e);
+                    }
+                    return Err(e).with_context(|| format!("get pci path of {}", path));
+                }
+            }
+        }
+
+        Ok(device)
+    }
+
+    /// The storage to mount the block device on mount_point in the guest.
+    pub(crate) fn storage(&self, fs_type: &str, options: &[String], mount_point: &str) -> Storage {
+        let mut options = options.to_vec();
+        if self.read_only && !options.iter().any(|o| o == "ro") {
+            options.push("ro".to_string());
+        }
+
+        Storage {
+            driver: self.driver.clone(),
+            source: self.source.clone(),
+            fs_type: fs_type.to_string(),
+            options,
+            mount_point: mount_point.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_unplugged(&self) -> bool {
+        self.unplugged.load(Ordering::SeqCst)
+    }
+
+    /// Remove the block device from the VM, only the first successful call does it.
+    pub(crate) async fn unplug(
+        &self,
+        h: &dyn Hypervisor,
+        indexes: &BlockIndexAllocator,
+    ) -> Result<()> {
+        if self.unplugged.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        if let Err(e) = h
+            .remove_device(Device::Block(BlockConfig {
+                id: self.id.clone(),
+                path_on_host: self.path_on_host.clone(),
+                is_readonly: self.read_only,
+                no_drop: false,
+                index: self.index,
+            }))
+            .await
+        {
+            self.unplugged.store(false, Ordering::SeqCst);
+            return Err(e).with_context(|| format!("remove block device {}", self.path_on_host));
+        }
+        indexes.release(self.index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_virt_drive_name() {
+        assert_eq!(get_virt_drive_name(0), "vda");
+        assert_eq!(get_virt_drive_name(1), "vdb");
+        assert_eq!(get_virt_drive_name(25), "vdz");
+        assert_eq!(get_virt_drive_name(26), "vdaa");
+        assert_eq!(get_virt_drive_name(27), "vdab");
+        assert_eq!(get_virt_drive_name(701), "vdzz");
+        assert_eq!(get_virt_drive_name(702), "vdaaa");
+    }
+
+    #[test]
+    fn test_block_index_allocator() {
+        let indexes = BlockIndexAllocator::new();
+        assert_eq!(indexes.allocate().unwrap(), 1);
+        assert_eq!(indexes.allocate().unwrap(), 2);
+        indexes.release(1).unwrap();
+        assert_eq!(indexes.allocate().unwrap(), 1);
+        assert_eq!(indexes.allocate().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_block_index_allocator_persist() {
+        let indexes = BlockIndexAllocator::new();
+        for _ in 0..3 {
+            indexes.allocate().unwrap();
+        }
+        indexes.release(2).unwrap();
+
+        let state = indexes.save().await.unwrap();
+        assert_eq!(state, vec![1, 3]);
+        let indexes = BlockIndexAllocator::restore((), state).await.unwrap();
+        assert_eq!(indexes.allocate().unwrap(), 2);
+        assert_eq!(indexes.allocate().unwrap(), 4);
+    }
+}
@@ -12,9 +12,11 @@ extern crate slog;
 
 logging::logger_with_subsystem!(sl, "resource");
 
+pub mod block_device;
 pub mod cgroups;
 pub mod manager;
 mod manager_inner;
+pub mod memory;
 pub mod network;
 pub mod resource_persist;
 use network::NetworkConfig;
@@ -5,7 +5,13 @@
 //
 
 use crate::resource_persist::ResourceState;
-use crate::{manager_inner::ResourceManagerInner, rootfs::Rootfs, volume::Volume, ResourceConfig};
+use crate::{
+    manager_inner::ResourceManagerInner,
+    network::NetworkConfig,
+    rootfs::Rootfs,
+    volume::{DirectVolumeDevice, Volume},
+    ResourceConfig,
+};
 use agent::{Agent, Storage};
 use anyhow::Result;
 use async_trait::async_trait;
@@ -60,6 +66,11 @@ impl ResourceManager {
         inner.setup_after_start_vm().await
     }
 
+    pub async fn handle_network(&self, network_config: NetworkConfig) -> Result<()> {
+        let mut inner = self.inner.write().await;
+        inner.handle_network(network_config).await
+    }
+
     pub async fn get_storage_for_sandbox(&self) -> Result<Vec<Storage>> {
         let inner = self.inner.read().await;
         inner.get_storage_for_sandbox().await
@@ -87,6 +98,11 @@ impl ResourceManager {
         inner.handler_volumes(cid, spec).await
     }
 
+    pub async fn get_direct_volume_device(&self, host_device: &str) -> Result<DirectVolumeDevice> {
+        let inner = self.inner.read().await;
+        inner.get_direct_volume_device(host_device).await
+    }
+
     pub async fn dump(&self) {
         let inner = self.inner.read().await;
         inner.dump().await
@@ -101,10 +117,30 @@ impl ResourceManager {
         inner.update_cgroups(cid, linux_resources).await
     }
 
+    pub async fn update_mem_resources(
+        &self,
+        cid: &str,
+        linux_resources: Option<&LinuxResources>,
+    ) -> Result<()> {
+        let inner = self.inner.read().await;
+        inner.update_mem_resources(cid, linux_resources).await
+    }
+
+    pub async fn delete_mem_resources(&self, cid: &str) -> Result<()> {
+        let inner = self.inner.read().await;
+        inner.delete_mem_resources(cid).await
+    }
+
     pub async fn delete_cgroups(&self) -> Result<()> {
         let inner = self.inner.read().await;
         inner.delete_cgroups().await
     }
+
+    /// Release the sandbox resources left on the host, once the VM is stopped.
+    pub async fn cleanup(&self) -> Result<()> {
+        let inner = self.inner.read().await;
+        inner.cleanup().await
+    }
 }
 
 #[async_trait]
@@ -18,12 +18,14 @@ use persist::sandbox_persist::Persist;
 use tokio::runtime;
 
 use crate::{
+    block_device::BlockIndexAllocator,
     cgroups::{CgroupArgs, CgroupsResource},
     manager::ManagerArgs,
-    network::{self, Network},
+    memory::MemoryResource,
+    network::{self, Network, NetworkConfig},
     rootfs::{RootFsResource, Rootfs},
     share_fs::{self, ShareFs},
-    volume::{Volume, VolumeResource},
+    volume::{self, DirectVolumeDevice, Volume, VolumeResource},
     ResourceConfig,
 };
 
@@ -34,10 +36,12 @@ pub(crate) struct ResourceManagerInner {
     hypervisor: Arc<dyn Hypervisor>,
     network: Option<Arc<dyn Network>>,
     share_fs: Option<Arc<dyn ShareFs>>,
+    block_indexes: Arc<BlockIndexAllocator>,
 
     pub rootfs_resource: RootFsResource,
     pub volume_resource: VolumeResource,
     pub cgroups_resource: CgroupsResource,
+    pub memory_resource: MemoryResource,
 }
 
 impl ResourceManagerInner {
@@ -48,6 +52,7 @@ impl ResourceManagerInner {
         toml_config: Arc<TomlConfig>,
     ) -> Result<Self> {
         let cgroups_resource = CgroupsResource::new(sid, &toml_config)?;
+        let memory_resource = MemoryResource::new(&toml_config);
         Ok(Self {
             sid: sid.to_string(),
             toml_config,
@@ -55,9 +60,11 @@ impl ResourceManagerInner {
             hypervisor,
             network: None,
             share_fs: None,
+            block_indexes: Arc::new(BlockIndexAllocator::new()),
             rootfs_resource: RootFsResource::new(),
             volume_resource: VolumeResource::new(),
             cgroups_resource,
+            memory_resource,
         })
     }
 
@@ -89,31 +96,7 @@ impl ResourceManagerInner {
                     };
                 }
                 ResourceConfig::Network(c) => {
-                    // 1. When using Rust asynchronous programming, we use .await to
-                    //    allow other task to run instead of waiting for the completion of the current task.
-                    // 2. Also, when handling the pod network, we need to set the shim threads
-                    //    into the network namespace to perform those operations.
-                    // However, as the increase of the I/O intensive tasks, two issues could be caused by the two points above:
-                    // a. When the future is blocked, the current thread (which is in the pod netns)
-                    //    might be take over by other tasks. After the future is finished, the thread take over
-                    //    the current task might not be in the pod netns. But the current task still need to run in pod netns
-                    // b. When finish setting up the network, the current thread will be set back to the host namespace.
-                    //    In Rust Async, if the current thread is taken over by other task, the netns is dropped on another thread,
-                    //    but it is not in netns. So, the previous thread would still remain in the pod netns.
-                    // The solution is to block the future on the current thread, it is enabled by spawn an os thread, create a
-                    // tokio runtime, and block the task on it.
-                    let hypervisor = self.hypervisor.clone();
-                    let network = thread::spawn(move || -> Result<Arc<dyn Network>> {
-                        let rt = runtime::Builder::new_current_thread().enable_io().build()?;
-                        let d = rt.block_on(network::new(&c)).context("new network")?;
-                        rt.block_on(d.setup(hypervisor.as_ref()))
-                            .context("setup network")?;
-                        Ok(d)
-                    })
-                    .join()
-                    .map_err(|e| anyhow!("{:?}", e))
-                    .context("Couldn't join on the associated thread")?
-                    .context("failed to set up network")?;
+                    let network = self.do_handle_network(c).context("handle network")?;
                     self.network = Some(network);
                 }
             };
@@ -122,6 +105,74 @@ impl ResourceManagerInner {
         Ok(())
     }
 
+    fn do_handle_network(&self, config: NetworkConfig) -> Result<Arc<dyn Network>> {
+        // 1. When using Rust asynchronous programming, we use .await to
+        //    allow other task to run instead of waiting for the completion of the current task.
+        // 2. Also, when handling the pod network, we need to set the shim threads
+        //    into the network namespace to perform those operations.
+        // However, as the increase of the I/O intensive tasks, two issues could be caused by the two points above:
+        // a. When the future is blocked, the current thread (which is in the pod netns)
+        //    might be take over by other tasks. After the future is finished, the thread take over
+        //    the current task might not be in the pod netns. But the current task still need to run in pod netns
+        // b. When finish setting up the network, the current thread will be set back to the host namespace.
+        //    In Rust Async, if the current thread is taken over by other task, the netns is dropped on another thread,
+        //    but it is not in netns. So, the previous thread would still remain in the pod netns.
+        // The solution is to block the future on the current thread, it is enabled by spawn an os thread, create a
+        // tokio runtime, and block the task on it.
+        let hypervisor = self.hypervisor.clone();
+        thread::spawn(move || -> Result<Arc<dyn Network>> {
+            let rt = runtime::Builder::new_current_thread().enable_io().build()?;
+            let d = rt.block_on(network::new(&config)).context("new network")?;
+            rt.block_on(d.setup(hypervisor.as_ref()))
+                .context("setup network")?;
+            Ok(d)
+        })
+        .join()
+        .map_err(|e| anyhow!("{:?}", e))
+        .context("Couldn't join on the associated thread")?
+        .context("failed to set up network")
+    }
+
+    /// handle_network replaces the network of the sandbox after the VM is started, e.g. when the
+    /// netns of the pod has changed. The interfaces of the old network are hot-unplugged.
+    pub async fn handle_network(&mut self, config: NetworkConfig) -> Result<()> {
+        self.remove_network().await.context("remove network")?;
+
+        let network = self.do_handle_network(config).context("handle network")?;
+        self.handle_interfaces(network.as_ref())
+            .await
+            .context("handle interfaces")?;
+        self.handle_neighbours(network.as_ref())
+            .await
+            .context("handle neighbors")?;
+        self.handle_routes(network.as_ref())
+            .await
+            .context("handle routes")?;
+        self.network = Some(network);
+
+        Ok(())
+    }
+
+    // remove_network detaches all the interfaces of the sandbox network, the network is kept if
+    // any of them fails to be detached.
+    async fn remove_network(&mut self) -> Result<()> {
+        if let Some(network) = self.network.clone() {
+            // the network is removed in the pod netns, see do_handle_network()
+            let hypervisor = self.hypervisor.clone();
+            thread::spawn(move || -> Result<()> {
+                let rt = runtime::Builder::new_current_thread().enable_io().build()?;
+                rt.block_on(network.remove(hypervisor.as_ref()))
+            })
+            .join()
+            .map_err(|e| anyhow!("{:?}", e))
+            .context("Couldn't join on the associated thread")?
+            .context("failed to remove network")?;
+            self.network = None;
+        }
+
+        Ok(())
+    }
+
     async fn handle_interfaces(&self, network: &dyn Network) -> Result<()> {
         for i in network.interfaces().await.context("get interfaces")? {
             // update interface
@@ -203,7 +254,8 @@ impl ResourceManagerInner {
         self.rootfs_resource
             .handler_rootfs(
                 &self.share_fs,
-                self.hypervisor.as_ref(),
+                &self.hypervisor,
+                &self.block_indexes,
                 &self.sid,
                 cid,
                 root,
@@ -219,7 +271,21 @@ impl ResourceManagerInner {
         spec: &oci::Spec,
     ) -> Result<Vec<Arc<dyn Volume>>> {
         self.volume_resource
-            .handler_volumes(&self.share_fs, cid, spec)
+            .handler_volumes(
+                &self.share_fs,
+                &self.agent,
+                &self.hypervisor,
+                &self.block_indexes,
+                &self.sid,
+                cid,
+                spec,
+            )
+            .await
+    }
+
+    pub async fn get_direct_volume_device(&self, host_device: &str) -> Result<DirectVolumeDevice> {
+        self.volume_resource
+            .get_direct_volume_device(host_device)
             .await
     }
 
@@ -233,10 +299,33 @@ impl ResourceManagerInner {
             .await
     }
 
+    pub async fn update_mem_resources(
+        &self,
+        cid: &str,
+        linux_resources: Option<&LinuxResources>,
+    ) -> Result<()> {
+        self.memory_resource
+            .update_mem_resources(cid, linux_resources, self.hypervisor.as_ref())
+            .await
+    }
+
+    pub async fn delete_mem_resources(&self, cid: &str) -> Result<()> {
+        self.memory_resource
+            .delete_mem_resources(cid, self.hypervisor.as_ref())
+            .await
+    }
+
     pub async fn delete_cgroups(&self) -> Result<()> {
         self.cgroups_resource.delete().await
     }
 
+    pub async fn cleanup(&self) -> Result<()> {
+        // the share fs mounts and the direct volume records of the containers are left on the
+        // host if the shim crashed
+        share_fs::cleanup_sandbox_share_dir(&self.sid).context("cleanup sandbox share dir")?;
+        volume::cleanup_sandbox_id_files(&self.sid).context("cleanup direct volume sandbox id")
+    }
+
     pub async fn dump(&self) {
         self.rootfs_resource.dump().await;
         self.volume_resource.dump().await;
@@ -257,9 +346,13 @@ impl Persist for ResourceManagerInner {
             }
         }
         let cgroup_state = self.cgroups_resource.save().await?;
+        let memory_state = self.memory_resource.save().await?;
+        let block_indexes = self.block_indexes.save().await?;
         Ok(ResourceState {
             endpoint: endpoint_state,
             cgroup_state: Some(cgroup_state),
+            memory_state: Some(memory_state),
+            block_indexes: Some(block_indexes),
         })
     }
 
@@ -268,6 +361,11 @@ impl Persist for ResourceManagerInner {
         resource_args: Self::ConstructorArgs,
         resource_state: Self::State,
     ) -> Result<Self> {
+        // the sandbox memory is sized from the state, the VM was booted with its default memory
+        let memory_resource = match resource_state.memory_state {
+            Some(memory_state) => MemoryResource::restore((), memory_state).await?,
+            None => MemoryResource::new(&resource_args.config),
+        };
         let args = CgroupArgs {
             sid: resource_args.sid.clone(),
             config: resource_args.config,
@@ -278,6 +376,10 @@ impl Persist for ResourceManagerInner {
             hypervisor: resource_args.hypervisor,
             network: None,
             share_fs: None,
+            block_indexes: Arc::new(
+                BlockIndexAllocator::restore((), resource_state.block_indexes.unwrap_or_default())
+                    .await?,
+            ),
             rootfs_resource: RootFsResource::new(),
             volume_resource: VolumeResource::new(),
             cgroups_resource: CgroupsResource::restore(
@@ -285,6 +387,7 @@ impl Persist for ResourceManagerInner {
                 resource_state.cgroup_state.unwrap_or_default(),
             )
             .await?,
+            memory_resource,
             toml_config: Arc::new(TomlConfig::default()),
         })
     }
@@ -0,0 +1,15 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct MemoryState {
+    pub default_mem_mb: u32,
+    pub container_mem_mb: HashMap<String, u32>,
+}
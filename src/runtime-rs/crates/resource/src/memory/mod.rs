@@ -0,0 +1,219 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+pub mod memory_persist;
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hypervisor::Hypervisor;
+use kata_types::config::TomlConfig;
+use memory_persist::MemoryState;
+use oci::LinuxResources;
+use persist::sandbox_persist::Persist;
+use tokio::sync::RwLock;
+
+const MIB: u64 = 1 << 20;
+
+/// MemoryResource sizes the sandbox memory according to the memory limits of the containers.
+///
+/// The sandbox memory is the default memory of the hypervisor plus the memory limits, and the
+/// hugepage limits, of all the containers.
+pub struct MemoryResource {
+    default_mem_mb: u32,
+    container_mem_mb: Arc<RwLock<HashMap<String, u32>>>,
+}
+
+impl MemoryResource {
+    pub fn new(toml_config: &TomlConfig) -> Self {
+        let default_mem_mb = toml_config
+            .hypervisor
+            .get(&toml_config.runtime.hypervisor_name)
+            .map(|hv| hv.memory_info.default_memory)
+            .unwrap_or_default();
+
+        Self {
+            default_mem_mb,
+            container_mem_mb: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// update_mem_resources records the memory limits of the container, and resizes the sandbox
+    /// memory if they have changed and the hypervisor supports it. The limits are only recorded
+    /// once the sandbox memory is resized.
+    pub async fn update_mem_resources(
+        &self,
+        cid: &str,
+        linux_resources: Option<&LinuxResources>,
+        h: &dyn Hypervisor,
+    ) -> Result<()> {
+        if !is_mem_resize_supported(h).await? {
+            return Ok(());
+        }
+
+        let mem_mb = calc_mem_mb(linux_resources);
+        // hold the lock until the memory is resized, so that the resizes don't race
+        let mut container_mem_mb = self.container_mem_mb.write().await;
+        if container_mem_mb.get(cid) == Some(&mem_mb) {
+            return Ok(());
+        }
+        let total_mem_mb = self.total_mem_mb(&container_mem_mb, Some(cid)) + mem_mb;
+
+        let new_mem_mb = h
+            .resize_memory(total_mem_mb)
+            .await
+            .with_context(|| format!("resize memory to {} MiB", total_mem_mb))?;
+        container_mem_mb.insert(cid.to_owned(), mem_mb);
+        info!(
+            sl!(),
+            "resize sandbox memory for container {} to {} MiB", cid, new_mem_mb
+        );
+
+        Ok(())
+    }
+
+    /// delete_mem_resources forgets the memory limits of a deleted container, and shrinks the
+    /// sandbox memory if the hypervisor supports it.
+    pub async fn delete_mem_resources(&self, cid: &str, h: &dyn Hypervisor) -> Result<()> {
+        let mut container_mem_mb = self.container_mem_mb.write().await;
+        let mem_mb = match container_mem_mb.remove(cid) {
+            Some(mem_mb) => mem_mb,
+            None => return Ok(()),
+        };
+        if mem_mb == 0 || !is_mem_resize_supported(h).await? {
+            return Ok(());
+        }
+
+        let total_mem_mb = self.total_mem_mb(&container_mem_mb, None);
+        // the container is gone anyway, the memory is given back by the next resize
+        if let Err(e) = h.resize_memory(total_mem_mb).await {
+            warn!(
+                sl!(),
+                "failed to resize memory to {} MiB after deleting container {}: {:?}",
+                total_mem_mb,
+                cid,
+                e
+            );
+        }
+
+        Ok(())
+    }
+
+    // the sandbox memory for the containers in container_mem_mb, except the container skip_cid
+    fn total_mem_mb(&self, container_mem_mb: &HashMap<String, u32>, skip_cid: Option<&str>) -> u32 {
+        self.default_mem_mb
+            + container_mem_mb
+                .iter()
+                .filter(|(cid, _)| Some(cid.as_str()) != skip_cid)
+                .map(|(_, mem_mb)| mem_mb)
+                .sum::<u32>()
+    }
+}
+
+#[async_trait]
+impl Persist for MemoryResource {
+    type State = MemoryState;
+    type ConstructorArgs = ();
+
+    /// Save a state of the component.
+    async fn save(&self) -> Result<Self::State> {
+        Ok(MemoryState {
+            default_mem_mb: self.default_mem_mb,
+            container_mem_mb: self.container_mem_mb.read().await.clone(),
+        })
+    }
+
+    /// Restore a component from a specified state.
+    async fn restore(_args: Self::ConstructorArgs, state: Self::State) -> Result<Self> {
+        Ok(Self {
+            default_mem_mb: state.default_mem_mb,
+            container_mem_mb: Arc::new(RwLock::new(state.container_mem_mb)),
+        })
+    }
+}
+
+async fn is_mem_resize_supported(h: &dyn Hypervisor) -> Result<bool> {
+    Ok(h.capabilities()
+        .await
+        .context("get hypervisor capabilities")?
+        .is_mem_resize_supported())
+}
+
+// The memory in MiB used by a container, which is its memory limit plus its hugepage limits.
+fn calc_mem_mb(linux_resources: Option<&LinuxResources>) -> u32 {
+    let resources = match linux_resources {
+        Some(r) => r,
+        None => return 0,
+    };
+
+    let mem_limit = resources
+        .memory
+        .as_ref()
+        .and_then(|m| m.limit)
+        .filter(|l| *l > 0)
+        .unwrap_or_default() as u64;
+    let hugepage_limit: u64 = resources.hugepage_limits.iter().map(|h| h.limit).sum();
+
+    // round up, the guest needs at least the memory required by the limits
+    ((mem_limit + hugepage_limit + MIB - 1) / MIB) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oci::{LinuxHugepageLimit, LinuxMemory};
+
+    #[test]
+    fn test_calc_mem_mb() {
+        assert_eq!(calc_mem_mb(None), 0);
+
+        let mut resources = LinuxResources::default();
+        assert_eq!(calc_mem_mb(Some(&resources)), 0);
+
+        // unlimited memory is not counted
+        resources.memory = Some(LinuxMemory {
+            limit: Some(-1),
+            ..Default::default()
+        });
+        assert_eq!(calc_mem_mb(Some(&resources)), 0);
+
+        resources.memory = Some(LinuxMemory {
+            limit: Some(512 * MIB as i64 + 1),
+            ..Default::default()
+        });
+        assert_eq!(calc_mem_mb(Some(&resources)), 513);
+
+        resources.hugepage_limits = vec![LinuxHugepageLimit {
+            page_size: "2MB".to_string(),
+            limit: 128 * MIB,
+        }];
+        assert_eq!(calc_mem_mb(Some(&resources)), 641);
+    }
+
+    #[test]
+    fn test_total_mem_mb() {
+        let memory_resource = MemoryResource {
+            default_mem_mb: 2048,
+            container_mem_mb: Arc::new(RwLock::new(HashMap::new())),
+        };
+        let mut container_mem_mb = HashMap::new();
+        assert_eq!(memory_resource.total_mem_mb(&container_mem_mb, None), 2048);
+
+        container_mem_mb.insert("c1".to_string(), 512);
+        container_mem_mb.insert("c2".to_string(), 256);
+        assert_eq!(memory_resource.total_mem_mb(&container_mem_mb, None), 2816);
+        // the container being resized is not counted twice
+        assert_eq!(
+            memory_resource.total_mem_mb(&container_mem_mb, Some("c1")),
+            2304
+        );
+        assert_eq!(
+            memory_resource.total_mem_mb(&container_mem_mb, Some("c3")),
+            2816
+        );
+    }
+}
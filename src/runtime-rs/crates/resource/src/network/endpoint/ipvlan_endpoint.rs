@@ -75,16 +75,16 @@ impl Endpoint for IPVlanEndpoint {
     }
 
     async fn detach(&self, h: &dyn Hypervisor) -> Result<()> {
-        self.net_pair
-            .del_network_model()
-            .await
-            .context("error deleting network model")?;
         let config = self
             .get_network_config()
             .context("error getting network config")?;
         h.remove_device(Device::Network(config))
             .await
             .context("error removing device by hypervisor")?;
+        self.net_pair
+            .del_network_model()
+            .await
+            .context("error deleting network model")?;
 
         Ok(())
     }
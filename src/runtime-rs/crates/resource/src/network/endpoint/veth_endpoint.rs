@@ -71,14 +71,14 @@ impl Endpoint for VethEndpoint {
     }
 
     async fn detach(&self, h: &dyn Hypervisor) -> Result<()> {
-        self.net_pair
-            .del_network_model()
-            .await
-            .context("del network model")?;
         let config = self.get_network_config().context("get network config")?;
         h.remove_device(Device::Network(config))
             .await
             .context("remove device")?;
+        self.net_pair
+            .del_network_model()
+            .await
+            .context("del network model")?;
         Ok(())
     }
     async fn save(&self) -> Option<EndpointState> {
@@ -81,6 +81,22 @@ impl Network for NetworkWithNetns {
         Ok(())
     }
 
+    // remove detaches all the endpoints, their devices are hot-unplugged from the VM. An endpoint
+    // is only dropped once it's detached, so that a failed removal can be retried.
+    async fn remove(&self, h: &dyn Hypervisor) -> Result<()> {
+        let mut inner = self.inner.write().await;
+        let _netns_guard = netns::NetnsGuard::new(&inner.netns_path).context("net netns guard")?;
+        while let Some(e) = inner.entity_list.last() {
+            let name = e.endpoint.name().await;
+            e.endpoint
+                .detach(h)
+                .await
+                .with_context(|| format!("detach endpoint {}", name))?;
+            inner.entity_list.pop();
+        }
+        Ok(())
+    }
+
     async fn interfaces(&self) -> Result<Vec<agent::Interface>> {
         let inner = self.inner.read().await;
         let mut interfaces = vec![];
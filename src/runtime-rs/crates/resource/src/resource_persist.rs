@@ -8,8 +8,11 @@ use crate::network::EndpointState;
 use serde::{Deserialize, Serialize};
 
 use crate::cgroups::cgroup_persist::CgroupState;
+use crate::memory::memory_persist::MemoryState;
 #[derive(Serialize, Deserialize, Default)]
 pub struct ResourceState {
     pub endpoint: Vec<EndpointState>,
     pub cgroup_state: Option<CgroupState>,
+    pub memory_state: Option<MemoryState>,
+    pub block_indexes: Option<Vec<u64>>,
 }
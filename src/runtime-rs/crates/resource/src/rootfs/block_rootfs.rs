@@ -0,0 +1,78 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use agent::Storage;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hypervisor::Hypervisor;
+use kata_types::mount::Mount;
+use std::sync::Arc;
+
+use super::{Rootfs, ROOTFS};
+use crate::{
+    block_device::{BlockIndexAllocator, PluggedBlockDevice},
+    share_fs::do_get_guest_path,
+};
+
+pub(crate) struct BlockRootfs {
+    guest_path: String,
+    storage: Storage,
+    device: PluggedBlockDevice,
+    hypervisor: Arc<dyn Hypervisor>,
+    block_indexes: Arc<BlockIndexAllocator>,
+}
+
+impl BlockRootfs {
+    pub async fn new(
+        hypervisor: &Arc<dyn Hypervisor>,
+        block_indexes: &Arc<BlockIndexAllocator>,
+        cid: &str,
+        rootfs: &Mount,
+    ) -> Result<Self> {
+        let device = PluggedBlockDevice::plug(
+            hypervisor.as_ref(),
+            block_indexes,
+            &rootfs.source,
+            rootfs.read_only,
+        )
+        .await
+        .context("plug rootfs block device")?;
+
+        // the agent mounts the device on the same path as the share fs rootfs
+        let guest_path = do_get_guest_path(ROOTFS, cid, false, false);
+        let storage = device.storage(&rootfs.fs_type, &rootfs.options, &guest_path);
+
+        Ok(BlockRootfs {
+            guest_path,
+            storage,
+            device,
+            hypervisor: Arc::clone(hypervisor),
+            block_indexes: Arc::clone(block_indexes),
+        })
+    }
+}
+
+#[async_trait]
+impl Rootfs for BlockRootfs {
+    async fn get_guest_rootfs_path(&self) -> Result<String> {
+        Ok(self.guest_path.clone())
+    }
+
+    async fn get_rootfs_mount(&self) -> Result<Vec<oci::Mount>> {
+        Ok(vec![])
+    }
+
+    async fn get_storage(&self) -> Option<Storage> {
+        Some(self.storage.clone())
+    }
+
+    async fn cleanup(&self) -> Result<()> {
+        self.device
+            .unplug(self.hypervisor.as_ref(), &self.block_indexes)
+            .await
+            .context("unplug rootfs block device")
+    }
+}
@@ -4,7 +4,9 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+mod block_rootfs;
 mod nydus_rootfs;
+mod overlay_rootfs;
 mod share_fs_rootfs;
 
 use agent::Storage;
@@ -15,13 +17,18 @@ use kata_types::mount::Mount;
 use std::{sync::Arc, vec::Vec};
 use tokio::sync::RwLock;
 
-use crate::share_fs::ShareFs;
+use crate::{
+    block_device::{is_block_device, BlockIndexAllocator},
+    share_fs::ShareFs,
+};
 
-use self::nydus_rootfs::NYDUS_ROOTFS_TYPE;
+use self::{nydus_rootfs::NYDUS_ROOTFS_TYPE, overlay_rootfs::is_overlay_rootfs};
 
 const ROOTFS: &str = "rootfs";
 const HYBRID_ROOTFS_LOWER_DIR: &str = "rootfs_lower";
 const TYPE_OVERLAY_FS: &str = "overlay";
+const KATA_OVERLAY_DEV_TYPE: &str = "overlayfs";
+
 #[async_trait]
 pub trait Rootfs: Send + Sync {
     async fn get_guest_rootfs_path(&self) -> Result<String>;
@@ -56,7 +63,8 @@ impl RootFsResource {
     pub async fn handler_rootfs(
         &self,
         share_fs: &Option<Arc<dyn ShareFs>>,
-        hypervisor: &dyn Hypervisor,
+        hypervisor: &Arc<dyn Hypervisor>,
+        block_indexes: &Arc<BlockIndexAllocator>,
         sid: &str,
         cid: &str,
         root: &oci::Root,
@@ -85,7 +93,21 @@ impl RootFsResource {
             mounts_vec if is_single_layer_rootfs(mounts_vec) => {
                 // Safe as single_layer_rootfs must have one layer
                 let layer = &mounts_vec[0];
-                let rootfs: Arc<dyn Rootfs> = if let Some(share_fs) = share_fs {
+                let rootfs: Arc<dyn Rootfs> = if layer.fs_type != NYDUS_ROOTFS_TYPE
+                    && is_block_device(&layer.source)
+                    && !hypervisor
+                        .hypervisor_config()
+                        .await
+                        .blockdev_info
+                        .disable_block_device_use
+                {
+                    // block device rootfs, e.g. from the devicemapper snapshotter
+                    Arc::new(
+                        block_rootfs::BlockRootfs::new(hypervisor, block_indexes, cid, layer)
+                            .await
+                            .context("new block rootfs")?,
+                    )
+                } else if let Some(share_fs) = share_fs {
                     // nydus rootfs
                     if layer.fs_type == NYDUS_ROOTFS_TYPE {
                         Arc::new(
@@ -114,6 +136,22 @@ impl RootFsResource {
                 inner.rootfs.push(Arc::clone(&rootfs));
                 Ok(rootfs)
             }
+            mounts_vec if is_overlay_rootfs(mounts_vec) => {
+                // multi-layer overlay rootfs, the layers are shared into the guest one by one
+                let rootfs: Arc<dyn Rootfs> = if let Some(share_fs) = share_fs {
+                    Arc::new(
+                        overlay_rootfs::OverlayRootfs::new(share_fs, cid, mounts_vec)
+                            .await
+                            .context("new overlay rootfs")?,
+                    )
+                } else {
+                    return Err(anyhow!("share fs is unavailable"));
+                };
+
+                let mut inner = self.inner.write().await;
+                inner.rootfs.push(Arc::clone(&rootfs));
+                Ok(rootfs)
+            }
             _ => {
                 return Err(anyhow!(
                     "unsupported rootfs mounts count {}",
@@ -3,14 +3,22 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 //
-use std::{fs, sync::Arc};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use super::{Rootfs, TYPE_OVERLAY_FS};
+use super::{Rootfs, KATA_OVERLAY_DEV_TYPE, TYPE_OVERLAY_FS};
 use crate::{
     rootfs::{HYBRID_ROOTFS_LOWER_DIR, ROOTFS},
     share_fs::{
-        do_get_guest_path, do_get_guest_share_path, get_host_rw_shared_path, rafs_mount, ShareFs,
-        ShareFsRootfsConfig, PASSTHROUGH_FS_DIR,
+        do_get_guest_path, do_get_guest_share_path, get_host_rw_shared_path, rafs_mount,
+        rafs_umount, ShareFs, ShareFsRootfsConfig, PASSTHROUGH_FS_DIR,
     },
 };
 use agent::Storage;
@@ -27,107 +35,134 @@ const NYDUS_ROOTFS_V5: &str = "v5";
 const NYDUS_ROOTFS_V6: &str = "v6";
 
 const SNAPSHOT_DIR: &str = "snapshotdir";
-const KATA_OVERLAY_DEV_TYPE: &str = "overlayfs";
 
 pub(crate) struct NydusRootfs {
     guest_path: String,
     rootfs: Storage,
+    hypervisor: Arc<dyn Hypervisor>,
+    share_fs: Arc<dyn ShareFs>,
+    // the rafs instance of the metadata mounted in the guest
+    rafs_meta: String,
+    rafs_mnt: String,
+    // config of the shared snapshot dir holding the upperdir and the workdir
+    snapshot: ShareFsRootfsConfig,
+    // the rootfs directory created under the share directory
+    rootfs_dir: PathBuf,
+    released: AtomicBool,
 }
 
 impl NydusRootfs {
     pub async fn new(
         share_fs: &Arc<dyn ShareFs>,
-        h: &dyn Hypervisor,
+        h: &Arc<dyn Hypervisor>,
         sid: &str,
         cid: &str,
         rootfs: &Mount,
     ) -> Result<Self> {
-        let share_fs_mount = share_fs.get_share_fs_mount();
         let extra_options =
             NydusExtraOptions::new(rootfs).context("failed to parse nydus extra options")?;
         info!(sl!(), "extra_option {:?}", &extra_options);
-        let rafs_meta = &extra_options.source;
-        let (rootfs_storage, rootfs_guest_path) = match extra_options.fs_version.as_str() {
+        match extra_options.fs_version.as_str() {
             // both nydus v5 and v6 can be handled by the builtin nydus in dragonball by using the rafs mode.
             // nydus v6 could also be handled by the guest kernel as well, but some kernel patch is not support in the upstream community. We will add an option to let runtime-rs handle nydus v6 in the guest kernel optionally once the patch is ready
             // see this issue (https://github.com/kata-containers/kata-containers/issues/5143)
-            NYDUS_ROOTFS_V5 | NYDUS_ROOTFS_V6 => {
-                // rafs mount the metadata of nydus rootfs
-                let rafs_mnt = do_get_guest_share_path(HYBRID_ROOTFS_LOWER_DIR, cid, true);
-                rafs_mount(
-                    h,
-                    rafs_meta.to_string(),
-                    rafs_mnt,
-                    extra_options.config.clone(),
-                    None,
-                )
-                .await
-                .context("failed to do rafs mount")?;
-                // create rootfs under the share directory
-                let container_share_dir = get_host_rw_shared_path(sid)
-                    .join(PASSTHROUGH_FS_DIR)
-                    .join(cid);
-                let rootfs_dir = container_share_dir.join(ROOTFS);
-                fs::create_dir_all(rootfs_dir).context("failed to create directory")?;
-                // mount point inside the guest
-                let rootfs_guest_path = do_get_guest_path(ROOTFS, cid, false, false);
-                // bind mount the snapshot dir under the share directory
-                share_fs_mount
-                    .share_rootfs(&ShareFsRootfsConfig {
-                        cid: cid.to_string(),
-                        source: extra_options.snapshot_dir.clone(),
-                        target: SNAPSHOT_DIR.to_string(),
-                        readonly: true,
-                        is_rafs: false,
-                    })
-                    .await
-                    .context("share nydus rootfs")?;
-                let mut options: Vec<String> = Vec::new();
-                options.push(
-                    "lowerdir=".to_string()
-                        + &do_get_guest_path(HYBRID_ROOTFS_LOWER_DIR, cid, false, true),
-                );
-                options.push(
-                    "workdir=".to_string()
-                        + &do_get_guest_path(
-                            format!("{}/{}", SNAPSHOT_DIR, "work").as_str(),
-                            cid,
-                            false,
-                            false,
-                        ),
-                );
-                options.push(
-                    "upperdir=".to_string()
-                        + &do_get_guest_path(
-                            format!("{}/{}", SNAPSHOT_DIR, "fs").as_str(),
-                            cid,
-                            false,
-                            false,
-                        ),
-                );
-                options.push("index=off".to_string());
-                Ok((
-                    Storage {
-                        driver: KATA_OVERLAY_DEV_TYPE.to_string(),
-                        source: TYPE_OVERLAY_FS.to_string(),
-                        fs_type: TYPE_OVERLAY_FS.to_string(),
-                        options,
-                        mount_point: rootfs_guest_path.clone(),
-                        ..Default::default()
-                    },
-                    rootfs_guest_path,
-                ))
-            }
+            NYDUS_ROOTFS_V5 | NYDUS_ROOTFS_V6 => {}
             _ => {
                 let errstr: &str = "new_nydus_rootfs: invalid nydus rootfs type";
                 error!(sl!(), "{}", errstr);
-                Err(anyhow!(errstr))
+                return Err(anyhow!(errstr));
             }
-        }?;
-        Ok(NydusRootfs {
-            guest_path: rootfs_guest_path,
-            rootfs: rootfs_storage,
-        })
+        }
+
+        // create rootfs under the share directory
+        let container_share_dir = get_host_rw_shared_path(sid)
+            .join(PASSTHROUGH_FS_DIR)
+            .join(cid);
+        let rootfs_dir = container_share_dir.join(ROOTFS);
+        // mount point inside the guest
+        let rootfs_guest_path = do_get_guest_path(ROOTFS, cid, false, false);
+        let mut options: Vec<String> = Vec::new();
+        options.push(
+            "lowerdir=".to_string() + &do_get_guest_path(HYBRID_ROOTFS_LOWER_DIR, cid, false, true),
+        );
+        options.push(
+            "workdir=".to_string()
+                + &do_get_guest_path(
+                    format!("{}/{}", SNAPSHOT_DIR, "work").as_str(),
+                    cid,
+                    false,
+                    false,
+                ),
+        );
+        options.push(
+            "upperdir=".to_string()
+                + &do_get_guest_path(
+                    format!("{}/{}", SNAPSHOT_DIR, "fs").as_str(),
+                    cid,
+                    false,
+                    false,
+                ),
+        );
+        options.push("index=off".to_string());
+
+        let nydus_rootfs = NydusRootfs {
+            guest_path: rootfs_guest_path.clone(),
+            rootfs: Storage {
+                driver: KATA_OVERLAY_DEV_TYPE.to_string(),
+                source: TYPE_OVERLAY_FS.to_string(),
+                fs_type: TYPE_OVERLAY_FS.to_string(),
+                options,
+                mount_point: rootfs_guest_path,
+                ..Default::default()
+            },
+            hypervisor: Arc::clone(h),
+            share_fs: Arc::clone(share_fs),
+            rafs_meta: extra_options.source.clone(),
+            rafs_mnt: do_get_guest_share_path(HYBRID_ROOTFS_LOWER_DIR, cid, true),
+            // bind mount the snapshot dir under the share directory
+            snapshot: ShareFsRootfsConfig {
+                cid: cid.to_string(),
+                source: extra_options.snapshot_dir.clone(),
+                target: SNAPSHOT_DIR.to_string(),
+                readonly: true,
+                is_rafs: false,
+            },
+            rootfs_dir,
+            released: AtomicBool::new(false),
+        };
+
+        // undo the steps done so far if any of them fails
+        if let Err(e) = nydus_rootfs.setup(&extra_options.config).await {
+            if let Err(err) = nydus_rootfs.cleanup().await {
+                warn!(sl!(), "failed to clean up nydus rootfs {}: {:?}", cid, err);
+            }
+            return Err(e);
+        }
+
+        Ok(nydus_rootfs)
+    }
+
+    async fn setup(&self, rafs_config: &str) -> Result<()> {
+        // rafs mount the metadata of nydus rootfs
+        rafs_mount(
+            self.hypervisor.as_ref(),
+            self.rafs_meta.clone(),
+            self.rafs_mnt.clone(),
+            rafs_config.to_string(),
+            None,
+        )
+        .await
+        .context("failed to do rafs mount")?;
+
+        fs::create_dir_all(&self.rootfs_dir).context("failed to create directory")?;
+
+        self.share_fs
+            .get_share_fs_mount()
+            .share_rootfs(&self.snapshot)
+            .await
+            .context("share nydus rootfs")?;
+
+        Ok(())
     }
 }
 
@@ -146,8 +181,38 @@ impl Rootfs for NydusRootfs {
     }
 
     async fn cleanup(&self) -> Result<()> {
-        // TODO: Clean up NydusRootfs after the container is killed
-        warn!(sl!(), "Cleaning up NydusRootfs is still unimplemented.");
+        if self.released.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        // umount the snapshot dir shared to the guest
+        self.share_fs
+            .get_share_fs_mount()
+            .umount_rootfs(&self.snapshot)
+            .await
+            .context("umount nydus snapshot dir")?;
+
+        if let Err(err) = fs::remove_dir(&self.rootfs_dir) {
+            if err.kind() != ErrorKind::NotFound {
+                warn!(
+                    sl!(),
+                    "failed to remove rootfs dir {:?}: {:?}", self.rootfs_dir, err
+                );
+            }
+        }
+
+        // the rafs instance is gone with the VM if it has been stopped
+        if let Err(err) = rafs_umount(
+            self.hypervisor.as_ref(),
+            self.rafs_meta.clone(),
+            self.rafs_mnt.clone(),
+        )
+        .await
+        {
+            warn!(sl!(), "failed to umount rafs {}: {:?}", self.rafs_mnt, err);
+        }
+
+        self.released.store(true, Ordering::SeqCst);
         Ok(())
     }
 }
@@ -0,0 +1,390 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use agent::Storage;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use kata_types::mount::Mount;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use super::{Rootfs, KATA_OVERLAY_DEV_TYPE, ROOTFS, TYPE_OVERLAY_FS};
+use crate::share_fs::{
+    do_get_guest_path, MountedInfo, ShareFs, ShareFsRootfsConfig, ShareFsVolumeConfig,
+};
+
+const LOWER_DIR_OPTION: &str = "lowerdir=";
+const UPPER_DIR_OPTION: &str = "upperdir=";
+const WORK_DIR_OPTION: &str = "workdir=";
+
+const LAYER_PREFIX: &str = "layer-";
+const SNAPSHOT_DIR: &str = "snapshotdir";
+
+/// The layers of an overlay rootfs, the lower directories are ordered from the top one as the
+/// lowerdir option of overlayfs.
+#[derive(Debug, Default, PartialEq)]
+struct OverlayLayers {
+    lower_dirs: Vec<String>,
+    upper_dir: Option<String>,
+    work_dir: Option<String>,
+}
+
+impl OverlayLayers {
+    fn parse(rootfs_mounts: &[Mount]) -> Result<Self> {
+        let mut layers = Self::default();
+        for m in rootfs_mounts {
+            if m.fs_type != TYPE_OVERLAY_FS {
+                return Err(anyhow!("unsupported rootfs layer {:?}", m));
+            }
+            for o in m.options.iter() {
+                if let Some(lower) = o.strip_prefix(LOWER_DIR_OPTION) {
+                    layers.lower_dirs.extend(
+                        lower
+                            .split(':')
+                            .filter(|l| !l.is_empty())
+                            .map(|l| l.to_string()),
+                    );
+                } else if let Some(upper) = o.strip_prefix(UPPER_DIR_OPTION) {
+                    set_once(&mut layers.upper_dir, upper, UPPER_DIR_OPTION)?;
+                } else if let Some(work) = o.strip_prefix(WORK_DIR_OPTION) {
+                    set_once(&mut layers.work_dir, work, WORK_DIR_OPTION)?;
+                }
+            }
+        }
+
+        if layers.lower_dirs.is_empty() {
+            return Err(anyhow!("no lower layer in rootfs {:?}", rootfs_mounts));
+        }
+        if layers.upper_dir.is_some() != layers.work_dir.is_some() {
+            return Err(anyhow!(
+                "upperdir and workdir must be set together in rootfs {:?}",
+                rootfs_mounts
+            ));
+        }
+
+        Ok(layers)
+    }
+}
+
+fn set_once(value: &mut Option<String>, new: &str, option: &str) -> Result<()> {
+    match value {
+        Some(old) if old != new => Err(anyhow!("conflict {}{} and {}", option, old, new)),
+        _ => {
+            *value = Some(new.to_string());
+            Ok(())
+        }
+    }
+}
+
+/// Check whether the rootfs mounts are the layers of an overlay rootfs.
+pub(crate) fn is_overlay_rootfs(rootfs_mounts: &[Mount]) -> bool {
+    !rootfs_mounts.is_empty() && rootfs_mounts.iter().all(|m| m.fs_type == TYPE_OVERLAY_FS)
+}
+
+// The layer is identified by the digest of its directory, the snapshotters keep one directory
+// per layer, so that the containers of the same image share the layers in the guest.
+fn layer_digest(lower_dir: &str) -> Result<String> {
+    let path = std::fs::canonicalize(lower_dir)
+        .with_context(|| format!("failed to canonicalize layer {}", lower_dir))?;
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_os_str().to_string_lossy().as_bytes());
+    Ok(format!("{}{:x}", LAYER_PREFIX, hasher.finalize()))
+}
+
+pub(crate) struct OverlayRootfs {
+    guest_path: String,
+    share_fs: Arc<dyn ShareFs>,
+    // digests of the lower layers shared to the guest, a layer is dropped once released so that
+    // a failed cleanup can be retried
+    layers: Mutex<Vec<String>>,
+    // config of the directory holding the upperdir and the workdir, taken once umounted
+    snapshot: Mutex<Option<ShareFsRootfsConfig>>,
+    storage: Storage,
+}
+
+impl OverlayRootfs {
+    pub async fn new(
+        share_fs: &Arc<dyn ShareFs>,
+        cid: &str,
+        rootfs_mounts: &[Mount],
+    ) -> Result<Self> {
+        let layers = OverlayLayers::parse(rootfs_mounts).context("parse overlay layers")?;
+        let mut rootfs = OverlayRootfs {
+            guest_path: do_get_guest_path(ROOTFS, cid, false, false),
+            share_fs: Arc::clone(share_fs),
+            layers: Mutex::new(vec![]),
+            snapshot: Mutex::new(None),
+            storage: Storage::default(),
+        };
+
+        // release the layers shared so far if any of them fails
+        if let Err(e) = rootfs.share_layers(cid, &layers).await {
+            if let Err(err) = rootfs.cleanup().await {
+                warn!(
+                    sl!(),
+                    "failed to clean up overlay rootfs {}: {:?}", cid, err
+                );
+            }
+            return Err(e);
+        }
+
+        Ok(rootfs)
+    }
+
+    async fn share_layers(&mut self, cid: &str, layers: &OverlayLayers) -> Result<()> {
+        let mut lower_dirs = Vec::with_capacity(layers.lower_dirs.len());
+        for lower_dir in layers.lower_dirs.iter() {
+            let guest_path = self
+                .share_lower_dir(lower_dir)
+                .await
+                .with_context(|| format!("share layer {}", lower_dir))?;
+            lower_dirs.push(guest_path);
+        }
+
+        let mut options = vec![format!("{}{}", LOWER_DIR_OPTION, lower_dirs.join(":"))];
+        if let (Some(upper_dir), Some(work_dir)) = (&layers.upper_dir, &layers.work_dir) {
+            let (upper_dir, work_dir) = self
+                .share_snapshot_dir(cid, upper_dir, work_dir)
+                .await
+                .context("share upper layer")?;
+            options.push(format!("{}{}", UPPER_DIR_OPTION, upper_dir));
+            options.push(format!("{}{}", WORK_DIR_OPTION, work_dir));
+            // the upper layer is on the shared fs, which can't keep the overlay index
+            options.push("index=off".to_string());
+        }
+
+        self.storage = Storage {
+            driver: KATA_OVERLAY_DEV_TYPE.to_string(),
+            source: TYPE_OVERLAY_FS.to_string(),
+            fs_type: TYPE_OVERLAY_FS.to_string(),
+            options,
+            mount_point: self.guest_path.clone(),
+            ..Default::default()
+        };
+
+        Ok(())
+    }
+
+    // The lower layers are shared read only in the sandbox scope, one mount per layer whatever
+    // number of containers use it.
+    async fn share_lower_dir(&mut self, lower_dir: &str) -> Result<String> {
+        let digest = layer_digest(lower_dir)?;
+        let mounted_info_set = self.share_fs.mounted_info_set();
+        let mut mounted_info_set = mounted_info_set.lock().await;
+
+        let guest_path = if let Some(mounted_info) = mounted_info_set.get_mut(&digest) {
+            mounted_info.ro_ref_count += 1;
+            mounted_info.guest_path.to_string_lossy().to_string()
+        } else {
+            let mount_result = self
+                .share_fs
+                .get_share_fs_mount()
+                .share_volume(&ShareFsVolumeConfig {
+                    cid: String::from(""),
+                    source: lower_dir.to_string(),
+                    target: digest.clone(),
+                    readonly: true,
+                    mount_options: vec!["ro".to_string()],
+                    mount: oci::Mount {
+                        destination: String::from(""),
+                        r#type: "bind".to_string(),
+                        source: lower_dir.to_string(),
+                        options: vec!["ro".to_string()],
+                    },
+                    is_rafs: false,
+                })
+                .await
+                .context("share layer")?;
+            mounted_info_set.insert(
+                digest.clone(),
+                MountedInfo::new(
+                    PathBuf::from_str(&mount_result.guest_path).context("convert guest path")?,
+                    true,
+                ),
+            );
+            mount_result.guest_path
+        };
+        self.layers.get_mut().push(digest);
+
+        Ok(guest_path)
+    }
+
+    // The upperdir and the workdir have to be on the same filesystem, the directory holding them
+    // is shared read write for the container only.
+    async fn share_snapshot_dir(
+        &mut self,
+        cid: &str,
+        upper_dir: &str,
+        work_dir: &str,
+    ) -> Result<(String, String)> {
+        let (upper_parent, upper_name) = split_path(upper_dir)?;
+        let (work_parent, work_name) = split_path(work_dir)?;
+        if upper_parent != work_parent {
+            return Err(anyhow!(
+                "upperdir {} and workdir {} are not in the same directory",
+                upper_dir,
+                work_dir
+            ));
+        }
+
+        let config = ShareFsRootfsConfig {
+            cid: cid.to_string(),
+            source: upper_parent.to_string(),
+            target: SNAPSHOT_DIR.to_string(),
+            readonly: false,
+            is_rafs: false,
+        };
+        let mount_result = self
+            .share_fs
+            .get_share_fs_mount()
+            .share_rootfs(&config)
+            .await
+            .context("share snapshot dir")?;
+        *self.snapshot.get_mut() = Some(config);
+
+        Ok((
+            format!("{}/{}", mount_result.guest_path, upper_name),
+            format!("{}/{}", mount_result.guest_path, work_name),
+        ))
+    }
+}
+
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    let p = Path::new(path);
+    match (
+        p.parent().and_then(|p| p.to_str()),
+        p.file_name().and_then(|f| f.to_str()),
+    ) {
+        (Some(parent), Some(name)) if !parent.is_empty() => Ok((parent, name)),
+        _ => Err(anyhow!("invalid overlay directory {}", path)),
+    }
+}
+
+#[async_trait]
+impl Rootfs for OverlayRootfs {
+    async fn get_guest_rootfs_path(&self) -> Result<String> {
+        Ok(self.guest_path.clone())
+    }
+
+    async fn get_rootfs_mount(&self) -> Result<Vec<oci::Mount>> {
+        Ok(vec![])
+    }
+
+    async fn get_storage(&self) -> Option<Storage> {
+        Some(self.storage.clone())
+    }
+
+    async fn cleanup(&self) -> Result<()> {
+        let share_fs_mount = self.share_fs.get_share_fs_mount();
+        let mut snapshot = self.snapshot.lock().await;
+        if let Some(config) = snapshot.as_ref() {
+            share_fs_mount
+                .umount_rootfs(config)
+                .await
+                .context("umount snapshot dir")?;
+            *snapshot = None;
+        }
+
+        let mut layers = self.layers.lock().await;
+        let mounted_info_set = self.share_fs.mounted_info_set();
+        let mut mounted_info_set = mounted_info_set.lock().await;
+        while let Some(digest) = layers.last() {
+            match mounted_info_set.get_mut(digest) {
+                Some(mounted_info) if mounted_info.ref_count() > 1 => {
+                    mounted_info.ro_ref_count = mounted_info.ro_ref_count.saturating_sub(1);
+                }
+                Some(mounted_info) => {
+                    info!(
+                        sl!(),
+                        "The layer will be umounted due to no references, layer = {}", digest
+                    );
+                    let file_name = mounted_info.file_name()?;
+                    share_fs_mount
+                        .umount_volume(&file_name)
+                        .await
+                        .context("umount layer")?;
+                    mounted_info_set.remove(digest);
+                }
+                None => warn!(sl!(), "The mounted info for layer {} not found", digest),
+            }
+            layers.pop();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay_mount(options: &[&str]) -> Mount {
+        Mount {
+            source: TYPE_OVERLAY_FS.to_string(),
+            fs_type: TYPE_OVERLAY_FS.to_string(),
+            options: options.iter().map(|o| o.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_overlay_layers() {
+        let mounts = vec![
+            overlay_mount(&["lowerdir=/s/3/fs:/s/2/fs", "index=off"]),
+            overlay_mount(&["workdir=/s/4/work", "upperdir=/s/4/fs", "lowerdir=/s/1/fs"]),
+        ];
+        assert!(is_overlay_rootfs(&mounts));
+        assert_eq!(
+            OverlayLayers::parse(&mounts).unwrap(),
+            OverlayLayers {
+                lower_dirs: vec![
+                    "/s/3/fs".to_string(),
+                    "/s/2/fs".to_string(),
+                    "/s/1/fs".to_string()
+                ],
+                upper_dir: Some("/s/4/fs".to_string()),
+                work_dir: Some("/s/4/work".to_string()),
+            }
+        );
+
+        // read only rootfs
+        let layers = OverlayLayers::parse(&[overlay_mount(&["lowerdir=/s/2/fs:/s/1/fs"])]).unwrap();
+        assert_eq!(layers.lower_dirs.len(), 2);
+        assert!(layers.upper_dir.is_none());
+
+        assert!(OverlayLayers::parse(&[overlay_mount(&["upperdir=/s/1/fs"])]).is_err());
+        assert!(
+            OverlayLayers::parse(&[overlay_mount(&["lowerdir=/s/1/fs", "upperdir=/s/2/fs"])])
+                .is_err()
+        );
+        assert!(OverlayLayers::parse(&[
+            overlay_mount(&["lowerdir=/s/1/fs", "upperdir=/s/2/fs", "workdir=/s/2/work"]),
+            overlay_mount(&["lowerdir=/s/3/fs", "upperdir=/s/4/fs", "workdir=/s/4/work"]),
+        ])
+        .is_err());
+
+        let bind = Mount {
+            source: "/s/1/fs".to_string(),
+            fs_type: "bind".to_string(),
+            ..Default::default()
+        };
+        assert!(!is_overlay_rootfs(&[bind.clone()]));
+        assert!(OverlayLayers::parse(&[bind]).is_err());
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(split_path("/s/4/fs").unwrap(), ("/s/4", "fs"));
+        assert!(split_path("/").is_err());
+        assert!(split_path("fs").is_err());
+    }
+}
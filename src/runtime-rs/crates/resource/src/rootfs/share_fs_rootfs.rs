@@ -7,7 +7,7 @@
 use agent::Storage;
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use kata_sys_util::mount::{umount_timeout, Mounter};
+use kata_sys_util::mount::{get_linux_mount_info, umount_timeout, Mounter};
 use kata_types::mount::Mount;
 use std::sync::Arc;
 
@@ -18,6 +18,8 @@ pub(crate) struct ShareFsRootfs {
     guest_path: String,
     share_fs: Arc<dyn ShareFs>,
     config: ShareFsRootfsConfig,
+    // the rootfs mounted in the bundle by the runtime, if any
+    bundle_rootfs: Option<String>,
 }
 
 impl ShareFsRootfs {
@@ -33,29 +35,37 @@ impl ShareFsRootfs {
                 "mount rootfs from {:?} to {}",
                 &rootfs, &bundle_rootfs
             ))?;
-            bundle_rootfs
+            Some(bundle_rootfs)
         } else {
-            bundle_path.to_string()
+            None
         };
 
         let share_fs_mount = share_fs.get_share_fs_mount();
         let config = ShareFsRootfsConfig {
             cid: cid.to_string(),
-            source: bundle_rootfs.to_string(),
+            source: bundle_rootfs
+                .clone()
+                .unwrap_or_else(|| bundle_path.to_string()),
             target: ROOTFS.to_string(),
             readonly: false,
             is_rafs: false,
         };
 
-        let mount_result = share_fs_mount
-            .share_rootfs(&config)
-            .await
-            .context("share rootfs")?;
+        let mount_result = match share_fs_mount.share_rootfs(&config).await {
+            Ok(mount_result) => mount_result,
+            Err(e) => {
+                if let Some(bundle_rootfs) = bundle_rootfs.as_ref() {
+                    let _ = umount_timeout(bundle_rootfs, 0);
+                }
+                return Err(e).context("share rootfs");
+            }
+        };
 
         Ok(ShareFsRootfs {
             guest_path: mount_result.guest_path,
             share_fs: Arc::clone(share_fs),
             config,
+            bundle_rootfs,
         })
     }
 }
@@ -82,8 +92,12 @@ impl Rootfs for ShareFsRootfs {
             .await
             .context("umount shared rootfs")?;
 
-        // Umount the bundle rootfs
-        umount_timeout(&self.config.source, 0).context("umount bundle rootfs")?;
+        // Umount the bundle rootfs if it's mounted by us and still there
+        if let Some(bundle_rootfs) = self.bundle_rootfs.as_ref() {
+            if get_linux_mount_info(bundle_rootfs).is_ok() {
+                umount_timeout(bundle_rootfs, 0).context("umount bundle rootfs")?;
+            }
+        }
         Ok(())
     }
 }
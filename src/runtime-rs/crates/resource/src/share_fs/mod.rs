@@ -5,14 +5,16 @@
 //
 
 mod share_virtio_fs;
-pub use share_virtio_fs::rafs_mount;
+pub use share_virtio_fs::{rafs_mount, rafs_umount};
 mod share_virtio_fs_inline;
 use share_virtio_fs_inline::ShareVirtioFsInline;
 mod share_virtio_fs_standalone;
 use share_virtio_fs_standalone::ShareVirtioFsStandalone;
 mod utils;
 use tokio::sync::Mutex;
-pub use utils::{do_get_guest_path, do_get_guest_share_path, get_host_rw_shared_path};
+pub use utils::{
+    cleanup_sandbox_share_dir, do_get_guest_path, do_get_guest_share_path, get_host_rw_shared_path,
+};
 mod virtio_fs_share_mount;
 use virtio_fs_share_mount::VirtiofsShareMount;
 pub use virtio_fs_share_mount::EPHEMERAL_PATH;
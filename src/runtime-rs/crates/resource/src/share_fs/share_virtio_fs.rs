@@ -105,3 +105,25 @@ pub async fn rafs_mount(
         .with_context(|| format!("fail to attach rafs {:?}", rafs_meta))?;
     Ok(())
 }
+
+pub async fn rafs_umount(h: &dyn Hypervisor, rafs_meta: String, rafs_mnt: String) -> Result<()> {
+    info!(
+        sl!(),
+        "Detaching rafs meta file {} from virtio-fs device, rafs mount point {}",
+        rafs_meta,
+        rafs_mnt
+    );
+    let virtio_fs = HypervisorDevice::ShareFsMount(ShareFsMountConfig {
+        source: rafs_meta.clone(),
+        fstype: ShareFsMountType::RAFS,
+        mount_point: rafs_mnt,
+        config: None,
+        tag: String::from(MOUNT_GUEST_TAG),
+        op: ShareFsOperation::Umount,
+        prefetch_list_path: None,
+    });
+    h.add_device(virtio_fs)
+        .await
+        .with_context(|| format!("fail to detach rafs {:?}", rafs_meta))?;
+    Ok(())
+}
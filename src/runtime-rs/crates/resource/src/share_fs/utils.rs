@@ -4,7 +4,11 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 use kata_sys_util::mount;
@@ -115,3 +119,129 @@ pub(crate) fn do_get_host_path(
     };
     path.to_str().unwrap().to_string()
 }
+
+/// Lazily umount the path if it's a mount point, so that it can be called again once the path
+/// has been umounted or removed.
+pub(crate) fn umount_if_mounted(path: &str) -> Result<()> {
+    if mount::get_linux_mount_info(path).is_ok() {
+        mount::umount_timeout(path, 0).with_context(|| format!("failed to umount {}", path))?;
+    }
+    Ok(())
+}
+
+/// Umount everything left under the shared directories of the sandbox and remove them. Only the
+/// sandbox id is needed, so that it also cleans up after a crashed shim.
+pub fn cleanup_sandbox_share_dir(sid: &str) -> Result<()> {
+    let share_dir = Path::new(KATA_HOST_SHARED_DIR).join(sid);
+    if !share_dir.exists() {
+        return Ok(());
+    }
+
+    // the deepest mount points go first, the ro directory itself is a bind mount of the rw one
+    let mut mount_points = get_mount_points_under(&share_dir)?;
+    mount_points.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+    for mount_point in mount_points.iter() {
+        mount::umount_all(mount_point, true)
+            .with_context(|| format!("failed to umount {:?}", mount_point))?;
+    }
+
+    // never remove the files through a bind mount which is still alive
+    let mount_points = get_mount_points_under(&share_dir)?;
+    if !mount_points.is_empty() {
+        return Err(anyhow!(
+            "mount points {:?} are left under {:?}",
+            mount_points,
+            share_dir
+        ));
+    }
+
+    std::fs::remove_dir_all(&share_dir).with_context(|| format!("failed to remove {:?}", share_dir))
+}
+
+fn get_mount_points_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mounts = std::fs::read_to_string(mount::PROC_MOUNTS_FILE)
+        .with_context(|| format!("failed to read {}", mount::PROC_MOUNTS_FILE))?;
+    Ok(parse_mount_points(&mounts)
+        .into_iter()
+        .filter(|p| p.starts_with(dir))
+        .collect())
+}
+
+// The mount point is the second field of the records in /proc/mounts.
+fn parse_mount_points(mounts: &str) -> Vec<PathBuf> {
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(unescape_mount_path)
+        .collect()
+}
+
+// The space, tab, newline and backslash are escaped as octal numbers in /proc/mounts.
+fn unescape_mount_path(path: &str) -> PathBuf {
+    let bytes = path.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() {
+            if let Some(c) = std::str::from_utf8(&bytes[i + 1..i + 4])
+                .ok()
+                .and_then(|octal| u8::from_str_radix(octal, 8).ok())
+            {
+                unescaped.push(c);
+                i += 4;
+                continue;
+            }
+        }
+        unescaped.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(OsStr::from_bytes(&unescaped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape_mount_path() {
+        assert_eq!(
+            unescape_mount_path("/run/kata-containers/shared"),
+            PathBuf::from("/run/kata-containers/shared")
+        );
+        assert_eq!(
+            unescape_mount_path("/mnt/a\\040b\\011c\\134d"),
+            PathBuf::from("/mnt/a b\tc\\d")
+        );
+        assert_eq!(
+            unescape_mount_path("/mnt/a\\04"),
+            PathBuf::from("/mnt/a\\04")
+        );
+    }
+
+    #[test]
+    fn test_parse_mount_points() {
+        let mounts = "proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0\n\
+            tmpfs /run/kata-containers/shared/sandboxes/sid/rw tmpfs rw 0 0\n\
+            tmpfs /run/kata-containers/shared/sandboxes/sid/ro tmpfs ro 0 0\n\
+            /dev/vda /run/kata-containers/shared/sandboxes/sid/rw/passthrough/a\\040b ext4 rw 0 0\n";
+        let share_dir = Path::new(KATA_HOST_SHARED_DIR).join("sid");
+        let mut mount_points: Vec<PathBuf> = parse_mount_points(mounts)
+            .into_iter()
+            .filter(|p| p.starts_with(&share_dir))
+            .collect();
+        mount_points.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+        assert_eq!(
+            mount_points,
+            vec![
+                share_dir.join("rw/passthrough/a b"),
+                share_dir.join("rw"),
+                share_dir.join("ro"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cleanup_sandbox_share_dir_not_exist() {
+        assert!(cleanup_sandbox_share_dir("not-exist-sandbox-id").is_ok());
+    }
+}
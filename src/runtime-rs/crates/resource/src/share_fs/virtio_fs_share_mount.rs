@@ -7,7 +7,7 @@
 use agent::Storage;
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use kata_sys_util::mount::{bind_remount, umount_timeout};
+use kata_sys_util::mount::bind_remount;
 use kata_types::k8s::is_watchable_mount;
 use kata_types::mount;
 use nix::sys::stat::stat;
@@ -20,7 +20,7 @@ const WATCHABLE_BIND_DEV_TYPE: &str = "watchable-bind";
 pub const EPHEMERAL_PATH: &str = "/run/kata-containers/sandbox/ephemeral";
 
 use super::{
-    utils::{self, do_get_host_path},
+    utils::{self, do_get_host_path, umount_if_mounted},
     ShareFsMount, ShareFsMountResult, ShareFsRootfsConfig, ShareFsVolumeConfig,
     KATA_GUEST_SHARE_DIR, PASSTHROUGH_FS_DIR,
 };
@@ -196,7 +196,7 @@ impl ShareFsMount for VirtiofsShareMount {
 
     async fn umount_volume(&self, file_name: &str) -> Result<()> {
         let host_dest = do_get_host_path(file_name, &self.id, "", true, false);
-        umount_timeout(&host_dest, 0).context("umount volume")?;
+        umount_if_mounted(&host_dest).context("umount volume")?;
         // Umount event will be propagated to ro directory
 
         // Remove the directory of mointpoint
@@ -213,7 +213,7 @@ impl ShareFsMount for VirtiofsShareMount {
 
     async fn umount_rootfs(&self, config: &ShareFsRootfsConfig) -> Result<()> {
         let host_dest = do_get_host_path(&config.target, &self.id, &config.cid, false, false);
-        umount_timeout(&host_dest, 0).context("umount rootfs")?;
+        umount_if_mounted(&host_dest).context("umount rootfs")?;
 
         // Remove the directory of mointpoint
         if let Ok(md) = fs::metadata(&host_dest) {
@@ -4,39 +4,276 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use anyhow::Result;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use hypervisor::Hypervisor;
+use kata_types::mount::{
+    join_path, DirectVolumeMountInfo, KATA_DIRECT_VOLUME_ROOT_PATH, KATA_MOUNT_INFO_FILE_NAME,
+};
 
-use super::Volume;
+use super::{DirectVolumeDevice, Volume, BIND};
+use crate::{
+    block_device::{is_block_device, BlockIndexAllocator, PluggedBlockDevice},
+    share_fs::DEFAULT_KATA_GUEST_SANDBOX_DIR,
+};
 
-#[derive(Debug)]
-pub(crate) struct BlockVolume {}
+const BLOCK_VOLUME_TYPE: &str = "block";
+const STORAGE_DIR: &str = "storage";
 
 /// BlockVolume: block device volume
+pub(crate) struct BlockVolume {
+    mount: oci::Mount,
+    storage: agent::Storage,
+    device: PluggedBlockDevice,
+    hypervisor: Arc<dyn Hypervisor>,
+    block_indexes: Arc<BlockIndexAllocator>,
+    // file recording the sandbox using the direct volume, for kata-ctl
+    sandbox_id_file: PathBuf,
+}
+
 impl BlockVolume {
-    pub(crate) fn new(_m: &oci::Mount) -> Result<Self> {
-        Ok(Self {})
+    pub(crate) async fn new(
+        hypervisor: &Arc<dyn Hypervisor>,
+        block_indexes: &Arc<BlockIndexAllocator>,
+        sid: &str,
+        m: &oci::Mount,
+    ) -> Result<Self> {
+        let mount_info = get_volume_mount_info(&m.source)?;
+        if mount_info.volume_type != BLOCK_VOLUME_TYPE {
+            return Err(anyhow!(
+                "unsupported direct volume type {}",
+                mount_info.volume_type
+            ));
+        }
+        if !is_block_device(&mount_info.device) {
+            return Err(anyhow!("{} is not a block device", mount_info.device));
+        }
+
+        let sandbox_id_file = get_direct_volume_path(&m.source)?.join(sid);
+        fs::write(&sandbox_id_file, "")
+            .with_context(|| format!("record sandbox id in {:?}", sandbox_id_file))?;
+
+        let read_only =
+            m.options.iter().any(|o| o == "ro") || mount_info.options.iter().any(|o| o == "ro");
+        let device = PluggedBlockDevice::plug(
+            hypervisor.as_ref(),
+            block_indexes,
+            &mount_info.device,
+            read_only,
+        )
+        .await
+        .context("plug volume block device")
+        .map_err(|e| {
+            let _ = fs::remove_file(&sandbox_id_file);
+            e
+        })?;
+
+        // the device is mounted in the sandbox storage dir of the guest, and bind mounted into
+        // the container from there
+        let guest_path = Path::new(DEFAULT_KATA_GUEST_SANDBOX_DIR)
+            .join(STORAGE_DIR)
+            .join(base64::encode_config(&m.source, base64::URL_SAFE))
+            .into_os_string()
+            .into_string()
+            .map_err(|e| anyhow!("failed to get guest path {:?}", e))?;
+        let storage = device.storage(&mount_info.fs_type, &mount_info.options, &guest_path);
+
+        Ok(Self {
+            mount: oci::Mount {
+                destination: m.destination.clone(),
+                r#type: BIND.to_string(),
+                source: guest_path,
+                options: m.options.clone(),
+            },
+            storage,
+            device,
+            hypervisor: Arc::clone(hypervisor),
+            block_indexes: Arc::clone(block_indexes),
+            sandbox_id_file,
+        })
     }
 }
 
 #[async_trait]
 impl Volume for BlockVolume {
     fn get_volume_mount(&self) -> anyhow::Result<Vec<oci::Mount>> {
-        todo!()
+        Ok(vec![self.mount.clone()])
     }
 
     fn get_storage(&self) -> Result<Vec<agent::Storage>> {
-        todo!()
+        Ok(vec![self.storage.clone()])
     }
 
     async fn cleanup(&self) -> Result<()> {
-        // TODO: Clean up BlockVolume
-        warn!(sl!(), "Cleaning up BlockVolume is still unimplemented.");
+        self.device
+            .unplug(self.hypervisor.as_ref(), &self.block_indexes)
+            .await
+            .context("unplug volume block device")?;
+
+        if let Err(err) = fs::remove_file(&self.sandbox_id_file) {
+            warn!(
+                sl!(),
+                "failed to remove sandbox id file {:?}: {:?}", self.sandbox_id_file, err
+            );
+        }
         Ok(())
     }
+
+    fn get_direct_volume_device(&self) -> Option<DirectVolumeDevice> {
+        if self.device.is_unplugged() {
+            return None;
+        }
+        Some(DirectVolumeDevice {
+            host_device: self.device.path_on_host.clone(),
+            device_id: self.device.id.clone(),
+            guest_path: self.storage.mount_point.clone(),
+        })
+    }
 }
 
-pub(crate) fn is_block_volume(_m: &oci::Mount) -> bool {
-    // attach block device
-    false
+// The mount info of a direct volume is kept in a directory named after the base64-encoded
+// volume path, as `kata-ctl direct-volume add` writes it.
+fn get_direct_volume_path(volume_path: &str) -> Result<PathBuf> {
+    join_path(KATA_DIRECT_VOLUME_ROOT_PATH, volume_path)
+        .with_context(|| format!("join direct volume path {}", volume_path))
+}
+
+fn get_volume_mount_info(volume_path: &str) -> Result<DirectVolumeMountInfo> {
+    let mount_info_file = get_direct_volume_path(volume_path)?.join(KATA_MOUNT_INFO_FILE_NAME);
+    let mount_info = fs::read_to_string(&mount_info_file)
+        .with_context(|| format!("read mount info {:?}", mount_info_file))?;
+
+    serde_json::from_str(&mount_info)
+        .with_context(|| format!("parse mount info {:?}", mount_info_file))
+}
+
+/// Check whether the mount is a direct-assigned volume, whose mount info was added by
+/// `kata-ctl direct-volume add`.
+pub(crate) fn is_block_volume(m: &oci::Mount) -> bool {
+    m.r#type == BIND
+        && get_direct_volume_path(&m.source)
+            .map(|p| p.join(KATA_MOUNT_INFO_FILE_NAME).exists())
+            .unwrap_or(false)
+}
+
+/// Remove the files recording the sandbox in the direct volume directories. Only the sandbox id
+/// is needed, so that it also cleans up after a crashed shim.
+pub fn cleanup_sandbox_id_files(sid: &str) -> Result<()> {
+    remove_sandbox_id_files(Path::new(KATA_DIRECT_VOLUME_ROOT_PATH), sid)
+}
+
+fn remove_sandbox_id_files(root: &Path, sid: &str) -> Result<()> {
+    if !root.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(root).with_context(|| format!("read dir {:?}", root))? {
+        let volume_dir = entry.context("read dir entry")?.path();
+        if !volume_dir.is_dir() {
+            continue;
+        }
+        let sandbox_id_file = safe_path::scoped_join(&volume_dir, sid)
+            .with_context(|| format!("join sandbox id file {:?} {}", volume_dir, sid))?;
+        if sandbox_id_file.is_file() {
+            fs::remove_file(&sandbox_id_file)
+                .with_context(|| format!("remove sandbox id file {:?}", sandbox_id_file))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use test_utils::skip_if_not_root;
+
+    #[test]
+    fn test_is_block_volume() {
+        let mut m = oci::Mount {
+            destination: "/data".to_string(),
+            r#type: "tmpfs".to_string(),
+            source: "/non/existent/volume".to_string(),
+            options: vec![],
+        };
+        assert!(!is_block_volume(&m));
+
+        m.r#type = BIND.to_string();
+        assert!(!is_block_volume(&m));
+
+        m.source = "".to_string();
+        assert!(!is_block_volume(&m));
+    }
+
+    #[test]
+    fn test_get_volume_mount_info() {
+        // the mount info is added under the real direct volume root, as kata-ctl does
+        skip_if_not_root!();
+
+        fs::create_dir_all(KATA_DIRECT_VOLUME_ROOT_PATH).unwrap();
+        let volume_path = "/test/block/volume/mount-info";
+        let volume_dir = get_direct_volume_path(volume_path).unwrap();
+        fs::create_dir_all(&volume_dir).unwrap();
+
+        let m = oci::Mount {
+            destination: "/data".to_string(),
+            r#type: BIND.to_string(),
+            source: volume_path.to_string(),
+            options: vec![],
+        };
+        get_volume_mount_info(volume_path).unwrap_err();
+        assert!(!is_block_volume(&m));
+
+        fs::write(
+            volume_dir.join(KATA_MOUNT_INFO_FILE_NAME),
+            r#"{"volume_type":"block","device":"/dev/sdz","fs_type":"ext4","metadata":{},"options":["ro"]}"#,
+        )
+        .unwrap();
+        let mount_info = get_volume_mount_info(volume_path).unwrap();
+        assert_eq!(mount_info.volume_type, BLOCK_VOLUME_TYPE);
+        assert_eq!(mount_info.device, "/dev/sdz");
+        assert_eq!(mount_info.fs_type, "ext4");
+        assert_eq!(mount_info.options, vec!["ro".to_string()]);
+        assert!(is_block_volume(&m));
+
+        fs::write(volume_dir.join(KATA_MOUNT_INFO_FILE_NAME), "invalid").unwrap();
+        get_volume_mount_info(volume_path).unwrap_err();
+
+        fs::remove_dir_all(&volume_dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_sandbox_id_files() {
+        let root = tempdir().unwrap();
+        let sid = "sandbox";
+
+        // nothing to clean up without the root
+        remove_sandbox_id_files(&root.path().join("missing"), sid).unwrap();
+
+        let used = root.path().join("used");
+        let other = root.path().join("other");
+        let empty = root.path().join("empty");
+        for dir in [&used, &other, &empty] {
+            fs::create_dir(dir).unwrap();
+        }
+        fs::write(used.join(sid), "").unwrap();
+        fs::write(used.join(KATA_MOUNT_INFO_FILE_NAME), "{}").unwrap();
+        fs::write(other.join("other-sandbox"), "").unwrap();
+        fs::write(root.path().join(sid), "").unwrap();
+
+        remove_sandbox_id_files(root.path(), sid).unwrap();
+        assert!(!used.join(sid).exists());
+        assert!(used.join(KATA_MOUNT_INFO_FILE_NAME).exists());
+        assert!(other.join("other-sandbox").exists());
+        assert!(empty.exists());
+        // only the volume directories are looked into
+        assert!(root.path().join(sid).exists());
+    }
 }
@@ -0,0 +1,352 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    collections::HashMap,
+    fs,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use agent::{Agent, CopyFileRequest};
+use anyhow::{anyhow, Context, Result};
+use tokio::{
+    task::JoinHandle,
+    time::{self, Duration},
+};
+
+/// The maximum number of file system entries to watch for each volume, the same as the agent
+/// watches for the watchable-bind storages.
+const MAX_ENTRIES_PER_VOLUME: usize = 16;
+
+/// The maximum size of a watched volume in bytes.
+const MAX_SIZE_PER_VOLUME: u64 = 1024 * 1024;
+
+/// How often to check for modified files.
+const WATCH_INTERVAL_SECS: u64 = 2;
+
+/// The maximum size of the data sent in one copy file request.
+const COPY_CHUNK_SIZE: usize = 256 * 1024;
+
+const DEFAULT_DIR_MODE: u32 = 0o750;
+
+/// Tracks the modified time of the entries of a volume, the same way as the agent checks the
+/// watchable-bind storages.
+#[derive(Debug)]
+struct VolumeScanner {
+    source: PathBuf,
+    watched_files: HashMap<PathBuf, SystemTime>,
+    size: u64,
+}
+
+impl VolumeScanner {
+    fn new(source: &Path) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            watched_files: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    /// Whether the volume is small enough to be watched.
+    fn watchable(&self) -> bool {
+        self.watched_files.len() <= MAX_ENTRIES_PER_VOLUME && self.size <= MAX_SIZE_PER_VOLUME
+    }
+
+    /// Scan the volume for the new and modified entries, the parent directories sort before
+    /// their entries.
+    fn scan(&mut self) -> Result<Vec<PathBuf>> {
+        let mut updated = Vec::new();
+        let mut scanned = Vec::new();
+        self.size = self
+            .scan_path(&self.source.clone(), &mut scanned, &mut updated)
+            .context("scan volume")?;
+        self.watched_files.retain(|path, _| scanned.contains(path));
+
+        updated.sort();
+        Ok(updated)
+    }
+
+    fn scan_path(
+        &mut self,
+        path: &Path,
+        scanned: &mut Vec<PathBuf>,
+        updated: &mut Vec<PathBuf>,
+    ) -> Result<u64> {
+        let metadata = path
+            .symlink_metadata()
+            .with_context(|| format!("failed to query metadata for {:?}", path))?;
+        let modified = metadata
+            .modified()
+            .with_context(|| format!("failed to get modified date for {:?}", path))?;
+
+        scanned.push(path.to_path_buf());
+        match self.watched_files.insert(path.to_path_buf(), modified) {
+            Some(old) if old >= modified => {}
+            _ => updated.push(path.to_path_buf()),
+        }
+
+        let mut size = 0;
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).with_context(|| format!("read dir {:?}", path))? {
+                let entry = entry.with_context(|| format!("read entry of {:?}", path))?;
+                size += self.scan_path(&entry.path(), scanned, updated)?;
+            }
+        } else if metadata.is_file() || metadata.file_type().is_symlink() {
+            size += metadata.len();
+        }
+
+        Ok(size)
+    }
+}
+
+fn target_path(source: &Path, guest_path: &Path, path: &Path) -> Result<String> {
+    let relative_path = path
+        .strip_prefix(source)
+        .with_context(|| format!("failed to strip prefix {:?} of {:?}", source, path))?;
+    let target = if relative_path.as_os_str().is_empty() {
+        guest_path.to_path_buf()
+    } else {
+        guest_path.join(relative_path)
+    };
+
+    target
+        .into_os_string()
+        .into_string()
+        .map_err(|e| anyhow!("invalid guest path {:?}", e))
+}
+
+/// Copies a volume into the guest through the agent, for the sandboxes without a shared
+/// filesystem, and keeps the copy in sync by checking the modified time of the files.
+///
+/// The files removed on the host are left in the guest, as the agent can't remove them. This is
+/// enough for the ConfigMap and Secret volumes, whose updates swap the `..data` symlink to a new
+/// directory.
+pub(crate) struct CopyWatcher {
+    agent: Arc<dyn Agent>,
+    guest_path: PathBuf,
+    scanner: VolumeScanner,
+}
+
+impl CopyWatcher {
+    pub(crate) fn new(agent: &Arc<dyn Agent>, source: &Path, guest_path: &Path) -> Self {
+        Self {
+            agent: Arc::clone(agent),
+            guest_path: guest_path.to_path_buf(),
+            scanner: VolumeScanner::new(source),
+        }
+    }
+
+    /// Whether the volume is small enough to be watched, the same limits as the agent's.
+    fn watchable(&self) -> bool {
+        self.scanner.watchable()
+    }
+
+    /// Copy the volume to the guest, then keep it in sync if it's small enough to be watched.
+    /// The larger volumes, e.g. the regular files over the size limit, are only copied once.
+    pub(crate) async fn start(mut self) -> Result<Option<JoinHandle<()>>> {
+        let updated = self.scanner.scan()?;
+        for path in updated.iter() {
+            self.copy(path)
+                .await
+                .with_context(|| format!("copy {:?} to guest", path))?;
+        }
+
+        if !self.watchable() {
+            warn!(
+                sl!(),
+                "{:?} is too large to be watched, its changes won't be copied to the guest",
+                self.scanner.source
+            );
+            return Ok(None);
+        }
+        Ok(Some(self.spawn()))
+    }
+
+    /// Copy the new and modified entries to the guest, the number of copied entries is
+    /// returned. Nothing is copied if the volume exceeds the limits to watch.
+    async fn sync(&mut self) -> Result<usize> {
+        let updated = self.scanner.scan()?;
+        if !self.watchable() {
+            return Err(anyhow!(
+                "{:?} has {} entries of {} bytes, exceeding the limits of {} entries and {} bytes",
+                self.scanner.source,
+                self.scanner.watched_files.len(),
+                self.scanner.size,
+                MAX_ENTRIES_PER_VOLUME,
+                MAX_SIZE_PER_VOLUME
+            ));
+        }
+        for path in updated.iter() {
+            self.copy(path)
+                .await
+                .with_context(|| format!("copy {:?} to guest", path))?;
+        }
+
+        Ok(updated.len())
+    }
+
+    async fn copy(&self, path: &Path) -> Result<()> {
+        let metadata = path
+            .symlink_metadata()
+            .with_context(|| format!("failed to query metadata for {:?}", path))?;
+        let file_type = metadata.file_type();
+        let req = CopyFileRequest {
+            path: target_path(&self.scanner.source, &self.guest_path, path)?,
+            file_mode: metadata.mode(),
+            dir_mode: DEFAULT_DIR_MODE,
+            uid: metadata.uid() as i32,
+            gid: metadata.gid() as i32,
+            ..Default::default()
+        };
+
+        if file_type.is_dir() {
+            self.agent.copy_file(req).await.context("copy dir")?;
+        } else if file_type.is_symlink() {
+            let link = fs::read_link(path).with_context(|| format!("read link {:?}", path))?;
+            let data = link.as_os_str().as_bytes().to_vec();
+            self.agent
+                .copy_file(CopyFileRequest {
+                    file_size: data.len() as i64,
+                    data,
+                    ..req
+                })
+                .await
+                .context("copy symlink")?;
+        } else if file_type.is_file() {
+            let file = fs::File::open(path).with_context(|| format!("open {:?}", path))?;
+            let file_size = metadata.len();
+            let mut data = vec![0; COPY_CHUNK_SIZE];
+            // one request at least, to create the empty files
+            let mut offset = 0;
+            loop {
+                let len = (file_size - offset).min(COPY_CHUNK_SIZE as u64) as usize;
+                file.read_exact_at(&mut data[..len], offset)
+                    .with_context(|| format!("read {:?}", path))?;
+                self.agent
+                    .copy_file(CopyFileRequest {
+                        file_size: file_size as i64,
+                        offset: offset as i64,
+                        data: data[..len].to_vec(),
+                        ..req.clone()
+                    })
+                    .await
+                    .context("copy file")?;
+                offset += len as u64;
+                if offset >= file_size {
+                    break;
+                }
+            }
+        } else {
+            debug!(sl!(), "skip copying special file {:?}", path);
+        }
+
+        Ok(())
+    }
+
+    /// Check the volume for changes periodically until the returned task is aborted, or the
+    /// volume becomes too large to watch.
+    fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(WATCH_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                match self.sync().await {
+                    Ok(0) => {}
+                    Ok(n) => debug!(sl!(), "copied {} entries of {:?}", n, self.scanner.source),
+                    Err(e) => warn!(sl!(), "failed to sync {:?}: {:?}", self.scanner.source, e),
+                }
+                if !self.watchable() {
+                    warn!(
+                        sl!(),
+                        "stop watching {:?} which is too large", self.scanner.source
+                    );
+                    return;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_target_path() {
+        let source = Path::new("/var/lib/kubelet/pods/1/volumes/kubernetes.io~configmap/c");
+        let guest = Path::new("/run/kata-containers/shared/containers/passthrough/cid/c");
+        assert_eq!(
+            target_path(source, guest, source).unwrap(),
+            "/run/kata-containers/shared/containers/passthrough/cid/c"
+        );
+        assert_eq!(
+            target_path(source, guest, &source.join("..data")).unwrap(),
+            "/run/kata-containers/shared/containers/passthrough/cid/c/..data"
+        );
+        assert!(target_path(source, guest, Path::new("/etc/hosts")).is_err());
+    }
+
+    #[test]
+    fn test_volume_scanner() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("configmap");
+        let data = source.join("..2022_01_01");
+        fs::create_dir_all(&data).unwrap();
+        fs::write(data.join("key"), "value").unwrap();
+        symlink("..2022_01_01", source.join("..data")).unwrap();
+        symlink("..data/key", source.join("key")).unwrap();
+
+        let mut scanner = VolumeScanner::new(&source);
+        assert_eq!(
+            scanner.scan().unwrap(),
+            vec![
+                source.clone(),
+                data.clone(),
+                data.join("key"),
+                source.join("..data"),
+                source.join("key"),
+            ]
+        );
+        assert!(scanner.scan().unwrap().is_empty());
+
+        // update the configmap as kubelet does, after the coarse file timestamps tick
+        std::thread::sleep(Duration::from_millis(20));
+        let new_data = source.join("..2022_01_02");
+        fs::create_dir_all(&new_data).unwrap();
+        fs::write(new_data.join("key"), "new value").unwrap();
+        fs::remove_file(source.join("..data")).unwrap();
+        symlink("..2022_01_02", source.join("..data")).unwrap();
+        fs::remove_dir_all(&data).unwrap();
+
+        let updated = scanner.scan().unwrap();
+        assert!(updated.contains(&new_data));
+        assert!(updated.contains(&new_data.join("key")));
+        assert!(updated.contains(&source.join("..data")));
+        let new_data_pos = updated.iter().position(|p| p == &new_data).unwrap();
+        let link_pos = updated
+            .iter()
+            .position(|p| p == &source.join("..data"))
+            .unwrap();
+        assert!(new_data_pos < link_pos);
+        assert!(!scanner.watched_files.contains_key(&data));
+
+        std::thread::sleep(Duration::from_millis(20));
+        for i in 0..MAX_ENTRIES_PER_VOLUME {
+            fs::write(new_data.join(format!("key{}", i)), "value").unwrap();
+        }
+        assert!(scanner.watchable());
+        assert_eq!(scanner.scan().unwrap().len(), MAX_ENTRIES_PER_VOLUME + 1);
+        assert!(!scanner.watchable());
+    }
+}
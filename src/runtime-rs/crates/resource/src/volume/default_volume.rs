@@ -34,8 +34,7 @@ impl Volume for DefaultVolume {
     }
 
     async fn cleanup(&self) -> Result<()> {
-        // TODO: Clean up DefaultVolume
-        warn!(sl!(), "Cleaning up DefaultVolume is still unimplemented.");
+        // the mount is passed through to the guest as is, nothing is set up on the host
         Ok(())
     }
 }
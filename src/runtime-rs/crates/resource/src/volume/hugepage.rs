@@ -114,8 +114,8 @@ pub(crate) fn get_huge_page_option(m: &oci::Mount) -> Result<Option<Vec<String>>
     Ok(None)
 }
 
-// TODO add hugepage limit to sandbox memory once memory hotplug is enabled
-// https://github.com/kata-containers/kata-containers/issues/5880
+// The hugepage limits are also added to the sandbox memory by the memory resource, when the
+// hypervisor supports memory resize.
 pub(crate) fn get_huge_page_limits_map(spec: &oci::Spec) -> Result<HashMap<PageSize, Limit>> {
     let mut hugepage_limits_map: HashMap<PageSize, Limit> = HashMap::new();
     if let Some(l) = &spec.linux {
@@ -5,27 +5,48 @@
 //
 
 mod block_volume;
+pub use block_volume::cleanup_sandbox_id_files;
+mod copy_watcher;
 mod default_volume;
 pub mod hugepage;
 mod share_fs_volume;
 mod shm_volume;
 use async_trait::async_trait;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use std::{sync::Arc, vec::Vec};
 use tokio::sync::RwLock;
 
-use crate::share_fs::ShareFs;
+use agent::Agent;
+use hypervisor::Hypervisor;
+
+use crate::{block_device::BlockIndexAllocator, share_fs::ShareFs};
 
 use self::hugepage::{get_huge_page_limits_map, get_huge_page_option};
 
 const BIND: &str = "bind";
 
+/// The block device plugged into the VM for a direct-assigned volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectVolumeDevice {
+    /// The block device on the host, as recorded in the mount info of the volume
+    pub host_device: String,
+    /// The id of the device in the hypervisor
+    pub device_id: String,
+    /// The mount point of the device in the guest
+    pub guest_path: String,
+}
+
 #[async_trait]
 pub trait Volume: Send + Sync {
     fn get_volume_mount(&self) -> Result<Vec<oci::Mount>>;
     fn get_storage(&self) -> Result<Vec<agent::Storage>>;
     async fn cleanup(&self) -> Result<()>;
+
+    /// The block device of a direct-assigned volume still plugged into the VM.
+    fn get_direct_volume_device(&self) -> Option<DirectVolumeDevice> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -46,6 +67,10 @@ impl VolumeResource {
     pub async fn handler_volumes(
         &self,
         share_fs: &Option<Arc<dyn ShareFs>>,
+        agent: &Arc<dyn Agent>,
+        hypervisor: &Arc<dyn Hypervisor>,
+        block_indexes: &Arc<BlockIndexAllocator>,
+        sid: &str,
         cid: &str,
         spec: &oci::Spec,
     ) -> Result<Vec<Arc<dyn Volume>>> {
@@ -59,9 +84,16 @@ impl VolumeResource {
                     shm_volume::ShmVolume::new(m, shm_size)
                         .with_context(|| format!("new shm volume {:?}", m))?,
                 )
+            } else if block_volume::is_block_volume(m) {
+                // direct-assigned volumes are bind mounts too, check them before share fs ones
+                Arc::new(
+                    block_volume::BlockVolume::new(hypervisor, block_indexes, sid, m)
+                        .await
+                        .with_context(|| format!("new block volume {:?}", m))?,
+                )
             } else if share_fs_volume::is_share_fs_volume(m) {
                 Arc::new(
-                    share_fs_volume::ShareFsVolume::new(share_fs, m, cid)
+                    share_fs_volume::ShareFsVolume::new(share_fs, agent, m, cid)
                         .await
                         .with_context(|| format!("new share fs volume {:?}", m))?,
                 )
@@ -76,11 +108,6 @@ impl VolumeResource {
                     hugepage::Hugepage::new(m, hugepage_limits, options)
                         .with_context(|| format!("handle hugepages {:?}", m))?,
                 )
-            } else if block_volume::is_block_volume(m) {
-                Arc::new(
-                    block_volume::BlockVolume::new(m)
-                        .with_context(|| format!("new block volume {:?}", m))?,
-                )
             } else if is_skip_volume(m) {
                 info!(sl!(), "skip volume {:?}", m);
                 continue;
@@ -99,6 +126,18 @@ impl VolumeResource {
         Ok(volumes)
     }
 
+    /// Find the direct-assigned volume backed by the block device of the host, `kata-ctl
+    /// direct-volume` identifies the volumes by the device of their mount info.
+    pub async fn get_direct_volume_device(&self, host_device: &str) -> Result<DirectVolumeDevice> {
+        let inner = self.inner.read().await;
+        inner
+            .volumes
+            .iter()
+            .filter_map(|v| v.get_direct_volume_device())
+            .find(|d| d.host_device == host_device)
+            .ok_or_else(|| anyhow!("no direct volume of device {} in the sandbox", host_device))
+    }
+
     pub async fn dump(&self) {
         let inner = self.inner.read().await;
         for v in &inner.volumes {
@@ -116,3 +155,51 @@ fn is_skip_volume(_m: &oci::Mount) -> bool {
     // TODO: support volume check
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeVolume {
+        device: Option<DirectVolumeDevice>,
+    }
+
+    #[async_trait]
+    impl Volume for FakeVolume {
+        fn get_volume_mount(&self) -> Result<Vec<oci::Mount>> {
+            Ok(vec![])
+        }
+        fn get_storage(&self) -> Result<Vec<agent::Storage>> {
+            Ok(vec![])
+        }
+        async fn cleanup(&self) -> Result<()> {
+            Ok(())
+        }
+        fn get_direct_volume_device(&self) -> Option<DirectVolumeDevice> {
+            self.device.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_direct_volume_device() {
+        let device = DirectVolumeDevice {
+            host_device: "/dev/sdb".to_string(),
+            device_id: "drive_1".to_string(),
+            guest_path: "/run/kata-containers/sandbox/storage/L2Rldi9zZGI=".to_string(),
+        };
+        let resource = VolumeResource::new();
+        {
+            let mut inner = resource.inner.write().await;
+            inner.volumes.push(Arc::new(FakeVolume { device: None }));
+            inner.volumes.push(Arc::new(FakeVolume {
+                device: Some(device.clone()),
+            }));
+        }
+
+        assert_eq!(
+            resource.get_direct_volume_device("/dev/sdb").await.unwrap(),
+            device
+        );
+        assert!(resource.get_direct_volume_device("/dev/sdc").await.is_err());
+    }
+}
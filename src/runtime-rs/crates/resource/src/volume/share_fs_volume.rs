@@ -10,12 +10,14 @@ use std::{
     sync::Arc,
 };
 
+use agent::Agent;
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use tokio::{sync::Mutex, task::JoinHandle};
 
-use super::Volume;
-use crate::share_fs::{MountedInfo, ShareFs, ShareFsVolumeConfig};
-use kata_types::mount;
+use super::{copy_watcher::CopyWatcher, Volume};
+use crate::share_fs::{do_get_guest_path, MountedInfo, ShareFs, ShareFsVolumeConfig};
+use kata_types::{k8s, mount};
 
 const SYS_MOUNT_PREFIX: [&str; 2] = ["/proc", "/sys"];
 
@@ -29,11 +31,17 @@ pub(crate) struct ShareFsVolume {
     share_fs: Option<Arc<dyn ShareFs>>,
     mounts: Vec<oci::Mount>,
     storages: Vec<agent::Storage>,
+    // task copying the changes of the volume to the guest, without share fs
+    copy_watcher: Option<JoinHandle<()>>,
+    // number of the mounts whose references have been dropped, a failed cleanup is retried
+    // from the first mount not released yet
+    released: Mutex<usize>,
 }
 
 impl ShareFsVolume {
     pub(crate) async fn new(
         share_fs: &Option<Arc<dyn ShareFs>>,
+        agent: &Arc<dyn Agent>,
         m: &oci::Mount,
         cid: &str,
     ) -> Result<Self> {
@@ -45,6 +53,8 @@ impl ShareFsVolume {
             share_fs: share_fs.as_ref().map(Arc::clone),
             mounts: vec![],
             storages: vec![],
+            copy_watcher: None,
+            released: Mutex::new(0),
         };
         match share_fs {
             None => {
@@ -58,13 +68,26 @@ impl ShareFsVolume {
                     Ok(src) => src,
                 };
 
-                if src.is_file() {
-                    // TODO: copy file
-                    debug!(sl!(), "FIXME: copy file {}", &m.source);
+                if src.is_file() || (src.is_dir() && is_copyable_dir(&m.source)) {
+                    // copy the volume into the guest, and keep it updated as the volumes, e.g.
+                    // ConfigMap and Secret, may change
+                    let guest_path = do_get_guest_path(&file_name, cid, false, false);
+                    volume.copy_watcher = CopyWatcher::new(agent, &src, Path::new(&guest_path))
+                        .start()
+                        .await
+                        .with_context(|| format!("copy {} to guest", &m.source))?;
+
+                    volume.mounts.push(oci::Mount {
+                        destination: m.destination.clone(),
+                        r#type: "bind".to_string(),
+                        source: guest_path,
+                        options: m.options.clone(),
+                    });
                 } else {
                     debug!(
                         sl!(),
-                        "Ignoring non-regular file as FS sharing not supported. mount: {:?}", m
+                        "Ignoring non-regular file or directory as FS sharing not supported. mount: {:?}",
+                        m
                     );
                 }
             }
@@ -160,14 +183,18 @@ impl Volume for ShareFsVolume {
     }
 
     async fn cleanup(&self) -> Result<()> {
+        if let Some(copy_watcher) = self.copy_watcher.as_ref() {
+            copy_watcher.abort();
+        }
+
         let share_fs = match self.share_fs.as_ref() {
             Some(fs) => fs,
             None => return Ok(()),
         };
-
+        let mut released = self.released.lock().await;
         let mounted_info_set = share_fs.mounted_info_set();
         let mut mounted_info_set = mounted_info_set.lock().await;
-        for m in self.mounts.iter() {
+        for m in self.mounts.iter().skip(*released) {
             let (host_source, mut mounted_info) = match mounted_info_set
                 .iter()
                 .find(|entry| entry.1.guest_path.as_os_str().to_str().unwrap() == m.source)
@@ -179,6 +206,7 @@ impl Volume for ShareFsVolume {
                         sl!(),
                         "The mounted info for guest path {} not found", m.source
                     );
+                    *released += 1;
                     continue;
                 }
             };
@@ -215,13 +243,14 @@ impl Volume for ShareFsVolume {
                     sl!(),
                     "The path will be umounted due to no references, host_source = {}", host_source
                 );
-                mounted_info_set.remove(&host_source);
                 // Umount the volume
                 share_fs_mount
                     .umount_volume(&file_name)
                     .await
-                    .context("Umount volume")?
+                    .context("Umount volume")?;
+                mounted_info_set.remove(&host_source);
             }
+            *released += 1;
         }
 
         Ok(())
@@ -255,6 +284,12 @@ fn is_host_device(dest: &str) -> bool {
     false
 }
 
+// Only the directories kubelet manages, and keeps small, are copied into the guest without
+// shared fs, the other directories may be arbitrarily large.
+fn is_copyable_dir(src: &str) -> bool {
+    k8s::is_configmap(src) || k8s::is_secret(src) || k8s::is_downward_api(src)
+}
+
 // Skip mounting certain system paths("/sys/*", "/proc/*")
 // from source on the host side into the container as it does not
 // make sense to do so.
@@ -301,4 +336,27 @@ mod test {
         assert!(is_system_mount(proc_sub_dir));
         assert!(!is_system_mount(not_sys_dir));
     }
+
+    #[test]
+    fn test_is_copyable_dir() {
+        let pod_volumes = "/var/lib/kubelet/pods/1/volumes";
+
+        assert!(is_copyable_dir(&format!(
+            "{}/kubernetes.io~configmap/config",
+            pod_volumes
+        )));
+        assert!(is_copyable_dir(&format!(
+            "{}/kubernetes.io~secret/token",
+            pod_volumes
+        )));
+        assert!(is_copyable_dir(&format!(
+            "{}/kubernetes.io~downward-api/podinfo",
+            pod_volumes
+        )));
+        assert!(!is_copyable_dir(&format!(
+            "{}/kubernetes.io~empty-dir/cache",
+            pod_volumes
+        )));
+        assert!(!is_copyable_dir("/home/data"));
+    }
 }
@@ -100,8 +100,8 @@ impl Volume for ShmVolume {
     }
 
     async fn cleanup(&self) -> Result<()> {
-        // TODO: Clean up ShmVolume
-        warn!(sl!(), "Cleaning up ShmVolume is still unimplemented.");
+        // the sandbox shm storage and the tmpfs mount live in the guest only, there is nothing
+        // to undo on the host
         Ok(())
     }
 }
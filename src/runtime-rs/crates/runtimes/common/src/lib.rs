@@ -4,6 +4,11 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+#[macro_use]
+extern crate slog;
+
+logging::logger_with_subsystem!(sl, "runtimes-common");
+
 mod container_manager;
 pub use container_manager::ContainerManager;
 pub mod error;
@@ -12,4 +17,6 @@ mod runtime_handler;
 pub use runtime_handler::{RuntimeHandler, RuntimeInstance};
 mod sandbox;
 pub use sandbox::Sandbox;
+mod shim_io;
+pub use shim_io::ShimIo;
 pub mod types;
@@ -6,7 +6,13 @@
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use containerd_shim_protos::{events::task::TaskOOM, protobuf::Message as ProtobufMessage};
+use containerd_shim_protos::{
+    events::task::{
+        TaskCreate, TaskDelete, TaskExecAdded, TaskExecStarted, TaskExit, TaskOOM, TaskPaused,
+        TaskResumed, TaskStart,
+    },
+    protobuf::Message as ProtobufMessage,
+};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
 /// message receiver buffer size
@@ -50,6 +56,14 @@ impl Message {
 }
 
 const TASK_OOM_EVENT_TOPIC: &str = "/tasks/oom";
+const TASK_CREATE_EVENT_TOPIC: &str = "/tasks/create";
+const TASK_START_EVENT_TOPIC: &str = "/tasks/start";
+const TASK_EXIT_EVENT_TOPIC: &str = "/tasks/exit";
+const TASK_DELETE_EVENT_TOPIC: &str = "/tasks/delete";
+const TASK_EXEC_ADDED_EVENT_TOPIC: &str = "/tasks/exec-added";
+const TASK_EXEC_STARTED_EVENT_TOPIC: &str = "/tasks/exec-started";
+const TASK_PAUSED_EVENT_TOPIC: &str = "/tasks/paused";
+const TASK_RESUMED_EVENT_TOPIC: &str = "/tasks/resumed";
 
 pub trait Event: std::fmt::Debug + Send {
     fn r#type(&self) -> String;
@@ -57,16 +71,64 @@ pub trait Event: std::fmt::Debug + Send {
     fn value(&self) -> Result<Vec<u8>>;
 }
 
-impl Event for TaskOOM {
-    fn r#type(&self) -> String {
-        TASK_OOM_EVENT_TOPIC.to_string()
-    }
+macro_rules! impl_event {
+    ($($event: ident, $topic: expr),*) => {
+        $(
+            impl Event for $event {
+                fn r#type(&self) -> String {
+                    $topic.to_string()
+                }
 
-    fn type_url(&self) -> String {
-        "containerd.events.TaskOOM".to_string()
-    }
+                fn type_url(&self) -> String {
+                    format!("containerd.events.{}", stringify!($event))
+                }
+
+                fn value(&self) -> Result<Vec<u8>> {
+                    self.write_to_bytes()
+                        .context(format!("get {} value", stringify!($event)))
+                }
+            }
+        )*
+    };
+}
+
+impl_event!(
+    TaskOOM,
+    TASK_OOM_EVENT_TOPIC,
+    TaskCreate,
+    TASK_CREATE_EVENT_TOPIC,
+    TaskStart,
+    TASK_START_EVENT_TOPIC,
+    TaskExit,
+    TASK_EXIT_EVENT_TOPIC,
+    TaskDelete,
+    TASK_DELETE_EVENT_TOPIC,
+    TaskExecAdded,
+    TASK_EXEC_ADDED_EVENT_TOPIC,
+    TaskExecStarted,
+    TASK_EXEC_STARTED_EVENT_TOPIC,
+    TaskPaused,
+    TASK_PAUSED_EVENT_TOPIC,
+    TaskResumed,
+    TASK_RESUMED_EVENT_TOPIC
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_type() {
+        let event = TaskExecAdded {
+            container_id: "c1".to_string(),
+            exec_id: "e1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(event.r#type(), "/tasks/exec-added");
+        assert_eq!(event.type_url(), "containerd.events.TaskExecAdded");
 
-    fn value(&self) -> Result<Vec<u8>> {
-        self.write_to_bytes().context("get oom value")
+        let value = event.value().unwrap();
+        let decoded = TaskExecAdded::parse_from_bytes(&value).unwrap();
+        assert_eq!(decoded, event);
     }
 }
@@ -20,4 +20,15 @@ pub trait Sandbox: Send + Sync {
     // utils
     async fn set_iptables(&self, is_ipv6: bool, data: Vec<u8>) -> Result<Vec<u8>>;
     async fn get_iptables(&self, is_ipv6: bool) -> Result<Vec<u8>>;
+
+    // network, rescan the netns after its interfaces have changed
+    async fn update_network(&self, netns: &str) -> Result<()>;
+
+    // direct volume, identified by the block device of the host backing it
+    async fn direct_volume_stats(&self, volume_device: &str) -> Result<String>;
+    async fn direct_volume_resize(&self, resize_req: agent::ResizeVolumeRequest) -> Result<()>;
+
+    // metrics
+    async fn agent_metrics(&self) -> Result<String>;
+    async fn hypervisor_metrics(&self) -> Result<String>;
 }
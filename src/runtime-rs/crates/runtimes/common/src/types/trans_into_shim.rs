@@ -11,9 +11,14 @@ use std::{
 };
 
 use anyhow::{anyhow, Result};
-use containerd_shim_protos::api;
+use containerd_shim_protos::{
+    api,
+    events::task::{TaskCreate, TaskDelete, TaskExit, TaskIO},
+    types::mount::Mount as ProtoMount,
+};
+use kata_types::mount::Mount;
 
-use super::{ProcessExitStatus, ProcessStateInfo, ProcessStatus, Response};
+use super::{ContainerConfig, ProcessExitStatus, ProcessStateInfo, ProcessStatus, Response};
 use crate::error::Error;
 
 fn system_time_into(time: time::SystemTime) -> ::protobuf::well_known_types::Timestamp {
@@ -37,6 +42,74 @@ fn option_system_time_into(
     }
 }
 
+fn trans_into_shim_mount(from: &Mount) -> ProtoMount {
+    ProtoMount {
+        field_type: from.fs_type.clone(),
+        source: from.source.clone(),
+        target: from.destination.to_string_lossy().to_string(),
+        options: ::protobuf::RepeatedField::from_vec(from.options.clone()),
+        ..Default::default()
+    }
+}
+
+impl From<&ContainerConfig> for TaskCreate {
+    fn from(from: &ContainerConfig) -> Self {
+        Self {
+            container_id: from.container_id.clone(),
+            bundle: from.bundle.clone(),
+            rootfs: from
+                .rootfs_mounts
+                .iter()
+                .map(trans_into_shim_mount)
+                .collect(),
+            io: ::protobuf::SingularPtrField::some(TaskIO {
+                stdin: from.stdin.clone().unwrap_or_default(),
+                stdout: from.stdout.clone().unwrap_or_default(),
+                stderr: from.stderr.clone().unwrap_or_default(),
+                terminal: from.terminal,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+// The id of the process in the task events, the init process takes the id of
+// its container.
+fn event_process_id(from: &ProcessStateInfo) -> String {
+    if from.exec_id.is_empty() {
+        from.container_id.clone()
+    } else {
+        from.exec_id.clone()
+    }
+}
+
+impl From<ProcessStateInfo> for TaskExit {
+    fn from(from: ProcessStateInfo) -> Self {
+        Self {
+            id: event_process_id(&from),
+            container_id: from.container_id,
+            pid: from.pid.pid,
+            exit_status: from.exit_status as u32,
+            exited_at: option_system_time_into(from.exited_at),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<ProcessStateInfo> for TaskDelete {
+    fn from(from: ProcessStateInfo) -> Self {
+        Self {
+            id: event_process_id(&from),
+            container_id: from.container_id,
+            pid: from.pid.pid,
+            exit_status: from.exit_status as u32,
+            exited_at: option_system_time_into(from.exited_at),
+            ..Default::default()
+        }
+    }
+}
+
 impl From<ProcessExitStatus> for api::WaitResponse {
     fn from(from: ProcessExitStatus) -> Self {
         Self {
@@ -0,0 +1,403 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use common::{
+    error::Error,
+    types::{ContainerConfig, ContainerProcess, ProcessStateInfo, ProcessStatus, ProcessType},
+};
+use kata_sys_util::mount::{bind_mount_unchecked, umount_timeout, Mounter};
+use nix::{
+    errno::Errno,
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+use oci::{LinuxResources, Process as OCIProcess};
+use rustjail::{
+    container::{BaseContainer, Container as _, LinuxContainer as JailContainer},
+    process::Process as JailProcess,
+    specconv::CreateOpts,
+};
+
+use super::process::Process;
+use crate::container_manager::ProcessWatcher;
+
+const ROOTFS: &str = "rootfs";
+
+pub struct Exec {
+    pub(crate) process: Process,
+    pub(crate) oci_process: OCIProcess,
+}
+
+pub struct Container {
+    pub container_id: String,
+    config: ContainerConfig,
+    logger: slog::Logger,
+    // rootfs of the bundle, mounted by us when the rootfs mounts are given
+    bundle_rootfs: PathBuf,
+    bundle_rootfs_mounted: bool,
+    pub(crate) jail: JailContainer,
+    init_process: Process,
+    exec_processes: HashMap<String, Exec>,
+}
+
+impl Container {
+    /// Create the container in `base` with rustjail, its init process is
+    /// left waiting for `start()` like with an OCI runtime.
+    pub async fn new(base: &str, config: ContainerConfig, mut spec: oci::Spec) -> Result<Self> {
+        let container_id = config.container_id.clone();
+        let logger = sl!().new(o!("container_id" => container_id.clone()));
+
+        let root = spec
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow!("no root in spec"))?;
+        let root_path = if root.path.is_empty() {
+            ROOTFS
+        } else {
+            root.path.as_str()
+        };
+        let bundle_rootfs = Path::new(&config.bundle).join(root_path);
+
+        let bundle_rootfs_mounted = match config.rootfs_mounts.as_slice() {
+            [] => false,
+            [mount] => {
+                mount.mount(&bundle_rootfs).with_context(|| {
+                    format!("mount rootfs from {:?} to {:?}", mount, &bundle_rootfs)
+                })?;
+                true
+            }
+            mounts => return Err(anyhow!("unsupported rootfs mounts count {}", mounts.len())),
+        };
+
+        let process = ContainerProcess::new(&container_id, "")?;
+        let init_process = Process::new(
+            &process,
+            &config.bundle,
+            config.stdin.clone(),
+            config.stdout.clone(),
+            config.stderr.clone(),
+            config.terminal,
+        );
+
+        let jail = match create_jail(base, &container_id, &bundle_rootfs, &mut spec) {
+            Ok(jail) => jail,
+            Err(err) => {
+                if bundle_rootfs_mounted {
+                    let _ = umount_timeout(&bundle_rootfs, 0);
+                }
+                return Err(err);
+            }
+        };
+
+        let mut container = Self {
+            container_id,
+            config,
+            logger,
+            bundle_rootfs,
+            bundle_rootfs_mounted,
+            jail,
+            init_process,
+            exec_processes: HashMap::new(),
+        };
+
+        let oci_process = spec
+            .process
+            .as_ref()
+            .ok_or_else(|| anyhow!("no process in spec"))?
+            .clone();
+        if let Err(err) = container.start_jail_process(None, &oci_process).await {
+            error!(container.logger, "failed to create container: {:?}", err);
+            container.destroy().await;
+            return Err(err);
+        }
+
+        Ok(container)
+    }
+
+    /// Start the init process, or the process `exec_id` of the container.
+    pub async fn start(&mut self, container_process: &ContainerProcess) -> Result<()> {
+        match container_process.process_type {
+            ProcessType::Container => {
+                self.jail.exec().await.context("start container")?;
+                self.init_process.set_status(ProcessStatus::Running).await;
+            }
+            ProcessType::Exec => {
+                let oci_process = self
+                    .exec_processes
+                    .get(container_process.exec_id())
+                    .ok_or_else(|| Error::ProcessNotFound(container_process.clone()))?
+                    .oci_process
+                    .clone();
+                self.start_jail_process(Some(container_process.exec_id()), &oci_process)
+                    .await
+                    .context("start exec process")?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn start_jail_process(
+        &mut self,
+        exec_id: Option<&str>,
+        oci_process: &OCIProcess,
+    ) -> Result<()> {
+        let (eid, init) = match exec_id {
+            Some(exec_id) => (exec_id.to_string(), false),
+            None => (self.container_id.clone(), true),
+        };
+
+        let p = JailProcess::new(&self.logger, oci_process, &eid, init, 0)
+            .context("new rustjail process")?;
+        if init {
+            self.jail.start(p).await.context("create init process")?;
+        } else {
+            self.jail.run(p).await.context("run exec process")?;
+        }
+
+        let pid = self
+            .jail
+            .processes
+            .values()
+            .find(|p| p.exec_id == eid)
+            .map(|p| p.pid)
+            .ok_or_else(|| anyhow!("failed to find started process {}", eid))?;
+        let jail_process = self
+            .jail
+            .processes
+            .get_mut(&pid)
+            .ok_or_else(|| anyhow!("failed to find started process {}", eid))?;
+
+        let process = match exec_id {
+            Some(exec_id) => {
+                &mut self
+                    .exec_processes
+                    .get_mut(exec_id)
+                    .ok_or_else(|| anyhow!("failed to find exec {}", exec_id))?
+                    .process
+            }
+            None => &mut self.init_process,
+        };
+        process
+            .start_io_and_wait(jail_process)
+            .await
+            .context("start io and wait")?;
+        if !init {
+            process.set_status(ProcessStatus::Running).await;
+        }
+        Ok(())
+    }
+
+    pub async fn exec_process(
+        &mut self,
+        container_process: &ContainerProcess,
+        stdin: Option<String>,
+        stdout: Option<String>,
+        stderr: Option<String>,
+        terminal: bool,
+        oci_process: OCIProcess,
+    ) -> Result<()> {
+        let exec_id = container_process.exec_id();
+        if self.exec_processes.contains_key(exec_id) {
+            return Err(anyhow!("exec process {} already exists", exec_id));
+        }
+
+        let process = Process::new(
+            container_process,
+            &self.config.bundle,
+            stdin,
+            stdout,
+            stderr,
+            terminal,
+        );
+        self.exec_processes.insert(
+            exec_id.to_string(),
+            Exec {
+                process,
+                oci_process,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn delete_exec_process(&mut self, container_process: &ContainerProcess) -> Result<()> {
+        self.exec_processes
+            .remove(container_process.exec_id())
+            .ok_or_else(|| Error::ProcessNotFound(container_process.clone()))?;
+        Ok(())
+    }
+
+    fn get_process(&self, container_process: &ContainerProcess) -> Result<&Process> {
+        match container_process.process_type {
+            ProcessType::Container => Ok(&self.init_process),
+            ProcessType::Exec => self
+                .exec_processes
+                .get(container_process.exec_id())
+                .map(|exec| &exec.process)
+                .ok_or_else(|| Error::ProcessNotFound(container_process.clone()).into()),
+        }
+    }
+
+    fn get_process_mut(&mut self, container_process: &ContainerProcess) -> Result<&mut Process> {
+        match container_process.process_type {
+            ProcessType::Container => Ok(&mut self.init_process),
+            ProcessType::Exec => self
+                .exec_processes
+                .get_mut(container_process.exec_id())
+                .map(|exec| &mut exec.process)
+                .ok_or_else(|| Error::ProcessNotFound(container_process.clone()).into()),
+        }
+    }
+
+    pub async fn state_process(
+        &self,
+        container_process: &ContainerProcess,
+    ) -> Result<ProcessStateInfo> {
+        self.get_process(container_process)?.state().await
+    }
+
+    pub fn wait_process(&self, container_process: &ContainerProcess) -> Result<ProcessWatcher> {
+        self.get_process(container_process)?.fetch_exit_watcher()
+    }
+
+    pub async fn kill_process(
+        &self,
+        container_process: &ContainerProcess,
+        signal: u32,
+        all: bool,
+    ) -> Result<()> {
+        let process = self.get_process(container_process)?;
+        if process.pid <= 0 || process.get_status().await == ProcessStatus::Stopped {
+            return Ok(());
+        }
+
+        let signal = Signal::try_from(signal as i32).context("invalid signal")?;
+        let pids = if all && container_process.process_type == ProcessType::Container {
+            self.jail
+                .cgroup_manager
+                .get_pids()
+                .context("get cgroup pids")?
+        } else {
+            vec![process.pid]
+        };
+
+        for pid in pids {
+            match signal::kill(Pid::from_raw(pid), signal) {
+                Err(Errno::ESRCH) => {
+                    info!(self.logger, "signal encounter ESRCH, pid {}", pid);
+                }
+                Err(err) => return Err(anyhow!(err).context(format!("signal pid {}", pid))),
+                Ok(()) => (),
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn close_io(&mut self, container_process: &ContainerProcess) -> Result<()> {
+        self.get_process_mut(container_process)?.close_io().await;
+        Ok(())
+    }
+
+    pub async fn stop_process(&mut self, container_process: &ContainerProcess) -> Result<()> {
+        self.get_process(container_process)?
+            .set_status(ProcessStatus::Stopped)
+            .await;
+        Ok(())
+    }
+
+    pub async fn pause(&mut self) -> Result<()> {
+        if self.init_process.get_status().await != ProcessStatus::Running {
+            return Err(anyhow!("container is not running"));
+        }
+        self.jail.pause().context("pause")?;
+        self.init_process.set_status(ProcessStatus::Paused).await;
+        Ok(())
+    }
+
+    pub async fn resume(&mut self) -> Result<()> {
+        if self.init_process.get_status().await != ProcessStatus::Paused {
+            return Err(anyhow!("container is not paused"));
+        }
+        self.jail.resume().context("resume")?;
+        self.init_process.set_status(ProcessStatus::Running).await;
+        Ok(())
+    }
+
+    pub fn resize_pty(
+        &self,
+        container_process: &ContainerProcess,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        self.get_process(container_process)?
+            .resize_pty(width, height)
+            .context("resize pty")
+    }
+
+    pub fn stats(&self) -> Result<agent::StatsContainerResponse> {
+        let stats = self.jail.stats().context("stats")?;
+        Ok(stats.into())
+    }
+
+    pub fn update(&mut self, resources: LinuxResources) -> Result<()> {
+        self.jail.set(resources).context("set resources")
+    }
+
+    /// Kill the processes of the container and release its resources.
+    pub async fn destroy(&mut self) {
+        if let Err(e) = self.jail.destroy().await {
+            warn!(self.logger, "failed to destroy container {:?}", e);
+        }
+
+        if self.bundle_rootfs_mounted {
+            if let Err(e) = umount_timeout(&self.bundle_rootfs, 0) {
+                warn!(self.logger, "failed to umount bundle rootfs {:?}", e);
+            }
+        }
+    }
+}
+
+/// Create the rustjail container in `base`/`id`. Like the agent does with
+/// the bundles in the guest, the rootfs is bind mounted in the container
+/// directory so that rustjail can detach it when destroying the container.
+fn create_jail(
+    base: &str,
+    id: &str,
+    bundle_rootfs: &Path,
+    spec: &mut oci::Spec,
+) -> Result<JailContainer> {
+    let rootfs = Path::new(base).join(id).join(ROOTFS);
+    fs::create_dir_all(&rootfs).with_context(|| format!("create rootfs dir {:?}", &rootfs))?;
+    bind_mount_unchecked(bundle_rootfs, &rootfs, false)
+        .with_context(|| format!("bind mount rootfs to {:?}", &rootfs))?;
+
+    if let Some(root) = spec.root.as_mut() {
+        root.path = rootfs.display().to_string();
+    }
+
+    let opts = CreateOpts {
+        cgroup_name: "".to_string(),
+        use_systemd_cgroup: false,
+        no_pivot_root: false,
+        no_new_keyring: false,
+        spec: Some(spec.clone()),
+        rootless_euid: false,
+        rootless_cgroup: false,
+    };
+
+    JailContainer::new(id, base, opts, &sl!()).map_err(|err| {
+        let _ = umount_timeout(&rootfs, 0);
+        let _ = fs::remove_dir_all(Path::new(base).join(id));
+        err
+    })
+}
@@ -0,0 +1,270 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use common::{
+    error::Error,
+    types::{
+        ContainerConfig, ContainerID, ContainerProcess, ExecProcessRequest, KillRequest,
+        ProcessExitStatus, ProcessStateInfo, ProcessType, ResizePTYRequest, ShutdownRequest,
+        StatsInfo, UpdateRequest, PID,
+    },
+    ContainerManager,
+};
+use oci::Process as OCIProcess;
+use shim_interface::KATA_PATH;
+use tokio::sync::Mutex;
+
+use super::{logger_with_process, reaper, Container};
+
+pub struct LinuxContainerManager {
+    sid: String,
+    pid: u32,
+    // the reaper looks up the exited processes in the containers, a mutex is
+    // used as the rustjail containers can't be shared between threads
+    containers: Arc<Mutex<HashMap<String, Container>>>,
+}
+
+impl LinuxContainerManager {
+    pub fn new(sid: &str, pid: u32) -> Result<Self> {
+        let containers = Arc::new(Mutex::new(HashMap::new()));
+        reaper::start(containers.clone()).context("start reaper")?;
+
+        Ok(Self {
+            sid: sid.to_string(),
+            pid,
+            containers,
+        })
+    }
+
+    fn containers_dir(&self) -> String {
+        [KATA_PATH, &self.sid, "containers"].join("/")
+    }
+}
+
+#[async_trait]
+impl ContainerManager for LinuxContainerManager {
+    async fn create_container(&self, config: ContainerConfig, spec: oci::Spec) -> Result<PID> {
+        let mut containers = self.containers.lock().await;
+        if containers.contains_key(&config.container_id) {
+            return Err(anyhow!("container {} already exists", &config.container_id));
+        }
+
+        let container = Container::new(&self.containers_dir(), config, spec)
+            .await
+            .context("new container")?;
+        // the pid of the container is reported rather than the shim one, it
+        // is used by the callers to join the namespaces of the container
+        let pid = container.jail.init_process_pid;
+        containers.insert(container.container_id.clone(), container);
+
+        Ok(PID { pid: pid as u32 })
+    }
+
+    async fn close_process_io(&self, process: &ContainerProcess) -> Result<()> {
+        let mut containers = self.containers.lock().await;
+        let container_id = &process.container_id.to_string();
+        let c = containers
+            .get_mut(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+
+        c.close_io(process).await.context("close io")?;
+        Ok(())
+    }
+
+    async fn delete_process(&self, process: &ContainerProcess) -> Result<ProcessStateInfo> {
+        let container_id = &process.container_id.container_id;
+        let mut containers = self.containers.lock().await;
+        match process.process_type {
+            ProcessType::Container => {
+                let mut c = containers
+                    .remove(container_id)
+                    .ok_or_else(|| Error::ContainerNotFound(container_id.to_string()))?;
+                c.destroy().await;
+                c.state_process(process).await.context("state process")
+            }
+            ProcessType::Exec => {
+                let c = containers
+                    .get_mut(container_id)
+                    .ok_or_else(|| Error::ContainerNotFound(container_id.to_string()))?;
+                let state = c.state_process(process).await.context("state process");
+                c.delete_exec_process(process).context("delete process")?;
+                state
+            }
+        }
+    }
+
+    async fn exec_process(&self, req: ExecProcessRequest) -> Result<()> {
+        if req.spec_type_url.is_empty() {
+            return Err(anyhow!("invalid type url"));
+        }
+        let oci_process: OCIProcess =
+            serde_json::from_slice(&req.spec_value).context("serde from slice")?;
+
+        let mut containers = self.containers.lock().await;
+        let container_id = &req.process.container_id.container_id;
+        let c = containers
+            .get_mut(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        c.exec_process(
+            &req.process,
+            req.stdin,
+            req.stdout,
+            req.stderr,
+            req.terminal,
+            oci_process,
+        )
+        .await
+        .context("exec")?;
+        Ok(())
+    }
+
+    async fn kill_process(&self, req: &KillRequest) -> Result<()> {
+        let containers = self.containers.lock().await;
+        let container_id = &req.process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        c.kill_process(&req.process, req.signal, req.all)
+            .await
+            .map_err(|err| {
+                warn!(
+                    sl!(),
+                    "failed to signal process {:?} {:?}", &req.process, err
+                );
+                err
+            })
+            .ok();
+        Ok(())
+    }
+
+    async fn wait_process(&self, process: &ContainerProcess) -> Result<ProcessExitStatus> {
+        let logger = logger_with_process(process);
+
+        let containers = self.containers.lock().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        let (watcher, status) = c.wait_process(process).context("wait")?;
+        drop(containers);
+
+        match watcher {
+            Some(mut watcher) => {
+                info!(logger, "begin wait exit");
+                while watcher.changed().await.is_ok() {}
+                info!(logger, "end wait exited");
+            }
+            None => {
+                warn!(logger, "failed to find watcher for wait process");
+            }
+        }
+
+        let status = status.read().await;
+
+        info!(logger, "wait process exit status {:?}", status);
+
+        // stop process
+        let mut containers = self.containers.lock().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get_mut(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        c.stop_process(process).await.context("stop container")?;
+        Ok(status.clone())
+    }
+
+    async fn start_process(&self, process: &ContainerProcess) -> Result<PID> {
+        let mut containers = self.containers.lock().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get_mut(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        c.start(process).await.context("start")?;
+        let state = c.state_process(process).await.context("state process")?;
+        Ok(state.pid)
+    }
+
+    async fn state_process(&self, process: &ContainerProcess) -> Result<ProcessStateInfo> {
+        let containers = self.containers.lock().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        let state = c.state_process(process).await.context("state process")?;
+        Ok(state)
+    }
+
+    async fn pause_container(&self, id: &ContainerID) -> Result<()> {
+        let mut containers = self.containers.lock().await;
+        let c = containers
+            .get_mut(&id.container_id)
+            .ok_or_else(|| Error::ContainerNotFound(id.container_id.clone()))?;
+        c.pause().await.context("pause")?;
+        Ok(())
+    }
+
+    async fn resume_container(&self, id: &ContainerID) -> Result<()> {
+        let mut containers = self.containers.lock().await;
+        let c = containers
+            .get_mut(&id.container_id)
+            .ok_or_else(|| Error::ContainerNotFound(id.container_id.clone()))?;
+        c.resume().await.context("resume")?;
+        Ok(())
+    }
+
+    async fn resize_process_pty(&self, req: &ResizePTYRequest) -> Result<()> {
+        let containers = self.containers.lock().await;
+        let c = containers
+            .get(&req.process.container_id.container_id)
+            .ok_or_else(|| {
+                Error::ContainerNotFound(req.process.container_id.container_id.clone())
+            })?;
+        c.resize_pty(&req.process, req.width, req.height)
+            .context("resize pty")?;
+        Ok(())
+    }
+
+    async fn stats_container(&self, id: &ContainerID) -> Result<StatsInfo> {
+        let containers = self.containers.lock().await;
+        let c = containers
+            .get(&id.container_id)
+            .ok_or_else(|| Error::ContainerNotFound(id.container_id.clone()))?;
+        let stats = c.stats().context("stats")?;
+        Ok(StatsInfo::from(Some(stats)))
+    }
+
+    async fn update_container(&self, req: UpdateRequest) -> Result<()> {
+        let resource = serde_json::from_slice::<oci::LinuxResources>(&req.value)
+            .context("deserialize LinuxResource")?;
+        let mut containers = self.containers.lock().await;
+        let container_id = &req.container_id;
+        let c = containers
+            .get_mut(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.to_string()))?;
+        c.update(resource).context("update_container")
+    }
+
+    async fn pid(&self) -> Result<PID> {
+        Ok(PID { pid: self.pid })
+    }
+
+    async fn connect_container(&self, _id: &ContainerID) -> Result<PID> {
+        Ok(PID { pid: self.pid })
+    }
+
+    async fn need_shutdown_sandbox(&self, req: &ShutdownRequest) -> bool {
+        req.is_now || self.containers.lock().await.is_empty() || self.sid == req.container_id
+    }
+
+    async fn is_sandbox_container(&self, process: &ContainerProcess) -> bool {
+        process.process_type == ProcessType::Container
+            && process.container_id.container_id == self.sid
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod container;
+use container::Container;
+mod manager;
+pub use manager::LinuxContainerManager;
+mod process;
+mod reaper;
+
+use std::sync::Arc;
+
+use common::types::{ContainerProcess, ProcessExitStatus};
+use tokio::sync::{watch, RwLock};
+
+pub type ProcessWatcher = (
+    Option<watch::Receiver<bool>>,
+    Arc<RwLock<ProcessExitStatus>>,
+);
+
+fn logger_with_process(container_process: &ContainerProcess) -> slog::Logger {
+    sl!().new(o!("container_id" => container_process.container_id.container_id.clone(), "exec_id" => container_process.exec_id.clone()))
+}
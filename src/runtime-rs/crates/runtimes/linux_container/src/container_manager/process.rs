@@ -0,0 +1,266 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    fs::File,
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context, Result};
+use awaitgroup::{WaitGroup, Worker as WaitGroupWorker};
+use common::{
+    types::{ContainerProcess, ProcessExitStatus, ProcessStateInfo, ProcessStatus, PID},
+    ShimIo,
+};
+use nix::{errno::Errno, unistd};
+use rustjail::{pipestream::PipeStream, process::Process as JailProcess};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{mpsc, watch, RwLock},
+};
+
+use super::{logger_with_process, ProcessWatcher};
+
+#[derive(Debug)]
+pub struct Process {
+    pub process: ContainerProcess,
+    pub pid: i32,
+    logger: slog::Logger,
+    pub bundle: String,
+
+    pub stdin: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub terminal: bool,
+    // master side of the terminal, kept to resize it
+    term_master: Option<File>,
+
+    pub status: Arc<RwLock<ProcessStatus>>,
+    pub exit_status: Arc<RwLock<ProcessExitStatus>>,
+    pub exit_watcher_rx: Option<watch::Receiver<bool>>,
+    pub exit_watcher_tx: Option<watch::Sender<bool>>,
+    // used to sync between stdin io copy thread(tokio) and the close it call.
+    // close io call should wait until the stdin io copy finished to
+    // prevent stdin data lost.
+    pub wg_stdin: WaitGroup,
+}
+
+impl Process {
+    pub fn new(
+        process: &ContainerProcess,
+        bundle: &str,
+        stdin: Option<String>,
+        stdout: Option<String>,
+        stderr: Option<String>,
+        terminal: bool,
+    ) -> Process {
+        let (sender, receiver) = watch::channel(false);
+
+        Process {
+            process: process.clone(),
+            pid: -1,
+            logger: logger_with_process(process),
+            bundle: bundle.to_string(),
+            stdin,
+            stdout,
+            stderr,
+            terminal,
+            term_master: None,
+            status: Arc::new(RwLock::new(ProcessStatus::Created)),
+            exit_status: Arc::new(RwLock::new(ProcessExitStatus::new())),
+            exit_watcher_rx: Some(receiver),
+            exit_watcher_tx: Some(sender),
+            wg_stdin: WaitGroup::new(),
+        }
+    }
+
+    /// Connect the shim io to the stdio of the started rustjail process `p`,
+    /// and wait for its exit code reported by the reaper.
+    pub async fn start_io_and_wait(&mut self, p: &mut JailProcess) -> Result<()> {
+        info!(self.logger, "start io and wait");
+        self.pid = p.pid;
+
+        // take over the parent side of the process stdio
+        let (stdin, stdout, stderr) = if self.terminal {
+            let master = p
+                .term_master
+                .take()
+                .ok_or_else(|| anyhow!("no terminal for process"))?;
+            let writer = unistd::dup(master).context("dup terminal")?;
+            let resizer = unistd::dup(master).context("dup terminal")?;
+            self.term_master = Some(unsafe { File::from_raw_fd(resizer) });
+            (Some(writer), Some(master), None)
+        } else {
+            (
+                p.parent_stdin.take(),
+                p.parent_stdout.take(),
+                p.parent_stderr.take(),
+            )
+        };
+
+        // new shim io
+        let shim_io = ShimIo::new(&self.stdin, &self.stdout, &self.stderr)
+            .await
+            .context("new shim io")?;
+
+        // start io copy for stdin, the stdin of the process is closed right
+        // away if there is nothing to copy to it
+        let wgw_stdin = self.wg_stdin.worker();
+        if let Some(fd) = stdin {
+            let pipe = pipe_stream(fd)?;
+            match shim_io.stdin {
+                Some(shim_stdin) => self.run_io_copy("stdin", wgw_stdin, shim_stdin, pipe),
+                None => wgw_stdin.done(),
+            }
+        } else {
+            wgw_stdin.done();
+        }
+
+        // prepare for wait group for stdout, stderr
+        let wg = WaitGroup::new();
+        let wgw = wg.worker();
+
+        // start io copy for stdout
+        if let Some(fd) = stdout {
+            let pipe = pipe_stream(fd)?;
+            match shim_io.stdout {
+                Some(shim_stdout) => self.run_io_copy("stdout", wgw.clone(), pipe, shim_stdout),
+                None => self.run_io_copy("stdout", wgw.clone(), pipe, Box::new(tokio::io::sink())),
+            }
+        }
+
+        // start io copy for stderr
+        if let Some(fd) = stderr {
+            let pipe = pipe_stream(fd)?;
+            match shim_io.stderr {
+                Some(shim_stderr) => self.run_io_copy("stderr", wgw, pipe, shim_stderr),
+                None => self.run_io_copy("stderr", wgw, pipe, Box::new(tokio::io::sink())),
+            }
+        }
+
+        let (exit_tx, exit_rx) = mpsc::channel(1);
+        p.exit_watchers.push(exit_tx);
+        self.run_io_wait(wg, exit_rx);
+        Ok(())
+    }
+
+    fn run_io_copy<R, W>(&self, io_name: &str, wgw: WaitGroupWorker, mut reader: R, mut writer: W)
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        info!(self.logger, "run io copy for {}", io_name);
+        let io_name = io_name.to_string();
+        let logger = self.logger.new(o!("io_name" => io_name));
+        let _ = tokio::spawn(async move {
+            match tokio::io::copy(&mut reader, &mut writer).await {
+                Err(e) => {
+                    warn!(logger, "run_io_copy: failed to copy stream: {}", e);
+                }
+                Ok(length) => {
+                    info!(logger, "run_io_copy: stop to copy stream length {}", length)
+                }
+            };
+
+            wgw.done();
+        });
+    }
+
+    fn run_io_wait(&mut self, mut wg: WaitGroup, mut exit_rx: mpsc::Receiver<i32>) {
+        let logger = self.logger.clone();
+        info!(logger, "start run io wait");
+        let exit_status = self.exit_status.clone();
+        let exit_notifier = self.exit_watcher_tx.take();
+        let status = self.status.clone();
+
+        let _ = tokio::spawn(async move {
+            info!(logger, "begin wait process");
+            let exit_code = match exit_rx.recv().await {
+                Some(code) => code,
+                None => {
+                    error!(logger, "failed to wait process");
+                    return;
+                }
+            };
+            info!(logger, "end wait process exit code {}", exit_code);
+
+            // wait on all of the process io streams terminated
+            info!(logger, "begin wait group io");
+            wg.wait().await;
+            info!(logger, "end wait group for io");
+
+            let mut exit_status = exit_status.write().await;
+            exit_status.update_exit_code(exit_code);
+            drop(exit_status);
+
+            let mut status = status.write().await;
+            *status = ProcessStatus::Stopped;
+            drop(status);
+
+            drop(exit_notifier);
+            info!(logger, "end io wait thread");
+        });
+    }
+
+    pub fn fetch_exit_watcher(&self) -> Result<ProcessWatcher> {
+        Ok((self.exit_watcher_rx.clone(), self.exit_status.clone()))
+    }
+
+    pub async fn state(&self) -> Result<ProcessStateInfo> {
+        let exit_status = self.exit_status.read().await;
+        Ok(ProcessStateInfo {
+            container_id: self.process.container_id.container_id.clone(),
+            exec_id: self.process.exec_id.clone(),
+            pid: PID {
+                pid: self.pid.max(0) as u32,
+            },
+            bundle: self.bundle.clone(),
+            stdin: self.stdin.clone(),
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+            terminal: self.terminal,
+            status: self.get_status().await,
+            exit_status: exit_status.exit_code,
+            exited_at: exit_status.exit_time,
+        })
+    }
+
+    pub async fn close_io(&mut self) {
+        self.wg_stdin.wait().await;
+    }
+
+    pub fn resize_pty(&self, width: u32, height: u32) -> Result<()> {
+        let master = match &self.term_master {
+            Some(master) => master,
+            None => return Ok(()),
+        };
+
+        let size = libc::winsize {
+            ws_row: height as libc::c_ushort,
+            ws_col: width as libc::c_ushort,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let ret = unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+        Errno::result(ret).context("set window size")?;
+        Ok(())
+    }
+
+    pub async fn get_status(&self) -> ProcessStatus {
+        let status = self.status.read().await;
+        *status
+    }
+
+    pub async fn set_status(&self, new_status: ProcessStatus) {
+        let mut status = self.status.write().await;
+        *status = new_status;
+    }
+}
+
+fn pipe_stream(fd: RawFd) -> Result<PipeStream> {
+    PipeStream::new(fd).with_context(|| format!("new pipe stream for fd {}", fd))
+}
@@ -0,0 +1,87 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use nix::{
+    errno::Errno,
+    sys::wait::{self, WaitPidFlag, WaitStatus},
+    unistd::Pid,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+};
+
+use super::Container;
+
+/// Make the shim the subreaper of the container processes and reap them on
+/// SIGCHLD, the exit code is reported to the watchers of the process the
+/// same way the agent does in the guest.
+pub(crate) fn start(containers: Arc<Mutex<HashMap<String, Container>>>) -> Result<()> {
+    let ret = unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) };
+    Errno::result(ret).context("set child subreaper")?;
+
+    let mut sigchild_stream = signal(SignalKind::child()).context("listen to SIGCHLD")?;
+    tokio::spawn(async move {
+        while sigchild_stream.recv().await.is_some() {
+            if let Err(err) = handle_sigchild(&containers).await {
+                warn!(sl!(), "failed to handle SIGCHLD {:?}", err);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_sigchild(containers: &Arc<Mutex<HashMap<String, Container>>>) -> Result<()> {
+    loop {
+        // Avoid reaping the children waited by rustjail, e.g. the hooks.
+        // The lock should be released immediately.
+        let _ = rustjail::container::WAIT_PID_LOCKER.lock().await;
+        let wait_status = match wait::waitpid(
+            Some(Pid::from_raw(-1)),
+            Some(WaitPidFlag::WNOHANG | WaitPidFlag::__WALL),
+        ) {
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return Ok(()),
+            Ok(s) => s,
+            Err(e) => return Err(anyhow!(e).context("waitpid reaper failed")),
+        };
+
+        let (pid, exit_code) = match wait_status {
+            WaitStatus::Exited(pid, code) => (pid.as_raw(), code),
+            WaitStatus::Signaled(pid, sig, _) => (pid.as_raw(), 128 + sig as i32),
+            _ => {
+                info!(sl!(), "got wrong status for process {:?}", wait_status);
+                continue;
+            }
+        };
+
+        let mut containers = containers.lock().await;
+        let process = containers
+            .values_mut()
+            .find_map(|c| c.jail.processes.remove(&pid));
+        let mut p = match process {
+            Some(p) => p,
+            None => {
+                info!(sl!(), "child {} exited unexpectedly", pid);
+                continue;
+            }
+        };
+        drop(containers);
+
+        info!(sl!(), "process {} exited with {}", pid, exit_code);
+        p.exit_code = exit_code;
+        for s in p.exit_watchers.iter_mut() {
+            let _ = s.send(exit_code).await;
+        }
+        let _ = p.exit_tx.take();
+        // close the socket file to notify readStdio to close terminal specifically
+        // in case this process's terminal has been inherited by its children.
+        p.notify_term_close();
+    }
+}
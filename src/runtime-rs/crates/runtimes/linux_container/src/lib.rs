@@ -3,14 +3,33 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 //
+
+#[macro_use]
+extern crate slog;
+
+logging::logger_with_subsystem!(sl, "linux-container");
+
+mod container_manager;
+pub mod sandbox;
+
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use common::{message::Message, RuntimeHandler, RuntimeInstance};
-use kata_types::config::TomlConfig;
+use kata_types::config::{TomlConfig, RUNTIME_NAME_LINUXCONTAINER};
 use tokio::sync::mpsc::Sender;
 
+/// Argument the shim binary is re-executed with by rustjail to set up the
+/// container process, see `init_child()`.
+pub const INIT_CHILD_ARG: &str = "init";
+
+/// Entry point of the container process set up by rustjail, the shim must
+/// call it and exit when it is started with `INIT_CHILD_ARG`.
+pub fn init_child() {
+    rustjail::container::init_child();
+}
+
 pub struct LinuxContainer {}
 
 #[async_trait]
@@ -20,7 +39,7 @@ impl RuntimeHandler for LinuxContainer {
     }
 
     fn name() -> String {
-        "linux_container".to_string()
+        RUNTIME_NAME_LINUXCONTAINER.to_string()
     }
 
     fn new_handler() -> Arc<dyn RuntimeHandler> {
@@ -29,14 +48,22 @@ impl RuntimeHandler for LinuxContainer {
 
     async fn new_instance(
         &self,
-        _sid: &str,
-        _msg_sender: Sender<Message>,
+        sid: &str,
+        msg_sender: Sender<Message>,
         _config: Arc<TomlConfig>,
     ) -> Result<RuntimeInstance> {
-        todo!()
+        let pid = std::process::id();
+
+        let sandbox = sandbox::LinuxSandbox::new(sid, msg_sender);
+        let container_manager = container_manager::LinuxContainerManager::new(sid, pid)
+            .context("new container manager")?;
+        Ok(RuntimeInstance {
+            sandbox: Arc::new(sandbox),
+            container_manager: Arc::new(container_manager),
+        })
     }
 
-    fn cleanup(&self, _id: &str) -> Result<()> {
-        todo!()
+    fn cleanup(&self, id: &str) -> Result<()> {
+        sandbox::cleanup_sandbox_dir(id).context("cleanup sandbox dir")
     }
 }
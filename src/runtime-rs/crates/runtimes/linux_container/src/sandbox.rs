@@ -0,0 +1,146 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{fs, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use common::{
+    message::{Action, Message},
+    Sandbox,
+};
+use kata_types::config::RUNTIME_NAME_LINUXCONTAINER;
+use safe_path::scoped_join;
+use serde::{Deserialize, Serialize};
+use shim_interface::KATA_PATH;
+use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+
+/// State persisted for the linux sandbox, only the sandbox type is needed to
+/// dispatch the cleanup of a sandbox whose shim is gone.
+#[derive(Serialize, Deserialize)]
+pub struct SandboxState {
+    pub sandbox_type: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SandboxStatus {
+    Init,
+    Running,
+    Stopped,
+}
+
+/// Sandbox of linux containers, the containers run directly on the host so
+/// there is no VM nor agent behind it.
+pub struct LinuxSandbox {
+    sid: String,
+    msg_sender: Arc<Mutex<Sender<Message>>>,
+    status: RwLock<SandboxStatus>,
+}
+
+impl LinuxSandbox {
+    pub fn new(sid: &str, msg_sender: Sender<Message>) -> Self {
+        Self {
+            sid: sid.to_string(),
+            msg_sender: Arc::new(Mutex::new(msg_sender)),
+            status: RwLock::new(SandboxStatus::Init),
+        }
+    }
+}
+
+#[async_trait]
+impl Sandbox for LinuxSandbox {
+    async fn start(&self, netns: Option<String>, _dns: Vec<String>) -> Result<()> {
+        let mut status = self.status.write().await;
+        if *status == SandboxStatus::Running {
+            warn!(sl!(), "sandbox is running, no need to start");
+            return Ok(());
+        }
+
+        // the containers join the netns through the namespaces of their spec
+        info!(sl!(), "start linux sandbox with netns {:?}", netns);
+
+        let sandbox_dir = scoped_join(KATA_PATH, &self.sid).context("sandbox dir")?;
+        fs::create_dir_all(&sandbox_dir)
+            .with_context(|| format!("create sandbox dir {:?}", &sandbox_dir))?;
+        let state = SandboxState {
+            sandbox_type: RUNTIME_NAME_LINUXCONTAINER.to_string(),
+        };
+        persist::to_disk(&state, &self.sid).context("save state")?;
+
+        *status = SandboxStatus::Running;
+        info!(sl!(), "linux sandbox {} started", &self.sid);
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<()> {
+        info!(sl!(), "begin stop sandbox");
+        let mut status = self.status.write().await;
+        *status = SandboxStatus::Stopped;
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        info!(sl!(), "shutdown");
+
+        self.stop().await.context("stop")?;
+        self.cleanup(&self.sid).await.context("cleanup")?;
+
+        // stop server
+        info!(sl!(), "send shutdown message");
+        let msg = Message::new(Action::Shutdown);
+        let sender = self.msg_sender.clone();
+        let sender = sender.lock().await;
+        sender.send(msg).await.context("send shutdown msg")?;
+        Ok(())
+    }
+
+    async fn cleanup(&self, id: &str) -> Result<()> {
+        cleanup_sandbox_dir(id)
+    }
+
+    async fn agent_sock(&self) -> Result<String> {
+        Err(anyhow!("linux sandbox has no agent"))
+    }
+
+    async fn set_iptables(&self, _is_ipv6: bool, _data: Vec<u8>) -> Result<Vec<u8>> {
+        Err(anyhow!("iptables are not supported by linux sandbox"))
+    }
+
+    async fn get_iptables(&self, _is_ipv6: bool) -> Result<Vec<u8>> {
+        Err(anyhow!("iptables are not supported by linux sandbox"))
+    }
+
+    async fn update_network(&self, _netns: &str) -> Result<()> {
+        Err(anyhow!("network update is not supported by linux sandbox"))
+    }
+
+    async fn direct_volume_stats(&self, _volume_device: &str) -> Result<String> {
+        Err(anyhow!("direct volumes are not supported by linux sandbox"))
+    }
+
+    async fn direct_volume_resize(&self, _resize_req: agent::ResizeVolumeRequest) -> Result<()> {
+        Err(anyhow!("direct volumes are not supported by linux sandbox"))
+    }
+
+    async fn agent_metrics(&self) -> Result<String> {
+        Err(anyhow!("agent metrics are not supported by linux sandbox"))
+    }
+
+    async fn hypervisor_metrics(&self) -> Result<String> {
+        Err(anyhow!(
+            "hypervisor metrics are not supported by linux sandbox"
+        ))
+    }
+}
+
+pub(crate) fn cleanup_sandbox_dir(sid: &str) -> Result<()> {
+    let sandbox_dir = scoped_join(KATA_PATH, sid).context("sandbox dir")?;
+    if sandbox_dir.exists() {
+        fs::remove_dir_all(&sandbox_dir)
+            .with_context(|| format!("remove sandbox dir {:?}", &sandbox_dir))?;
+    }
+    Ok(())
+}
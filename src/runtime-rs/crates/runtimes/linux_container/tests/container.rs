@@ -0,0 +1,218 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+// The container processes are set up by rustjail re-executing the current
+// binary with `INIT_CHILD_ARG`, so these tests run without the libtest
+// harness and dispatch it the way the shim does.
+
+use std::{fs, os::unix::fs::symlink, path::Path, sync::Arc};
+
+use common::{
+    types::{ContainerConfig, ContainerProcess, KillRequest, ProcessStatus},
+    ContainerManager, RuntimeHandler,
+};
+use kata_types::config::TomlConfig;
+use linux_container::{init_child, LinuxContainer, INIT_CHILD_ARG};
+use shim_interface::KATA_PATH;
+use tempfile::TempDir;
+use test_utils::skip_if_not_root;
+use tokio::sync::mpsc::channel;
+
+const SIGKILL: u32 = 9;
+
+fn main() {
+    if std::env::args().nth(1).as_deref() == Some(INIT_CHILD_ARG) {
+        init_child();
+        std::process::exit(0);
+    }
+
+    // rustjail needs root to set up the namespaces, mounts and cgroups
+    skip_if_not_root!();
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("build tokio runtime");
+    rt.block_on(run());
+}
+
+async fn run() {
+    // a single container manager is used, as each one reaps all the children
+    // of the process
+    let sid = format!("linux-container-test-{}", std::process::id());
+    // created by the shim before the sandbox starts
+    fs::create_dir_all(KATA_PATH).expect("create kata path");
+    let (sender, _receiver) = channel(1);
+    let instance = LinuxContainer::new_handler()
+        .new_instance(&sid, sender, Arc::new(TomlConfig::default()))
+        .await
+        .expect("new instance");
+    instance
+        .sandbox
+        .start(None, vec![])
+        .await
+        .expect("start sandbox");
+    let cm = instance.container_manager.as_ref();
+
+    test_container_not_found(cm).await;
+    test_create_invalid_spec(cm).await;
+    test_create_start_kill_state(cm).await;
+
+    instance
+        .sandbox
+        .cleanup(&sid)
+        .await
+        .expect("cleanup sandbox");
+    assert!(!Path::new(KATA_PATH).join(&sid).exists());
+}
+
+// The bundle rootfs is the host root, so that the process of the container
+// can be run from it. It is read-only in the container and the devices are
+// created in a tmpfs, nothing is written to the host root.
+fn new_bundle() -> (TempDir, ContainerConfig, oci::Spec) {
+    let bundle = tempfile::tempdir().expect("create bundle");
+    symlink("/", bundle.path().join("rootfs")).expect("link rootfs");
+
+    let config = ContainerConfig {
+        container_id: "container".to_string(),
+        bundle: bundle.path().display().to_string(),
+        rootfs_mounts: vec![],
+        terminal: false,
+        options: None,
+        stdin: None,
+        stdout: None,
+        stderr: None,
+    };
+    let spec = serde_json::from_str(
+        r#"{
+            "ociVersion": "1.0.2",
+            "process": {
+                "user": { "uid": 0, "gid": 0 },
+                "args": ["/bin/sleep", "1000"],
+                "env": ["PATH=/usr/sbin:/usr/bin:/sbin:/bin"],
+                "cwd": "/",
+                "capabilities": {
+                    "bounding": ["CAP_KILL"],
+                    "effective": ["CAP_KILL"],
+                    "inheritable": ["CAP_KILL"],
+                    "permitted": ["CAP_KILL"],
+                    "ambient": ["CAP_KILL"]
+                }
+            },
+            "root": { "path": "rootfs", "readonly": true },
+            "mounts": [
+                { "destination": "/proc", "type": "proc", "source": "proc" },
+                {
+                    "destination": "/dev",
+                    "type": "tmpfs",
+                    "source": "tmpfs",
+                    "options": ["nosuid", "mode=755"]
+                }
+            ],
+            "linux": {
+                "namespaces": [
+                    { "type": "pid" },
+                    { "type": "ipc" },
+                    { "type": "uts" },
+                    { "type": "mount" }
+                ]
+            }
+        }"#,
+    )
+    .expect("parse spec");
+    (bundle, config, spec)
+}
+
+async fn test_container_not_found(cm: &dyn ContainerManager) {
+    let process = ContainerProcess::new("unknown", "").unwrap();
+
+    cm.start_process(&process).await.unwrap_err();
+    cm.state_process(&process).await.unwrap_err();
+    cm.kill_process(&KillRequest {
+        process: process.clone(),
+        signal: SIGKILL,
+        all: false,
+    })
+    .await
+    .unwrap_err();
+    cm.delete_process(&process).await.unwrap_err();
+}
+
+async fn test_create_invalid_spec(cm: &dyn ContainerManager) {
+    let (_bundle, config, mut spec) = new_bundle();
+    spec.root = None;
+    let err = cm.create_container(config, spec).await.unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("no root in spec"),
+        "{:?}",
+        err
+    );
+
+    let (_bundle, mut config, spec) = new_bundle();
+    config.rootfs_mounts = vec![Default::default(), Default::default()];
+    let err = cm.create_container(config, spec).await.unwrap_err();
+    assert!(
+        format!("{:?}", err).contains("unsupported rootfs mounts count 2"),
+        "{:?}",
+        err
+    );
+
+    // nothing is left behind by the failed creations
+    cm.state_process(&ContainerProcess::new("container", "").unwrap())
+        .await
+        .unwrap_err();
+}
+
+async fn test_create_start_kill_state(cm: &dyn ContainerManager) {
+    let (_bundle, config, spec) = new_bundle();
+    let process = ContainerProcess::new(&config.container_id, "").unwrap();
+
+    // create
+    let pid = cm
+        .create_container(config.clone(), spec.clone())
+        .await
+        .expect("create container");
+    assert!(pid.pid > 0);
+    let state = cm.state_process(&process).await.expect("state");
+    assert_eq!(state.status, ProcessStatus::Created);
+    assert_eq!(state.pid.pid, pid.pid);
+    assert_eq!(state.bundle, config.bundle);
+    cm.create_container(config, spec).await.unwrap_err();
+
+    // start
+    let started = cm.start_process(&process).await.expect("start container");
+    assert_eq!(started.pid, pid.pid);
+    let state = cm.state_process(&process).await.expect("state");
+    assert_eq!(state.status, ProcessStatus::Running);
+
+    // kill
+    cm.kill_process(&KillRequest {
+        process: process.clone(),
+        signal: SIGKILL,
+        all: false,
+    })
+    .await
+    .expect("kill container");
+    let exit_status = cm.wait_process(&process).await.expect("wait container");
+    assert_eq!(exit_status.exit_code, 128 + SIGKILL as i32);
+
+    // state after the exit
+    let state = cm.state_process(&process).await.expect("state");
+    assert_eq!(state.status, ProcessStatus::Stopped);
+    assert_eq!(state.exit_status, 128 + SIGKILL as i32);
+    assert!(state.exited_at.is_some());
+
+    // the stopped process can still be killed, the container is then deleted
+    cm.kill_process(&KillRequest {
+        process: process.clone(),
+        signal: SIGKILL,
+        all: false,
+    })
+    .await
+    .expect("kill stopped container");
+    cm.delete_process(&process).await.expect("delete container");
+    cm.state_process(&process).await.unwrap_err();
+}
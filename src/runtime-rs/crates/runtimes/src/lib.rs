@@ -10,7 +10,11 @@ extern crate slog;
 logging::logger_with_subsystem!(sl, "runtimes");
 
 pub mod manager;
+#[cfg(feature = "linux")]
+pub use linux_container::{init_child, INIT_CHILD_ARG};
 pub use manager::RuntimeHandlerManager;
 pub use shim_interface;
+mod shim_metrics;
 mod shim_mgmt;
 mod static_resource;
+pub mod tracer;
@@ -8,7 +8,7 @@ use std::{str::from_utf8, sync::Arc};
 
 use anyhow::{anyhow, Context, Result};
 
-use crate::{shim_mgmt::server::MgmtServer, static_resource::StaticResourceManager};
+use crate::{shim_mgmt::server::MgmtServer, static_resource::StaticResourceManager, tracer};
 use common::{
     message::Message,
     types::{Request, Response},
@@ -33,6 +33,8 @@ use virt_container::{
 #[cfg(feature = "wasm")]
 use wasm_container::WasmContainer;
 
+const KATA_CONF_FILE: &str = "KATA_CONF_FILE";
+
 struct RuntimeHandlerManagerInner {
     id: String,
     msg_sender: Sender<Message>,
@@ -121,6 +123,7 @@ impl RuntimeHandlerManagerInner {
         }
 
         let config = load_config(spec, options).context("load config")?;
+        tracer::setup_tracing(&self.id, &config).context("setup tracing")?;
         self.init_runtime_handler(netns, dns, Arc::new(config))
             .await
             .context("init runtime handler")?;
@@ -163,31 +166,44 @@ impl RuntimeHandlerManager {
         let sender = inner.msg_sender.clone();
         let sandbox_state = persist::from_disk::<SandboxState>(&inner.id)
             .context("failed to load the sandbox state")?;
+        let toml_config = load_host_config();
+        // the spans of the cleanup are exported like the ones of the shim being cleaned up
+        if let Err(e) = tracer::setup_tracing(&inner.id, &toml_config) {
+            warn!(sl!(), "failed to setup tracing: {:?}", e);
+        }
         let sandbox_args = SandboxRestoreArgs {
             sid: inner.id.clone(),
-            toml_config: TomlConfig::default(),
+            toml_config,
             sender,
         };
         match sandbox_state.sandbox_type.clone() {
             #[cfg(feature = "linux")]
             name if name == LinuxContainer::name() => {
-                // TODO :support linux container (https://github.com/kata-containers/kata-containers/issues/4905)
-                return Ok(());
+                LinuxContainer::new_handler()
+                    .cleanup(&inner.id)
+                    .context("failed to cleanup the linux sandbox")?;
             }
             #[cfg(feature = "wasm")]
             name if name == WasmContainer::name() => {
-                // TODO :support wasm container (https://github.com/kata-containers/kata-containers/issues/4906)
-                return Ok(());
+                WasmContainer::new_handler()
+                    .cleanup(&inner.id)
+                    .context("failed to cleanup the wasm sandbox")?;
             }
             #[cfg(feature = "virt")]
             name if name == VirtContainer::name() => {
-                let sandbox = VirtSandbox::restore(sandbox_args, sandbox_state)
-                    .await
-                    .context("failed to restore the sandbox")?;
-                sandbox
-                    .cleanup(&inner.id)
-                    .await
-                    .context("failed to cleanup the resource")?;
+                match VirtSandbox::restore(sandbox_args, sandbox_state).await {
+                    Ok(sandbox) => sandbox
+                        .cleanup(&inner.id)
+                        .await
+                        .context("failed to cleanup the resource")?,
+                    Err(e) => {
+                        // the VM can't be reached without the state, still clean up the host
+                        warn!(sl!(), "failed to restore the sandbox: {:?}", e);
+                        VirtContainer::new_handler()
+                            .cleanup(&inner.id)
+                            .context("failed to cleanup the virt sandbox")?;
+                    }
+                }
             }
             _ => {
                 return Ok(());
@@ -340,7 +356,6 @@ impl RuntimeHandlerManager {
 /// TODO: https://github.com/kata-containers/kata-containers/issues/3961
 /// 3. environment
 fn load_config(spec: &oci::Spec, option: &Option<Vec<u8>>) -> Result<TomlConfig> {
-    const KATA_CONF_FILE: &str = "KATA_CONF_FILE";
     let annotation = Annotation::new(spec.annotations.clone());
     let config_path = if let Some(path) = annotation.get_sandbox_config_path() {
         path
@@ -386,6 +401,19 @@ fn load_config(spec: &oci::Spec, option: &Option<Vec<u8>>) -> Result<TomlConfig>
     Ok(toml_config)
 }
 
+// The config of the sandbox is not persisted, the cleanup of a crashed shim uses the config
+// on the host instead.
+fn load_host_config() -> TomlConfig {
+    let config_path = std::env::var(KATA_CONF_FILE).unwrap_or_default();
+    match TomlConfig::load_from_file(&config_path) {
+        Ok((toml_config, _)) => toml_config,
+        Err(e) => {
+            warn!(sl!(), "failed to load the config {}: {:?}", config_path, e);
+            TomlConfig::default()
+        }
+    }
+}
+
 // this update the agent-specfic kernel parameters into hypervisor's bootinfo
 // the agent inside the VM will read from file cmdline to get the params and function
 fn update_agent_kernel_params(config: &mut TomlConfig) -> Result<()> {
@@ -0,0 +1,180 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use prometheus::{
+    process_collector::ProcessCollector, Encoder, IntCounter, IntCounterVec, Opts, Registry,
+    TextEncoder,
+};
+
+const NAMESPACE_KATA_SHIM: &str = "kata_shim";
+const NAMESPACE_KATA_HYPERVISOR: &str = "kata_hypervisor";
+const SANDBOX_ID_LABEL: &str = "sandbox_id";
+
+/// ShimMetrics collects the metrics of the shim process and the hypervisor, all of them are
+/// labelled with the sandbox id.
+pub(crate) struct ShimMetrics {
+    sid: String,
+    registry: Registry,
+    scrape_count: IntCounter,
+    // hypervisor counters, indexed by the metrics group reported by the hypervisor
+    hypervisor_counters: Mutex<HashMap<String, IntCounterVec>>,
+}
+
+impl ShimMetrics {
+    pub(crate) fn new(sid: &str) -> Result<Self> {
+        let labels = HashMap::from([(SANDBOX_ID_LABEL.to_string(), sid.to_string())]);
+        let registry =
+            Registry::new_custom(None, Some(labels)).context("new shim metrics registry")?;
+
+        let process_collector =
+            ProcessCollector::new(std::process::id() as i32, NAMESPACE_KATA_SHIM);
+        registry
+            .register(Box::new(process_collector))
+            .context("register shim process metrics")?;
+
+        let scrape_count = IntCounter::new(
+            format!("{}_{}", NAMESPACE_KATA_SHIM, "scrape_count"),
+            "Metrics scrape count",
+        )
+        .context("new scrape count")?;
+        registry
+            .register(Box::new(scrape_count.clone()))
+            .context("register scrape count")?;
+
+        Ok(Self {
+            sid: sid.to_string(),
+            registry,
+            scrape_count,
+            hypervisor_counters: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// update the hypervisor counters with the json metrics reported by the hypervisor, which
+    /// are grouped as `{"group": {"item": increment}}`
+    pub(crate) fn update_hypervisor_metrics(&self, metrics: &str) -> Result<()> {
+        let groups: HashMap<String, HashMap<String, u64>> =
+            serde_json::from_str(metrics).context("parse hypervisor metrics")?;
+
+        let mut counters = self
+            .hypervisor_counters
+            .lock()
+            .map_err(|e| anyhow!("failed to lock hypervisor counters {:?}", e))?;
+        for (group, items) in groups {
+            if !counters.contains_key(&group) {
+                let counter = IntCounterVec::new(
+                    Opts::new(
+                        format!("{}_{}", NAMESPACE_KATA_HYPERVISOR, group),
+                        format!("Hypervisor {} statistics.", group),
+                    ),
+                    &["item"],
+                )
+                .with_context(|| format!("new hypervisor counter {}", group))?;
+                self.registry
+                    .register(Box::new(counter.clone()))
+                    .with_context(|| format!("register hypervisor counter {}", group))?;
+                counters.insert(group.clone(), counter);
+            }
+
+            // the hypervisor reports the increments since the last scrape
+            if let Some(counter) = counters.get(&group) {
+                for (item, inc) in items {
+                    counter.with_label_values(&[&item]).inc_by(inc);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// gather the shim and hypervisor metrics in the prometheus text format
+    pub(crate) fn gather(&self) -> Result<String> {
+        self.scrape_count.inc();
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("encode shim metrics")?;
+        String::from_utf8(buffer).context("shim metrics to string")
+    }
+
+    /// label the metrics in the prometheus text format, e.g. the agent metrics, with the
+    /// sandbox id
+    pub(crate) fn label_metrics(&self, metrics: &str) -> String {
+        add_label(metrics, SANDBOX_ID_LABEL, &self.sid)
+    }
+}
+
+// add a label to every sample of the metrics in the prometheus text format
+fn add_label(metrics: &str, key: &str, value: &str) -> String {
+    let mut labelled = String::with_capacity(metrics.len());
+    for line in metrics.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            labelled.push_str(line);
+        } else {
+            let name_end = trimmed
+                .find(|c: char| c == '{' || c.is_whitespace())
+                .unwrap_or(trimmed.len());
+            let (name, rest) = trimmed.split_at(name_end);
+            labelled.push_str(name);
+            match rest.strip_prefix('{') {
+                Some(labels) if labels.starts_with('}') => {
+                    labelled.push_str(&format!("{{{}=\"{}\"{}", key, value, labels));
+                }
+                Some(labels) => {
+                    labelled.push_str(&format!("{{{}=\"{}\",{}", key, value, labels));
+                }
+                None => {
+                    labelled.push_str(&format!("{{{}=\"{}\"}}{}", key, value, rest));
+                }
+            }
+        }
+        labelled.push('\n');
+    }
+    labelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_label() {
+        let metrics = r#"# HELP kata_agent_threads Agent process threads
+# TYPE kata_agent_threads gauge
+kata_agent_threads 5
+
+kata_guest_load{item="load1"} 0.1
+kata_guest_tasks{} 3
+"#;
+        let expected = r#"# HELP kata_agent_threads Agent process threads
+# TYPE kata_agent_threads gauge
+kata_agent_threads{sandbox_id="sid"} 5
+
+kata_guest_load{sandbox_id="sid",item="load1"} 0.1
+kata_guest_tasks{sandbox_id="sid"} 3
+"#;
+        assert_eq!(add_label(metrics, "sandbox_id", "sid"), expected);
+    }
+
+    #[test]
+    fn test_update_hypervisor_metrics() {
+        let metrics = ShimMetrics::new("sid").unwrap();
+        metrics
+            .update_hypervisor_metrics(r#"{"vcpu":{"exit_io_in":2,"failures":0}}"#)
+            .unwrap();
+        metrics
+            .update_hypervisor_metrics(r#"{"vcpu":{"exit_io_in":3,"failures":0}}"#)
+            .unwrap();
+
+        let text = metrics.gather().unwrap();
+        assert!(text.contains(r#"kata_hypervisor_vcpu{item="exit_io_in",sandbox_id="sid"} 5"#));
+        assert!(text.contains(r#"kata_shim_scrape_count{sandbox_id="sid"} 1"#));
+    }
+}
@@ -7,17 +7,24 @@
 // This defines the handlers corresponding to the url when a request is sent to destined url,
 // the handler function should be invoked, and the corresponding data will be in the response
 
-use anyhow::{anyhow, Result};
+use agent::ResizeVolumeRequest;
+use anyhow::{anyhow, Context, Result};
 use common::Sandbox;
 use hyper::{Body, Method, Request, Response, StatusCode};
 use std::sync::Arc;
 
-use shim_interface::shim_mgmt::{AGENT_URL, IP6_TABLE_URL, IP_TABLE_URL};
+use shim_interface::shim_mgmt::{
+    AGENT_URL, DIRECT_VOLUME_PATH_KEY, DIRECT_VOLUME_RESIZE_URL, DIRECT_VOLUME_STATS_URL,
+    IP6_TABLE_URL, IP_TABLE_URL, METRICS_URL, NETWORK_URL,
+};
+
+use crate::shim_metrics::ShimMetrics;
 
 // main router for response, this works as a multiplexer on
 // http arrival which invokes the corresponding handler function
 pub(crate) async fn handler_mux(
     sandbox: Arc<dyn Sandbox>,
+    metrics: Arc<ShimMetrics>,
     req: Request<Body>,
 ) -> Result<Response<Body>> {
     info!(
@@ -34,6 +41,12 @@ pub(crate) async fn handler_mux(
         (&Method::PUT, IP6_TABLE_URL) | (&Method::GET, IP6_TABLE_URL) => {
             ipv6_table_handler(sandbox, req).await
         }
+        (&Method::GET, DIRECT_VOLUME_STATS_URL) => direct_volume_stats_handler(sandbox, req).await,
+        (&Method::POST, DIRECT_VOLUME_RESIZE_URL) => {
+            direct_volume_resize_handler(sandbox, req).await
+        }
+        (&Method::PUT, NETWORK_URL) => network_handler(sandbox, req).await,
+        (&Method::GET, METRICS_URL) => metrics_url_handler(sandbox, metrics, req).await,
         _ => Ok(not_found(req).await),
     }
 }
@@ -101,3 +114,224 @@ async fn generic_ip_table_handler(
         _ => Err(anyhow!("IP Tables only takes PUT and GET")),
     }
 }
+
+/// the handler to get the stats of a direct volume, the block device of the volume on the host,
+/// as kata-ctl reads it from the mount info, is passed as the `path` query parameter
+async fn direct_volume_stats_handler(
+    sandbox: Arc<dyn Sandbox>,
+    req: Request<Body>,
+) -> Result<Response<Body>> {
+    let query = req.uri().query().unwrap_or_default();
+    let volume_path = url::form_urlencoded::parse(query.as_bytes())
+        .find(|(k, _)| k == DIRECT_VOLUME_PATH_KEY)
+        .map(|(_, v)| v.into_owned())
+        .ok_or_else(|| anyhow!("missing volume path in the query"))?;
+
+    let stats = sandbox
+        .direct_volume_stats(&volume_path)
+        .await
+        .context("get direct volume stats")?;
+    Response::builder()
+        .body(Body::from(stats))
+        .map_err(|e| anyhow!(e))
+}
+
+/// the handler to resize a direct volume, the request body is a json encoded
+/// `ResizeVolumeRequest` whose `volume_guest_path` is the block device of the volume on the host
+async fn direct_volume_resize_handler(
+    sandbox: Arc<dyn Sandbox>,
+    req: Request<Body>,
+) -> Result<Response<Body>> {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let resize_req: ResizeVolumeRequest =
+        serde_json::from_slice(&body).context("parse resize volume request")?;
+
+    sandbox
+        .direct_volume_resize(resize_req)
+        .await
+        .context("resize direct volume")?;
+    Response::builder()
+        .body(Body::from(""))
+        .map_err(|e| anyhow!(e))
+}
+
+/// the handler to update the sandbox network, the request body is the path of the netns, which
+/// is rescanned after CNI added or removed interfaces or the pod moved to another netns
+async fn network_handler(sandbox: Arc<dyn Sandbox>, req: Request<Body>) -> Result<Response<Body>> {
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    let netns = std::str::from_utf8(&body).context("parse netns path")?;
+    if netns.is_empty() {
+        return Err(anyhow!("missing netns path in the request"));
+    }
+
+    sandbox
+        .update_network(netns)
+        .await
+        .context("update network")?;
+    Response::builder()
+        .body(Body::from(""))
+        .map_err(|e| anyhow!(e))
+}
+
+/// the handler of metrics, returns the shim, hypervisor and guest metrics in the prometheus
+/// text format, a failure to get the metrics from one of them doesn't fail the others
+async fn metrics_url_handler(
+    sandbox: Arc<dyn Sandbox>,
+    metrics: Arc<ShimMetrics>,
+    _req: Request<Body>,
+) -> Result<Response<Body>> {
+    match sandbox.hypervisor_metrics().await {
+        Ok(hypervisor_metrics) => {
+            if let Err(err) = metrics.update_hypervisor_metrics(&hypervisor_metrics) {
+                warn!(sl!(), "failed to update hypervisor metrics: {:?}", err);
+            }
+        }
+        Err(err) => warn!(sl!(), "failed to get hypervisor metrics: {:?}", err),
+    }
+
+    let mut body = metrics.gather().context("gather shim metrics")?;
+    match sandbox.agent_metrics().await {
+        Ok(agent_metrics) => body.push_str(&metrics.label_metrics(&agent_metrics)),
+        Err(err) => warn!(sl!(), "failed to get agent metrics: {:?}", err),
+    }
+
+    Response::builder()
+        .body(Body::from(body))
+        .map_err(|e| anyhow!(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    // sandbox recording the direct volume requests it gets
+    #[derive(Default)]
+    struct MockSandbox {
+        stats_devices: Mutex<Vec<String>>,
+        resize_requests: Mutex<Vec<ResizeVolumeRequest>>,
+        netns_list: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Sandbox for MockSandbox {
+        async fn start(&self, _netns: Option<String>, _dns: Vec<String>) -> Result<()> {
+            Ok(())
+        }
+        async fn stop(&self) -> Result<()> {
+            Ok(())
+        }
+        async fn cleanup(&self, _container_id: &str) -> Result<()> {
+            Ok(())
+        }
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+        async fn agent_sock(&self) -> Result<String> {
+            Ok(String::new())
+        }
+        async fn set_iptables(&self, _is_ipv6: bool, data: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(data)
+        }
+        async fn get_iptables(&self, _is_ipv6: bool) -> Result<Vec<u8>> {
+            Ok(vec![])
+        }
+        async fn update_network(&self, netns: &str) -> Result<()> {
+            self.netns_list.lock().unwrap().push(netns.to_string());
+            Ok(())
+        }
+        async fn direct_volume_stats(&self, volume_device: &str) -> Result<String> {
+            self.stats_devices
+                .lock()
+                .unwrap()
+                .push(volume_device.to_string());
+            Ok(String::from(r#"{"usage":[]}"#))
+        }
+        async fn direct_volume_resize(&self, resize_req: ResizeVolumeRequest) -> Result<()> {
+            if resize_req.size == 0 {
+                return Err(anyhow!("invalid size"));
+            }
+            self.resize_requests.lock().unwrap().push(resize_req);
+            Ok(())
+        }
+        async fn agent_metrics(&self) -> Result<String> {
+            Ok(String::new())
+        }
+        async fn hypervisor_metrics(&self) -> Result<String> {
+            Ok(String::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_direct_volume_stats_handler() {
+        let sandbox = Arc::new(MockSandbox::default());
+
+        let uri = url::form_urlencoded::Serializer::new(format!("{}?", DIRECT_VOLUME_STATS_URL))
+            .append_pair(DIRECT_VOLUME_PATH_KEY, "/dev/sdb")
+            .finish();
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let resp = direct_volume_stats_handler(sandbox.clone(), req)
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        assert_eq!(body.as_ref(), br#"{"usage":[]}"#);
+        assert_eq!(*sandbox.stats_devices.lock().unwrap(), vec!["/dev/sdb"]);
+
+        let req = Request::get(DIRECT_VOLUME_STATS_URL)
+            .body(Body::empty())
+            .unwrap();
+        assert!(direct_volume_stats_handler(sandbox.clone(), req)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_direct_volume_resize_handler() {
+        let sandbox = Arc::new(MockSandbox::default());
+
+        let resize_req = ResizeVolumeRequest {
+            volume_guest_path: String::from("/dev/sdb"),
+            size: 1 << 30,
+        };
+        let req = Request::post(DIRECT_VOLUME_RESIZE_URL)
+            .body(Body::from(serde_json::to_string(&resize_req).unwrap()))
+            .unwrap();
+        direct_volume_resize_handler(sandbox.clone(), req)
+            .await
+            .unwrap();
+        assert_eq!(*sandbox.resize_requests.lock().unwrap(), vec![resize_req]);
+
+        // a bad request or a failed resize is an error
+        let req = Request::post(DIRECT_VOLUME_RESIZE_URL)
+            .body(Body::from("size"))
+            .unwrap();
+        assert!(direct_volume_resize_handler(sandbox.clone(), req)
+            .await
+            .is_err());
+        let req = Request::post(DIRECT_VOLUME_RESIZE_URL)
+            .body(Body::from(r#"{"volume_guest_path":"/dev/sdb","size":0}"#))
+            .unwrap();
+        assert!(direct_volume_resize_handler(sandbox.clone(), req)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_network_handler() {
+        let sandbox = Arc::new(MockSandbox::default());
+
+        let req = Request::put(NETWORK_URL)
+            .body(Body::from("/var/run/netns/cni-1234"))
+            .unwrap();
+        network_handler(sandbox.clone(), req).await.unwrap();
+        assert_eq!(
+            *sandbox.netns_list.lock().unwrap(),
+            vec!["/var/run/netns/cni-1234"]
+        );
+
+        let req = Request::put(NETWORK_URL).body(Body::empty()).unwrap();
+        assert!(network_handler(sandbox.clone(), req).await.is_err());
+    }
+}
@@ -20,6 +20,7 @@ use shim_interface::{mgmt_socket_addr, shim_mgmt::ERR_NO_SHIM_SERVER};
 use tokio::net::UnixListener;
 
 use super::handlers::handler_mux;
+use crate::shim_metrics::ShimMetrics;
 
 /// The shim management server instance
 pub struct MgmtServer {
@@ -28,6 +29,9 @@ pub struct MgmtServer {
 
     /// The sandbox instance
     pub sandbox: Arc<dyn Sandbox>,
+
+    /// The metrics of shim and hypervisor
+    metrics: Arc<ShimMetrics>,
 }
 
 impl MgmtServer {
@@ -36,12 +40,10 @@ impl MgmtServer {
         Ok(Self {
             s_addr: mgmt_socket_addr(sid).context(ERR_NO_SHIM_SERVER)?,
             sandbox,
+            metrics: Arc::new(ShimMetrics::new(sid).context("new shim metrics")?),
         })
     }
 
-    // TODO(when metrics is supported): write metric addresses to fs
-    // TODO(when metrics is supported): register shim metrics
-    // TODO(when metrics is supported): register sandbox metrics
     // running management http server in an infinite loop, able to serve concurrent requests
     pub async fn run(self: Arc<Self>) {
         let listener = listener_from_path(self.s_addr.clone()).await.unwrap();
@@ -54,7 +56,9 @@ impl MgmtServer {
                 if let Err(err) = Http::new()
                     .serve_connection(
                         stream,
-                        service_fn(|request| handler_mux(me.sandbox.clone(), request)),
+                        service_fn(|request| {
+                            handler_mux(me.sandbox.clone(), me.metrics.clone(), request)
+                        }),
                     )
                     .await
                 {
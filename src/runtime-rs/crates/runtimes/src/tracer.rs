@@ -0,0 +1,63 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use kata_types::config::TomlConfig;
+use opentelemetry::{global, sdk::propagation::TraceContextPropagator, KeyValue};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+const TRACING_SERVICE_NAME: &str = "kata";
+const DEFAULT_JAEGER_ENDPOINT: &str = "http://localhost:14268/api/traces";
+
+static TRACING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Setup the tracing of runtime-rs if it is enabled in the configuration, the spans are
+/// exported to the jaeger collector and the trace context is propagated to the agent.
+pub fn setup_tracing(sid: &str, config: &TomlConfig) -> Result<()> {
+    let runtime = &config.runtime;
+    if !runtime.enable_tracing || TRACING_ENABLED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let endpoint = if runtime.jaeger_endpoint.is_empty() {
+        DEFAULT_JAEGER_ENDPOINT
+    } else {
+        runtime.jaeger_endpoint.as_str()
+    };
+
+    let mut pipeline = opentelemetry_jaeger::new_pipeline()
+        .with_service_name(TRACING_SERVICE_NAME)
+        .with_tags(vec![KeyValue::new("sandbox_id", sid.to_string())])
+        .with_collector_endpoint(endpoint);
+    if !runtime.jaeger_user.is_empty() {
+        pipeline = pipeline
+            .with_collector_username(&runtime.jaeger_user)
+            .with_collector_password(&runtime.jaeger_password);
+    }
+    let tracer = pipeline
+        .install_batch(opentelemetry::runtime::Tokio)
+        .context("install jaeger pipeline")?;
+
+    let subscriber = Registry::default().with(OpenTelemetryLayer::new(tracer));
+    tracing::subscriber::set_global_default(subscriber).context("set tracing subscriber")?;
+
+    // the agent extracts the parent span from the ttrpc metadata in the w3c trace context format
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    TRACING_ENABLED.store(true, Ordering::SeqCst);
+    info!(sl!(), "tracing setup, jaeger endpoint {}", endpoint);
+    Ok(())
+}
+
+/// Flush the pending spans and stop the tracing.
+pub fn end_tracing() {
+    if TRACING_ENABLED.swap(false, Ordering::SeqCst) {
+        global::shutdown_tracer_provider();
+    }
+}
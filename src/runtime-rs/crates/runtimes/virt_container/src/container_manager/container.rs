@@ -140,15 +140,20 @@ impl Container {
         // TODO: handler device
 
         // update cgroups
+        let linux_resources = spec
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.resources.as_ref());
         self.resource_manager
-            .update_cgroups(
-                &config.container_id,
-                spec.linux
-                    .as_ref()
-                    .and_then(|linux| linux.resources.as_ref()),
-            )
+            .update_cgroups(&config.container_id, linux_resources)
             .await?;
 
+        // resize the sandbox memory for the memory limits of the container
+        self.resource_manager
+            .update_mem_resources(&config.container_id, linux_resources)
+            .await
+            .context("update memory resources")?;
+
         // create container
         let r = agent::CreateContainerRequest {
             process_id: agent::ContainerProcessID::new(&config.container_id, ""),
@@ -308,6 +313,21 @@ impl Container {
             .context("stop process")
     }
 
+    /// Remove the container from the guest if it exited by itself, and release its resources
+    /// on the host.
+    pub async fn cleanup(&self) -> Result<()> {
+        let mut inner = self.inner.write().await;
+        let container_id = self.container_id.container_id.clone();
+        inner
+            .cleanup_container(&container_id, true)
+            .await
+            .context("cleanup container")?;
+        self.resource_manager
+            .delete_mem_resources(&container_id)
+            .await
+            .context("delete memory resources")
+    }
+
     pub async fn pause(&self) -> Result<()> {
         let inner = self.inner.read().await;
         if inner.init_process.get_status().await == ProcessStatus::Paused {
@@ -370,6 +390,10 @@ impl Container {
         self.resource_manager
             .update_cgroups(&self.config.container_id, Some(resources))
             .await?;
+        self.resource_manager
+            .update_mem_resources(&self.config.container_id, Some(resources))
+            .await
+            .context("update memory resources")?;
 
         let req = agent::UpdateContainerRequest {
             container_id: self.container_id.container_id.clone(),
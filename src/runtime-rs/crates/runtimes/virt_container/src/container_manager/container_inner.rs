@@ -31,6 +31,8 @@ pub struct ContainerInner {
     pub(crate) exec_processes: HashMap<String, Exec>,
     pub(crate) rootfs: Vec<Arc<dyn Rootfs>>,
     pub(crate) volumes: Vec<Arc<dyn Volume>>,
+    // whether the container has been removed from the guest
+    removed: bool,
 }
 
 impl ContainerInner {
@@ -42,6 +44,7 @@ impl ContainerInner {
             exec_processes: HashMap::new(),
             rootfs: vec![],
             volumes: vec![],
+            removed: false,
         }
     }
 
@@ -157,32 +160,39 @@ impl ContainerInner {
         }
     }
 
-    async fn cleanup_container(&mut self, cid: &str, force: bool) -> Result<()> {
+    pub(crate) async fn cleanup_container(&mut self, cid: &str, force: bool) -> Result<()> {
         // wait until the container process
         // terminated and the status write lock released.
         info!(self.logger, "wait on container terminated");
         let exit_status = self.get_exit_status().await;
         let _locked_exit_status = exit_status.read().await;
         info!(self.logger, "container terminated");
-        let timeout: u32 = 10;
-        self.agent
-            .remove_container(agent::RemoveContainerRequest::new(cid, timeout))
-            .await
-            .or_else(|e| {
-                if force {
-                    warn!(
-                        self.logger,
-                        "stop container: agent remove container failed: {}", e
-                    );
-                    Ok(agent::Empty::new())
-                } else {
-                    Err(e)
-                }
-            })?;
+        if !self.removed {
+            let timeout: u32 = 10;
+            self.agent
+                .remove_container(agent::RemoveContainerRequest::new(cid, timeout))
+                .await
+                .or_else(|e| {
+                    if force {
+                        warn!(
+                            self.logger,
+                            "stop container: agent remove container failed: {}", e
+                        );
+                        Ok(agent::Empty::new())
+                    } else {
+                        Err(e)
+                    }
+                })?;
+            self.removed = true;
+        }
 
         // close the exit channel to wakeup wait service
         // send to notify watchers who are waiting for the process exit
         self.init_process.stop().await;
+
+        // the resources are released once the container is gone in the guest
+        self.clean_volumes().await.context("clean volumes")?;
+        self.clean_rootfs().await.context("clean rootfs")?;
         Ok(())
     }
 
@@ -248,9 +258,6 @@ impl ContainerInner {
             .signal_process(agent::SignalProcessRequest { process_id, signal })
             .await?;
 
-        self.clean_volumes().await.context("clean volumes")?;
-        self.clean_rootfs().await.context("clean rootfs")?;
-
         Ok(())
     }
 
@@ -286,9 +293,8 @@ impl ContainerInner {
                 );
             }
         }
-        if !unhandled.is_empty() {
-            self.volumes = unhandled;
-        }
+        // only the failed ones are cleaned up again
+        self.volumes = unhandled;
         Ok(())
     }
 
@@ -305,9 +311,7 @@ impl ContainerInner {
                 );
             }
         }
-        if !unhandled.is_empty() {
-            self.rootfs = unhandled;
-        }
+        self.rootfs = unhandled;
         Ok(())
     }
 }
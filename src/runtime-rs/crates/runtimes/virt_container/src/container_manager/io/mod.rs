@@ -6,5 +6,3 @@
 
 mod container_io;
 pub use container_io::ContainerIo;
-mod shim_io;
-pub use shim_io::ShimIo;
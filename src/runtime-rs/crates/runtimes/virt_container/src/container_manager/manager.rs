@@ -12,6 +12,7 @@ use agent::Agent;
 use async_trait::async_trait;
 use common::{
     error::Error,
+    message::{Action, Event, Message},
     types::{
         ContainerConfig, ContainerID, ContainerProcess, ExecProcessRequest, KillRequest,
         ProcessExitStatus, ProcessStateInfo, ProcessType, ResizePTYRequest, ShutdownRequest,
@@ -19,9 +20,13 @@ use common::{
     },
     ContainerManager,
 };
+use containerd_shim_protos::events::task::{
+    TaskCreate, TaskDelete, TaskExecAdded, TaskExecStarted, TaskExit, TaskPaused, TaskResumed,
+    TaskStart,
+};
 use oci::Process as OCIProcess;
 use resource::ResourceManager;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc::Sender, RwLock};
 
 use super::{logger_with_process, Container};
 
@@ -31,6 +36,7 @@ pub struct VirtContainerManager {
     containers: Arc<RwLock<HashMap<String, Container>>>,
     resource_manager: Arc<ResourceManager>,
     agent: Arc<dyn Agent>,
+    msg_sender: Sender<Message>,
 }
 
 impl VirtContainerManager {
@@ -39,6 +45,7 @@ impl VirtContainerManager {
         pid: u32,
         agent: Arc<dyn Agent>,
         resource_manager: Arc<ResourceManager>,
+        msg_sender: Sender<Message>,
     ) -> Self {
         Self {
             sid: sid.to_string(),
@@ -46,13 +53,68 @@ impl VirtContainerManager {
             containers: Default::default(),
             resource_manager,
             agent,
+            msg_sender,
         }
     }
+
+    // Publish the exit event of the process once it has exited.
+    async fn watch_process_exit(&self, process: &ContainerProcess) -> Result<()> {
+        let containers = self.containers.read().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        let (watcher, _) = c.wait_process(process).await.context("wait")?;
+        drop(containers);
+
+        let containers = self.containers.clone();
+        let sender = self.msg_sender.clone();
+        let process = process.clone();
+        tokio::spawn(async move {
+            let logger = logger_with_process(&process);
+            match watcher {
+                Some(mut watcher) => while watcher.changed().await.is_ok() {},
+                None => {
+                    warn!(logger, "failed to find watcher for exit event");
+                    return;
+                }
+            }
+
+            let containers = containers.read().await;
+            let state = match containers.get(&process.container_id.container_id) {
+                Some(c) => c.state_process(&process).await,
+                None => Err(Error::ContainerNotFound(process.container_id.to_string()).into()),
+            };
+            drop(containers);
+
+            match state {
+                Ok(state) => publish_event(&sender, TaskExit::from(state)).await,
+                Err(err) => warn!(logger, "failed to get exit state {:?}", err),
+            }
+        });
+
+        Ok(())
+    }
+}
+
+// Send the task event to the service manager, which forwards it to containerd.
+// The event is only for notifications, failing to send it doesn't fail the
+// operation.
+async fn publish_event<E: Event + 'static>(sender: &Sender<Message>, event: E) {
+    let topic = event.r#type();
+    let msg = Message::new(Action::Event(Arc::new(event)));
+    if let Err(err) = sender.send(msg).await {
+        warn!(sl!(), "failed to send event {}: {:?}", topic, err);
+    }
 }
 
 #[async_trait]
 impl ContainerManager for VirtContainerManager {
     async fn create_container(&self, config: ContainerConfig, spec: oci::Spec) -> Result<PID> {
+        let event = TaskCreate {
+            pid: self.pid,
+            ..TaskCreate::from(&config)
+        };
         let container = Container::new(
             self.pid,
             config,
@@ -64,7 +126,9 @@ impl ContainerManager for VirtContainerManager {
         let mut containers = self.containers.write().await;
         container.create(spec).await.context("create")?;
         containers.insert(container.container_id.to_string(), container);
+        drop(containers);
 
+        publish_event(&self.msg_sender, event).await;
         Ok(PID { pid: self.pid })
     }
 
@@ -85,9 +149,16 @@ impl ContainerManager for VirtContainerManager {
             ProcessType::Container => {
                 let mut containers = self.containers.write().await;
                 let c = containers
-                    .remove(container_id)
+                    .get(container_id)
                     .ok_or_else(|| Error::ContainerNotFound(container_id.to_string()))?;
-                c.state_process(process).await.context("state process")
+                let state = c.state_process(process).await.context("state process")?;
+                // keep the container to retry the deletion if the cleanup fails
+                c.cleanup().await.context("cleanup container")?;
+                containers.remove(container_id);
+                drop(containers);
+
+                publish_event(&self.msg_sender, TaskDelete::from(state.clone())).await;
+                Ok(state)
             }
             ProcessType::Exec => {
                 let containers = self.containers.read().await;
@@ -125,6 +196,14 @@ impl ContainerManager for VirtContainerManager {
         )
         .await
         .context("exec")?;
+        drop(containers);
+
+        let event = TaskExecAdded {
+            container_id: container_id.clone(),
+            exec_id: req.process.exec_id.clone(),
+            ..Default::default()
+        };
+        publish_event(&self.msg_sender, event).await;
         Ok(())
     }
 
@@ -190,6 +269,33 @@ impl ContainerManager for VirtContainerManager {
             .get(container_id)
             .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
         c.start(process).await.context("start")?;
+        drop(containers);
+
+        // the start event is published before the process is watched, so that it's not forwarded
+        // after the exit event of a process exiting right away
+        match process.process_type {
+            ProcessType::Container => {
+                let event = TaskStart {
+                    container_id: container_id.clone(),
+                    pid: self.pid,
+                    ..Default::default()
+                };
+                publish_event(&self.msg_sender, event).await;
+            }
+            ProcessType::Exec => {
+                let event = TaskExecStarted {
+                    container_id: container_id.clone(),
+                    exec_id: process.exec_id.clone(),
+                    pid: self.pid,
+                    ..Default::default()
+                };
+                publish_event(&self.msg_sender, event).await;
+            }
+        }
+
+        self.watch_process_exit(process)
+            .await
+            .context("watch process exit")?;
         Ok(PID { pid: self.pid })
     }
 
@@ -209,6 +315,13 @@ impl ContainerManager for VirtContainerManager {
             .get(&id.container_id)
             .ok_or_else(|| Error::ContainerNotFound(id.container_id.clone()))?;
         c.pause().await.context("pause")?;
+        drop(containers);
+
+        let event = TaskPaused {
+            container_id: id.container_id.clone(),
+            ..Default::default()
+        };
+        publish_event(&self.msg_sender, event).await;
         Ok(())
     }
 
@@ -218,6 +331,13 @@ impl ContainerManager for VirtContainerManager {
             .get(&id.container_id)
             .ok_or_else(|| Error::ContainerNotFound(id.container_id.clone()))?;
         c.resume().await.context("resume")?;
+        drop(containers);
+
+        let event = TaskResumed {
+            container_id: id.container_id.clone(),
+            ..Default::default()
+        };
+        publish_event(&self.msg_sender, event).await;
         Ok(())
     }
 
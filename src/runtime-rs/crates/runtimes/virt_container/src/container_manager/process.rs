@@ -9,16 +9,16 @@ use std::sync::Arc;
 use agent::Agent;
 use anyhow::{Context, Result};
 use awaitgroup::{WaitGroup, Worker as WaitGroupWorker};
-use common::types::{ContainerProcess, ProcessExitStatus, ProcessStateInfo, ProcessStatus, PID};
+use common::{
+    types::{ContainerProcess, ProcessExitStatus, ProcessStateInfo, ProcessStatus, PID},
+    ShimIo,
+};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     sync::{watch, RwLock},
 };
 
-use super::{
-    io::{ContainerIo, ShimIo},
-    logger_with_process,
-};
+use super::{io::ContainerIo, logger_with_process};
 
 pub type ProcessWatcher = (
     Option<watch::Receiver<bool>>,
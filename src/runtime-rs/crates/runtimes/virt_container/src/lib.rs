@@ -84,24 +84,31 @@ impl RuntimeHandler for VirtContainer {
 
         let sandbox = sandbox::VirtSandbox::new(
             sid,
-            msg_sender,
+            msg_sender.clone(),
             agent.clone(),
             hypervisor,
             resource_manager.clone(),
         )
         .await
         .context("new virt sandbox")?;
-        let container_manager =
-            container_manager::VirtContainerManager::new(sid, pid, agent, resource_manager);
+        let container_manager = container_manager::VirtContainerManager::new(
+            sid,
+            pid,
+            agent,
+            resource_manager,
+            msg_sender,
+        );
         Ok(RuntimeInstance {
             sandbox: Arc::new(sandbox),
             container_manager: Arc::new(container_manager),
         })
     }
 
-    fn cleanup(&self, _id: &str) -> Result<()> {
-        // TODO
-        Ok(())
+    fn cleanup(&self, id: &str) -> Result<()> {
+        // the VM is cleaned up with the persisted sandbox state, only the host files of the
+        // sandbox are left to clean up without it
+        resource::share_fs::cleanup_sandbox_share_dir(id).context("cleanup sandbox share dir")?;
+        resource::volume::cleanup_sandbox_id_files(id).context("cleanup direct volume sandbox id")
     }
 }
 
@@ -145,7 +152,7 @@ async fn new_hypervisor(toml_config: &TomlConfig) -> Result<Arc<dyn Hypervisor>>
     }
 }
 
-fn new_agent(toml_config: &TomlConfig) -> Result<Arc<KataAgent>> {
+pub(crate) fn new_agent(toml_config: &TomlConfig) -> Result<Arc<KataAgent>> {
     let agent_name = &toml_config.runtime.agent_name;
     let agent_config = toml_config
         .agent
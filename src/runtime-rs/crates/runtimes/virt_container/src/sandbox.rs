@@ -7,7 +7,8 @@
 use std::sync::Arc;
 
 use agent::{
-    self, kata::KataAgent, types::KernelModule, Agent, GetIPTablesRequest, SetIPTablesRequest,
+    self, types::KernelModule, Agent, Empty, GetIPTablesRequest, ResizeVolumeRequest,
+    SetIPTablesRequest, VolumeStatsRequest,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
@@ -16,19 +17,22 @@ use common::{
     Sandbox,
 };
 use containerd_shim_protos::events::task::TaskOOM;
-use hypervisor::{dragonball::Dragonball, Hypervisor, HYPERVISOR_DRAGONBALL};
-use kata_types::config::{
-    default::{DEFAULT_AGENT_LOG_PORT, DEFAULT_AGENT_VSOCK_PORT},
-    TomlConfig,
+use hypervisor::{
+    dragonball::Dragonball, qemu::Qemu, Hypervisor, HYPERVISOR_DRAGONBALL, HYPERVISOR_QEMU,
 };
+use kata_types::config::TomlConfig;
 use resource::{
     manager::ManagerArgs,
     network::{NetworkConfig, NetworkWithNetNsConfig},
     ResourceConfig, ResourceManager,
 };
 use tokio::sync::{mpsc::Sender, Mutex, RwLock};
+use tracing::instrument;
 
-use crate::health_check::HealthCheck;
+#[cfg(feature = "cloud-hypervisor")]
+use hypervisor::{ch::CloudHypervisor, HYPERVISOR_NAME_CH};
+
+use crate::{health_check::HealthCheck, new_agent};
 use persist::{self, sandbox_persist::Persist};
 
 pub(crate) const VIRTCONTAINER: &str = "virt_container";
@@ -94,20 +98,8 @@ impl VirtSandbox {
     ) -> Result<Vec<ResourceConfig>> {
         let mut resource_configs = vec![];
 
-        let config = self.resource_manager.config().await;
         if let Some(netns_path) = netns {
-            let network_config = ResourceConfig::Network(NetworkConfig::NetworkResourceWithNetNs(
-                NetworkWithNetNsConfig {
-                    network_model: config.runtime.internetworking_model.clone(),
-                    netns_path,
-                    queues: self
-                        .hypervisor
-                        .hypervisor_config()
-                        .await
-                        .network_info
-                        .network_queues as usize,
-                },
-            ));
+            let network_config = ResourceConfig::Network(self.network_config(netns_path).await);
             resource_configs.push(network_config);
         }
 
@@ -117,10 +109,25 @@ impl VirtSandbox {
 
         Ok(resource_configs)
     }
+
+    async fn network_config(&self, netns_path: String) -> NetworkConfig {
+        let config = self.resource_manager.config().await;
+        NetworkConfig::NetworkResourceWithNetNs(NetworkWithNetNsConfig {
+            network_model: config.runtime.internetworking_model.clone(),
+            netns_path,
+            queues: self
+                .hypervisor
+                .hypervisor_config()
+                .await
+                .network_info
+                .network_queues as usize,
+        })
+    }
 }
 
 #[async_trait]
 impl Sandbox for VirtSandbox {
+    #[instrument(skip_all)]
     async fn start(&self, netns: Option<String>, dns: Vec<String>) -> Result<()> {
         let id = &self.sid;
 
@@ -229,12 +236,14 @@ impl Sandbox for VirtSandbox {
         Ok(())
     }
 
+    #[instrument(skip_all)]
     async fn stop(&self) -> Result<()> {
         info!(sl!(), "begin stop sandbox");
         self.hypervisor.stop_vm().await.context("stop vm")?;
         Ok(())
     }
 
+    #[instrument(skip_all)]
     async fn shutdown(&self) -> Result<()> {
         info!(sl!(), "shutdown");
 
@@ -252,6 +261,12 @@ impl Sandbox for VirtSandbox {
             .await
             .context("delete hypervisor")?;
 
+        // the leftovers are cleaned up again by `shim delete`, don't block the shutdown
+        info!(sl!(), "cleanup resources");
+        if let Err(e) = self.resource_manager.cleanup().await {
+            warn!(sl!(), "failed to cleanup resources: {:?}", e);
+        }
+
         info!(sl!(), "stop monitor");
         self.monitor.stop().await;
 
@@ -267,10 +282,20 @@ impl Sandbox for VirtSandbox {
         Ok(())
     }
 
+    #[instrument(skip_all)]
     async fn cleanup(&self, _id: &str) -> Result<()> {
-        self.resource_manager.delete_cgroups().await?;
-        self.hypervisor.cleanup().await?;
-        // TODO: cleanup other snadbox resource
+        self.resource_manager
+            .delete_cgroups()
+            .await
+            .context("delete cgroups")?;
+        self.hypervisor
+            .cleanup()
+            .await
+            .context("delete hypervisor")?;
+        self.resource_manager
+            .cleanup()
+            .await
+            .context("cleanup resources")?;
         Ok(())
     }
 
@@ -299,6 +324,74 @@ impl Sandbox for VirtSandbox {
             .context("sandbox: failed to get iptables")?;
         Ok(resp.data)
     }
+
+    async fn update_network(&self, netns: &str) -> Result<()> {
+        info!(sl!(), "sb: update_network invoked {}", netns);
+        let network_config = self.network_config(netns.to_string()).await;
+        self.resource_manager
+            .handle_network(network_config)
+            .await
+            .context("handle network")
+    }
+
+    async fn direct_volume_stats(&self, volume_device: &str) -> Result<String> {
+        info!(
+            sl!(),
+            "sb: direct_volume_stats invoked for {}", volume_device
+        );
+        let device = self
+            .resource_manager
+            .get_direct_volume_device(volume_device)
+            .await
+            .context("sandbox: failed to find direct volume")?;
+        let req = VolumeStatsRequest {
+            volume_guest_path: device.guest_path,
+        };
+        let resp = self
+            .agent
+            .get_volume_stats(req)
+            .await
+            .context("sandbox: failed to get direct volume stats")?;
+        serde_json::to_string(&resp).context("sandbox: failed to serialize direct volume stats")
+    }
+
+    async fn direct_volume_resize(&self, resize_req: ResizeVolumeRequest) -> Result<()> {
+        info!(sl!(), "sb: direct_volume_resize invoked {:?}", resize_req);
+        // the request carries the block device of the host, like the stats one
+        let device = self
+            .resource_manager
+            .get_direct_volume_device(&resize_req.volume_guest_path)
+            .await
+            .context("sandbox: failed to find direct volume")?;
+        self.hypervisor
+            .resize_block_device(&device.device_id, resize_req.size)
+            .await
+            .context("sandbox: failed to resize direct volume device")?;
+        self.agent
+            .resize_volume(ResizeVolumeRequest {
+                volume_guest_path: device.guest_path,
+                size: resize_req.size,
+            })
+            .await
+            .context("sandbox: failed to resize direct volume")?;
+        Ok(())
+    }
+
+    async fn agent_metrics(&self) -> Result<String> {
+        let resp = self
+            .agent
+            .get_metrics(Empty::new())
+            .await
+            .context("sandbox: failed to get agent metrics")?;
+        Ok(resp.metrics)
+    }
+
+    async fn hypervisor_metrics(&self) -> Result<String> {
+        self.hypervisor
+            .get_hypervisor_metrics()
+            .await
+            .context("sandbox: failed to get hypervisor metrics")
+    }
 }
 
 #[async_trait]
@@ -324,24 +417,14 @@ impl Persist for VirtSandbox {
         let config = sandbox_args.toml_config;
         let r = sandbox_state.resource.unwrap_or_default();
         let h = sandbox_state.hypervisor.unwrap_or_default();
-        let hypervisor = match h.hypervisor_type.as_str() {
-            // TODO support other hypervisors
-            HYPERVISOR_DRAGONBALL => Ok(Arc::new(Dragonball::restore((), h).await?)),
-            _ => Err(anyhow!("Unsupported hypervisor {}", &h.hypervisor_type)),
-        }?;
-        let agent = Arc::new(KataAgent::new(kata_types::config::Agent {
-            debug: true,
-            enable_tracing: false,
-            server_port: DEFAULT_AGENT_VSOCK_PORT,
-            log_port: DEFAULT_AGENT_LOG_PORT,
-            dial_timeout_ms: 10,
-            reconnect_timeout_ms: 3_000,
-            request_timeout_ms: 30_000,
-            health_check_request_timeout_ms: 90_000,
-            kernel_modules: Default::default(),
-            container_pipe_size: 0,
-            debug_console_enabled: false,
-        }));
+        let hypervisor: Arc<dyn Hypervisor> = match h.hypervisor_type.as_str() {
+            HYPERVISOR_DRAGONBALL => Arc::new(Dragonball::restore((), h).await?),
+            HYPERVISOR_QEMU => Arc::new(Qemu::restore((), h).await?),
+            #[cfg(feature = "cloud-hypervisor")]
+            HYPERVISOR_NAME_CH => Arc::new(CloudHypervisor::restore((), h).await?),
+            _ => return Err(anyhow!("Unsupported hypervisor {}", &h.hypervisor_type)),
+        };
+        let agent = new_agent(&config).context("new agent")?;
         let sid = sandbox_args.sid;
         let args = ManagerArgs {
             sid: sid.clone(),
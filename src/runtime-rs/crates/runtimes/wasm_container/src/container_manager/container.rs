@@ -0,0 +1,478 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use common::{
+    types::{ContainerConfig, ProcessExitStatus, ProcessStateInfo, ProcessStatus, PID},
+    ShimIo,
+};
+use kata_sys_util::mount::{umount_timeout, Mounter};
+use kata_types::{container::ContainerType, k8s};
+use safe_path::scoped_join;
+use tokio::{
+    sync::{oneshot, watch, Mutex, Notify, RwLock},
+    task::JoinHandle,
+};
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasmtime::{Config, Engine, Linker, Module, Store, Trap};
+use wasmtime_wasi::{
+    sync::{ambient_authority, Dir, WasiCtxBuilder},
+    I32Exit, WasiCtx,
+};
+
+use super::{
+    io::{copy_output, copy_stdin},
+    ProcessWatcher,
+};
+
+const ROOTFS: &str = "rootfs";
+
+/// Exit code of a module stopped by a trap, the same as a process aborted
+/// by SIGABRT.
+const WASM_TRAP_EXIT_CODE: i32 = 128 + libc::SIGABRT;
+
+/// WebAssembly module of a container, compiled with its own engine so that
+/// bumping the epoch to interrupt it does not disturb other containers.
+struct WasmModule {
+    engine: Engine,
+    module: Module,
+}
+
+impl WasmModule {
+    async fn load(path: PathBuf) -> Result<Self> {
+        tokio::task::spawn_blocking(move || {
+            let mut config = Config::new();
+            config.epoch_interruption(true);
+            let engine = Engine::new(&config).context("new engine")?;
+            let module = Module::from_file(&engine, &path)
+                .with_context(|| format!("compile module {:?}", &path))?;
+            Ok(Self { engine, module })
+        })
+        .await
+        .context("join compile task")?
+    }
+
+    /// Run the default export of the module, blocking until it returns or
+    /// is interrupted. `ready` is notified once the module can be interrupted.
+    fn run(&self, wasi: WasiCtx, ready: oneshot::Sender<()>) -> Result<()> {
+        let mut linker = Linker::new(&self.engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |cx| cx).context("add wasi to linker")?;
+
+        let mut store = Store::new(&self.engine, wasi);
+        // trap on the first epoch increment, which is done by kill
+        store.set_epoch_deadline(1);
+        let _ = ready.send(());
+
+        linker
+            .module(&mut store, "", &self.module)
+            .context("instantiate module")?;
+        linker
+            .get_default(&mut store, "")
+            .context("get default export")?
+            .typed::<(), ()>(&store)
+            .context("typed default export")?
+            .call(&mut store, ())
+    }
+
+    fn interrupt(&self) {
+        self.engine.increment_epoch();
+    }
+}
+
+fn exit_code(logger: &slog::Logger, result: Result<()>, signal: u32) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            if let Some(exit) = err.downcast_ref::<I32Exit>() {
+                return exit.0;
+            }
+            if let Some(Trap::Interrupt) = err.downcast_ref::<Trap>() {
+                return 128 + signal as i32;
+            }
+            warn!(logger, "module stopped with error {:?}", err);
+            WASM_TRAP_EXIT_CODE
+        }
+    }
+}
+
+pub struct Container {
+    pub container_id: String,
+    pid: u32,
+    logger: slog::Logger,
+    config: ContainerConfig,
+
+    rootfs: PathBuf,
+    rootfs_mounted: bool,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+    // none for the pod sandbox container, which only waits to be killed
+    module: Option<Arc<WasmModule>>,
+
+    status: Arc<RwLock<ProcessStatus>>,
+    exit_status: Arc<RwLock<ProcessExitStatus>>,
+    exit_watcher_rx: Option<watch::Receiver<bool>>,
+    exit_watcher_tx: Mutex<Option<watch::Sender<bool>>>,
+    stdin_task: Mutex<Option<JoinHandle<()>>>,
+    kill_signal: Arc<AtomicU32>,
+    kill_notify: Arc<Notify>,
+}
+
+impl Container {
+    pub async fn new(pid: u32, config: ContainerConfig, spec: oci::Spec) -> Result<Self> {
+        let container_id = config.container_id.clone();
+        let logger = sl!().new(o!("container_id" => container_id.clone()));
+
+        let root = spec
+            .root
+            .as_ref()
+            .map(|r| r.path.as_str())
+            .filter(|p| !p.is_empty())
+            .unwrap_or(ROOTFS);
+        let rootfs = Path::new(&config.bundle).join(root);
+
+        let rootfs_mounted = match config.rootfs_mounts.as_slice() {
+            [] => false,
+            [mount] => {
+                mount
+                    .mount(&rootfs)
+                    .with_context(|| format!("mount rootfs from {:?} to {:?}", mount, &rootfs))?;
+                true
+            }
+            mounts => return Err(anyhow!("unsupported rootfs mounts count {}", mounts.len())),
+        };
+
+        let mut container = Self {
+            container_id,
+            pid,
+            logger,
+            config,
+            rootfs,
+            rootfs_mounted,
+            args: vec![],
+            envs: vec![],
+            module: None,
+            status: Arc::new(RwLock::new(ProcessStatus::Created)),
+            exit_status: Arc::new(RwLock::new(ProcessExitStatus::new())),
+            exit_watcher_rx: None,
+            exit_watcher_tx: Mutex::new(None),
+            stdin_task: Mutex::new(None),
+            kill_signal: Arc::new(AtomicU32::new(0)),
+            kill_notify: Arc::new(Notify::new()),
+        };
+        if let Err(err) = container.load(&spec).await {
+            container.cleanup().await;
+            return Err(err);
+        }
+
+        let (sender, receiver) = watch::channel(false);
+        container.exit_watcher_rx = Some(receiver);
+        container.exit_watcher_tx = Mutex::new(Some(sender));
+        Ok(container)
+    }
+
+    async fn load(&mut self, spec: &oci::Spec) -> Result<()> {
+        if k8s::container_type(spec) == ContainerType::PodSandbox {
+            info!(self.logger, "pod sandbox container, no module to load");
+            return Ok(());
+        }
+
+        let process = spec
+            .process
+            .as_ref()
+            .ok_or_else(|| anyhow!("no process in spec"))?;
+        let module_path = process
+            .args
+            .first()
+            .ok_or_else(|| anyhow!("no module in process args"))?;
+        let module_path = scoped_join(&self.rootfs, module_path).context("module path")?;
+        info!(self.logger, "load module {:?}", &module_path);
+
+        self.module = Some(Arc::new(
+            WasmModule::load(module_path).await.context("load module")?,
+        ));
+        self.args = process.args.clone();
+        self.envs = process
+            .env
+            .iter()
+            .filter_map(|e| e.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Ok(())
+    }
+
+    fn wasi_builder(&self) -> Result<WasiCtxBuilder> {
+        let rootfs = Dir::open_ambient_dir(&self.rootfs, ambient_authority())
+            .with_context(|| format!("open rootfs {:?}", &self.rootfs))?;
+        WasiCtxBuilder::new()
+            .args(&self.args)
+            .context("set args")?
+            .envs(&self.envs)
+            .context("set envs")?
+            .preopened_dir(rootfs, "/")
+            .context("preopen rootfs")
+    }
+
+    pub async fn start(&self) -> Result<()> {
+        let status = self.get_status().await;
+        if status != ProcessStatus::Created {
+            return Err(anyhow!("container is in {:?} status, cannot start", status));
+        }
+
+        let shim_io = ShimIo::new(&self.config.stdin, &self.config.stdout, &self.config.stderr)
+            .await
+            .context("new shim io")?;
+        let kill_signal = self.kill_signal.clone();
+        let exit_status = self.exit_status.clone();
+        let process_status = self.status.clone();
+        let logger = self.logger.clone();
+
+        let module = match &self.module {
+            Some(module) => module.clone(),
+            None => {
+                let kill_notify = self.kill_notify.clone();
+                let exit_notifier = self.exit_watcher_tx.lock().await.take();
+                self.set_status(ProcessStatus::Running).await;
+                tokio::spawn(async move {
+                    kill_notify.notified().await;
+                    let signal = kill_signal.load(Ordering::SeqCst);
+                    exit_status
+                        .write()
+                        .await
+                        .update_exit_code(128 + signal as i32);
+                    *process_status.write().await = ProcessStatus::Stopped;
+                    drop(exit_notifier);
+                    info!(logger, "pod sandbox container exited");
+                });
+                return Ok(());
+            }
+        };
+
+        let mut builder = self.wasi_builder()?;
+        if let Some(stdin) = shim_io.stdin {
+            let (reader, task) = copy_stdin(logger.new(o!("io_name" => "stdin")), stdin);
+            builder = builder.stdin(Box::new(ReadPipe::new(reader)));
+            *self.stdin_task.lock().await = Some(task);
+        } else {
+            builder = builder.stdin(Box::new(ReadPipe::new(std::io::empty())));
+        }
+
+        let mut output_tasks = vec![];
+        let stdout = match shim_io.stdout {
+            Some(stdout) => {
+                let (writer, task) = copy_output(logger.new(o!("io_name" => "stdout")), stdout);
+                output_tasks.push(task);
+                Some(writer)
+            }
+            None => None,
+        };
+        // with a terminal, stderr shares the stream of stdout
+        let stderr = match shim_io.stderr {
+            Some(stderr) if !self.config.terminal => {
+                let (writer, task) = copy_output(logger.new(o!("io_name" => "stderr")), stderr);
+                output_tasks.push(task);
+                Some(writer)
+            }
+            _ if self.config.terminal => stdout.clone(),
+            _ => None,
+        };
+        builder = match stdout {
+            Some(w) => builder.stdout(Box::new(WritePipe::new(w))),
+            None => builder.stdout(Box::new(WritePipe::new(std::io::sink()))),
+        };
+        builder = match stderr {
+            Some(w) => builder.stderr(Box::new(WritePipe::new(w))),
+            None => builder.stderr(Box::new(WritePipe::new(std::io::sink()))),
+        };
+        let wasi = builder.build();
+
+        // the container is running once the module can be interrupted, a kill in between would
+        // bump the epoch before the deadline is set and be lost. The status is locked until then,
+        // so that the kill waits and the exit status is not overwritten.
+        let mut status = self.status.write().await;
+        if *status != ProcessStatus::Created {
+            return Err(anyhow!(
+                "container is in {:?} status, cannot start",
+                *status
+            ));
+        }
+        let exit_notifier = self.exit_watcher_tx.lock().await.take();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            info!(logger, "begin run module");
+            let result = match tokio::task::spawn_blocking(move || module.run(wasi, ready_tx)).await
+            {
+                Ok(result) => result,
+                Err(e) => Err(anyhow!("join module task: {}", e)),
+            };
+            let exit_code = exit_code(&logger, result, kill_signal.load(Ordering::SeqCst));
+            info!(logger, "end run module exit code {}", exit_code);
+
+            // the module side of the pipes is dropped with the wasi context,
+            // wait for the remaining output to be copied to the shim io.
+            for task in output_tasks {
+                let _ = task.await;
+            }
+
+            exit_status.write().await.update_exit_code(exit_code);
+            *process_status.write().await = ProcessStatus::Stopped;
+            drop(exit_notifier);
+            info!(logger, "end module wait thread");
+        });
+        // the sender is dropped if the module fails before it runs
+        let _ = ready_rx.await;
+        *status = ProcessStatus::Running;
+
+        Ok(())
+    }
+
+    pub async fn kill(&self, signal: u32) -> Result<()> {
+        // signal 0 only checks that the process exists
+        if signal == 0 {
+            return Ok(());
+        }
+        // a module can't handle signals, only the ones terminating a process stop it
+        if signal != libc::SIGKILL as u32 && signal != libc::SIGTERM as u32 {
+            info!(
+                self.logger,
+                "ignore signal {} unsupported by module", signal
+            );
+            return Ok(());
+        }
+
+        match self.get_status().await {
+            ProcessStatus::Created => {
+                info!(self.logger, "kill container before start");
+                self.exit_status
+                    .write()
+                    .await
+                    .update_exit_code(128 + signal as i32);
+                self.set_status(ProcessStatus::Stopped).await;
+                drop(self.exit_watcher_tx.lock().await.take());
+            }
+            ProcessStatus::Running => {
+                info!(self.logger, "kill container with signal {}", signal);
+                self.kill_signal.store(signal, Ordering::SeqCst);
+                match &self.module {
+                    Some(module) => module.interrupt(),
+                    None => self.kill_notify.notify_one(),
+                }
+                // unblock a module waiting for its stdin
+                if let Some(task) = self.stdin_task.lock().await.take() {
+                    task.abort();
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub async fn close_io(&self) {
+        // wait for the stdin copy to finish so that no stdin data is lost
+        if let Some(task) = self.stdin_task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+
+    pub fn fetch_exit_watcher(&self) -> ProcessWatcher {
+        (self.exit_watcher_rx.clone(), self.exit_status.clone())
+    }
+
+    pub async fn state(&self) -> ProcessStateInfo {
+        let exit_status = self.exit_status.read().await;
+        ProcessStateInfo {
+            container_id: self.container_id.clone(),
+            exec_id: "".to_string(),
+            pid: PID { pid: self.pid },
+            bundle: self.config.bundle.clone(),
+            stdin: self.config.stdin.clone(),
+            stdout: self.config.stdout.clone(),
+            stderr: self.config.stderr.clone(),
+            terminal: self.config.terminal,
+            status: self.get_status().await,
+            exit_status: exit_status.exit_code,
+            exited_at: exit_status.exit_time,
+        }
+    }
+
+    pub async fn cleanup(&self) {
+        if self.get_status().await == ProcessStatus::Running {
+            let _ = self.kill(libc::SIGKILL as u32).await;
+        }
+
+        if self.rootfs_mounted {
+            if let Err(e) = umount_timeout(&self.rootfs, 0) {
+                warn!(self.logger, "failed to umount rootfs {:?}", e);
+            }
+        }
+    }
+
+    pub async fn get_status(&self) -> ProcessStatus {
+        *self.status.read().await
+    }
+
+    async fn set_status(&self, new_status: ProcessStatus) {
+        *self.status.write().await = new_status;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_wat(wat: &str) -> WasmModule {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).unwrap();
+        let module = Module::new(&engine, wat).unwrap();
+        WasmModule { engine, module }
+    }
+
+    #[test]
+    fn test_module_exit_code() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+
+        let module = load_wat(
+            r#"(module
+                (import "wasi_snapshot_preview1" "proc_exit" (func $exit (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start") (call $exit (i32.const 3))))"#,
+        );
+        let result = module.run(WasiCtxBuilder::new().build(), oneshot::channel().0);
+        assert_eq!(exit_code(&logger, result, 0), 3);
+
+        let module = load_wat(r#"(module (func (export "_start") unreachable))"#);
+        let result = module.run(WasiCtxBuilder::new().build(), oneshot::channel().0);
+        assert_eq!(exit_code(&logger, result, 0), WASM_TRAP_EXIT_CODE);
+    }
+
+    #[test]
+    fn test_module_interrupt() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+
+        let module = Arc::new(load_wat(
+            r#"(module (func (export "_start") (loop $l (br $l))))"#,
+        ));
+        let m = module.clone();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let handle = std::thread::spawn(move || m.run(WasiCtxBuilder::new().build(), ready_tx));
+
+        ready_rx.blocking_recv().unwrap();
+        module.interrupt();
+        let result = handle.join().unwrap();
+        assert_eq!(
+            exit_code(&logger, result, libc::SIGKILL as u32),
+            128 + libc::SIGKILL
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::io::{self, Read, Write};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::{channel, Receiver, Sender},
+    task::JoinHandle,
+};
+
+/// Number of chunks buffered between the wasm stdio and the shim io.
+const PIPE_BUFFER_SIZE: usize = 32;
+const STDIN_READ_BUFFER_SIZE: usize = 4096;
+
+/// Blocking reader used as the stdin of a wasm module, fed by the task
+/// copying the shim stdin. It hits EOF once that task is gone.
+pub(crate) struct ChannelReader {
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(data) => self.pending = data,
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Blocking writer used as the stdout or stderr of a wasm module, drained by
+/// the task copying to the shim io.
+#[derive(Clone)]
+pub(crate) struct ChannelWriter {
+    tx: Sender<Vec<u8>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .blocking_send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "shim io closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) fn copy_stdin(
+    logger: slog::Logger,
+    mut reader: Box<dyn AsyncRead + Send + Unpin>,
+) -> (ChannelReader, JoinHandle<()>) {
+    let (tx, rx) = channel(PIPE_BUFFER_SIZE);
+    let task = tokio::spawn(async move {
+        let mut buf = vec![0u8; STDIN_READ_BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(len) => {
+                    if tx.send(buf[..len].to_vec()).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!(logger, "copy_stdin: failed to read stream: {}", e);
+                    break;
+                }
+            }
+        }
+        info!(logger, "copy_stdin: stop to copy stream");
+    });
+
+    (
+        ChannelReader {
+            rx,
+            pending: vec![],
+        },
+        task,
+    )
+}
+
+pub(crate) fn copy_output(
+    logger: slog::Logger,
+    mut writer: Box<dyn AsyncWrite + Send + Unpin>,
+) -> (ChannelWriter, JoinHandle<()>) {
+    let (tx, mut rx) = channel::<Vec<u8>>(PIPE_BUFFER_SIZE);
+    let task = tokio::spawn(async move {
+        let mut length = 0;
+        while let Some(data) = rx.recv().await {
+            if let Err(e) = writer.write_all(&data).await {
+                warn!(logger, "copy_output: failed to write stream: {}", e);
+                return;
+            }
+            length += data.len();
+        }
+        let _ = writer.flush().await;
+        info!(logger, "copy_output: stop to copy stream length {}", length);
+    });
+
+    (ChannelWriter { tx }, task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_copy_stdio() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+
+        let (mut input, shim_stdin) = tokio::io::duplex(64);
+        let (mut reader, stdin_task) = copy_stdin(logger.clone(), Box::new(shim_stdin));
+        input.write_all(b"hello wasm").await.unwrap();
+        drop(input);
+        stdin_task.await.unwrap();
+
+        let (shim_stdout, mut output) = tokio::io::duplex(64);
+        let (mut writer, stdout_task) = copy_output(logger, Box::new(shim_stdout));
+
+        // the module side of the pipes is blocking
+        tokio::task::spawn_blocking(move || {
+            let mut data = String::new();
+            reader.read_to_string(&mut data).unwrap();
+            assert_eq!(data, "hello wasm");
+            writer.write_all(data.as_bytes()).unwrap();
+        })
+        .await
+        .unwrap();
+        stdout_task.await.unwrap();
+
+        let mut data = String::new();
+        output.read_to_string(&mut data).await.unwrap();
+        assert_eq!(data, "hello wasm");
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use common::{
+    error::Error,
+    types::{
+        ContainerConfig, ContainerID, ContainerProcess, ExecProcessRequest, KillRequest,
+        ProcessExitStatus, ProcessStateInfo, ProcessType, ResizePTYRequest, ShutdownRequest,
+        StatsInfo, UpdateRequest, PID,
+    },
+    ContainerManager,
+};
+use tokio::sync::RwLock;
+
+use super::Container;
+
+pub struct WasmContainerManager {
+    sid: String,
+    pid: u32,
+    containers: Arc<RwLock<HashMap<String, Container>>>,
+}
+
+impl WasmContainerManager {
+    pub fn new(sid: &str, pid: u32) -> Self {
+        Self {
+            sid: sid.to_string(),
+            pid,
+            containers: Default::default(),
+        }
+    }
+}
+
+/// Wasm containers run a single module, there is no other process to exec
+/// into or to address.
+fn check_container_process(process: &ContainerProcess) -> Result<()> {
+    if process.process_type != ProcessType::Container {
+        return Err(Error::ProcessNotFound(process.clone()).into());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl ContainerManager for WasmContainerManager {
+    async fn create_container(&self, config: ContainerConfig, spec: oci::Spec) -> Result<PID> {
+        let mut containers = self.containers.write().await;
+        if containers.contains_key(&config.container_id) {
+            return Err(anyhow!("container {} already exists", &config.container_id));
+        }
+
+        let container = Container::new(self.pid, config, spec)
+            .await
+            .context("new container")?;
+        containers.insert(container.container_id.clone(), container);
+
+        Ok(PID { pid: self.pid })
+    }
+
+    async fn close_process_io(&self, process: &ContainerProcess) -> Result<()> {
+        check_container_process(process)?;
+        let containers = self.containers.read().await;
+        let container_id = &process.container_id.to_string();
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+
+        c.close_io().await;
+        Ok(())
+    }
+
+    async fn delete_process(&self, process: &ContainerProcess) -> Result<ProcessStateInfo> {
+        check_container_process(process)?;
+        let container_id = &process.container_id.container_id;
+        let mut containers = self.containers.write().await;
+        let c = containers
+            .remove(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.to_string()))?;
+        c.cleanup().await;
+        Ok(c.state().await)
+    }
+
+    async fn exec_process(&self, _req: ExecProcessRequest) -> Result<()> {
+        Err(anyhow!("exec is not supported by wasm container"))
+    }
+
+    async fn kill_process(&self, req: &KillRequest) -> Result<()> {
+        check_container_process(&req.process)?;
+        let containers = self.containers.read().await;
+        let container_id = &req.process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        c.kill(req.signal)
+            .await
+            .map_err(|err| {
+                warn!(
+                    sl!(),
+                    "failed to signal process {:?} {:?}", &req.process, err
+                );
+                err
+            })
+            .ok();
+        Ok(())
+    }
+
+    async fn wait_process(&self, process: &ContainerProcess) -> Result<ProcessExitStatus> {
+        check_container_process(process)?;
+        let containers = self.containers.read().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        let (watcher, status) = c.fetch_exit_watcher();
+        drop(containers);
+
+        match watcher {
+            Some(mut watcher) => {
+                info!(sl!(), "begin wait exit");
+                while watcher.changed().await.is_ok() {}
+                info!(sl!(), "end wait exited");
+            }
+            None => {
+                warn!(sl!(), "failed to find watcher for wait process");
+            }
+        }
+
+        let status = status.read().await;
+        info!(sl!(), "wait process exit status {:?}", status);
+        Ok(status.clone())
+    }
+
+    async fn start_process(&self, process: &ContainerProcess) -> Result<PID> {
+        check_container_process(process)?;
+        let containers = self.containers.read().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        c.start().await.context("start")?;
+        Ok(PID { pid: self.pid })
+    }
+
+    async fn state_process(&self, process: &ContainerProcess) -> Result<ProcessStateInfo> {
+        check_container_process(process)?;
+        let containers = self.containers.read().await;
+        let container_id = &process.container_id.container_id;
+        let c = containers
+            .get(container_id)
+            .ok_or_else(|| Error::ContainerNotFound(container_id.clone()))?;
+        Ok(c.state().await)
+    }
+
+    async fn pause_container(&self, _id: &ContainerID) -> Result<()> {
+        Err(anyhow!("pause is not supported by wasm container"))
+    }
+
+    async fn resume_container(&self, _id: &ContainerID) -> Result<()> {
+        Err(anyhow!("resume is not supported by wasm container"))
+    }
+
+    async fn resize_process_pty(&self, _req: &ResizePTYRequest) -> Result<()> {
+        // the module output is not attached to a real terminal
+        Ok(())
+    }
+
+    async fn stats_container(&self, id: &ContainerID) -> Result<StatsInfo> {
+        let containers = self.containers.read().await;
+        if !containers.contains_key(&id.container_id) {
+            return Err(Error::ContainerNotFound(id.container_id.clone()).into());
+        }
+        // the modules run in the shim process, there is no per container
+        // cgroup to collect metrics from
+        Ok(StatsInfo { value: None })
+    }
+
+    async fn update_container(&self, _req: UpdateRequest) -> Result<()> {
+        Err(anyhow!("update is not supported by wasm container"))
+    }
+
+    async fn pid(&self) -> Result<PID> {
+        Ok(PID { pid: self.pid })
+    }
+
+    async fn connect_container(&self, _id: &ContainerID) -> Result<PID> {
+        Ok(PID { pid: self.pid })
+    }
+
+    async fn need_shutdown_sandbox(&self, req: &ShutdownRequest) -> bool {
+        req.is_now || self.containers.read().await.is_empty() || self.sid == req.container_id
+    }
+
+    async fn is_sandbox_container(&self, process: &ContainerProcess) -> bool {
+        process.process_type == ProcessType::Container
+            && process.container_id.container_id == self.sid
+    }
+}
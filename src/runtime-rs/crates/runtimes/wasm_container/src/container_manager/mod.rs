@@ -0,0 +1,21 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+mod container;
+use container::Container;
+mod io;
+mod manager;
+pub use manager::WasmContainerManager;
+
+use std::sync::Arc;
+
+use common::types::ProcessExitStatus;
+use tokio::sync::{watch, RwLock};
+
+pub type ProcessWatcher = (
+    Option<watch::Receiver<bool>>,
+    Arc<RwLock<ProcessExitStatus>>,
+);
@@ -3,13 +3,23 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 //
+
+#[macro_use]
+extern crate slog;
+
+logging::logger_with_subsystem!(sl, "wasm-container");
+
+mod container_manager;
+pub mod sandbox;
+
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 use common::{message::Message, RuntimeHandler, RuntimeInstance};
-use kata_types::config::TomlConfig;
+use kata_types::config::{TomlConfig, RUNTIME_NAME_WASMCONTAINER};
 use tokio::sync::mpsc::Sender;
+
 pub struct WasmContainer {}
 
 #[async_trait]
@@ -19,7 +29,7 @@ impl RuntimeHandler for WasmContainer {
     }
 
     fn name() -> String {
-        "wasm_container".to_string()
+        RUNTIME_NAME_WASMCONTAINER.to_string()
     }
 
     fn new_handler() -> Arc<dyn RuntimeHandler> {
@@ -28,14 +38,21 @@ impl RuntimeHandler for WasmContainer {
 
     async fn new_instance(
         &self,
-        _sid: &str,
-        _msg_sender: Sender<Message>,
+        sid: &str,
+        msg_sender: Sender<Message>,
         _config: Arc<TomlConfig>,
     ) -> Result<RuntimeInstance> {
-        todo!()
+        let pid = std::process::id();
+
+        let sandbox = sandbox::WasmSandbox::new(sid, msg_sender);
+        let container_manager = container_manager::WasmContainerManager::new(sid, pid);
+        Ok(RuntimeInstance {
+            sandbox: Arc::new(sandbox),
+            container_manager: Arc::new(container_manager),
+        })
     }
 
-    fn cleanup(&self, _id: &str) -> Result<()> {
-        todo!()
+    fn cleanup(&self, id: &str) -> Result<()> {
+        sandbox::cleanup_sandbox_dir(id).context("cleanup sandbox dir")
     }
 }
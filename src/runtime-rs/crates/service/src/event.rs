@@ -0,0 +1,263 @@
+// Copyright (c) 2019-2022 Alibaba Cloud
+// Copyright (c) 2019-2022 Ant Group
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::{
+    convert::TryInto,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use common::message::Event;
+use containerd_shim_protos::{
+    protobuf::{
+        well_known_types::{Any, Timestamp},
+        SingularPtrField,
+    },
+    shim::events::{Envelope, ForwardRequest},
+    shim_async::{Client, EventsClient},
+};
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+
+/// max number of events waiting to be forwarded to containerd
+const EVENT_QUEUE_SIZE: usize = 128;
+/// number of attempts to forward an event before dropping it
+const FORWARD_ATTEMPTS: u32 = 5;
+/// interval between two attempts to forward an event
+const FORWARD_RETRY_INTERVAL_MS: u64 = 200;
+/// timeout of a forward request
+const FORWARD_TIMEOUT_NANOS: i64 = 5_000_000_000;
+
+/// EventPublisher forwards the task events to containerd over a persistent
+/// ttrpc connection.
+///
+/// The events are queued and forwarded in order by a background task, which
+/// reconnects to containerd and retries when forwarding fails. The queue is
+/// bounded, the events published while it's full are dropped.
+pub(crate) struct EventPublisher {
+    namespace: String,
+    sender: Sender<Envelope>,
+}
+
+impl EventPublisher {
+    pub(crate) fn new(ttrpc_address: &str, namespace: &str) -> Self {
+        let (sender, receiver) = channel(EVENT_QUEUE_SIZE);
+        let forwarder = TtrpcForwarder {
+            address: ttrpc_address.to_string(),
+            client: None,
+        };
+        tokio::spawn(forward_events(
+            forwarder,
+            receiver,
+            FORWARD_RETRY_INTERVAL_MS,
+        ));
+
+        Self {
+            namespace: namespace.to_string(),
+            sender,
+        }
+    }
+
+    pub(crate) fn publish(&self, event: Arc<dyn Event>) -> Result<()> {
+        let any = Any {
+            type_url: event.type_url(),
+            value: event.value().context("get event value")?,
+            ..Default::default()
+        };
+        let envelope = Envelope {
+            timestamp: SingularPtrField::some(timestamp_now()),
+            namespace: self.namespace.clone(),
+            topic: event.r#type(),
+            event: SingularPtrField::some(any),
+            ..Default::default()
+        };
+
+        self.sender.try_send(envelope).map_err(|err| match err {
+            TrySendError::Full(e) => anyhow!("event queue is full, drop event {}", e.topic),
+            TrySendError::Closed(e) => anyhow!("event publisher is closed, drop event {}", e.topic),
+        })
+    }
+}
+
+fn timestamp_now() -> Timestamp {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Timestamp {
+        seconds: now.as_secs().try_into().unwrap_or_default(),
+        nanos: now.subsec_nanos().try_into().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+trait Forwarder: Send {
+    async fn forward(&mut self, req: &ForwardRequest) -> Result<()>;
+}
+
+/// TtrpcForwarder forwards the events to containerd, it reconnects after a failure.
+struct TtrpcForwarder {
+    address: String,
+    client: Option<EventsClient>,
+}
+
+#[async_trait]
+impl Forwarder for TtrpcForwarder {
+    async fn forward(&mut self, req: &ForwardRequest) -> Result<()> {
+        if self.client.is_none() {
+            let c = Client::connect(&self.address)
+                .with_context(|| format!("connect to containerd {}", self.address))?;
+            self.client = Some(EventsClient::new(c));
+        }
+        let events_client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| anyhow!("no events client"))?;
+
+        let result = events_client
+            .forward(ttrpc::context::with_timeout(FORWARD_TIMEOUT_NANOS), req)
+            .await
+            .context("forward event");
+        if result.is_err() {
+            // reconnect on the next attempt
+            self.client = None;
+        }
+        result.map(|_| ())
+    }
+}
+
+async fn forward_events<F: Forwarder>(
+    mut forwarder: F,
+    mut receiver: Receiver<Envelope>,
+    retry_interval_ms: u64,
+) {
+    while let Some(envelope) = receiver.recv().await {
+        let topic = envelope.topic.clone();
+        let req = ForwardRequest {
+            envelope: SingularPtrField::some(envelope),
+            ..Default::default()
+        };
+
+        for attempt in 1..=FORWARD_ATTEMPTS {
+            match forwarder.forward(&req).await {
+                Ok(_) => break,
+                Err(err) => {
+                    if attempt == FORWARD_ATTEMPTS {
+                        error!(
+                            sl!(),
+                            "failed to forward event {}, drop it: {:?}", topic, err
+                        );
+                    } else {
+                        warn!(
+                            sl!(),
+                            "failed to forward event {} (attempt {}): {:?}", topic, attempt, err
+                        );
+                        tokio::time::sleep(Duration::from_millis(retry_interval_ms)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    info!(sl!(), "event publisher exits");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use containerd_shim_protos::events::task::{TaskExit, TaskStart};
+    use tokio::sync::mpsc::UnboundedSender;
+
+    // MockForwarder fails the first attempts of every event, and reports the forwarded topics.
+    struct MockForwarder {
+        failures: u32,
+        attempts: u32,
+        forwarded: UnboundedSender<String>,
+    }
+
+    #[async_trait]
+    impl Forwarder for MockForwarder {
+        async fn forward(&mut self, req: &ForwardRequest) -> Result<()> {
+            self.attempts += 1;
+            if self.attempts <= self.failures {
+                return Err(anyhow!("containerd is not reachable"));
+            }
+            self.attempts = 0;
+            self.forwarded
+                .send(req.get_envelope().topic.clone())
+                .map_err(|e| anyhow!("send: {}", e))
+        }
+    }
+
+    fn new_publisher(sender: Sender<Envelope>) -> EventPublisher {
+        EventPublisher {
+            namespace: "k8s.io".to_string(),
+            sender,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_queue() {
+        let (sender, mut receiver) = channel(2);
+        let publisher = new_publisher(sender);
+
+        publisher.publish(Arc::new(TaskStart::default())).unwrap();
+        publisher.publish(Arc::new(TaskExit::default())).unwrap();
+        // the queue is full, the event is dropped
+        let err = publisher
+            .publish(Arc::new(TaskExit::default()))
+            .unwrap_err();
+        assert!(err.to_string().contains("queue is full"));
+
+        // the events are queued in order
+        let envelope = receiver.recv().await.unwrap();
+        assert_eq!(envelope.namespace, "k8s.io");
+        assert_eq!(envelope.topic, "/tasks/start");
+        assert_eq!(receiver.recv().await.unwrap().topic, "/tasks/exit");
+
+        drop(receiver);
+        let err = publisher
+            .publish(Arc::new(TaskStart::default()))
+            .unwrap_err();
+        assert!(err.to_string().contains("closed"));
+    }
+
+    #[tokio::test]
+    async fn test_forward_events_retry() {
+        let (sender, receiver) = channel(EVENT_QUEUE_SIZE);
+        let publisher = new_publisher(sender);
+        let (forwarded_sender, mut forwarded) = tokio::sync::mpsc::unbounded_channel();
+
+        // the events are forwarded once containerd is reachable
+        let forwarder = MockForwarder {
+            failures: FORWARD_ATTEMPTS - 1,
+            attempts: 0,
+            forwarded: forwarded_sender.clone(),
+        };
+        publisher.publish(Arc::new(TaskStart::default())).unwrap();
+        publisher.publish(Arc::new(TaskExit::default())).unwrap();
+        drop(publisher);
+        forward_events(forwarder, receiver, 1).await;
+        assert_eq!(forwarded.recv().await.unwrap(), "/tasks/start");
+        assert_eq!(forwarded.recv().await.unwrap(), "/tasks/exit");
+
+        // the event is dropped after the last attempt, the next one is still forwarded
+        let (sender, receiver) = channel(EVENT_QUEUE_SIZE);
+        let publisher = new_publisher(sender);
+        let forwarder = MockForwarder {
+            failures: FORWARD_ATTEMPTS,
+            attempts: 0,
+            forwarded: forwarded_sender,
+        };
+        publisher.publish(Arc::new(TaskStart::default())).unwrap();
+        publisher.publish(Arc::new(TaskExit::default())).unwrap();
+        drop(publisher);
+        forward_events(forwarder, receiver, 1).await;
+        assert_eq!(forwarded.recv().await.unwrap(), "/tasks/exit");
+        assert!(forwarded.recv().await.is_none());
+    }
+}
@@ -9,6 +9,9 @@ extern crate slog;
 
 logging::logger_with_subsystem!(sl, "service");
 
+mod event;
 mod manager;
 pub use manager::ServiceManager;
+#[cfg(feature = "linux")]
+pub use runtimes::{init_child, INIT_CHILD_ARG};
 mod task_service;
@@ -7,83 +7,36 @@
 use std::{
     fs,
     os::unix::io::{FromRawFd, RawFd},
-    process::Stdio,
     sync::Arc,
 };
 
 use anyhow::{Context, Result};
-use common::message::{Action, Event, Message};
-use containerd_shim_protos::{
-    protobuf::{well_known_types::Any, Message as ProtobufMessage},
-    shim_async,
-};
+use common::message::{Action, Message};
+use containerd_shim_protos::shim_async;
 use runtimes::RuntimeHandlerManager;
-use tokio::{
-    io::AsyncWriteExt,
-    process::Command,
-    sync::mpsc::{channel, Receiver},
-};
+use tokio::sync::mpsc::{channel, Receiver};
 use ttrpc::asynchronous::Server;
 
-use crate::task_service::TaskService;
+use crate::{event::EventPublisher, task_service::TaskService};
 /// message buffer size
 const MESSAGE_BUFFER_SIZE: usize = 8;
 use shim_interface::KATA_PATH;
 
+/// environment variable set by containerd with the address of its ttrpc socket
+const TTRPC_ADDRESS_ENV: &str = "TTRPC_ADDRESS";
+/// suffix of the ttrpc socket of containerd, appended to its main socket
+const TTRPC_ADDRESS_SUFFIX: &str = ".ttrpc";
+
 pub struct ServiceManager {
     receiver: Option<Receiver<Message>>,
     handler: Arc<RuntimeHandlerManager>,
     task_server: Option<Server>,
-    binary: String,
-    address: String,
-    namespace: String,
-}
-
-async fn send_event(
-    containerd_binary: String,
-    address: String,
-    namespace: String,
-    event: Arc<dyn Event>,
-) -> Result<()> {
-    let any = Any {
-        type_url: event.type_url(),
-        value: event.value().context("get event value")?,
-        ..Default::default()
-    };
-    let data = any.write_to_bytes().context("write to any")?;
-    let mut child = Command::new(containerd_binary)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .args([
-            "--address",
-            &address,
-            "publish",
-            "--topic",
-            &event.r#type(),
-            "--namespace",
-            &namespace,
-        ])
-        .spawn()
-        .context("spawn containerd cmd to publish event")?;
-
-    let stdin = child.stdin.as_mut().context("failed to open stdin")?;
-    stdin
-        .write_all(&data)
-        .await
-        .context("failed to write to stdin")?;
-    let output = child
-        .wait_with_output()
-        .await
-        .context("failed to read stdout")?;
-    info!(sl!(), "get output: {:?}", output);
-    Ok(())
+    event_publisher: EventPublisher,
 }
 
 impl ServiceManager {
     pub async fn new(
         id: &str,
-        containerd_binary: &str,
         address: &str,
         namespace: &str,
         task_server_fd: RawFd,
@@ -96,13 +49,16 @@ impl ServiceManager {
         );
         let mut task_server = unsafe { Server::from_raw_fd(task_server_fd) };
         task_server = task_server.set_domain_unix();
+
+        let ttrpc_address = std::env::var(TTRPC_ADDRESS_ENV)
+            .unwrap_or_else(|_| format!("{}{}", address, TTRPC_ADDRESS_SUFFIX));
+        let event_publisher = EventPublisher::new(&format!("unix://{}", ttrpc_address), namespace);
+
         Ok(Self {
             receiver: Some(receiver),
             handler,
             task_server: Some(task_server),
-            binary: containerd_binary.to_string(),
-            address: address.to_string(),
-            namespace: namespace.to_string(),
+            event_publisher,
         })
     }
 
@@ -120,19 +76,19 @@ impl ServiceManager {
                     Action::Stop => self.stop_listen().await.context("stop listen"),
                     Action::Shutdown => {
                         self.stop_listen().await.context("stop listen")?;
+                        // flush the pending spans before the shim exits
+                        runtimes::tracer::end_tracing();
                         break;
                     }
                     Action::Event(event) => {
                         info!(sl!(), "get event {:?}", &event);
-                        send_event(
-                            self.binary.clone(),
-                            self.address.clone(),
-                            self.namespace.clone(),
-                            event,
-                        )
-                        .await
-                        .context("send event")?;
-                        Ok(())
+                        self.event_publisher
+                            .publish(event)
+                            .map_err(|err| {
+                                warn!(sl!(), "failed to publish event {:?}", err);
+                                err
+                            })
+                            .context("publish event")
                     }
                 };
 
@@ -155,7 +111,10 @@ impl ServiceManager {
         let handler = RuntimeHandlerManager::new(sid, sender)
             .await
             .context("new runtime handler")?;
-        handler.cleanup().await.context("runtime handler cleanup")?;
+        let result = handler.cleanup().await.context("runtime handler cleanup");
+        // flush the spans of the cleanup, the shim exits right after
+        runtimes::tracer::end_tracing();
+        result?;
         let temp_dir = [KATA_PATH, sid].join("/");
         if std::fs::metadata(temp_dir.as_str()).is_ok() {
             // try to remove dir and skip the result
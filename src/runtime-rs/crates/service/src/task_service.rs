@@ -12,6 +12,7 @@ use std::{
 use async_trait::async_trait;
 use common::types::{Request, Response};
 use containerd_shim_protos::{api, shim_async};
+use tracing::instrument;
 use ttrpc::{self, r#async::TtrpcContext};
 
 use runtimes::RuntimeHandlerManager;
@@ -55,7 +56,8 @@ macro_rules! impl_service {
     ($($name: tt | $req: ty | $resp: ty),*) => {
         #[async_trait]
         impl shim_async::Task for TaskService {
-            $(async fn $name(&self, ctx: &TtrpcContext, req: $req) -> ttrpc::Result<$resp> {
+            $(#[instrument(skip_all)]
+            async fn $name(&self, ctx: &TtrpcContext, req: $req) -> ttrpc::Result<$resp> {
                 handler_message(&self.handler, ctx, req).await
             })*
         }
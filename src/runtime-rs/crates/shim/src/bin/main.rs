@@ -26,6 +26,8 @@ enum Action {
     Run(Args),
     Start(Args),
     Delete(Args),
+    #[cfg(feature = "linux")]
+    Init,
     Help,
     Version,
 }
@@ -59,6 +61,10 @@ fn parse_args(args: &[OsString]) -> Result<Action> {
     } else if rest_args[0] == "delete" {
         Ok(Action::Delete(shim_args))
     } else {
+        #[cfg(feature = "linux")]
+        if rest_args[0] == shim::INIT_CHILD_ARG {
+            return Ok(Action::Init);
+        }
         Err(anyhow!(Error::InvalidArgument))
     }
 }
@@ -153,6 +159,12 @@ fn real_main() -> Result<()> {
             let rt = get_tokio_runtime().context("get tokio runtime")?;
             rt.block_on(shim.run())?
         }
+        #[cfg(feature = "linux")]
+        Action::Init => {
+            // re-executed by rustjail to set up a linux container process
+            shim::init_child();
+            std::process::exit(0);
+        }
         Action::Help => show_help(&args[0]),
         Action::Version => show_version(None),
     }
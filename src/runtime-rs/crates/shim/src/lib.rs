@@ -17,6 +17,8 @@ mod logger;
 mod panic_hook;
 mod shim;
 pub use crate::shim::ShimExecutor;
+#[cfg(feature = "linux")]
+pub use service::{init_child, INIT_CHILD_ARG};
 mod core_sched;
 #[rustfmt::skip]
 pub mod config;
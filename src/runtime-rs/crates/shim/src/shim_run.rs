@@ -48,7 +48,6 @@ impl ShimExecutor {
         let server_fd = get_server_fd().context("get server fd")?;
         let mut service_manager = service::ServiceManager::new(
             &self.args.id,
-            &self.args.publish_binary,
             &self.args.address,
             &self.args.namespace,
             server_fd,
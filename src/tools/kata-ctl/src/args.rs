@@ -4,6 +4,7 @@
 //
 
 use clap::{Args, Parser, Subcommand};
+use kata_types::config::default::DEFAULT_AGENT_DBG_CONSOLE_PORT;
 
 use thiserror::Error;
 
@@ -23,10 +24,10 @@ pub enum Commands {
     DirectVolume(DirectVolumeCommand),
 
     /// Display settings
-    Env,
+    Env(EnvArgument),
 
     /// Enter into guest VM by debug console
-    Exec,
+    Exec(ExecArguments),
 
     /// Manage VM factory
     Factory,
@@ -70,18 +71,32 @@ pub enum CheckSubCommand {
 }
 
 #[derive(Debug, Args)]
-pub struct MetricsCommand {
-    #[clap(subcommand)]
-    pub metrics_cmd: MetricsSubCommand,
+pub struct EnvArgument {
+    /// Format output as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// Path to the configuration file, the default configuration files are searched if not set
+    #[clap(long)]
+    pub config: Option<String>,
 }
 
-#[derive(Debug, Subcommand)]
-pub enum MetricsSubCommand {
-    /// Arguments for metrics
-    MetricsArgs,
+#[derive(Debug, Args)]
+pub struct ExecArguments {
+    /// Sandbox ID to enter
+    pub sandbox_id: String,
+
+    /// The vsock port of the debug console in the guest
+    #[clap(long, default_value_t = DEFAULT_AGENT_DBG_CONSOLE_PORT)]
+    pub vport: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct MetricsCommand {
+    /// Sandbox ID to gather the metrics of
+    pub sandbox_id: String,
 }
 
-// #[derive(Parser, Debug)]
 #[derive(Debug, Args)]
 pub struct IptablesCommand {
     #[clap(subcommand)]
@@ -90,8 +105,32 @@ pub struct IptablesCommand {
 
 #[derive(Debug, Subcommand)]
 pub enum IpTablesArguments {
-    /// Configure iptables
-    Metrics,
+    /// Get the iptables of the guest
+    Get(IptablesGetArgs),
+
+    /// Replace the iptables of the guest with the rules of a file
+    Set(IptablesSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct IptablesGetArgs {
+    pub sandbox_id: String,
+
+    /// Operate on the ip6tables instead of the iptables
+    #[clap(long)]
+    pub v6: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct IptablesSetArgs {
+    pub sandbox_id: String,
+
+    /// File of the rules, in the iptables-save format
+    pub file: String,
+
+    /// Operate on the ip6tables instead of the iptables
+    #[clap(long)]
+    pub v6: bool,
 }
 
 #[derive(Debug, Args)]
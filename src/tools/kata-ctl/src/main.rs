@@ -16,10 +16,11 @@ use std::process::exit;
 
 use args::{Commands, KataCtlCli};
 
-use ops::check_ops::{
-    handle_check, handle_env, handle_exec, handle_factory, handle_iptables, handle_metrics,
-    handle_version,
-};
+use ops::check_ops::{handle_check, handle_factory, handle_version};
+use ops::env_ops::handle_env;
+use ops::exec_ops::handle_exec;
+use ops::iptables_ops::handle_iptables;
+use ops::metrics_ops::handle_metrics;
 use ops::volume_ops::handle_direct_volume;
 
 fn real_main() -> Result<()> {
@@ -28,8 +29,8 @@ fn real_main() -> Result<()> {
     match args.command {
         Commands::Check(args) => handle_check(args),
         Commands::DirectVolume(args) => handle_direct_volume(args),
-        Commands::Env => handle_env(),
-        Commands::Exec => handle_exec(),
+        Commands::Env(args) => handle_env(args),
+        Commands::Exec(args) => handle_exec(args),
         Commands::Factory => handle_factory(),
         Commands::Iptables(args) => handle_iptables(args),
         Commands::Metrics(args) => handle_metrics(args),
@@ -4,5 +4,9 @@
 //
 
 pub mod check_ops;
+pub mod env_ops;
+pub mod exec_ops;
+pub mod iptables_ops;
+pub mod metrics_ops;
 pub mod version;
 pub mod volume_ops;
@@ -5,7 +5,7 @@
 
 use crate::arch::arch_specific::get_checks;
 
-use crate::args::{CheckArgument, CheckSubCommand, IptablesCommand, MetricsCommand};
+use crate::args::{CheckArgument, CheckSubCommand};
 
 use crate::check;
 
@@ -104,24 +104,9 @@ pub fn handle_check(checkcmd: CheckArgument) -> Result<()> {
     Ok(())
 }
 
-pub fn handle_env() -> Result<()> {
-    Ok(())
-}
-
-pub fn handle_exec() -> Result<()> {
-    Ok(())
-}
-
 pub fn handle_factory() -> Result<()> {
-    Ok(())
-}
-
-pub fn handle_iptables(_args: IptablesCommand) -> Result<()> {
-    Ok(())
-}
-
-pub fn handle_metrics(_args: MetricsCommand) -> Result<()> {
-    Ok(())
+    // runtime-rs doesn't create the VMs from a factory, there is nothing to manage
+    Err(anyhow!("VM factory is not supported by runtime-rs"))
 }
 
 pub fn handle_version() -> Result<()> {
@@ -0,0 +1,94 @@
+// Copyright (c) 2022 Intel Corporation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::EnvArgument;
+use crate::ops::version;
+use crate::utils;
+
+use anyhow::{Context, Result};
+use kata_types::config::TomlConfig;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct MetaInfo {
+    version: String,
+    config_path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct HostInfo {
+    kernel: String,
+    distro_name: String,
+    distro_version: String,
+    cpu_vendor: String,
+    cpu_model: String,
+}
+
+// EnvInfo is the information displayed by 'kata-ctl env', the configuration is the merged
+// one the runtime runs with, including the drop-in files.
+#[derive(Debug, Serialize)]
+struct EnvInfo {
+    meta: MetaInfo,
+    host: HostInfo,
+    config: TomlConfig,
+}
+
+#[cfg(any(target_arch = "s390x", target_arch = "x86_64"))]
+fn get_cpu_details() -> (String, String) {
+    utils::get_generic_cpu_details(crate::check::PROC_CPUINFO)
+        .unwrap_or_else(|_| (String::from(utils::UNKNOWN), String::from(utils::UNKNOWN)))
+}
+
+#[cfg(not(any(target_arch = "s390x", target_arch = "x86_64")))]
+fn get_cpu_details() -> (String, String) {
+    (String::from(utils::UNKNOWN), String::from(utils::UNKNOWN))
+}
+
+fn get_host_info() -> HostInfo {
+    let kernel = utils::get_kernel_version(utils::PROC_VERSION_FILE)
+        .unwrap_or_else(|_| String::from(utils::UNKNOWN));
+    let (distro_name, distro_version) =
+        utils::get_distro_details(utils::OS_RELEASE, utils::OS_RELEASE_CLR)
+            .unwrap_or_else(|_| (String::from(utils::UNKNOWN), String::from(utils::UNKNOWN)));
+    let (cpu_vendor, cpu_model) = get_cpu_details();
+
+    HostInfo {
+        kernel,
+        distro_name,
+        distro_version,
+        cpu_vendor,
+        cpu_model,
+    }
+}
+
+fn get_env_info(config_file: &str) -> Result<EnvInfo> {
+    let (config, config_path) =
+        TomlConfig::load_from_file(config_file).context("load configuration")?;
+
+    Ok(EnvInfo {
+        meta: MetaInfo {
+            version: version::get().unwrap_or_else(|_| String::from(utils::UNKNOWN)),
+            config_path: config_path.to_string_lossy().to_string(),
+        },
+        host: get_host_info(),
+        config,
+    })
+}
+
+pub fn handle_env(env_args: EnvArgument) -> Result<()> {
+    let env_info = get_env_info(env_args.config.as_deref().unwrap_or_default())?;
+
+    let output = if env_args.json {
+        serde_json::to_string_pretty(&env_info).context("format environment as json")?
+    } else {
+        // go through toml::Value, which orders the tables after the plain values as the toml
+        // format requires
+        let value = toml::Value::try_from(&env_info).context("convert environment to toml")?;
+        toml::to_string_pretty(&value).context("format environment as toml")?
+    };
+    println!("{}", output);
+
+    Ok(())
+}
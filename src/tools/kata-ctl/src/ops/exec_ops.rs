@@ -0,0 +1,162 @@
+// Copyright (c) 2022 Intel Corporation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::ExecArguments;
+use crate::utils;
+
+use anyhow::{anyhow, Context, Result};
+use nix::sys::socket::{connect, socket, AddressFamily, SockFlag, SockType, VsockAddr};
+use nix::sys::termios::{self, SetArg, Termios};
+use nix::unistd::isatty;
+use shim_interface::shim_mgmt::AGENT_URL;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+const HVSOCK_SCHEME: &str = "hvsock://";
+const VSOCK_SCHEME: &str = "vsock://";
+
+// The shim reports the agent address as <scheme>://<address>:<agent port>, the debug console
+// listens on the same address with another port.
+fn parse_agent_address(agent_url: &str) -> Result<&str> {
+    agent_url
+        .trim()
+        .rsplit_once(':')
+        .map(|(address, _)| address)
+        .filter(|address| address.starts_with(HVSOCK_SCHEME) || address.starts_with(VSOCK_SCHEME))
+        .ok_or_else(|| anyhow!("invalid agent address {:?}", agent_url))
+}
+
+// The hybrid vsock is a unix socket on the host, the port to connect to is sent first, and
+// the hypervisor answers "OK <port>" once connected.
+fn connect_hvsock(path: &str, port: u32) -> Result<File> {
+    let mut stream =
+        UnixStream::connect(path).with_context(|| format!("connect to hvsock {}", path))?;
+    stream
+        .write_all(format!("connect {}\n", port).as_bytes())
+        .context("send connect request")?;
+
+    // read the response byte by byte, not to consume the console output following it
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while byte[0] != b'\n' {
+        stream
+            .read_exact(&mut byte)
+            .context("read connect response")?;
+        response.push(byte[0]);
+    }
+    if !response.starts_with(b"OK") {
+        return Err(anyhow!(
+            "failed to connect to port {} of {}: {}",
+            port,
+            path,
+            String::from_utf8_lossy(&response).trim()
+        ));
+    }
+
+    Ok(unsafe { File::from_raw_fd(stream.into_raw_fd()) })
+}
+
+fn connect_vsock(cid: &str, port: u32) -> Result<File> {
+    let cid = cid
+        .parse::<u32>()
+        .with_context(|| format!("invalid vsock cid {}", cid))?;
+    let fd = socket(
+        AddressFamily::Vsock,
+        SockType::Stream,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .context("create vsock socket")?;
+    // own the fd right away so that it's closed if the connection fails
+    let file = unsafe { File::from_raw_fd(fd) };
+    connect(fd, &VsockAddr::new(cid, port))
+        .with_context(|| format!("connect to vsock {}:{}", cid, port))?;
+
+    Ok(file)
+}
+
+fn connect_console(agent_url: &str, port: u32) -> Result<File> {
+    let address = parse_agent_address(agent_url)?;
+    if let Some(path) = address.strip_prefix(HVSOCK_SCHEME) {
+        connect_hvsock(path, port)
+    } else if let Some(cid) = address.strip_prefix(VSOCK_SCHEME) {
+        connect_vsock(cid, port)
+    } else {
+        Err(anyhow!("unsupported agent address {:?}", agent_url))
+    }
+}
+
+// Put the terminal in raw mode, so that the keys, e.g. ctrl-c, are handled by the console in
+// the guest, the original settings are returned to restore them.
+fn set_raw_terminal(fd: RawFd) -> Result<Option<Termios>> {
+    if !isatty(fd).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let original = termios::tcgetattr(fd).context("get terminal attributes")?;
+    let mut raw = original.clone();
+    termios::cfmakeraw(&mut raw);
+    termios::tcsetattr(fd, SetArg::TCSANOW, &raw).context("set terminal raw mode")?;
+
+    Ok(Some(original))
+}
+
+fn run_console(console: File) -> Result<()> {
+    let mut console_in = console.try_clone().context("clone console")?;
+    // the stdin is copied until the console is closed, the thread is blocked on the stdin
+    // then and goes away with the process
+    thread::spawn(move || io::copy(&mut io::stdin(), &mut console_in));
+
+    let mut console_out = console;
+    let mut stdout = io::stdout();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match console_out.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("read console"),
+        };
+        stdout.write_all(&buf[..n]).context("write stdout")?;
+        stdout.flush().context("flush stdout")?;
+    }
+}
+
+pub fn handle_exec(exec_args: ExecArguments) -> Result<()> {
+    let agent_url = utils::shim_mgmt_get(&exec_args.sandbox_id, AGENT_URL)
+        .with_context(|| format!("get agent url of sandbox {}", exec_args.sandbox_id))?;
+    let agent_url = String::from_utf8(agent_url).context("agent url is not utf8")?;
+    let console = connect_console(&agent_url, exec_args.vport)
+        .with_context(|| format!("connect to debug console of {}", exec_args.sandbox_id))?;
+
+    let stdin_fd = io::stdin().as_raw_fd();
+    let original = set_raw_terminal(stdin_fd)?;
+    let result = run_console(console);
+    if let Some(original) = original {
+        termios::tcsetattr(stdin_fd, SetArg::TCSANOW, &original)
+            .context("restore terminal attributes")?;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_agent_address() {
+        assert_eq!(
+            parse_agent_address("hvsock:///run/kata/sid/root/kata.hvsock:1024").unwrap(),
+            "hvsock:///run/kata/sid/root/kata.hvsock"
+        );
+        assert_eq!(parse_agent_address("vsock://3:1024").unwrap(), "vsock://3");
+        assert!(parse_agent_address("").is_err());
+        assert!(parse_agent_address("unix:///tmp/agent.sock:1024").is_err());
+    }
+}
@@ -0,0 +1,41 @@
+// Copyright (c) 2022 Intel Corporation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::{IpTablesArguments, IptablesCommand};
+use crate::utils;
+
+use anyhow::{Context, Result};
+use shim_interface::shim_mgmt::{IP6_TABLE_URL, IP_TABLE_URL};
+use std::fs;
+use std::io::{self, Write};
+
+fn iptables_url(v6: bool) -> &'static str {
+    if v6 {
+        IP6_TABLE_URL
+    } else {
+        IP_TABLE_URL
+    }
+}
+
+pub fn handle_iptables(iptables_cmd: IptablesCommand) -> Result<()> {
+    match iptables_cmd.iptables {
+        IpTablesArguments::Get(args) => {
+            let rules = utils::shim_mgmt_get(&args.sandbox_id, iptables_url(args.v6))
+                .with_context(|| format!("get iptables of sandbox {}", args.sandbox_id))?;
+            io::stdout()
+                .write_all(&rules)
+                .context("write iptables to stdout")?;
+        }
+        IpTablesArguments::Set(args) => {
+            let rules = fs::read(&args.file)
+                .with_context(|| format!("read iptables file {}", args.file))?;
+            utils::shim_mgmt_put(&args.sandbox_id, iptables_url(args.v6), rules)
+                .with_context(|| format!("set iptables of sandbox {}", args.sandbox_id))?;
+            println!("iptables set successfully");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+// Copyright (c) 2022 Intel Corporation
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::args::MetricsCommand;
+use crate::utils;
+
+use anyhow::{Context, Result};
+use shim_interface::shim_mgmt::METRICS_URL;
+use std::io::{self, Write};
+
+// The shim gathers the metrics of the shim, the hypervisor and the guest, all of them in the
+// prometheus text format.
+pub fn handle_metrics(metrics_cmd: MetricsCommand) -> Result<()> {
+    let metrics = utils::shim_mgmt_get(&metrics_cmd.sandbox_id, METRICS_URL)
+        .with_context(|| format!("get metrics of sandbox {}", metrics_cmd.sandbox_id))?;
+    io::stdout()
+        .write_all(&metrics)
+        .context("write metrics to stdout")?;
+
+    Ok(())
+}
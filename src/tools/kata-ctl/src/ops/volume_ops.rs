@@ -5,15 +5,15 @@
 
 use crate::args::{DirectVolSubcommand, DirectVolumeCommand};
 
-use anyhow::{anyhow, Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
 use futures::executor;
+use hyper::{Body, Response};
 use kata_types::mount::{
-    DirectVolumeMountInfo, KATA_DIRECT_VOLUME_ROOT_PATH, KATA_MOUNT_INFO_FILE_NAME,
+    join_path, DirectVolumeMountInfo, KATA_DIRECT_VOLUME_ROOT_PATH, KATA_MOUNT_INFO_FILE_NAME,
 };
 use nix;
 use reqwest::StatusCode;
-use safe_path;
-use std::{fs, path::PathBuf, time::Duration};
+use std::{fs, time::Duration};
 use url;
 
 use agent::ResizeVolumeRequest;
@@ -41,7 +41,7 @@ pub fn handle_direct_volume(vol_cmd: DirectVolumeCommand) -> Result<()> {
         }
     };
     if let Some(cmd_result) = cmd_result {
-        println!("{:?}", cmd_result);
+        println!("{}", cmd_result);
     }
 
     Ok(())
@@ -63,12 +63,8 @@ async fn resize(volume_path: &str, size: u64) -> Result<Option<String>> {
         .await?;
     let status = response.status();
     if status != StatusCode::OK {
-        let body = format!("{:?}", response.into_body());
-        return Err(anyhow!(
-            "failed to resize volume ({:?}): {:?}",
-            status,
-            body
-        ));
+        let body = read_body(response).await?;
+        return Err(anyhow!("failed to resize volume ({:?}): {}", status, body));
     }
 
     Ok(None)
@@ -78,27 +74,30 @@ async fn stats(volume_path: &str) -> Result<Option<String>> {
     let sandbox_id = get_sandbox_id_for_volume(volume_path)?;
     let mount_info = get_volume_mount_info(volume_path)?;
 
-    let req_url = url::form_urlencoded::Serializer::new(String::from(DIRECT_VOLUME_STATS_URL))
+    let req_url = url::form_urlencoded::Serializer::new(format!("{}?", DIRECT_VOLUME_STATS_URL))
         .append_pair(DIRECT_VOLUME_PATH_KEY, &mount_info.device)
         .finish();
 
     let shim_client = MgmtClient::new(&sandbox_id, Some(TIMEOUT))?;
     let response = shim_client.get(&req_url).await?;
-    // turn body into string
-    let body = format!("{:?}", response.into_body());
+    let status = response.status();
+    let body = read_body(response).await?;
+    if status != StatusCode::OK {
+        return Err(anyhow!(
+            "failed to get volume stats ({:?}): {}",
+            status,
+            body
+        ));
+    }
 
     Ok(Some(body))
 }
 
-// join_path joins user provided volumepath with kata direct-volume root path
-// the volume_path is base64-encoded and then safely joined to the end of path prefix
-fn join_path(prefix: &str, volume_path: &str) -> Result<PathBuf> {
-    if volume_path.is_empty() {
-        return Err(anyhow!("volume path must not be empty"));
-    }
-    let b64_encoded_path = base64::encode(volume_path.as_bytes());
-
-    Ok(safe_path::scoped_join(prefix, b64_encoded_path)?)
+async fn read_body(response: Response<Body>) -> Result<String> {
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .context("read response body")?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
 }
 
 // add writes the mount info (json string) of a direct volume into a filesystem path known to Kata Containers.
@@ -206,60 +205,6 @@ mod tests {
         fs::remove_dir_all(&joined_volume_path).expect("failed to cleanup test")
     }
 
-    #[test]
-    fn test_path_join() {
-        #[derive(Debug)]
-        struct TestData<'a> {
-            rootfs: &'a str,
-            volume_path: &'a str,
-            result: Result<PathBuf>,
-        }
-        // the safe_path::scoped_join requires the prefix path to exist on testing machine
-        let root_fs = tempdir().expect("failed to create tmpdir").into_path();
-        let root_fs_str = root_fs.to_str().unwrap();
-
-        let relative_secret_path = "../../etc/passwd";
-        let b64_relative_secret_path = base64::encode(relative_secret_path);
-
-        // this byte array b64encodes to "/abcdddd"
-        let b64_abs_path = vec![253, 166, 220, 117, 215, 93];
-        let converted_relative_path = "abcdddd";
-
-        let tests = &[
-            TestData {
-                rootfs: root_fs_str,
-                volume_path: "",
-                result: Err(anyhow!("volume path must not be empty")),
-            },
-            TestData {
-                rootfs: root_fs_str,
-                volume_path: relative_secret_path,
-                result: Ok(root_fs.join(b64_relative_secret_path)),
-            },
-            TestData {
-                rootfs: root_fs_str,
-                volume_path: unsafe { std::str::from_utf8_unchecked(&b64_abs_path) },
-                result: Ok(root_fs.join(converted_relative_path)),
-            },
-        ];
-        for (i, d) in tests.iter().enumerate() {
-            let msg = format!("test[{}]: {:?}", i, d);
-            let result = join_path(d.rootfs, d.volume_path);
-            let msg = format!("{}, result: {:?}", msg, result);
-            if d.result.is_ok() {
-                assert!(
-                    result.as_ref().unwrap() == d.result.as_ref().unwrap(),
-                    "{}",
-                    msg
-                );
-                continue;
-            }
-            let expected_error = format!("{}", d.result.as_ref().unwrap_err());
-            let actual_error = format!("{}", result.unwrap_err());
-            assert!(actual_error == expected_error, "{}", msg);
-        }
-    }
-
     #[test]
     #[serial]
     fn test_add_remove() {
@@ -11,7 +11,10 @@ use crate::arch::arch_specific;
 use crate::check::get_single_cpu_info;
 
 use anyhow::{anyhow, Context, Result};
+use hyper::StatusCode;
+use shim_interface::shim_mgmt::client::MgmtClient;
 use std::fs;
+use std::time::Duration;
 
 const NON_PRIV_USER: &str = "nobody";
 
@@ -27,7 +30,7 @@ pub fn drop_privs() -> Result<()> {
     Ok(())
 }
 
-const PROC_VERSION_FILE: &str = "/proc/version";
+pub const PROC_VERSION_FILE: &str = "/proc/version";
 
 pub fn get_kernel_version(proc_version_file: &str) -> Result<String> {
     let contents = fs::read_to_string(proc_version_file)
@@ -43,12 +46,12 @@ pub fn get_kernel_version(proc_version_file: &str) -> Result<String> {
     Ok(kernel_version)
 }
 
-const OS_RELEASE: &str = "/etc/os-release";
+pub const OS_RELEASE: &str = "/etc/os-release";
 
 // Clear Linux has a different path (for stateless support)
-const OS_RELEASE_CLR: &str = "/usr/lib/os-release";
+pub const OS_RELEASE_CLR: &str = "/usr/lib/os-release";
 
-const UNKNOWN: &str = "unknown";
+pub const UNKNOWN: &str = "unknown";
 
 fn get_field_fn(line: &str, delimiter: &str, file_name: &str) -> Result<String> {
     let fields: Vec<&str> = line.split(delimiter).collect();
@@ -110,7 +113,6 @@ pub fn get_distro_details(os_release: &str, os_release_clr: &str) -> Result<(Str
 pub fn get_generic_cpu_details(cpu_info_file: &str) -> Result<(String, String)> {
     let cpu_info = get_single_cpu_info(cpu_info_file, "\n\n")?;
     let lines = cpu_info.lines();
-    println!("Single cpu info: {}", cpu_info);
     let mut vendor = String::new();
     let mut model = String::new();
 
@@ -144,6 +146,52 @@ pub fn get_generic_cpu_details(cpu_info_file: &str) -> Result<(String, String)>
     Ok((vendor, model))
 }
 
+const SHIM_MGMT_TIMEOUT: Duration = Duration::from_millis(2000);
+
+// The shim management client is async, run the request on a tokio runtime and return the
+// response body if the request succeeds.
+fn shim_mgmt_request(sandbox_id: &str, uri: &str, data: Option<Vec<u8>>) -> Result<Vec<u8>> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("create tokio runtime")?;
+
+    rt.block_on(async {
+        let client = MgmtClient::new(sandbox_id, Some(SHIM_MGMT_TIMEOUT))
+            .with_context(|| format!("create shim management client for {}", sandbox_id))?;
+        let response = match data {
+            Some(data) => client.put(uri, data).await?,
+            None => client.get(uri).await?,
+        };
+
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .context("read response body")?;
+        if status != StatusCode::OK {
+            return Err(anyhow!(
+                "request to {} of sandbox {} failed ({}): {}",
+                uri,
+                sandbox_id,
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+
+        Ok(body.to_vec())
+    })
+}
+
+/// Send a GET request to the shim management server of the sandbox.
+pub fn shim_mgmt_get(sandbox_id: &str, uri: &str) -> Result<Vec<u8>> {
+    shim_mgmt_request(sandbox_id, uri, None)
+}
+
+/// Send a PUT request to the shim management server of the sandbox.
+pub fn shim_mgmt_put(sandbox_id: &str, uri: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+    shim_mgmt_request(sandbox_id, uri, Some(data))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
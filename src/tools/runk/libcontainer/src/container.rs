@@ -4,6 +4,7 @@
 //
 
 use crate::cgroup::{freeze, remove_cgroup_dir};
+use crate::criu::{self, CriuOpts};
 use crate::status::{self, get_current_container_state, Status};
 use anyhow::{anyhow, Result};
 use cgroups;
@@ -15,9 +16,11 @@ use nix::{
     sys::signal::SIGKILL,
     unistd::{chdir, unlink, Pid},
 };
-use oci::{ContainerState, State as OCIState};
+use oci::{ContainerState, LinuxResources, State as OCIState};
 use procfs;
+use protocols::agent::CgroupStats;
 use rustjail::cgroups::fs::Manager as CgroupManager;
+use rustjail::cgroups::notifier;
 use rustjail::{
     container::{BaseContainer, LinuxContainer, EXEC_FIFO_FILENAME},
     process::{Process, ProcessOperations},
@@ -30,10 +33,12 @@ use std::{
     fs,
     path::{Path, PathBuf},
 };
+use tokio::sync::mpsc::Receiver;
 
 use kata_sys_util::hooks::HookStates;
 
 pub const CONFIG_FILE_NAME: &str = "config.json";
+const CRIU_PID_FILENAME: &str = "criu.pid";
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ContainerAction {
@@ -198,6 +203,64 @@ impl Container {
         remove_cgroup_dir(&self.cgroup)?;
         self.status.remove_dir()
     }
+
+    /// Update the cgroup resources of the container, the new resources are saved in the status
+    /// to be reported by the state command.
+    pub fn update(&mut self, resources: LinuxResources, logger: &Logger) -> Result<()> {
+        if self.state == ContainerState::Stopped {
+            return Err(anyhow!(
+                "cannot update container {} that is stopped",
+                self.status.id
+            ));
+        }
+
+        let mut runner = load_linux_container(&self.status, None, logger)?;
+        runner.set(resources)?;
+
+        self.status.config = runner.config.clone();
+        self.status.save()
+    }
+
+    /// Get the cgroup statistics of the container.
+    pub fn stats(&self, logger: &Logger) -> Result<CgroupStats> {
+        let runner = load_linux_container(&self.status, None, logger)?;
+        let mut stats = runner.stats()?;
+        Ok(stats.take_cgroup_stats())
+    }
+
+    /// Watch the memory cgroup of the container, an event is received for each OOM kill and
+    /// the channel is closed when the container exits.
+    pub async fn notify_oom(&self, logger: &Logger) -> Result<Receiver<String>> {
+        let runner = load_linux_container(&self.status, None, logger)?;
+        let cg_path = runner.cgroup_manager.as_ref().get_cgroup_path("memory")?;
+        notifier::notify_oom(&self.status.id, cg_path).await
+    }
+
+    /// Checkpoint the container with CRIU. The container is destroyed after the checkpoint,
+    /// so that it can be restored with the same id, unless it's requested to leave it running.
+    pub fn checkpoint(&self, opts: &CriuOpts, logger: &Logger) -> Result<()> {
+        if self.state != ContainerState::Running && self.state != ContainerState::Paused {
+            return Err(anyhow!(
+                "cannot checkpoint container {}: current status is: {:?}",
+                self.status.id,
+                self.state
+            ));
+        }
+
+        let spec = self
+            .status
+            .config
+            .spec
+            .as_ref()
+            .ok_or_else(|| anyhow!("spec config was not present in the status"))?;
+        criu::dump(self.status.pid, spec, opts, logger)?;
+
+        // criu kills the dumped processes, only their state dir and cgroup are left
+        if !opts.leave_running {
+            self.destroy()?;
+        }
+        Ok(())
+    }
 }
 
 /// Used to run a process. If init is set, it will create a container and run the process in it.
@@ -320,6 +383,33 @@ impl ContainerLauncher {
         Ok(())
     }
 
+    /// Restore the container from a checkpoint instead of spawning its init process.
+    /// It's used for init containers only.
+    pub fn restore(&mut self, opts: &CriuOpts, logger: &Logger) -> Result<()> {
+        if !self.init {
+            return Err(anyhow!("restore is used for init-container only"));
+        }
+
+        let spec = self
+            .runner
+            .config
+            .spec
+            .as_ref()
+            .ok_or_else(|| anyhow!("spec config was not present"))?;
+        let criu_pid_file = Path::new(&self.runner.root).join(CRIU_PID_FILENAME);
+        let pid = criu::restore(spec, opts, &criu_pid_file, logger)?;
+        self.runner.init_process_pid = pid;
+
+        let status = self.get_status()?;
+        status.save()?;
+        debug!(logger, "saved status is {:?}", status);
+
+        if let Some(pid_file) = self.pid_file.as_ref() {
+            fs::write(pid_file, format!("{}", pid))?;
+        }
+        Ok(())
+    }
+
     /// Generate runk specified Status
     fn get_status(&self) -> Result<Status> {
         let oci_state = self.runner.oci_state()?;
@@ -0,0 +1,206 @@
+// Copyright 2021-2022 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Context, Result};
+use libc::pid_t;
+use oci::Spec;
+use slog::{debug, Logger};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CRIU: &str = "criu";
+const DUMP_LOG_FILE: &str = "dump.log";
+const RESTORE_LOG_FILE: &str = "restore.log";
+
+/// Options of the checkpoint and restore commands, which are passed to CRIU.
+#[derive(Debug, Default, Clone)]
+pub struct CriuOpts {
+    /// Directory of the checkpoint images
+    pub image_path: PathBuf,
+    /// Directory of the CRIU logs and temporary files, the image directory if not set
+    pub work_path: Option<PathBuf>,
+    /// Leave the container running after the checkpoint
+    pub leave_running: bool,
+    pub tcp_established: bool,
+    pub ext_unix_sk: bool,
+    pub shell_job: bool,
+    pub file_locks: bool,
+}
+
+impl CriuOpts {
+    fn common_args(&self, spec: &Spec, log_file: &str) -> Result<Vec<String>> {
+        let root = spec
+            .root
+            .as_ref()
+            .ok_or_else(|| anyhow!("root config was not present in the spec"))?;
+
+        let mut args = vec![
+            "--images-dir".to_string(),
+            path_to_string(&self.image_path)?,
+            "--root".to_string(),
+            root.path.clone(),
+            "--log-file".to_string(),
+            log_file.to_string(),
+            // the processes are restored in the cgroups they were dumped from
+            "--manage-cgroups".to_string(),
+        ];
+        if let Some(work_path) = self.work_path.as_ref() {
+            args.push("--work-dir".to_string());
+            args.push(path_to_string(work_path)?);
+        }
+
+        let flags = [
+            (self.tcp_established, "--tcp-established"),
+            (self.ext_unix_sk, "--ext-unix-sk"),
+            (self.shell_job, "--shell-job"),
+            (self.file_locks, "--file-locks"),
+        ];
+        for (enabled, flag) in flags {
+            if enabled {
+                args.push(flag.to_string());
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+fn path_to_string(path: &Path) -> Result<String> {
+    path.to_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("invalid path {:?}", path))
+}
+
+// The bind mounts come from outside of the container, CRIU has to be told how to map them.
+// They are keyed by their destination in the dump, and mapped back to their source on restore.
+fn ext_mount_args(spec: &Spec, restore: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    for m in spec.mounts.iter() {
+        if m.r#type != "bind" && !m.options.iter().any(|o| o == "bind" || o == "rbind") {
+            continue;
+        }
+        let external = if restore { &m.source } else { &m.destination };
+        args.push("--ext-mount-map".to_string());
+        args.push(format!("{}:{}", m.destination, external));
+    }
+    args
+}
+
+fn run_criu(args: &[String], logger: &Logger) -> Result<()> {
+    debug!(logger, "run criu with args {:?}", args);
+    let output = Command::new(CRIU)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run {}, is it installed", CRIU))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed with {}: {}",
+            CRIU,
+            args.first().map(|s| s.as_str()).unwrap_or_default(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Checkpoint the process tree of the container init process into the image directory.
+pub fn dump(pid: pid_t, spec: &Spec, opts: &CriuOpts, logger: &Logger) -> Result<()> {
+    fs::create_dir_all(&opts.image_path)
+        .with_context(|| format!("failed to create image path {:?}", opts.image_path))?;
+
+    let mut args = vec!["dump".to_string(), "--tree".to_string(), pid.to_string()];
+    args.extend(opts.common_args(spec, DUMP_LOG_FILE)?);
+    args.extend(ext_mount_args(spec, false));
+    if opts.leave_running {
+        args.push("--leave-running".to_string());
+    }
+
+    run_criu(&args, logger)
+}
+
+/// Restore the process tree from the image directory, the pid of the restored init process
+/// is returned.
+pub fn restore(spec: &Spec, opts: &CriuOpts, pid_file: &Path, logger: &Logger) -> Result<pid_t> {
+    let mut args = vec![
+        "restore".to_string(),
+        // criu exits once the processes are restored, they are reparented to init then
+        "--restore-detached".to_string(),
+        "--pidfile".to_string(),
+        path_to_string(pid_file)?,
+    ];
+    args.extend(opts.common_args(spec, RESTORE_LOG_FILE)?);
+    args.extend(ext_mount_args(spec, true));
+
+    run_criu(&args, logger)?;
+
+    let pid = fs::read_to_string(pid_file)
+        .with_context(|| format!("failed to read criu pid file {:?}", pid_file))?;
+    pid.trim()
+        .parse::<pid_t>()
+        .with_context(|| format!("invalid pid {:?} restored by criu", pid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::*;
+    use oci::Mount;
+
+    #[test]
+    fn test_criu_common_args() {
+        let spec = create_dummy_spec();
+        let opts = CriuOpts {
+            image_path: PathBuf::from("/tmp/checkpoint"),
+            tcp_established: true,
+            shell_job: true,
+            ..Default::default()
+        };
+        let args = opts.common_args(&spec, DUMP_LOG_FILE).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--images-dir",
+                "/tmp/checkpoint",
+                "--root",
+                TEST_ROOTFS_PATH,
+                "--log-file",
+                DUMP_LOG_FILE,
+                "--manage-cgroups",
+                "--tcp-established",
+                "--shell-job",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ext_mount_args() {
+        let mut spec = create_dummy_spec();
+        spec.mounts = vec![
+            Mount {
+                destination: "/proc".to_string(),
+                r#type: "proc".to_string(),
+                source: "proc".to_string(),
+                options: vec![],
+            },
+            Mount {
+                destination: "/etc/hosts".to_string(),
+                r#type: "none".to_string(),
+                source: "/run/hosts".to_string(),
+                options: vec!["rbind".to_string()],
+            },
+        ];
+
+        assert_eq!(
+            ext_mount_args(&spec, false),
+            vec!["--ext-mount-map", "/etc/hosts:/etc/hosts"]
+        );
+        assert_eq!(
+            ext_mount_args(&spec, true),
+            vec!["--ext-mount-map", "/etc/hosts:/run/hosts"]
+        );
+    }
+}
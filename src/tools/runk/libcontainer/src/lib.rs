@@ -7,6 +7,7 @@ pub mod activated_builder;
 pub mod cgroup;
 pub mod container;
 pub mod created_builder;
+pub mod criu;
 pub mod init_builder;
 pub mod status;
 pub mod utils;
@@ -0,0 +1,28 @@
+// Copyright 2021-2022 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::Result;
+use libcontainer::container::Container;
+use libcontainer::criu::CriuOpts;
+use liboci_cli::Checkpoint;
+use slog::{info, Logger};
+use std::path::Path;
+
+pub fn run(opts: Checkpoint, root: &Path, logger: &Logger) -> Result<()> {
+    let container = Container::load(root, &opts.container_id)?;
+    let criu_opts = CriuOpts {
+        image_path: opts.image_path,
+        work_path: opts.work_path,
+        leave_running: opts.leave_running,
+        tcp_established: opts.tcp_established,
+        ext_unix_sk: opts.ext_unix_sk,
+        shell_job: opts.shell_job,
+        file_locks: opts.file_locks,
+    };
+    container.checkpoint(&criu_opts, logger)?;
+
+    info!(&logger, "checkpoint command finished successfully");
+    Ok(())
+}
@@ -0,0 +1,101 @@
+// Copyright 2021-2022 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Result};
+use libcontainer::container::Container;
+use liboci_cli::Events;
+use oci::ContainerState;
+use serde::Serialize;
+use slog::{info, Logger};
+use std::path::Path;
+use std::time::Duration;
+
+const EVENT_TYPE_STATS: &str = "stats";
+const EVENT_TYPE_OOM: &str = "oom";
+
+// The event is printed as a json line, in the same format as runc.
+#[derive(Serialize, Debug)]
+struct Event<T: Serialize> {
+    #[serde(rename = "type")]
+    event_type: String,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<T>,
+}
+
+fn print_event<T: Serialize>(event_type: &str, id: &str, data: Option<T>) -> Result<()> {
+    let event = Event {
+        event_type: event_type.to_string(),
+        id: id.to_string(),
+        data,
+    };
+    println!("{}", serde_json::to_string(&event)?);
+    Ok(())
+}
+
+fn print_stats(container: &Container, logger: &Logger) -> Result<()> {
+    let stats = container.stats(logger)?;
+    print_event(EVENT_TYPE_STATS, &container.status.id, Some(stats))
+}
+
+pub async fn run(opts: Events, root: &Path, logger: &Logger) -> Result<()> {
+    let container = Container::load(root, &opts.container_id)?;
+    if container.state == ContainerState::Stopped {
+        return Err(anyhow!("container {} is not running", container.status.id));
+    }
+
+    // only display the stats once
+    if opts.stats {
+        return print_stats(&container, logger);
+    }
+
+    if opts.interval == 0 {
+        return Err(anyhow!("the interval must be greater than 0"));
+    }
+
+    let mut oom_rx = container.notify_oom(logger).await?;
+    let mut ticker = tokio::time::interval(Duration::from_secs(opts.interval as u64));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => print_stats(&container, logger)?,
+            oom = oom_rx.recv() => match oom {
+                Some(_) => print_event::<()>(EVENT_TYPE_OOM, &container.status.id, None)?,
+                // the channel is closed once the container exits
+                None => break,
+            },
+        }
+    }
+
+    info!(&logger, "events command finished successfully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_format() {
+        let event = Event::<()> {
+            event_type: EVENT_TYPE_OOM.to_string(),
+            id: "test".to_string(),
+            data: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"oom","id":"test"}"#
+        );
+
+        let event = Event {
+            event_type: EVENT_TYPE_STATS.to_string(),
+            id: "test".to_string(),
+            data: Some(vec![1, 2]),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"stats","id":"test","data":[1,2]}"#
+        );
+    }
+}
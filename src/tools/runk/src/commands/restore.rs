@@ -0,0 +1,72 @@
+// Copyright 2021-2022 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::Result;
+use clap::Parser;
+use libcontainer::criu::CriuOpts;
+use libcontainer::init_builder::InitContainerBuilder;
+use slog::{info, Logger};
+use std::path::{Path, PathBuf};
+
+/// Restore a container from a previous checkpoint
+// liboci-cli doesn't provide the restore command, the options follow the ones of runc.
+#[derive(Parser, Debug)]
+pub struct Restore {
+    /// Path to the bundle directory, containing config.json and root filesystem
+    #[clap(short, long, default_value = ".")]
+    pub bundle: PathBuf,
+    /// Unix socket (file) path , which will receive file descriptor of the writing end of the pseudoterminal
+    #[clap(short, long)]
+    pub console_socket: Option<PathBuf>,
+    /// File to write pid of the container created
+    #[clap(short, long)]
+    pub pid_file: Option<PathBuf>,
+    /// Path to the criu image files of the checkpoint
+    #[clap(long, default_value = "checkpoint")]
+    pub image_path: PathBuf,
+    /// Path for saving the criu work files and logs
+    #[clap(long)]
+    pub work_path: Option<PathBuf>,
+    /// Allow open tcp connections
+    #[clap(long)]
+    pub tcp_established: bool,
+    /// Allow external unix sockets
+    #[clap(long)]
+    pub ext_unix_sk: bool,
+    /// Allow shell jobs
+    #[clap(long)]
+    pub shell_job: bool,
+    /// Handle file locks
+    #[clap(long)]
+    pub file_locks: bool,
+    /// Name of the container instance to be restored
+    #[clap(forbid_empty_values = true, required = true)]
+    pub container_id: String,
+}
+
+pub fn run(opts: Restore, root: &Path, logger: &Logger) -> Result<()> {
+    let mut launcher = InitContainerBuilder::default()
+        .id(opts.container_id)
+        .bundle(opts.bundle)
+        .root(root.to_path_buf())
+        .console_socket(opts.console_socket)
+        .pid_file(opts.pid_file)
+        .build()?
+        .create_launcher(logger)?;
+
+    let criu_opts = CriuOpts {
+        image_path: opts.image_path,
+        work_path: opts.work_path,
+        leave_running: false,
+        tcp_established: opts.tcp_established,
+        ext_unix_sk: opts.ext_unix_sk,
+        shell_job: opts.shell_job,
+        file_locks: opts.file_locks,
+    };
+    launcher.restore(&criu_opts, logger)?;
+
+    info!(&logger, "restore command finished successfully");
+    Ok(())
+}
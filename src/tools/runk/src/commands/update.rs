@@ -0,0 +1,166 @@
+// Copyright 2021-2022 Kata Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use anyhow::{anyhow, Context, Result};
+use libcontainer::container::Container;
+use liboci_cli::Update;
+use oci::{LinuxBlockIo, LinuxCpu, LinuxMemory, LinuxPids, LinuxResources};
+use slog::{info, Logger};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Read the resources in the OCI json format from a file, or from the stdin for "-".
+fn read_resources(path: &Path) -> Result<LinuxResources> {
+    let mut content = String::new();
+    if path == Path::new("-") {
+        io::stdin().read_to_string(&mut content)?;
+    } else {
+        File::open(path)
+            .with_context(|| format!("failed to open resources file {:?}", path))?
+            .read_to_string(&mut content)?;
+    }
+    serde_json::from_str(&content).context("failed to parse resources")
+}
+
+// Apply the options of the command line to the current resources of the container.
+fn update_resources(opts: &Update, mut resources: LinuxResources) -> LinuxResources {
+    if let Some(weight) = opts.blkio_weight {
+        resources
+            .block_io
+            .get_or_insert_with(LinuxBlockIo::default)
+            .weight = Some(weight as u16);
+    }
+
+    let cpu = resources.cpu.get_or_insert_with(LinuxCpu::default);
+    if let Some(period) = opts.cpu_period {
+        cpu.period = Some(period as u64);
+    }
+    if let Some(quota) = opts.cpu_quota {
+        cpu.quota = Some(quota as i64);
+    }
+    if let Some(rt_period) = opts.cpu_rt_period {
+        cpu.realtime_period = Some(rt_period as u64);
+    }
+    if let Some(rt_runtime) = opts.cpu_rt_runtime {
+        cpu.realtime_runtime = Some(rt_runtime as i64);
+    }
+    if let Some(shares) = opts.cpu_share {
+        cpu.shares = Some(shares as u64);
+    }
+    if let Some(cpus) = opts.cpuset_cpus.as_ref() {
+        cpu.cpus = cpus.clone();
+    }
+    if let Some(mems) = opts.cpuset_mems.as_ref() {
+        cpu.mems = mems.clone();
+    }
+
+    let memory = resources.memory.get_or_insert_with(LinuxMemory::default);
+    if let Some(limit) = opts.memory {
+        memory.limit = Some(limit as i64);
+    }
+    if let Some(reservation) = opts.memory_reservation {
+        memory.reservation = Some(reservation as i64);
+    }
+    if let Some(swap) = opts.memory_swap {
+        memory.swap = Some(swap as i64);
+    }
+
+    if let Some(limit) = opts.pids_limit {
+        resources.pids = Some(LinuxPids {
+            limit: limit as i64,
+        });
+    }
+
+    resources
+}
+
+pub fn run(opts: Update, root: &Path, logger: &Logger) -> Result<()> {
+    let mut container = Container::load(root, &opts.container_id)?;
+
+    let resources = if let Some(path) = opts.resources.as_ref() {
+        read_resources(path)?
+    } else {
+        let current = container
+            .status
+            .config
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.linux.as_ref())
+            .ok_or_else(|| anyhow!("linux config was not present in the status"))?
+            .resources
+            .clone()
+            .unwrap_or_default();
+        update_resources(&opts, current)
+    };
+
+    container.update(resources, logger)?;
+
+    info!(&logger, "update command finished successfully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::io::Write;
+
+    #[test]
+    fn test_update_resources() {
+        let opts = Update::try_parse_from([
+            "update",
+            "--memory",
+            "1048576",
+            "--cpu-quota",
+            "20000",
+            "--cpuset-cpus",
+            "0-1",
+            "--pids-limit",
+            "10",
+            "test",
+        ])
+        .unwrap();
+
+        let current = LinuxResources {
+            memory: Some(LinuxMemory {
+                reservation: Some(4096),
+                ..Default::default()
+            }),
+            cpu: Some(LinuxCpu {
+                period: Some(100000),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let resources = update_resources(&opts, current);
+
+        // the options are applied over the current resources
+        let memory = resources.memory.unwrap();
+        assert_eq!(memory.limit, Some(1048576));
+        assert_eq!(memory.reservation, Some(4096));
+        let cpu = resources.cpu.unwrap();
+        assert_eq!(cpu.quota, Some(20000));
+        assert_eq!(cpu.period, Some(100000));
+        assert_eq!(cpu.cpus, "0-1");
+        assert_eq!(resources.pids.unwrap().limit, 10);
+        assert!(resources.block_io.is_none());
+    }
+
+    #[test]
+    fn test_read_resources() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(br#"{"memory": {"limit": 1048576}, "pids": {"limit": 10}}"#)
+            .unwrap();
+
+        let resources = read_resources(file.path()).unwrap();
+        assert_eq!(resources.memory.unwrap().limit, Some(1048576));
+        assert_eq!(resources.pids.unwrap().limit, 10);
+
+        file.as_file().set_len(0).unwrap();
+        assert!(read_resources(file.path()).is_err());
+        assert!(read_resources(Path::new("/path/not/exist")).is_err());
+    }
+}
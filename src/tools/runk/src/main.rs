@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use clap::{crate_description, crate_name, Parser};
 use liboci_cli::{CommonCmd, GlobalOpts};
 use liboci_cli::{Create, Delete, Kill, Start, State};
@@ -26,6 +26,7 @@ enum SubCommand {
     Standard(StandardCmd),
     #[clap(flatten)]
     Common(CommonCmd),
+    Restore(commands::restore::Restore),
     /// Launch an init process (do not call it outside of runk)
     Init {},
 }
@@ -66,10 +67,13 @@ async fn cmd_run(subcmd: SubCommand, root_path: &Path, logger: &Logger) -> Resul
             CommonCmd::Ps(ps) => commands::ps::run(ps, root_path, logger),
             CommonCmd::Pause(pause) => commands::pause::run(pause, root_path, logger),
             CommonCmd::Resume(resume) => commands::resume::run(resume, root_path, logger),
-            _ => {
-                return Err(anyhow!("command is not implemented yet"));
+            CommonCmd::Update(update) => commands::update::run(update, root_path, logger),
+            CommonCmd::Events(events) => commands::events::run(events, root_path, logger).await,
+            CommonCmd::Checkpointt(checkpoint) => {
+                commands::checkpoint::run(checkpoint, root_path, logger)
             }
         },
+        SubCommand::Restore(restore) => commands::restore::run(restore, root_path, logger),
         _ => unreachable!(),
     }
 }